@@ -0,0 +1,598 @@
+// 批注：高亮与笔记
+//
+// 高亮记录页码、QuadPoints、颜色和选中的文字；笔记正文为 Markdown，
+// 可以关联到某条高亮，正文中的 #标签 会被提取出来单独保存。
+// 坐标与 get_page_text 一致：PDF 点为单位，原点在页面左上角。
+
+use regex::Regex;
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde::Serialize;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::LazyLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::search;
+use crate::storage::{now_secs, Storage};
+
+pub const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS highlights (
+    id TEXT PRIMARY KEY,
+    document_id TEXT NOT NULL,
+    page INTEGER NOT NULL,
+    quads TEXT NOT NULL,
+    color TEXT NOT NULL,
+    text TEXT NOT NULL DEFAULT '',
+    created_at INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS idx_highlights_document ON highlights(document_id, page);
+CREATE TABLE IF NOT EXISTS notes (
+    id TEXT PRIMARY KEY,
+    document_id TEXT NOT NULL,
+    highlight_id TEXT REFERENCES highlights(id) ON DELETE SET NULL,
+    page INTEGER,
+    body TEXT NOT NULL,
+    tags TEXT NOT NULL DEFAULT '[]',
+    created_at INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS idx_notes_document ON notes(document_id);
+CREATE INDEX IF NOT EXISTS idx_notes_highlight ON notes(highlight_id);
+";
+
+pub const DEFAULT_HIGHLIGHT_COLOR: &str = "#ffeb3b";
+
+const HIGHLIGHT_COLUMNS: &str = "id, document_id, page, quads, color, text, created_at, updated_at";
+const NOTE_COLUMNS: &str = "id, document_id, highlight_id, page, body, tags, created_at, updated_at";
+
+static COLOR_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^#[0-9a-fA-F]{6}$").unwrap());
+// #标签：# 后紧跟文字，# 前是行首或空白（排除 Markdown 标题和 URL 锚点）
+static TAG_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?:^|\s)#([\p{L}\p{N}_\-/]+)").unwrap());
+static CODE_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?s)```.*?```|`[^`\n]*`").unwrap());
+
+// 一个四边形，依次为左上、右上、左下、右下四个点的 x, y（与 PDF QuadPoints 顺序相同）
+pub type Quad = [f32; 8];
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Highlight {
+    pub id: String,
+    pub document_id: String,
+    pub page: u32,
+    pub quads: Vec<Quad>,
+    pub color: String,
+    pub text: String,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Note {
+    pub id: String,
+    pub document_id: String,
+    pub highlight_id: Option<String>,
+    pub page: Option<u32>,
+    // Markdown 正文
+    pub body: String,
+    pub tags: Vec<String>,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Annotations {
+    pub highlights: Vec<Highlight>,
+    pub notes: Vec<Note>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TagCount {
+    pub tag: String,
+    pub count: u32,
+}
+
+/// 生成批注 ID：纳秒时间戳加进程内计数，保证同一时刻创建的批注也不重复
+fn new_id() -> String {
+    static COUNTER: AtomicU32 = AtomicU32::new(0);
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or(0);
+    let seq = COUNTER.fetch_add(1, Ordering::Relaxed) & 0xffff;
+    format!("{:x}{:04x}", nanos, seq)
+}
+
+/// 提取 Markdown 正文中的 #标签（忽略代码块），去重并保持出现顺序
+pub fn extract_tags(body: &str) -> Vec<String> {
+    let text = CODE_RE.replace_all(body, " ");
+    let mut tags: Vec<String> = Vec::new();
+    for caps in TAG_RE.captures_iter(&text) {
+        let tag = caps[1].trim_end_matches(['-', '/']).to_string();
+        // 纯数字（如 #1）多半是编号，不算标签
+        if tag.is_empty() || tag.chars().all(|c| c.is_ascii_digit()) {
+            continue;
+        }
+        if !tags.iter().any(|t| t.eq_ignore_ascii_case(&tag)) {
+            tags.push(tag);
+        }
+    }
+    tags
+}
+
+fn validate_color(color: &str) -> Result<(), String> {
+    if COLOR_RE.is_match(color) {
+        Ok(())
+    } else {
+        Err(format!("Invalid highlight color: {}", color))
+    }
+}
+
+fn highlight_from_row(row: &Row) -> rusqlite::Result<Highlight> {
+    let quads: String = row.get(3)?;
+    Ok(Highlight {
+        id: row.get(0)?,
+        document_id: row.get(1)?,
+        page: row.get(2)?,
+        quads: serde_json::from_str(&quads).unwrap_or_default(),
+        color: row.get(4)?,
+        text: row.get(5)?,
+        created_at: row.get(6)?,
+        updated_at: row.get(7)?,
+    })
+}
+
+fn note_from_row(row: &Row) -> rusqlite::Result<Note> {
+    let tags: String = row.get(5)?;
+    Ok(Note {
+        id: row.get(0)?,
+        document_id: row.get(1)?,
+        highlight_id: row.get(2)?,
+        page: row.get(3)?,
+        body: row.get(4)?,
+        tags: serde_json::from_str(&tags).unwrap_or_default(),
+        created_at: row.get(6)?,
+        updated_at: row.get(7)?,
+    })
+}
+
+fn find_highlight(conn: &Connection, id: &str) -> rusqlite::Result<Option<Highlight>> {
+    conn.query_row(
+        &format!("SELECT {} FROM highlights WHERE id = ?1", HIGHLIGHT_COLUMNS),
+        params![id],
+        highlight_from_row,
+    )
+    .optional()
+}
+
+fn find_note(conn: &Connection, id: &str) -> rusqlite::Result<Option<Note>> {
+    conn.query_row(
+        &format!("SELECT {} FROM notes WHERE id = ?1", NOTE_COLUMNS),
+        params![id],
+        note_from_row,
+    )
+    .optional()
+}
+
+/* ---------------------------------
+ * 高亮
+ * --------------------------------- */
+
+pub fn add_highlight(
+    storage: &Storage,
+    document_id: &str,
+    page: u32,
+    quads: Vec<Quad>,
+    color: Option<&str>,
+    text: &str,
+) -> Result<Highlight, String> {
+    if page == 0 {
+        return Err("Page numbers start at 1".to_string());
+    }
+    if quads.is_empty() {
+        return Err("A highlight needs at least one quad".to_string());
+    }
+    let color = color.unwrap_or(DEFAULT_HIGHLIGHT_COLOR);
+    validate_color(color)?;
+
+    let now = now_secs();
+    let highlight = Highlight {
+        id: new_id(),
+        document_id: document_id.to_string(),
+        page,
+        quads,
+        color: color.to_string(),
+        text: text.trim().to_string(),
+        created_at: now,
+        updated_at: now,
+    };
+    insert_highlight(storage, &highlight)?;
+    Ok(highlight)
+}
+
+/// 写入一条高亮，ID 已存在时忽略（导入时用于去重），返回是否写入
+pub fn insert_highlight(storage: &Storage, highlight: &Highlight) -> Result<bool, String> {
+    let quads_json = serde_json::to_string(&highlight.quads).map_err(|e| e.to_string())?;
+    let inserted = storage.with_conn(|conn| {
+        conn.execute(
+            "INSERT OR IGNORE INTO highlights (id, document_id, page, quads, color, text, created_at, updated_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![
+                highlight.id,
+                highlight.document_id,
+                highlight.page,
+                quads_json,
+                highlight.color,
+                highlight.text,
+                highlight.created_at,
+                highlight.updated_at
+            ],
+        )
+    })? > 0;
+
+    if inserted && !highlight.text.is_empty() {
+        search::index_text(
+            storage,
+            &highlight.document_id,
+            "highlight",
+            Some(&highlight.id),
+            Some(highlight.page),
+            &highlight.text,
+        )?;
+    }
+    Ok(inserted)
+}
+
+pub fn edit_highlight(storage: &Storage, id: &str, color: Option<&str>, text: Option<&str>) -> Result<Highlight, String> {
+    if let Some(color) = color {
+        validate_color(color)?;
+    }
+    let text = text.map(str::trim);
+    let highlight = storage.with_conn(|conn| {
+        conn.execute(
+            "UPDATE highlights SET color = COALESCE(?2, color), text = COALESCE(?3, text), updated_at = ?4
+             WHERE id = ?1",
+            params![id, color, text, now_secs()],
+        )?;
+        find_highlight(conn, id)
+    })?;
+    let highlight = highlight.ok_or_else(|| format!("Highlight not found: {}", id))?;
+
+    if text.is_some() {
+        if highlight.text.is_empty() {
+            search::remove_text(storage, &highlight.document_id, "highlight", &highlight.id)?;
+        } else {
+            search::index_text(
+                storage,
+                &highlight.document_id,
+                "highlight",
+                Some(&highlight.id),
+                Some(highlight.page),
+                &highlight.text,
+            )?;
+        }
+    }
+    Ok(highlight)
+}
+
+/// 删除高亮；关联的笔记保留，只解除关联
+pub fn delete_highlight_by_id(storage: &Storage, id: &str) -> Result<(), String> {
+    let removed = storage.with_conn(|conn| {
+        let highlight = find_highlight(conn, id)?;
+        conn.execute("DELETE FROM highlights WHERE id = ?1", params![id])?;
+        Ok(highlight)
+    })?;
+    if let Some(highlight) = removed {
+        search::remove_text(storage, &highlight.document_id, "highlight", &highlight.id)?;
+    }
+    Ok(())
+}
+
+/* ---------------------------------
+ * 笔记
+ * --------------------------------- */
+
+/// 新建笔记；关联高亮时页码取高亮所在页
+pub fn add_note(
+    storage: &Storage,
+    document_id: &str,
+    body: &str,
+    highlight_id: Option<&str>,
+    page: Option<u32>,
+) -> Result<Note, String> {
+    let body = body.trim();
+    if body.is_empty() {
+        return Err("Note is empty".to_string());
+    }
+
+    let highlight = match highlight_id {
+        Some(hid) => storage.with_conn(|conn| find_highlight(conn, hid))?,
+        None => None,
+    };
+    if highlight_id.is_some() && highlight.is_none() {
+        println!("⚠️ 笔记关联的高亮不存在: {:?}", highlight_id);
+    }
+
+    let now = now_secs();
+    let note = Note {
+        id: new_id(),
+        document_id: document_id.to_string(),
+        highlight_id: highlight.as_ref().map(|h| h.id.clone()),
+        page: highlight.as_ref().map(|h| h.page).or(page),
+        body: body.to_string(),
+        tags: extract_tags(body),
+        created_at: now,
+        updated_at: now,
+    };
+    insert_note(storage, &note)?;
+    Ok(note)
+}
+
+/// 写入一条笔记，ID 已存在时忽略，返回是否写入
+pub fn insert_note(storage: &Storage, note: &Note) -> Result<bool, String> {
+    let tags_json = serde_json::to_string(&note.tags).map_err(|e| e.to_string())?;
+    let inserted = storage.with_conn(|conn| {
+        conn.execute(
+            "INSERT OR IGNORE INTO notes (id, document_id, highlight_id, page, body, tags, created_at, updated_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![
+                note.id,
+                note.document_id,
+                note.highlight_id,
+                note.page,
+                note.body,
+                tags_json,
+                note.created_at,
+                note.updated_at
+            ],
+        )
+    })? > 0;
+
+    if inserted {
+        search::index_text(storage, &note.document_id, "note", Some(&note.id), note.page, &note.body)?;
+    }
+    Ok(inserted)
+}
+
+pub fn edit_note(storage: &Storage, id: &str, body: &str) -> Result<Note, String> {
+    let body = body.trim();
+    if body.is_empty() {
+        return Err("Note is empty".to_string());
+    }
+    let tags_json = serde_json::to_string(&extract_tags(body)).map_err(|e| e.to_string())?;
+    let note = storage.with_conn(|conn| {
+        conn.execute(
+            "UPDATE notes SET body = ?2, tags = ?3, updated_at = ?4 WHERE id = ?1",
+            params![id, body, tags_json, now_secs()],
+        )?;
+        find_note(conn, id)
+    })?;
+    let note = note.ok_or_else(|| format!("Note not found: {}", id))?;
+
+    search::index_text(storage, &note.document_id, "note", Some(&note.id), note.page, &note.body)?;
+    Ok(note)
+}
+
+pub fn delete_note_by_id(storage: &Storage, id: &str) -> Result<(), String> {
+    let removed = storage.with_conn(|conn| {
+        let note = find_note(conn, id)?;
+        conn.execute("DELETE FROM notes WHERE id = ?1", params![id])?;
+        Ok(note)
+    })?;
+    if let Some(note) = removed {
+        search::remove_text(storage, &note.document_id, "note", &note.id)?;
+    }
+    Ok(())
+}
+
+/* ---------------------------------
+ * 查询
+ * --------------------------------- */
+
+pub fn document_annotations(storage: &Storage, document_id: &str) -> Result<Annotations, String> {
+    storage.with_conn(|conn| {
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM highlights WHERE document_id = ?1 ORDER BY page, created_at",
+            HIGHLIGHT_COLUMNS
+        ))?;
+        let highlights = stmt
+            .query_map(params![document_id], highlight_from_row)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM notes WHERE document_id = ?1 ORDER BY page IS NULL, page, created_at",
+            NOTE_COLUMNS
+        ))?;
+        let notes = stmt
+            .query_map(params![document_id], note_from_row)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        Ok(Annotations { highlights, notes })
+    })
+}
+
+/// 按标签查找整个文献库的笔记（不区分大小写）
+pub fn notes_with_tag(storage: &Storage, tag: &str) -> Result<Vec<Note>, String> {
+    let tag = tag.trim().trim_start_matches('#');
+    storage.with_conn(|conn| {
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM notes
+             WHERE EXISTS (SELECT 1 FROM json_each(notes.tags) WHERE value = ?1 COLLATE NOCASE)
+             ORDER BY updated_at DESC",
+            NOTE_COLUMNS
+        ))?;
+        let rows = stmt.query_map(params![tag], note_from_row)?;
+        rows.collect()
+    })
+}
+
+pub fn tag_counts(storage: &Storage) -> Result<Vec<TagCount>, String> {
+    storage.with_conn(|conn| {
+        let mut stmt = conn.prepare(
+            "SELECT t.value, COUNT(*) FROM notes, json_each(notes.tags) t
+             GROUP BY t.value COLLATE NOCASE
+             ORDER BY COUNT(*) DESC, t.value",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok(TagCount {
+                tag: row.get(0)?,
+                count: row.get(1)?,
+            })
+        })?;
+        rows.collect()
+    })
+}
+
+/* ---------------------------------
+ * 命令
+ * --------------------------------- */
+
+#[tauri::command]
+pub fn create_highlight(
+    document_id: String,
+    page: u32,
+    quads: Vec<Quad>,
+    color: Option<String>,
+    text: Option<String>,
+    storage: tauri::State<'_, Storage>,
+) -> Result<Highlight, String> {
+    add_highlight(
+        &storage,
+        &document_id,
+        page,
+        quads,
+        color.as_deref(),
+        text.as_deref().unwrap_or(""),
+    )
+}
+
+#[tauri::command]
+pub fn update_highlight(
+    id: String,
+    color: Option<String>,
+    text: Option<String>,
+    storage: tauri::State<'_, Storage>,
+) -> Result<Highlight, String> {
+    edit_highlight(&storage, &id, color.as_deref(), text.as_deref())
+}
+
+#[tauri::command]
+pub fn delete_highlight(id: String, storage: tauri::State<'_, Storage>) -> Result<(), String> {
+    delete_highlight_by_id(&storage, &id)
+}
+
+#[tauri::command]
+pub fn create_note(
+    document_id: String,
+    body: String,
+    highlight_id: Option<String>,
+    page: Option<u32>,
+    storage: tauri::State<'_, Storage>,
+) -> Result<Note, String> {
+    add_note(&storage, &document_id, &body, highlight_id.as_deref(), page)
+}
+
+#[tauri::command]
+pub fn update_note(id: String, body: String, storage: tauri::State<'_, Storage>) -> Result<Note, String> {
+    edit_note(&storage, &id, &body)
+}
+
+#[tauri::command]
+pub fn delete_note(id: String, storage: tauri::State<'_, Storage>) -> Result<(), String> {
+    delete_note_by_id(&storage, &id)
+}
+
+#[tauri::command]
+pub fn list_annotations(document_id: String, storage: tauri::State<'_, Storage>) -> Result<Annotations, String> {
+    document_annotations(&storage, &document_id)
+}
+
+#[tauri::command]
+pub fn list_notes_by_tag(tag: String, storage: tauri::State<'_, Storage>) -> Result<Vec<Note>, String> {
+    notes_with_tag(&storage, &tag)
+}
+
+#[tauri::command]
+pub fn list_tags(storage: tauri::State<'_, Storage>) -> Result<Vec<TagCount>, String> {
+    tag_counts(&storage)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    // PRD：批注写入需在 200 ms 内完成
+    const WRITE_BUDGET: Duration = Duration::from_millis(200);
+
+    fn quad() -> Quad {
+        [72.0, 100.0, 300.0, 100.0, 72.0, 112.0, 300.0, 112.0]
+    }
+
+    #[test]
+    fn annotation_writes_stay_within_budget() {
+        // 与应用相同：磁盘上的数据库文件，WAL 模式
+        let dir = std::env::temp_dir().join(format!("papertalk-annotations-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("papertalk.db");
+        let _ = std::fs::remove_file(&path);
+        let storage = Storage::open(&path).unwrap();
+        // 先写入一些已有批注，避免只测到空表
+        for i in 0..200 {
+            add_highlight(&storage, "doc", i % 20 + 1, vec![quad()], None, "existing text").unwrap();
+        }
+
+        let start = Instant::now();
+        let highlight = add_highlight(
+            &storage,
+            "doc",
+            3,
+            vec![quad(), quad()],
+            Some("#a5d6a7"),
+            "scaled dot-product attention",
+        )
+        .unwrap();
+        assert!(start.elapsed() <= WRITE_BUDGET, "highlight write took {:?}", start.elapsed());
+
+        let start = Instant::now();
+        let note = add_note(&storage, "doc", "Key idea #attention #transformer", Some(&highlight.id), None).unwrap();
+        assert!(start.elapsed() <= WRITE_BUDGET, "note write took {:?}", start.elapsed());
+
+        let start = Instant::now();
+        edit_note(&storage, &note.id, "Revised #attention").unwrap();
+        assert!(start.elapsed() <= WRITE_BUDGET, "note update took {:?}", start.elapsed());
+
+        let start = Instant::now();
+        delete_highlight_by_id(&storage, &highlight.id).unwrap();
+        assert!(start.elapsed() <= WRITE_BUDGET, "highlight delete took {:?}", start.elapsed());
+
+        drop(storage);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn notes_round_trip_with_tags_and_anchor() {
+        let storage = Storage::open_in_memory().unwrap();
+        let highlight = add_highlight(&storage, "doc", 5, vec![quad()], None, "layer norm").unwrap();
+        let note = add_note(
+            &storage,
+            "doc",
+            "# Heading\nSee `#not-a-tag` and #归一化 #Norm, issue #12",
+            Some(&highlight.id),
+            None,
+        )
+        .unwrap();
+        assert_eq!(note.page, Some(5));
+        assert_eq!(note.tags, vec!["归一化", "Norm"]);
+
+        let annotations = document_annotations(&storage, "doc").unwrap();
+        assert_eq!(annotations.highlights.len(), 1);
+        assert_eq!(annotations.highlights[0].quads, vec![quad()]);
+        assert_eq!(annotations.notes[0].highlight_id.as_deref(), Some(highlight.id.as_str()));
+        assert_eq!(notes_with_tag(&storage, "#norm").unwrap().len(), 1);
+
+        // 删除高亮后笔记仍在，只是不再关联
+        delete_highlight_by_id(&storage, &highlight.id).unwrap();
+        let annotations = document_annotations(&storage, "doc").unwrap();
+        assert!(annotations.highlights.is_empty());
+        assert_eq!(annotations.notes[0].highlight_id, None);
+    }
+}
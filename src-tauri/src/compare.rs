@@ -0,0 +1,461 @@
+// 多模型对比翻译：同一段原文同时交给几个服务，并排展示，用户选定的译文进入翻译记录
+//
+// 每个服务的输出通过 compare_chunk / compare_reasoning_chunk / compare_complete 事件推送，
+// payload 带上 provider，前端据此分栏显示。候选译文单独保存，选中后写入 translations 表和翻译记忆。
+
+use rusqlite::{params, OptionalExtension};
+use serde::Serialize;
+use tauri::Manager;
+
+use crate::providers::{self, Completion, CompletionRequest, ModelConfig, ProviderTarget, StreamDelta};
+use crate::scheduler::{Priority, Scheduler};
+use crate::storage::{now_secs, Storage};
+use crate::{emit_to_window, segment, translation_memory, translations, usage, TRANSLATE_SYSTEM_PROMPT};
+
+pub const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS translation_candidates (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    comparison_id TEXT NOT NULL,
+    document_id TEXT,
+    source TEXT NOT NULL,
+    provider TEXT NOT NULL,
+    model TEXT NOT NULL,
+    translation TEXT NOT NULL,
+    picked INTEGER NOT NULL DEFAULT 0,
+    created_at INTEGER NOT NULL,
+    reasoning TEXT,
+    -- 选中后写入的 translations 记录，同一次对比的候选共用一条
+    translation_id INTEGER
+);
+CREATE INDEX IF NOT EXISTS idx_translation_candidates_comparison ON translation_candidates(comparison_id);
+";
+
+// 一次最多对比的服务数，和设置页里可配置的服务数一致
+const MAX_PROVIDERS: usize = 3;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TranslationCandidate {
+    // 失败的服务没有候选记录
+    pub id: Option<i64>,
+    pub provider: String,
+    pub model: String,
+    pub translation: String,
+    pub reasoning: String,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TranslationComparison {
+    pub id: String,
+    pub source: String,
+    pub candidates: Vec<TranslationCandidate>,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CandidateChunk<'a> {
+    comparison_id: &'a str,
+    provider: &'a str,
+    text: &'a str,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CandidateComplete<'a> {
+    comparison_id: &'a str,
+    provider: &'a str,
+    content: &'a str,
+    error: Option<&'a str>,
+}
+
+fn comparison_id() -> String {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or_default();
+    format!("cmp-{:x}", nanos)
+}
+
+fn save_candidate(
+    storage: &Storage,
+    comparison_id: &str,
+    document_id: Option<&str>,
+    source: &str,
+    completion: &Completion,
+) -> Result<i64, String> {
+    storage.with_conn(|conn| {
+        conn.execute(
+            "INSERT INTO translation_candidates
+                 (comparison_id, document_id, source, provider, model, translation, created_at, reasoning)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![
+                comparison_id,
+                document_id,
+                source,
+                completion.provider,
+                completion.model,
+                completion.content.trim(),
+                now_secs(),
+                Some(completion.reasoning.trim()).filter(|r| !r.is_empty())
+            ],
+        )?;
+        Ok(conn.last_insert_rowid())
+    })
+}
+
+// 单个服务依次翻译各段：不重试也不回退，失败只影响这一栏
+async fn run_candidate(
+    target: ProviderTarget,
+    source: String,
+    segments: Vec<(&'static str, CompletionRequest)>,
+    comparison_id: String,
+    document_id: Option<String>,
+    app_handle: tauri::AppHandle,
+) -> TranslationCandidate {
+    let provider = target.provider.clone();
+    let storage = app_handle.state::<Storage>();
+    let mut result = Completion::default();
+    let mut total = usage::Usage::default();
+    let mut error = None;
+
+    for (index, (separator, request)) in segments.iter().enumerate() {
+        let emit = |event: &str, text: &str| {
+            emit_to_window(
+                &app_handle,
+                event,
+                CandidateChunk {
+                    comparison_id: &comparison_id,
+                    provider: &provider,
+                    text,
+                },
+            );
+        };
+        if index > 0 {
+            emit("compare_chunk", separator);
+        }
+        let part = {
+            let scheduler = app_handle.state::<Scheduler>();
+            let _permit = scheduler.acquire(&provider, request.priority).await;
+            let mut on_delta = |delta: StreamDelta| match delta {
+                StreamDelta::Content(text) => emit("compare_chunk", text),
+                StreamDelta::Reasoning(text) => emit("compare_reasoning_chunk", text),
+                StreamDelta::Fallback { .. } => {}
+            };
+            providers::stream_completion(&target, request, &mut on_delta).await
+        };
+        let part = match part {
+            Ok(part) => part,
+            Err(e) => {
+                error = Some(if segments.len() > 1 {
+                    format!("Segment {}/{} failed: {}", index + 1, segments.len(), e.message)
+                } else {
+                    e.message
+                });
+                break;
+            }
+        };
+
+        usage::record_completion(&storage, document_id.as_deref(), "compare", &part);
+        if let Some(used) = &part.usage {
+            total.prompt_tokens += used.prompt_tokens;
+            total.completion_tokens += used.completion_tokens;
+            total.estimated |= used.estimated;
+        }
+        if index > 0 {
+            result.content.push_str(separator);
+        }
+        result.content.push_str(part.content.trim());
+        result.reasoning.push_str(&part.reasoning);
+        if result.finish_reason.as_deref() != Some("length") {
+            result.finish_reason = part.finish_reason;
+        }
+        result.provider = part.provider;
+        result.model = part.model;
+    }
+    result.usage = Some(total);
+
+    let candidate = match error {
+        None => {
+            let id = match save_candidate(&storage, &comparison_id, document_id.as_deref(), &source, &result) {
+                Ok(id) => Some(id),
+                Err(e) => {
+                    println!("⚠️ 保存候选译文失败：{}", e);
+                    None
+                }
+            };
+            TranslationCandidate {
+                id,
+                provider: result.provider,
+                model: result.model,
+                translation: result.content.trim().to_string(),
+                reasoning: result.reasoning.trim().to_string(),
+                error: None,
+            }
+        }
+        Some(message) => {
+            println!("[compare] ❌ {} 失败：{}", target.display_name(), message);
+            TranslationCandidate {
+                id: None,
+                provider: provider.clone(),
+                model: target.model.clone(),
+                translation: String::new(),
+                reasoning: String::new(),
+                error: Some(message),
+            }
+        }
+    };
+
+    emit_to_window(
+        &app_handle,
+        "compare_complete",
+        CandidateComplete {
+            comparison_id: &comparison_id,
+            provider: &candidate.provider,
+            content: &candidate.translation,
+            error: candidate.error.as_deref(),
+        },
+    );
+    candidate
+}
+
+/// 把选中的文字同时交给多个服务翻译，返回各服务的候选译文
+#[tauri::command]
+pub async fn compare_translations(
+    text: String,
+    providers: Vec<String>,
+    config: ModelConfig,
+    document_id: Option<String>,
+    show_reasoning: Option<bool>,
+    priority: Option<Priority>,
+    app_handle: tauri::AppHandle,
+) -> Result<TranslationComparison, String> {
+    let source = text.trim().to_string();
+    if source.is_empty() {
+        return Err("Select some text first".to_string());
+    }
+    let mut wanted: Vec<String> = Vec::new();
+    for provider in providers {
+        if !wanted.contains(&provider) {
+            wanted.push(provider);
+        }
+    }
+    if wanted.len() < 2 {
+        return Err("Choose at least two providers to compare".to_string());
+    }
+    if wanted.len() > MAX_PROVIDERS {
+        return Err(format!("At most {} providers can be compared at once", MAX_PROVIDERS));
+    }
+    println!("⚖️ 收到对比翻译请求，文本：{}，服务：{:?}", source, wanted);
+
+    // 配置不完整的服务直接作为失败候选返回，不影响其他服务
+    let mut targets = Vec::new();
+    let mut skipped = Vec::new();
+    for provider in &wanted {
+        match config.target(provider) {
+            Ok(target) => targets.push(target),
+            Err(e) => skipped.push((provider.clone(), e)),
+        }
+    }
+    if targets.is_empty() {
+        let reasons: Vec<String> = skipped.iter().map(|(p, e)| format!("{}: {}", p, e)).collect();
+        return Err(format!("No provider is available: {}", reasons.join("; ")));
+    }
+
+    app_handle.state::<Scheduler>().configure(&config.rate_limits);
+    let id = comparison_id();
+    // 各服务使用同样的分段和翻译记忆示例，对比才公平；分段按参与对比的服务中最紧的预算
+    let model = segment::tokenizer_model(&config);
+    let storage = app_handle.state::<Storage>();
+    let segments: Vec<(&'static str, CompletionRequest)> =
+        segment::split_for_targets(&targets, &model, &source, TRANSLATE_SYSTEM_PROMPT)
+            .into_iter()
+            .map(|segment| {
+                let examples = translation_memory::few_shot_prompt(&storage, &segment.text, &model);
+                let request = CompletionRequest {
+                    system: format!("{}{}", TRANSLATE_SYSTEM_PROMPT, examples),
+                    prompt: segment.text,
+                    images: Vec::new(),
+                    show_reasoning: show_reasoning.unwrap_or(false),
+                    priority: priority.unwrap_or_default(),
+                };
+                (segment.separator, request)
+            })
+            .collect();
+    if segments.len() > 1 {
+        println!("✂️ 选区较长，分 {} 段对比翻译", segments.len());
+    }
+    let tasks: Vec<_> = targets
+        .into_iter()
+        .map(|target| {
+            tauri::async_runtime::spawn(run_candidate(
+                target,
+                source.clone(),
+                segments.clone(),
+                id.clone(),
+                document_id.clone(),
+                app_handle.clone(),
+            ))
+        })
+        .collect();
+
+    let mut candidates = Vec::new();
+    for task in tasks {
+        match task.await {
+            Ok(candidate) => candidates.push(candidate),
+            Err(e) => println!("⚠️ 对比翻译任务异常退出：{}", e),
+        }
+    }
+    for (provider, error) in skipped {
+        candidates.push(TranslationCandidate {
+            id: None,
+            model: String::new(),
+            provider,
+            translation: String::new(),
+            reasoning: String::new(),
+            error: Some(error),
+        });
+    }
+    // 按用户选择的顺序返回
+    candidates.sort_by_key(|c| wanted.iter().position(|p| *p == c.provider));
+
+    Ok(TranslationComparison {
+        id,
+        source,
+        candidates,
+    })
+}
+
+/// 记录用户选中的候选译文，并写入翻译记录供翻译记忆使用
+#[tauri::command]
+pub fn pick_translation(candidate_id: i64, storage: tauri::State<'_, Storage>) -> Result<i64, String> {
+    pick_candidate(&storage, candidate_id)
+}
+
+// 同一次对比只对应一条翻译记录：重复选择同一候选直接返回，改选其他候选时改写这条记录
+fn pick_candidate(storage: &Storage, candidate_id: i64) -> Result<i64, String> {
+    let candidate = storage.with_conn(|conn| {
+        conn.query_row(
+            "SELECT comparison_id, document_id, source, provider, model, translation, reasoning, picked,
+                    (SELECT MAX(translation_id) FROM translation_candidates c WHERE c.comparison_id = t.comparison_id)
+             FROM translation_candidates t WHERE id = ?1",
+            params![candidate_id],
+            |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, Option<String>>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, String>(3)?,
+                    row.get::<_, String>(4)?,
+                    row.get::<_, String>(5)?,
+                    row.get::<_, Option<String>>(6)?,
+                    row.get::<_, bool>(7)?,
+                    row.get::<_, Option<i64>>(8)?,
+                ))
+            },
+        )
+        .optional()
+    })?;
+    let Some((comparison_id, document_id, source, provider, model, translation, reasoning, picked, translation_id)) =
+        candidate
+    else {
+        return Err("Translation candidate not found".to_string());
+    };
+    if let (true, Some(translation_id)) = (picked, translation_id) {
+        return Ok(translation_id);
+    }
+    println!("✅ 对比翻译选用 {} / {}", provider, model);
+
+    // 用户选定的译文即确认过的译文，进入翻译记忆
+    if let Err(e) = translation_memory::remember(storage, &source, &translation, document_id.as_deref()) {
+        println!("⚠️ 保存翻译记忆失败：{}", e);
+    }
+    let completion = Completion {
+        content: translation,
+        reasoning: reasoning.unwrap_or_default(),
+        provider,
+        model,
+        ..Default::default()
+    };
+    let updated = match translation_id {
+        Some(id) => translations::update_translation(storage, id, document_id.as_deref(), &source, &completion)?,
+        None => None,
+    };
+    let translation_id = match updated {
+        Some(id) => id,
+        None => translations::record_translation(storage, document_id.as_deref(), &source, &completion)?,
+    };
+
+    // 同一次对比只保留一个选择
+    storage.with_conn(|conn| {
+        conn.execute(
+            "UPDATE translation_candidates SET picked = (id = ?1), translation_id = ?2 WHERE comparison_id = ?3",
+            params![candidate_id, translation_id, comparison_id],
+        )
+    })?;
+    Ok(translation_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate(storage: &Storage, comparison_id: &str, provider: &str, translation: &str) -> i64 {
+        let completion = Completion {
+            content: translation.to_string(),
+            provider: provider.to_string(),
+            model: format!("{}-model", provider),
+            ..Default::default()
+        };
+        save_candidate(storage, comparison_id, Some("doc-1"), "Hello world.", &completion).unwrap()
+    }
+
+    fn translations(storage: &Storage) -> Vec<(i64, String, String)> {
+        storage
+            .with_conn(|conn| {
+                let mut stmt = conn.prepare("SELECT id, translation, provider FROM translations ORDER BY id")?;
+                let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?;
+                rows.collect()
+            })
+            .unwrap()
+    }
+
+    fn picked(storage: &Storage, comparison_id: &str) -> Vec<i64> {
+        storage
+            .with_conn(|conn| {
+                let mut stmt =
+                    conn.prepare("SELECT id FROM translation_candidates WHERE comparison_id = ?1 AND picked = 1")?;
+                let rows = stmt.query_map(params![comparison_id], |row| row.get(0))?;
+                rows.collect()
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn picking_is_idempotent_per_comparison() {
+        let storage = Storage::open_in_memory().unwrap();
+        let first = candidate(&storage, "cmp-1", "deepseek", "你好，世界。");
+        let second = candidate(&storage, "cmp-1", "kimi", "世界你好。");
+
+        // 重复选择同一候选不会产生新的翻译记录
+        let id = pick_candidate(&storage, first).unwrap();
+        assert_eq!(pick_candidate(&storage, first).unwrap(), id);
+        assert_eq!(translations(&storage), vec![(id, "你好，世界。".to_string(), "deepseek".to_string())]);
+        assert_eq!(picked(&storage, "cmp-1"), vec![first]);
+
+        // 改选其他候选时改写同一条记录
+        assert_eq!(pick_candidate(&storage, second).unwrap(), id);
+        assert_eq!(translations(&storage), vec![(id, "世界你好。".to_string(), "kimi".to_string())]);
+        assert_eq!(picked(&storage, "cmp-1"), vec![second]);
+        let hits = crate::search::search(&storage, "世界你好", 10).unwrap();
+        assert_eq!(hits.len(), 1);
+
+        // 另一次对比有自己的记录
+        let other = candidate(&storage, "cmp-2", "deepseek", "哈喽，世界。");
+        let other_id = pick_candidate(&storage, other).unwrap();
+        assert_ne!(other_id, id);
+        assert_eq!(translations(&storage).len(), 2);
+        assert_eq!(picked(&storage, "cmp-1"), vec![second]);
+
+        assert!(pick_candidate(&storage, 9999).is_err());
+    }
+}
@@ -0,0 +1,556 @@
+// 公式讲解：把选中的公式还原成 LaTeX，并逐个解释符号与公式的作用
+//
+// 从 PDF 复制出的公式是一串错位的 Unicode（数学斜体字母、上下标、希腊字母），
+// 先在本地尽量转成 LaTeX 再交给模型修正；框选的公式图片则交给视觉模型识别。
+// 模型按固定的分段标记输出，解析成 latex、符号表、讲解三部分返回。
+
+use serde::Serialize;
+
+use crate::providers::{CompletionRequest, ModelConfig};
+use crate::scheduler::Priority;
+use crate::{run_completion, vision, StreamEvents};
+
+const EQUATION_SYSTEM_PROMPT: &str = "你是一个数学与学术写作助手，熟悉 LaTeX，擅长解释论文中的公式。";
+const OUTPUT_FORMAT: &str = "请严格按以下格式输出，不要输出其他内容：
+[LATEX]
+公式的 LaTeX 代码，不要加 $ 或代码块
+[SYMBOLS]
+每行一个符号，格式为：符号的 LaTeX | 含义
+[EXPLANATION]
+用中文说明这个公式表达了什么、在文中起什么作用";
+// 附带的上下文只取选区附近的一段，避免提示词过长
+const MAX_CONTEXT_CHARS: usize = 1500;
+
+const EQUATION_EVENTS: StreamEvents = StreamEvents {
+    kind: "equation",
+    chunk: "equation_chunk",
+    reasoning: "reasoning_chunk",
+    complete: "equation_complete",
+};
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct EquationSymbol {
+    pub symbol: String,
+    pub meaning: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct EquationExplanation {
+    pub latex: String,
+    pub symbols: Vec<EquationSymbol>,
+    pub explanation: String,
+    pub provider: String,
+    pub model: String,
+}
+
+/* ---------------------------------
+ * Unicode 公式转 LaTeX
+ * --------------------------------- */
+
+fn greek_command(c: char) -> Option<&'static str> {
+    Some(match c {
+        'α' => r"\alpha",
+        'β' => r"\beta",
+        'γ' => r"\gamma",
+        'δ' => r"\delta",
+        'ϵ' => r"\epsilon",
+        'ε' => r"\varepsilon",
+        'ζ' => r"\zeta",
+        'η' => r"\eta",
+        'θ' => r"\theta",
+        'ϑ' => r"\vartheta",
+        'ι' => r"\iota",
+        'κ' => r"\kappa",
+        'λ' => r"\lambda",
+        'μ' => r"\mu",
+        'ν' => r"\nu",
+        'ξ' => r"\xi",
+        'π' => r"\pi",
+        'ϖ' => r"\varpi",
+        'ρ' => r"\rho",
+        'ϱ' => r"\varrho",
+        'σ' => r"\sigma",
+        'ς' => r"\varsigma",
+        'τ' => r"\tau",
+        'υ' => r"\upsilon",
+        'ϕ' => r"\phi",
+        'φ' => r"\varphi",
+        'χ' => r"\chi",
+        'ψ' => r"\psi",
+        'ω' => r"\omega",
+        'Γ' => r"\Gamma",
+        'Δ' => r"\Delta",
+        'Θ' => r"\Theta",
+        'Λ' => r"\Lambda",
+        'Ξ' => r"\Xi",
+        'Π' => r"\Pi",
+        'Σ' => r"\Sigma",
+        'Υ' => r"\Upsilon",
+        'Φ' => r"\Phi",
+        'Ψ' => r"\Psi",
+        'Ω' => r"\Omega",
+        _ => return None,
+    })
+}
+
+fn operator_command(c: char) -> Option<&'static str> {
+    Some(match c {
+        '∑' => r"\sum",
+        '∏' => r"\prod",
+        '∫' => r"\int",
+        '∮' => r"\oint",
+        '∂' => r"\partial",
+        '∇' => r"\nabla",
+        '∞' => r"\infty",
+        '√' => r"\sqrt",
+        '±' => r"\pm",
+        '∓' => r"\mp",
+        '×' => r"\times",
+        '÷' => r"\div",
+        '·' | '⋅' => r"\cdot",
+        '∘' => r"\circ",
+        '⊗' => r"\otimes",
+        '⊕' => r"\oplus",
+        '≤' | '⩽' => r"\leq",
+        '≥' | '⩾' => r"\geq",
+        '≠' => r"\neq",
+        '≈' => r"\approx",
+        '≡' => r"\equiv",
+        '∼' => r"\sim",
+        '≃' => r"\simeq",
+        '∝' => r"\propto",
+        '≪' => r"\ll",
+        '≫' => r"\gg",
+        '∈' => r"\in",
+        '∉' => r"\notin",
+        '⊂' => r"\subset",
+        '⊆' => r"\subseteq",
+        '∪' => r"\cup",
+        '∩' => r"\cap",
+        '∅' => r"\emptyset",
+        '∀' => r"\forall",
+        '∃' => r"\exists",
+        '¬' => r"\neg",
+        '∧' => r"\wedge",
+        '∨' => r"\vee",
+        '→' => r"\to",
+        '←' => r"\leftarrow",
+        '↦' => r"\mapsto",
+        '⇒' => r"\Rightarrow",
+        '⇔' => r"\Leftrightarrow",
+        '‖' => r"\|",
+        '⟨' => r"\langle",
+        '⟩' => r"\rangle",
+        '⌊' => r"\lfloor",
+        '⌋' => r"\rfloor",
+        '⌈' => r"\lceil",
+        '⌉' => r"\rceil",
+        '…' => r"\ldots",
+        '⋯' => r"\cdots",
+        'ℓ' => r"\ell",
+        'ℝ' => r"\mathbb{R}",
+        'ℕ' => r"\mathbb{N}",
+        'ℤ' => r"\mathbb{Z}",
+        'ℂ' => r"\mathbb{C}",
+        'ℚ' => r"\mathbb{Q}",
+        'ℒ' => r"\mathcal{L}",
+        _ => return None,
+    })
+}
+
+fn superscript(c: char) -> Option<char> {
+    Some(match c {
+        '⁰' => '0',
+        '¹' => '1',
+        '²' => '2',
+        '³' => '3',
+        '⁴' => '4',
+        '⁵' => '5',
+        '⁶' => '6',
+        '⁷' => '7',
+        '⁸' => '8',
+        '⁹' => '9',
+        '⁺' => '+',
+        '⁻' => '-',
+        '⁼' => '=',
+        '⁽' => '(',
+        '⁾' => ')',
+        'ⁿ' => 'n',
+        'ⁱ' => 'i',
+        'ᵀ' => 'T',
+        'ᵈ' => 'd',
+        'ᵏ' => 'k',
+        'ᵗ' => 't',
+        _ => return None,
+    })
+}
+
+fn subscript(c: char) -> Option<char> {
+    Some(match c {
+        '₀' => '0',
+        '₁' => '1',
+        '₂' => '2',
+        '₃' => '3',
+        '₄' => '4',
+        '₅' => '5',
+        '₆' => '6',
+        '₇' => '7',
+        '₈' => '8',
+        '₉' => '9',
+        '₊' => '+',
+        '₋' => '-',
+        '₌' => '=',
+        '₍' => '(',
+        '₎' => ')',
+        'ₐ' => 'a',
+        'ₑ' => 'e',
+        'ₒ' => 'o',
+        'ₓ' => 'x',
+        'ᵢ' => 'i',
+        'ⱼ' => 'j',
+        'ₖ' => 'k',
+        'ₙ' => 'n',
+        'ₜ' => 't',
+        _ => return None,
+    })
+}
+
+/// 数学字母数字符号区（粗体、斜体、手写体等）还原成普通字母、数字或希腊字母
+fn plain_math_char(c: char) -> char {
+    let code = c as u32;
+    let mapped = match code {
+        // 13 种字体的拉丁字母，每种 52 个
+        0x1D400..=0x1D6A3 => {
+            let index = (code - 0x1D400) % 52;
+            if index < 26 {
+                char::from_u32('A' as u32 + index)
+            } else {
+                char::from_u32('a' as u32 + index - 26)
+            }
+        }
+        0x1D6A4 => Some('ı'),
+        0x1D6A5 => Some('ȷ'),
+        // 5 种字体的希腊字母，每种 58 个
+        0x1D6A8..=0x1D7C9 => match (code - 0x1D6A8) % 58 {
+            17 => Some('Θ'),
+            i @ 0..=16 => char::from_u32(0x391 + i),
+            i @ 18..=24 => char::from_u32(0x3A3 + i - 18),
+            25 => Some('∇'),
+            i @ 26..=50 => char::from_u32(0x3B1 + i - 26),
+            51 => Some('∂'),
+            52 => Some('ϵ'),
+            53 => Some('ϑ'),
+            54 => Some('κ'),
+            55 => Some('ϕ'),
+            56 => Some('ϱ'),
+            _ => Some('ϖ'),
+        },
+        0x1D7CE..=0x1D7FF => char::from_u32('0' as u32 + (code - 0x1D7CE) % 10),
+        _ => None,
+    };
+    match c {
+        // 斜体 h 在 Unicode 里提前占了位置
+        'ℎ' => 'h',
+        '−' | '–' => '-',
+        '∗' => '*',
+        '∣' => '|',
+        '′' => '\'',
+        _ => mapped.unwrap_or(c),
+    }
+}
+
+/// 把从 PDF 复制出的公式文本尽量转成 LaTeX
+pub fn normalize_math(text: &str) -> String {
+    let mut out = String::new();
+    let mut chars = text.chars().map(plain_math_char).peekable();
+    while let Some(c) = chars.next() {
+        if let Some(first) = superscript(c) {
+            let mut group = first.to_string();
+            while let Some(next) = chars.peek().and_then(|c| superscript(*c)) {
+                group.push(next);
+                chars.next();
+            }
+            out.push_str(&format!("^{{{}}}", group));
+        } else if let Some(first) = subscript(c) {
+            let mut group = first.to_string();
+            while let Some(next) = chars.peek().and_then(|c| subscript(*c)) {
+                group.push(next);
+                chars.next();
+            }
+            out.push_str(&format!("_{{{}}}", group));
+        } else if let Some(command) = greek_command(c).or_else(|| operator_command(c)) {
+            out.push_str(command);
+            // 命令后紧跟字母时需要空格分隔
+            if chars.peek().is_some_and(|c| c.is_ascii_alphabetic()) {
+                out.push(' ');
+            }
+        } else if c.is_whitespace() {
+            if !out.ends_with(' ') && !out.is_empty() {
+                out.push(' ');
+            }
+        } else if ('Α'..='Ω').contains(&c) {
+            // 与拉丁字母同形的希腊大写字母
+            out.push(match c {
+                'Α' => 'A',
+                'Β' => 'B',
+                'Ε' => 'E',
+                'Ζ' => 'Z',
+                'Η' => 'H',
+                'Ι' => 'I',
+                'Κ' => 'K',
+                'Μ' => 'M',
+                'Ν' => 'N',
+                'Ο' => 'O',
+                'Ρ' => 'P',
+                'Τ' => 'T',
+                'Χ' => 'X',
+                other => other,
+            });
+        } else {
+            out.push(c);
+        }
+    }
+    out.trim().to_string()
+}
+
+/* ---------------------------------
+ * 解析模型输出
+ * --------------------------------- */
+
+// 去掉模型常加的 $$、\[ \]、代码块包裹
+fn strip_math_delimiters(latex: &str) -> String {
+    let mut s = latex.trim();
+    if let Some(inner) = s.strip_prefix("```") {
+        s = inner.trim_start_matches(|c: char| c.is_ascii_alphabetic());
+        s = s.strip_suffix("```").unwrap_or(s).trim();
+    }
+    for (open, close) in [("$$", "$$"), (r"\[", r"\]"), ("$", "$")] {
+        if let Some(inner) = s.strip_prefix(open).and_then(|s| s.strip_suffix(close)) {
+            s = inner.trim();
+            break;
+        }
+    }
+    s.to_string()
+}
+
+fn parse_symbol(line: &str) -> Option<EquationSymbol> {
+    // 模型有时输出成 Markdown 表格，首尾多出 |
+    let line = line.trim().trim_start_matches(['-', '*', '•']).trim().trim_matches('|').trim();
+    let (symbol, meaning) = line
+        .split_once('|')
+        .or_else(|| line.split_once('：'))
+        .or_else(|| line.split_once(": "))?;
+    let symbol = strip_math_delimiters(symbol.trim().trim_matches('`'));
+    let meaning = meaning.trim().trim_start_matches('|').trim();
+    // 表头或分隔行
+    if symbol.is_empty() || meaning.is_empty() || symbol == "符号" || meaning.chars().all(|c| c == '-' || c == '|') {
+        return None;
+    }
+    Some(EquationSymbol {
+        symbol,
+        meaning: meaning.to_string(),
+    })
+}
+
+/// 按 [LATEX] [SYMBOLS] [EXPLANATION] 分段解析；模型没按格式输出时整段作为讲解
+pub fn parse_explanation(content: &str, fallback_latex: &str) -> (String, Vec<EquationSymbol>, String) {
+    let mut sections: Vec<(&str, String)> = Vec::new();
+    for line in content.lines() {
+        let marker = line.trim().to_ascii_uppercase();
+        match marker.as_str() {
+            "[LATEX]" => sections.push(("latex", String::new())),
+            "[SYMBOLS]" => sections.push(("symbols", String::new())),
+            "[EXPLANATION]" => sections.push(("explanation", String::new())),
+            _ => {
+                if let Some((_, body)) = sections.last_mut() {
+                    body.push_str(line);
+                    body.push('\n');
+                }
+            }
+        }
+    }
+    if sections.is_empty() {
+        return (fallback_latex.to_string(), Vec::new(), content.trim().to_string());
+    }
+
+    let section = |name: &str| {
+        sections
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, body)| body.trim().to_string())
+            .unwrap_or_default()
+    };
+    let latex = strip_math_delimiters(&section("latex"));
+    let symbols = section("symbols").lines().filter_map(parse_symbol).collect();
+    (
+        if latex.is_empty() { fallback_latex.to_string() } else { latex },
+        symbols,
+        section("explanation"),
+    )
+}
+
+fn truncate_context(context: &str) -> String {
+    let context = context.split_whitespace().collect::<Vec<_>>().join(" ");
+    match context.char_indices().nth(MAX_CONTEXT_CHARS) {
+        Some((end, _)) => format!("{}…", &context[..end]),
+        None => context,
+    }
+}
+
+/// 讲解选中的公式：text 为复制出的公式文本，image 为框选的公式截图（PNG 的 base64），二者给一个即可
+#[tauri::command]
+pub async fn explain_equation(
+    text: Option<String>,
+    image: Option<String>,
+    context: Option<String>,
+    config: ModelConfig,
+    show_reasoning: Option<bool>,
+    document_id: Option<String>,
+    app_handle: tauri::AppHandle,
+) -> Result<EquationExplanation, String> {
+    let context = context
+        .map(|c| truncate_context(&c))
+        .filter(|c| !c.is_empty())
+        .map(|c| format!("公式所在段落的上下文：\n{}\n\n", c))
+        .unwrap_or_default();
+
+    let (prompt, images, fallback_latex) = match (text.as_deref().map(str::trim), image) {
+        (_, Some(image)) => (
+            format!("图片是论文中的一个公式，请识别成 LaTeX 并讲解。\n\n{}{}", context, OUTPUT_FORMAT),
+            vec![vision::png_base64(&image)?],
+            String::new(),
+        ),
+        (Some(text), None) if !text.is_empty() => {
+            let normalized = normalize_math(text);
+            println!("🧮 公式初步转换：{} => {}", text, normalized);
+            (
+                format!(
+                    "下面是从 PDF 中复制出的公式，复制时符号可能错位或丢失上下标。\n\
+                     原始文本：\n{}\n\n初步转换的 LaTeX：\n{}\n\n\
+                     请结合上下文还原正确的 LaTeX 并讲解。\n\n{}{}",
+                    text, normalized, context, OUTPUT_FORMAT
+                ),
+                Vec::new(),
+                normalized,
+            )
+        }
+        _ => return Err("Select an equation or a region first".to_string()),
+    };
+
+    let request = CompletionRequest {
+        system: EQUATION_SYSTEM_PROMPT.to_string(),
+        prompt,
+        images,
+        show_reasoning: show_reasoning.unwrap_or(false),
+        priority: Priority::Interactive,
+    };
+    let completion = run_completion(&config, &request, &EQUATION_EVENTS, document_id.as_deref(), &app_handle).await?;
+    let (latex, symbols, explanation) = parse_explanation(&completion.content, &fallback_latex);
+    Ok(EquationExplanation {
+        latex,
+        symbols,
+        explanation,
+        provider: completion.provider,
+        model: completion.model,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_math_char_maps_styled_letters() {
+        let cases = [
+            ('\u{1D400}', 'A'), // 粗体 A
+            ('\u{1D41A}', 'a'), // 粗体 a
+            ('\u{1D465}', 'x'), // 斜体 x
+            ('\u{1D4B3}', 'X'), // 手写体 X
+            ('\u{1D6A3}', 'z'), // 等宽 z，拉丁字母区最后一个
+            ('ℎ', 'h'),         // 斜体 h 不在数学字母区
+            ('\u{1D6A4}', 'ı'),
+            ('\u{1D6A5}', 'ȷ'),
+            ('\u{1D6A8}', 'Α'), // 粗体 Alpha
+            ('\u{1D6B9}', 'Θ'), // 粗体 Theta 符号
+            ('\u{1D6BA}', 'Σ'),
+            ('\u{1D6C1}', '∇'),
+            ('\u{1D6C2}', 'α'),
+            ('\u{1D6FC}', 'α'), // 斜体 alpha
+            ('\u{1D6DB}', '∂'),
+            ('\u{1D6DC}', 'ϵ'),
+            ('\u{1D6E1}', 'ϖ'),
+            ('\u{1D7CE}', '0'),
+            ('\u{1D7D7}', '9'),
+            ('\u{1D7D8}', '0'), // 双线体 0
+            ('\u{1D7FF}', '9'),
+            ('−', '-'),
+            ('∗', '*'),
+            ('′', '\''),
+            ('x', 'x'),
+            ('中', '中'),
+            ('\u{1D7CC}', '\u{1D7CC}'), // 区内未分配的码位保持原样
+        ];
+        for (input, expected) in cases {
+            assert_eq!(plain_math_char(input), expected, "U+{:X}", input as u32);
+        }
+    }
+
+    #[test]
+    fn normalize_math_produces_latex() {
+        let cases = [
+            ("𝑥² + 𝑦²", "x^{2} + y^{2}"),
+            ("𝑥ᵢⱼ", "x_{ij}"),
+            ("𝐖ᵀ𝐱", "W^{T}x"),
+            ("∑ᵢ 𝑤ᵢ𝑥ᵢ", r"\sum_{i} w_{i}x_{i}"),
+            ("α𝑥", r"\alpha x"),
+            ("𝛼∈ℝⁿ", r"\alpha\in\mathbb{R}^{n}"),
+            ("ℎ(𝑥) − 1", "h(x) - 1"),
+            ("Α𝐁Γ", r"AB\Gamma"),
+            ("  a \n  b  ", "a b"),
+            ("", ""),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(normalize_math(input), expected, "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn parse_explanation_tolerates_malformed_output() {
+        // 没有分段标记：整段作为讲解
+        let (latex, symbols, explanation) = parse_explanation("  这是一个损失函数。 ", "L = x");
+        assert_eq!(latex, "L = x");
+        assert!(symbols.is_empty());
+        assert_eq!(explanation, "这是一个损失函数。");
+
+        // 标记大小写不一、LaTeX 带包裹、符号表写成 Markdown 表格、标记前有多余内容
+        let content = "好的，以下是讲解：
+[latex]
+$$
+E = mc^2
+$$
+[Symbols]
+| 符号 | 含义 |
+|---|---|
+| $E$ | 能量 |
+- `m`: 质量
+* c：光速
+没有分隔符的行
+[EXPLANATION]
+质能方程。
+";
+        let (latex, symbols, explanation) = parse_explanation(content, "fallback");
+        assert_eq!(latex, "E = mc^2");
+        let symbols: Vec<(&str, &str)> = symbols.iter().map(|s| (s.symbol.as_str(), s.meaning.as_str())).collect();
+        assert_eq!(symbols, vec![("E", "能量"), ("m", "质量"), ("c", "光速")]);
+        assert_eq!(explanation, "质能方程。");
+
+        // 缺少 LATEX 段或内容为空时退回原公式
+        let (latex, symbols, explanation) = parse_explanation("[LATEX]\n```latex\n```\n[EXPLANATION]\n说明", "a+b");
+        assert_eq!(latex, "a+b");
+        assert!(symbols.is_empty());
+        assert_eq!(explanation, "说明");
+        let (latex, _, explanation) = parse_explanation("[SYMBOLS]\nx | 变量", "y");
+        assert_eq!(latex, "y");
+        assert_eq!(explanation, "");
+    }
+}
@@ -0,0 +1,499 @@
+// 笔记导出：Markdown / Obsidian 文献笔记与 Anki 卡片
+//
+// Markdown 带 YAML front-matter（标题、作者、DOI、标签），依次列出高亮及其笔记、
+// 独立笔记、翻译记录和文中出现的术语。Obsidian 版本用 callout 展示高亮并附块 ID，
+// 方便在其他笔记中引用。Anki 导出为带文件头的制表符分隔文本，可直接导入。
+
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+use crate::annotations::{self, Annotations, Note};
+use crate::file_scope::{self, FileScope};
+use crate::glossary::{self, GlossaryHit};
+use crate::library::{self, LibraryEntry};
+use crate::pdf_text::PdfTextCache;
+use crate::storage::{now_secs, Storage};
+use crate::translations::{self, TranslationRecord};
+
+// 原文不超过这么多个词的翻译记录才做成卡片
+const MAX_CARD_WORDS: usize = 6;
+const MAX_CARD_CHARS: usize = 60;
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum MarkdownFlavor {
+    #[default]
+    Markdown,
+    Obsidian,
+}
+
+pub struct ExportData {
+    pub entry: LibraryEntry,
+    pub annotations: Annotations,
+    pub translations: Vec<TranslationRecord>,
+    pub glossary: Vec<GlossaryHit>,
+}
+
+pub struct AnkiCard {
+    pub front: String,
+    pub back: String,
+    pub tags: Vec<String>,
+}
+
+/* ---------------------------------
+ * 格式工具
+ * --------------------------------- */
+
+/// Unix 时间戳转 YYYY-MM-DD（UTC）
+pub fn format_date(secs: i64) -> String {
+    // Howard Hinnant 的 civil_from_days 算法
+    let days = secs.div_euclid(86_400);
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+fn yaml_string(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', " "))
+}
+
+fn quote_block(text: &str) -> String {
+    text.lines()
+        .map(|line| if line.trim().is_empty() { ">".to_string() } else { format!("> {}", line) })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn document_title(entry: &LibraryEntry) -> String {
+    entry.title.clone().unwrap_or_else(|| {
+        std::path::Path::new(&entry.path)
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_else(|| "Untitled".to_string())
+    })
+}
+
+/// 可用作文件名的标题
+fn file_stem(entry: &LibraryEntry) -> String {
+    let title: String = document_title(entry)
+        .chars()
+        .map(|c| if matches!(c, '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|') { ' ' } else { c })
+        .collect();
+    let title = title.split_whitespace().collect::<Vec<_>>().join(" ");
+    title.chars().take(80).collect()
+}
+
+fn note_tags(notes: &[Note]) -> Vec<String> {
+    let mut seen = HashSet::new();
+    notes
+        .iter()
+        .flat_map(|n| n.tags.iter())
+        .filter(|t| seen.insert(t.to_lowercase()))
+        .cloned()
+        .collect()
+}
+
+fn page_label(page: Option<u32>) -> String {
+    page.map(|p| format!("p. {}", p)).unwrap_or_default()
+}
+
+/* ---------------------------------
+ * Markdown
+ * --------------------------------- */
+
+fn front_matter(data: &ExportData, flavor: MarkdownFlavor) -> String {
+    let entry = &data.entry;
+    let mut yaml = vec!["---".to_string(), format!("title: {}", yaml_string(&document_title(entry)))];
+    if !entry.authors.is_empty() {
+        yaml.push("authors:".to_string());
+        yaml.extend(entry.authors.iter().map(|a| format!("  - {}", yaml_string(a))));
+    }
+    if let Some(year) = &entry.year {
+        yaml.push(format!("year: {}", year));
+    }
+    if let Some(doi) = &entry.doi {
+        yaml.push(format!("doi: {}", yaml_string(doi)));
+    }
+    if let Some(arxiv_id) = &entry.arxiv_id {
+        yaml.push(format!("arxiv: {}", yaml_string(arxiv_id)));
+    }
+    let mut tags = vec!["papertalk".to_string()];
+    tags.extend(note_tags(&data.annotations.notes));
+    yaml.push("tags:".to_string());
+    yaml.extend(tags.iter().map(|t| format!("  - {}", yaml_string(t))));
+    if flavor == MarkdownFlavor::Obsidian {
+        yaml.push("aliases:".to_string());
+        yaml.push(format!("  - {}", yaml_string(&document_title(entry))));
+    }
+    yaml.push(format!("source: {}", yaml_string(&entry.path)));
+    yaml.push(format!("exported: {}", format_date(now_secs())));
+    yaml.push("---".to_string());
+    yaml.join("\n")
+}
+
+pub fn render_markdown(data: &ExportData, flavor: MarkdownFlavor) -> String {
+    let mut out = vec![front_matter(data, flavor), String::new(), format!("# {}", document_title(&data.entry))];
+    if !data.entry.authors.is_empty() {
+        out.push(String::new());
+        out.push(data.entry.authors.join(", "));
+    }
+
+    let notes = &data.annotations.notes;
+    if !data.annotations.highlights.is_empty() {
+        out.push(String::new());
+        out.push("## 高亮".to_string());
+        for highlight in &data.annotations.highlights {
+            out.push(String::new());
+            let text = if highlight.text.is_empty() { "（无文字）" } else { highlight.text.as_str() };
+            match flavor {
+                MarkdownFlavor::Obsidian => {
+                    out.push(format!("> [!quote] p. {}", highlight.page));
+                    out.push(quote_block(text));
+                    out.push(format!("^hl-{}", highlight.id));
+                }
+                MarkdownFlavor::Markdown => {
+                    out.push(quote_block(text));
+                    out.push(">".to_string());
+                    out.push(format!("> — p. {}", highlight.page));
+                }
+            }
+            for note in notes.iter().filter(|n| n.highlight_id.as_deref() == Some(highlight.id.as_str())) {
+                out.push(String::new());
+                out.push(note.body.clone());
+            }
+        }
+    }
+
+    let standalone: Vec<&Note> = notes.iter().filter(|n| n.highlight_id.is_none()).collect();
+    if !standalone.is_empty() {
+        out.push(String::new());
+        out.push("## 笔记".to_string());
+        for note in standalone {
+            out.push(String::new());
+            let label = page_label(note.page);
+            out.push(if label.is_empty() { "###".to_string() } else { format!("### {}", label) });
+            out.push(String::new());
+            out.push(note.body.clone());
+        }
+    }
+
+    if !data.translations.is_empty() {
+        out.push(String::new());
+        out.push("## 翻译".to_string());
+        for record in &data.translations {
+            out.push(String::new());
+            out.push(quote_block(&record.source));
+            out.push(String::new());
+            out.push(record.translation.clone());
+        }
+    }
+
+    if !data.glossary.is_empty() {
+        out.push(String::new());
+        out.push("## 术语".to_string());
+        out.push(String::new());
+        out.push("| 术语 | 译法 | 出现次数 | 页码 |".to_string());
+        out.push("| --- | --- | --- | --- |".to_string());
+        for hit in &data.glossary {
+            let pages: Vec<String> = hit.pages.iter().map(|p| p.to_string()).collect();
+            out.push(format!(
+                "| {} | {} | {} | {} |",
+                hit.term.replace('|', "\\|"),
+                hit.translation.replace('|', "\\|"),
+                hit.count,
+                pages.join(", ")
+            ));
+        }
+    }
+
+    out.push(String::new());
+    out.join("\n")
+}
+
+/* ---------------------------------
+ * Anki
+ * --------------------------------- */
+
+/// 术语卡片优先；短的翻译记录也做成卡片，同一词条只保留一张
+pub fn anki_cards(data: &ExportData) -> Vec<AnkiCard> {
+    let mut seen = HashSet::new();
+    let mut cards = Vec::new();
+    for hit in &data.glossary {
+        if seen.insert(hit.term.to_lowercase()) {
+            cards.push(AnkiCard {
+                front: hit.term.clone(),
+                back: hit.translation.clone(),
+                tags: vec!["papertalk".to_string(), "glossary".to_string()],
+            });
+        }
+    }
+    for record in &data.translations {
+        let source = record.source.trim();
+        if source.split_whitespace().count() > MAX_CARD_WORDS || source.chars().count() > MAX_CARD_CHARS {
+            continue;
+        }
+        if seen.insert(source.to_lowercase()) {
+            cards.push(AnkiCard {
+                front: source.to_string(),
+                back: record.translation.trim().to_string(),
+                tags: vec!["papertalk".to_string(), "translation".to_string()],
+            });
+        }
+    }
+    cards
+}
+
+fn anki_field(value: &str) -> String {
+    let value = value.trim().replace("\r\n", "\n").replace('\n', "<br>");
+    if value.contains(['\t', '"']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value
+    }
+}
+
+/// Anki 文本导入格式：文件头指定分隔符、笔记类型、牌组和标签列
+pub fn render_anki(data: &ExportData) -> String {
+    let deck = format!("PaperTalk::{}", file_stem(&data.entry).replace("::", " "));
+    let mut lines = vec![
+        "#separator:tab".to_string(),
+        "#html:true".to_string(),
+        "#notetype:Basic".to_string(),
+        format!("#deck:{}", deck),
+        "#tags column:3".to_string(),
+    ];
+    for card in anki_cards(data) {
+        lines.push(format!(
+            "{}\t{}\t{}",
+            anki_field(&card.front),
+            anki_field(&card.back),
+            card.tags.join(" ")
+        ));
+    }
+    lines.push(String::new());
+    lines.join("\n")
+}
+
+/* ---------------------------------
+ * 命令
+ * --------------------------------- */
+
+fn load_export_data(app_handle: &tauri::AppHandle, document_id: &str) -> Result<ExportData, String> {
+    use tauri::Manager;
+
+    let storage = app_handle.state::<Storage>();
+    let entry = library::find_entry(&storage, document_id)?
+        .ok_or_else(|| format!("Document not in library: {}", document_id))?;
+    // 文件不在原位置时跳过术语统计，其余内容照常导出
+    let glossary = match app_handle.state::<FileScope>().resolve_pdf(&entry.path) {
+        Ok(path) => glossary::document_hits(&storage, &app_handle.state::<PdfTextCache>(), &path)?,
+        Err(e) => {
+            println!("⚠️ 无法读取文档，导出时跳过术语: {}", e);
+            Vec::new()
+        }
+    };
+    Ok(ExportData {
+        annotations: annotations::document_annotations(&storage, document_id)?,
+        translations: translations::document_translations(&storage, document_id)?,
+        glossary,
+        entry,
+    })
+}
+
+async fn export_file(
+    app_handle: tauri::AppHandle,
+    document_id: String,
+    filter: &str,
+    extension: &str,
+    render: impl FnOnce(&ExportData) -> String + Send + 'static,
+) -> Result<Option<String>, String> {
+    let data = {
+        let app_handle = app_handle.clone();
+        tauri::async_runtime::spawn_blocking(move || load_export_data(&app_handle, &document_id))
+            .await
+            .map_err(|e| format!("Export failed: {}", e))??
+    };
+
+    let file_name = format!("{}.{}", file_stem(&data.entry), extension);
+    let Some(dest): Option<PathBuf> = file_scope::save_dialog(&app_handle, filter, &[extension], file_name).await? else {
+        return Ok(None);
+    };
+    std::fs::write(&dest, render(&data)).map_err(|e| format!("Failed to write {}: {}", dest.display(), e))?;
+    println!("📤 已导出: {}", dest.display());
+    Ok(Some(dest.to_string_lossy().to_string()))
+}
+
+/// 导出文献笔记，flavor 为 "markdown"（默认）或 "obsidian"
+#[tauri::command]
+pub async fn export_markdown(
+    document_id: String,
+    flavor: Option<MarkdownFlavor>,
+    app_handle: tauri::AppHandle,
+) -> Result<Option<String>, String> {
+    let flavor = flavor.unwrap_or_default();
+    export_file(app_handle, document_id, "Markdown", "md", move |data| render_markdown(data, flavor)).await
+}
+
+#[tauri::command]
+pub async fn export_anki_deck(document_id: String, app_handle: tauri::AppHandle) -> Result<Option<String>, String> {
+    export_file(app_handle, document_id, "Anki", "txt", render_anki).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::annotations::Highlight;
+
+    fn fixture() -> ExportData {
+        let note = |id: &str, highlight_id: Option<&str>, page: Option<u32>, body: &str, tags: &[&str]| Note {
+            id: id.to_string(),
+            document_id: "doc".to_string(),
+            highlight_id: highlight_id.map(str::to_string),
+            page,
+            body: body.to_string(),
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+            created_at: 0,
+            updated_at: 0,
+        };
+        let translation = |source: &str, translation: &str| TranslationRecord {
+            id: 0,
+            document_id: Some("doc".to_string()),
+            source: source.to_string(),
+            translation: translation.to_string(),
+            provider: "deepseek".to_string(),
+            model: "deepseek-chat".to_string(),
+            created_at: 0,
+            reasoning: None,
+        };
+        ExportData {
+            entry: LibraryEntry {
+                id: "doc".to_string(),
+                path: "/papers/attention.pdf".to_string(),
+                title: Some("Attention: Is \"All\" You Need?".to_string()),
+                authors: vec!["Ashish Vaswani".to_string(), "Noam Shazeer".to_string()],
+                doi: None,
+                arxiv_id: Some("1706.03762".to_string()),
+                year: Some("2017".to_string()),
+                page_count: 15,
+                added_at: 0,
+                last_opened_at: 0,
+                last_page: 1,
+                scroll_offset: 0.0,
+                zoom: 100,
+                missing: false,
+            },
+            annotations: Annotations {
+                highlights: vec![Highlight {
+                    id: "h1".to_string(),
+                    document_id: "doc".to_string(),
+                    page: 3,
+                    quads: Vec::new(),
+                    color: "yellow".to_string(),
+                    text: "Attention weights\n\nare softmax scores".to_string(),
+                    created_at: 0,
+                    updated_at: 0,
+                }],
+                notes: vec![
+                    note("n1", Some("h1"), Some(3), "Key idea.", &["method", "Attention"]),
+                    note("n2", None, Some(5), "Check the ablation.", &["attention", "todo"]),
+                    note("n3", None, None, "General remark.", &[]),
+                ],
+            },
+            translations: vec![
+                translation("self-attention", "自注意力"),
+                translation("Self-Attention", "自注意力机制"),
+                translation("The dominant sequence transduction models are based on recurrence", "主流模型基于循环"),
+                translation("multi-head\tattention", "多头\"注意力\""),
+            ],
+            glossary: vec![GlossaryHit {
+                term: "self-attention".to_string(),
+                translation: "自注意力 | 术语".to_string(),
+                count: 4,
+                pages: vec![2, 3],
+            }],
+        }
+    }
+
+    #[test]
+    fn format_date_converts_unix_seconds() {
+        let cases = [
+            (0, "1970-01-01"),
+            (86_399, "1970-01-01"),
+            (-1, "1969-12-31"),
+            (951_782_400, "2000-02-29"),
+            (1_709_164_800, "2024-02-29"),
+            (1_735_689_599, "2024-12-31"),
+            (253_402_214_400, "9999-12-31"),
+        ];
+        for (secs, expected) in cases {
+            assert_eq!(format_date(secs), expected, "{}", secs);
+        }
+    }
+
+    #[test]
+    fn markdown_export_lists_annotations_by_flavor() {
+        let data = fixture();
+        let markdown = render_markdown(&data, MarkdownFlavor::Markdown);
+        for expected in [
+            "title: \"Attention: Is \\\"All\\\" You Need?\"",
+            "authors:\n  - \"Ashish Vaswani\"\n  - \"Noam Shazeer\"",
+            "arxiv: \"1706.03762\"",
+            // 标签不区分大小写去重
+            "tags:\n  - \"papertalk\"\n  - \"method\"\n  - \"Attention\"\n  - \"todo\"\nsource:",
+            "> Attention weights\n>\n> are softmax scores\n>\n> — p. 3\n\nKey idea.",
+            "## 笔记\n\n### p. 5\n\nCheck the ablation.\n\n###\n\nGeneral remark.",
+            "> self-attention\n\n自注意力",
+            "| self-attention | 自注意力 \\| 术语 | 4 | 2, 3 |",
+        ] {
+            assert!(markdown.contains(expected), "missing {:?} in\n{}", expected, markdown);
+        }
+        assert!(!markdown.contains("aliases:"));
+        assert!(!markdown.contains("doi:"));
+
+        let obsidian = render_markdown(&data, MarkdownFlavor::Obsidian);
+        assert!(obsidian.contains("aliases:\n  - \"Attention: Is \\\"All\\\" You Need?\""));
+        assert!(obsidian.contains("> [!quote] p. 3\n> Attention weights\n>\n> are softmax scores\n^hl-h1\n\nKey idea."));
+    }
+
+    #[test]
+    fn anki_export_dedupes_cards_and_escapes_fields() {
+        let data = fixture();
+        let cards = anki_cards(&data);
+        let cards: Vec<(&str, &str, &str)> = cards
+            .iter()
+            .map(|c| (c.front.as_str(), c.back.as_str(), c.tags[1].as_str()))
+            .collect();
+        // 术语优先，同一词条只保留一张；长句不做卡片
+        assert_eq!(
+            cards,
+            vec![
+                ("self-attention", "自注意力 | 术语", "glossary"),
+                ("multi-head\tattention", "多头\"注意力\"", "translation"),
+            ]
+        );
+
+        let deck = render_anki(&data);
+        let lines: Vec<&str> = deck.lines().collect();
+        assert_eq!(
+            lines,
+            vec![
+                "#separator:tab",
+                "#html:true",
+                "#notetype:Basic",
+                "#deck:PaperTalk::Attention Is All You Need",
+                "#tags column:3",
+                "self-attention\t自注意力 | 术语\tpapertalk glossary",
+                "\"multi-head\tattention\"\t\"多头\"\"注意力\"\"\"\tpapertalk translation",
+            ]
+        );
+        assert_eq!(anki_field(" line one\r\nline two "), "line one<br>line two");
+    }
+}
@@ -0,0 +1,405 @@
+// 文件访问范围：后端只读取用户明确授权过的 PDF
+//
+// 授权来源只有三种：通过后端弹出的文件对话框选择、拖拽到窗口上、
+// 位于用户选择的文献库文件夹内。路径一律先 canonicalize，
+// 这样 ".." 和符号链接都无法绕出授权范围。
+
+use rusqlite::params;
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use tauri_plugin_dialog::DialogExt;
+
+use crate::storage::{now_secs, Storage};
+
+pub const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS approved_paths (
+    path TEXT PRIMARY KEY,
+    kind TEXT NOT NULL,
+    added_at INTEGER NOT NULL
+);
+";
+
+// PDF 规范允许文件头出现在前 1024 字节内
+const PDF_HEADER_WINDOW: usize = 1024;
+
+#[derive(Default)]
+pub struct FileScope {
+    files: RwLock<HashSet<PathBuf>>,
+    folders: RwLock<Vec<PathBuf>>,
+}
+
+impl FileScope {
+    pub fn load(storage: &Storage) -> Result<Self, String> {
+        let entries: Vec<(String, String)> = storage.with_conn(|conn| {
+            let mut stmt = conn.prepare("SELECT path, kind FROM approved_paths")?;
+            let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+            rows.collect()
+        })?;
+
+        let scope = FileScope::default();
+        for (path, kind) in entries {
+            let path = PathBuf::from(path);
+            match kind.as_str() {
+                "folder" => scope.folders.write().unwrap_or_else(|e| e.into_inner()).push(path),
+                _ => {
+                    scope.files.write().unwrap_or_else(|e| e.into_inner()).insert(path);
+                }
+            }
+        }
+        Ok(scope)
+    }
+
+    pub fn allow_file(&self, storage: &Storage, path: &Path) -> Result<PathBuf, String> {
+        let canonical = canonicalize(path)?;
+        ensure_pdf(&canonical)?;
+        persist(storage, &canonical, "file")?;
+        self.files
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(canonical.clone());
+        Ok(canonical)
+    }
+
+    pub fn allow_folder(&self, storage: &Storage, path: &Path) -> Result<PathBuf, String> {
+        let canonical = canonicalize(path)?;
+        if !canonical.is_dir() {
+            return Err(format!("Not a folder: {}", canonical.display()));
+        }
+        persist(storage, &canonical, "folder")?;
+        let mut folders = self.folders.write().unwrap_or_else(|e| e.into_inner());
+        if !folders.contains(&canonical) {
+            folders.push(canonical.clone());
+        }
+        Ok(canonical)
+    }
+
+    pub fn remove_folder(&self, storage: &Storage, path: &str) -> Result<(), String> {
+        storage.with_conn(|conn| {
+            conn.execute(
+                "DELETE FROM approved_paths WHERE path = ?1 AND kind = 'folder'",
+                params![path],
+            )?;
+            Ok(())
+        })?;
+        self.folders
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .retain(|p| p.to_string_lossy() != path);
+        Ok(())
+    }
+
+    pub fn folders(&self) -> Vec<PathBuf> {
+        self.folders.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    pub fn is_allowed(&self, canonical: &Path) -> bool {
+        if self
+            .files
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .contains(canonical)
+        {
+            return true;
+        }
+        self.folders
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .any(|folder| canonical.starts_with(folder))
+    }
+
+    /// 校验前端传来的路径：在授权范围内且确实是 PDF，返回规范化后的路径
+    pub fn resolve_pdf(&self, raw: &str) -> Result<PathBuf, String> {
+        // Remove the protocol prefix if present (Tauri 2.x uses asset://)
+        let clean_path = raw.strip_prefix("asset://localhost/").unwrap_or(raw);
+        let canonical = canonicalize(Path::new(clean_path))?;
+        if !self.is_allowed(&canonical) {
+            return Err(format!(
+                "Access denied: {} was not opened through PaperTalk or a library folder",
+                canonical.display()
+            ));
+        }
+        ensure_pdf(&canonical)?;
+        Ok(canonical)
+    }
+}
+
+fn canonicalize(path: &Path) -> Result<PathBuf, String> {
+    std::fs::canonicalize(path).map_err(|e| format!("Cannot access {}: {}", path.display(), e))
+}
+
+fn persist(storage: &Storage, canonical: &Path, kind: &str) -> Result<(), String> {
+    storage.with_conn(|conn| {
+        conn.execute(
+            "INSERT OR IGNORE INTO approved_paths (path, kind, added_at) VALUES (?1, ?2, ?3)",
+            params![canonical.to_string_lossy(), kind, now_secs()],
+        )?;
+        Ok(())
+    })
+}
+
+/// 检查文件头中的 %PDF- 标记，防止借 PDF 接口读取其他文件
+pub fn ensure_pdf(path: &Path) -> Result<(), String> {
+    if !path.is_file() {
+        return Err(format!("Not a file: {}", path.display()));
+    }
+    let mut header = Vec::with_capacity(PDF_HEADER_WINDOW);
+    File::open(path)
+        .and_then(|f| f.take(PDF_HEADER_WINDOW as u64).read_to_end(&mut header))
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    if header.windows(5).any(|w| w == b"%PDF-") {
+        Ok(())
+    } else {
+        Err(format!("Not a PDF file: {}", path.display()))
+    }
+}
+
+/// 处理拖拽到窗口上的文件，返回其中通过校验的 PDF
+pub fn allow_dropped(scope: &FileScope, storage: &Storage, paths: &[PathBuf]) -> Vec<String> {
+    paths
+        .iter()
+        .filter_map(|path| match scope.allow_file(storage, path) {
+            Ok(canonical) => Some(canonical.to_string_lossy().to_string()),
+            Err(e) => {
+                println!("⚠️ 忽略拖入的文件 {}：{}", path.display(), e);
+                None
+            }
+        })
+        .collect()
+}
+
+/// 由后端弹出文件对话框，选中的文件自动加入授权范围
+#[tauri::command]
+pub async fn open_pdf_dialog(app_handle: tauri::AppHandle) -> Result<Option<String>, String> {
+    use tauri::Manager;
+
+    let (tx, rx) = tokio::sync::oneshot::channel();
+    app_handle
+        .dialog()
+        .file()
+        .add_filter("PDF", &["pdf"])
+        .pick_file(move |file| {
+            let _ = tx.send(file);
+        });
+
+    let Some(file) = rx.await.map_err(|_| "Dialog closed unexpectedly".to_string())? else {
+        return Ok(None);
+    };
+    let path = file.into_path().map_err(|e| format!("Invalid path: {}", e))?;
+
+    let scope = app_handle.state::<FileScope>();
+    let storage = app_handle.state::<Storage>();
+    let canonical = scope.allow_file(&storage, &path)?;
+    Ok(Some(canonical.to_string_lossy().to_string()))
+}
+
+/// 弹出文件选择对话框，返回选中的路径（读取导入文件用，不加入授权范围）
+pub async fn pick_file_dialog(app_handle: &tauri::AppHandle, filter: &str, extensions: &[&str]) -> Result<Option<PathBuf>, String> {
+    let (tx, rx) = tokio::sync::oneshot::channel();
+    app_handle
+        .dialog()
+        .file()
+        .add_filter(filter, extensions)
+        .pick_file(move |file| {
+            let _ = tx.send(file);
+        });
+
+    let Some(file) = rx.await.map_err(|_| "Dialog closed unexpectedly".to_string())? else {
+        return Ok(None);
+    };
+    file.into_path().map(Some).map_err(|e| format!("Invalid path: {}", e))
+}
+
+/// 弹出保存对话框，返回用户选择的路径（导出文件用，不加入授权范围）
+pub async fn save_dialog(
+    app_handle: &tauri::AppHandle,
+    filter: &str,
+    extensions: &[&str],
+    file_name: String,
+) -> Result<Option<PathBuf>, String> {
+    let (tx, rx) = tokio::sync::oneshot::channel();
+    app_handle
+        .dialog()
+        .file()
+        .add_filter(filter, extensions)
+        .set_file_name(file_name)
+        .save_file(move |file| {
+            let _ = tx.send(file);
+        });
+
+    let Some(file) = rx.await.map_err(|_| "Dialog closed unexpectedly".to_string())? else {
+        return Ok(None);
+    };
+    file.into_path().map(Some).map_err(|e| format!("Invalid path: {}", e))
+}
+
+#[tauri::command]
+pub async fn choose_library_folder(app_handle: tauri::AppHandle) -> Result<Option<String>, String> {
+    use tauri::Manager;
+
+    let (tx, rx) = tokio::sync::oneshot::channel();
+    app_handle.dialog().file().pick_folder(move |folder| {
+        let _ = tx.send(folder);
+    });
+
+    let Some(folder) = rx.await.map_err(|_| "Dialog closed unexpectedly".to_string())? else {
+        return Ok(None);
+    };
+    let path = folder.into_path().map_err(|e| format!("Invalid path: {}", e))?;
+
+    let scope = app_handle.state::<FileScope>();
+    let storage = app_handle.state::<Storage>();
+    let canonical = scope.allow_folder(&storage, &path)?;
+    Ok(Some(canonical.to_string_lossy().to_string()))
+}
+
+#[tauri::command]
+pub fn list_library_folders(scope: tauri::State<'_, FileScope>) -> Vec<String> {
+    scope
+        .folders()
+        .iter()
+        .map(|p| p.to_string_lossy().to_string())
+        .collect()
+}
+
+#[tauri::command]
+pub fn remove_library_folder(
+    path: String,
+    scope: tauri::State<'_, FileScope>,
+    storage: tauri::State<'_, Storage>,
+) -> Result<(), String> {
+    scope.remove_folder(&storage, &path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Fixture {
+        root: PathBuf,
+        library: PathBuf,
+        outside: PathBuf,
+    }
+
+    impl Fixture {
+        fn new(name: &str) -> Self {
+            let root = std::env::temp_dir().join(format!("papertalk-scope-{}-{}", name, std::process::id()));
+            let _ = std::fs::remove_dir_all(&root);
+            let library = root.join("library");
+            let outside = root.join("outside");
+            std::fs::create_dir_all(library.join("sub")).unwrap();
+            std::fs::create_dir_all(&outside).unwrap();
+            // 用 canonicalize 后的根目录，避免 /tmp 本身是符号链接时比较失败
+            let root = std::fs::canonicalize(&root).unwrap();
+            Fixture {
+                library: root.join("library"),
+                outside: root.join("outside"),
+                root,
+            }
+        }
+
+        fn write(&self, path: &Path, bytes: &[u8]) -> PathBuf {
+            std::fs::write(path, bytes).unwrap();
+            path.to_path_buf()
+        }
+    }
+
+    impl Drop for Fixture {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.root);
+        }
+    }
+
+    const PDF: &[u8] = b"%PDF-1.7\n%\xe2\xe3\xcf\xd3\n";
+
+    #[test]
+    fn resolve_pdf_only_accepts_granted_pdfs() {
+        let fx = Fixture::new("resolve");
+        let storage = Storage::open_in_memory().unwrap();
+        let scope = FileScope::default();
+        scope.allow_folder(&storage, &fx.library).unwrap();
+
+        let inside = fx.write(&fx.library.join("sub").join("paper.pdf"), PDF);
+        let secret = fx.write(&fx.outside.join("secret.pdf"), PDF);
+        let fake = fx.write(&fx.library.join("notes.pdf"), b"just some text, not a PDF");
+        let granted = fx.write(&fx.outside.join("granted.pdf"), PDF);
+
+        // 授权文件夹内的 PDF 可以访问，asset:// 前缀会被去掉
+        assert_eq!(scope.resolve_pdf(inside.to_str().unwrap()).unwrap(), inside);
+        let asset = format!("asset://localhost/{}", inside.display());
+        assert_eq!(scope.resolve_pdf(&asset).unwrap(), inside);
+
+        // 从未授权的路径
+        let err = scope.resolve_pdf(secret.to_str().unwrap()).unwrap_err();
+        assert!(err.starts_with("Access denied"), "{}", err);
+
+        // 用 .. 绕出文件夹
+        let traversal = fx.library.join("sub").join("..").join("..").join("outside").join("secret.pdf");
+        let err = scope.resolve_pdf(traversal.to_str().unwrap()).unwrap_err();
+        assert!(err.starts_with("Access denied"), "{}", err);
+
+        // 改名为 .pdf 的非 PDF 文件
+        let err = scope.resolve_pdf(fake.to_str().unwrap()).unwrap_err();
+        assert!(err.starts_with("Not a PDF file"), "{}", err);
+
+        // 不存在的文件
+        let missing = fx.library.join("missing.pdf");
+        assert!(scope.resolve_pdf(missing.to_str().unwrap()).is_err());
+
+        // 单独授权的文件
+        scope.allow_file(&storage, &granted).unwrap();
+        assert_eq!(scope.resolve_pdf(granted.to_str().unwrap()).unwrap(), granted);
+        assert!(scope.resolve_pdf(secret.to_str().unwrap()).is_err());
+
+        // 授权记录持久化后重新加载仍然有效
+        let reloaded = FileScope::load(&storage).unwrap();
+        assert!(reloaded.is_allowed(&inside));
+        assert!(reloaded.is_allowed(&granted));
+        assert!(!reloaded.is_allowed(&secret));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlink_out_of_the_library_is_rejected() {
+        let fx = Fixture::new("symlink");
+        let storage = Storage::open_in_memory().unwrap();
+        let scope = FileScope::default();
+        scope.allow_folder(&storage, &fx.library).unwrap();
+
+        let secret = fx.write(&fx.outside.join("secret.pdf"), PDF);
+        let link = fx.library.join("link.pdf");
+        std::os::unix::fs::symlink(&secret, &link).unwrap();
+        let dir_link = fx.library.join("escape");
+        std::os::unix::fs::symlink(&fx.outside, &dir_link).unwrap();
+
+        for path in [link, dir_link.join("secret.pdf")] {
+            let err = scope.resolve_pdf(path.to_str().unwrap()).unwrap_err();
+            assert!(err.starts_with("Access denied"), "{}", err);
+        }
+    }
+
+    #[test]
+    fn ensure_pdf_checks_the_header_window() {
+        let fx = Fixture::new("header");
+        let mut late = vec![b' '; PDF_HEADER_WINDOW - 5];
+        late.extend_from_slice(b"%PDF-1.4");
+        let mut too_late = vec![b' '; PDF_HEADER_WINDOW];
+        too_late.extend_from_slice(b"%PDF-1.4");
+
+        let cases: [(&str, &[u8], bool); 5] = [
+            ("plain.pdf", PDF, true),
+            ("late.pdf", &late, true),
+            ("too-late.pdf", &too_late, false),
+            ("empty.pdf", b"", false),
+            ("html.pdf", b"<html>%PDF</html>", false),
+        ];
+        for (name, bytes, ok) in cases {
+            let path = fx.write(&fx.library.join(name), bytes);
+            assert_eq!(ensure_pdf(&path).is_ok(), ok, "{}", name);
+        }
+        assert!(ensure_pdf(&fx.library).is_err());
+    }
+}
@@ -0,0 +1,294 @@
+// 术语库：个人词库中的术语及其译法
+//
+// 术语不区分大小写；英文术语按整词匹配，中文术语按子串匹配。
+// 匹配用的正则由整个术语表编成，术语表不变时复用。
+
+use regex::Regex;
+use rusqlite::params;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, LazyLock, Mutex};
+
+use crate::file_scope::FileScope;
+use crate::library;
+use crate::ocr;
+use crate::pdf_text::{PageText, PdfTextCache};
+use crate::storage::{now_secs, Storage};
+
+pub const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS glossary (
+    term TEXT PRIMARY KEY COLLATE NOCASE,
+    translation TEXT NOT NULL,
+    note TEXT NOT NULL DEFAULT '',
+    created_at INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL
+);
+";
+
+// 最近一次编译的术语表
+static COMPILED: LazyLock<Mutex<Option<Arc<Glossary>>>> = LazyLock::new(|| Mutex::new(None));
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct GlossaryTerm {
+    pub term: String,
+    pub translation: String,
+    pub note: String,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GlossaryHit {
+    pub term: String,
+    pub translation: String,
+    pub count: usize,
+    pub pages: Vec<u32>,
+}
+
+pub fn upsert_term(storage: &Storage, term: &str, translation: &str, note: &str) -> Result<GlossaryTerm, String> {
+    let term = term.trim();
+    let translation = translation.trim();
+    if term.is_empty() || translation.is_empty() {
+        return Err("Term and translation are required".to_string());
+    }
+    let now = now_secs();
+    storage.with_conn(|conn| {
+        conn.execute(
+            "INSERT INTO glossary (term, translation, note, created_at, updated_at) VALUES (?1, ?2, ?3, ?4, ?4)
+             ON CONFLICT(term) DO UPDATE SET
+                translation = excluded.translation, note = excluded.note, updated_at = excluded.updated_at",
+            params![term, translation, note.trim(), now],
+        )?;
+        conn.query_row(
+            "SELECT term, translation, note, created_at, updated_at FROM glossary WHERE term = ?1",
+            params![term],
+            |row| {
+                Ok(GlossaryTerm {
+                    term: row.get(0)?,
+                    translation: row.get(1)?,
+                    note: row.get(2)?,
+                    created_at: row.get(3)?,
+                    updated_at: row.get(4)?,
+                })
+            },
+        )
+    })
+}
+
+pub fn all_terms(storage: &Storage) -> Result<Vec<GlossaryTerm>, String> {
+    storage.with_conn(|conn| {
+        let mut stmt = conn.prepare(
+            "SELECT term, translation, note, created_at, updated_at FROM glossary ORDER BY term COLLATE NOCASE",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok(GlossaryTerm {
+                term: row.get(0)?,
+                translation: row.get(1)?,
+                note: row.get(2)?,
+                created_at: row.get(3)?,
+                updated_at: row.get(4)?,
+            })
+        })?;
+        rows.collect()
+    })
+}
+
+/// 把术语编成一个正则：长词优先，含字母数字开头结尾的术语加词边界
+fn terms_regex(terms: &[GlossaryTerm]) -> Option<Regex> {
+    let mut sorted: Vec<&str> = terms.iter().map(|t| t.term.as_str()).collect();
+    sorted.sort_by_key(|t| std::cmp::Reverse(t.chars().count()));
+    let alternatives: Vec<String> = sorted
+        .iter()
+        .map(|term| {
+            let escaped = regex::escape(term);
+            let word_start = term.chars().next().is_some_and(|c| c.is_ascii_alphanumeric());
+            let word_end = term.chars().last().is_some_and(|c| c.is_ascii_alphanumeric());
+            format!(
+                "{}{}{}",
+                if word_start { r"\b" } else { "" },
+                escaped,
+                if word_end { r"\b" } else { "" }
+            )
+        })
+        .collect();
+    if alternatives.is_empty() {
+        return None;
+    }
+    Regex::new(&format!("(?i)(?:{})", alternatives.join("|"))).ok()
+}
+
+/// 编译好的术语表：正则只在构建时生成一次
+pub struct Glossary {
+    terms: Vec<GlossaryTerm>,
+    re: Option<Regex>,
+    // 小写术语 → terms 中的下标
+    by_lower: HashMap<String, usize>,
+}
+
+impl Glossary {
+    pub fn new(terms: Vec<GlossaryTerm>) -> Glossary {
+        let re = terms_regex(&terms);
+        let by_lower = terms
+            .iter()
+            .enumerate()
+            .map(|(i, t)| (t.term.to_lowercase(), i))
+            .collect();
+        Glossary { terms, re, by_lower }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.terms.is_empty()
+    }
+
+    /// 在文本中查找术语出现的位置，返回 (起止字节偏移, 术语)
+    pub fn find_terms(&self, text: &str) -> Vec<(usize, usize, &GlossaryTerm)> {
+        let Some(re) = &self.re else {
+            return Vec::new();
+        };
+        re.find_iter(text)
+            .filter_map(|m| {
+                self.by_lower
+                    .get(&m.as_str().to_lowercase())
+                    .map(|i| (m.start(), m.end(), &self.terms[*i]))
+            })
+            .collect()
+    }
+}
+
+/// 读取术语库并返回编译好的术语表；术语表与上次相同时复用已编译的正则
+pub fn load(storage: &Storage) -> Result<Arc<Glossary>, String> {
+    let terms = all_terms(storage)?;
+    let mut compiled = COMPILED.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(glossary) = compiled.as_ref().filter(|g| g.terms == terms) {
+        return Ok(glossary.clone());
+    }
+    let glossary = Arc::new(Glossary::new(terms));
+    *compiled = Some(glossary.clone());
+    Ok(glossary)
+}
+
+/// 统计术语在各页出现的次数，按出现次数排序
+pub fn glossary_hits(glossary: &Glossary, pages: &[impl AsRef<PageText>]) -> Vec<GlossaryHit> {
+    let mut hits: HashMap<&str, GlossaryHit> = HashMap::new();
+    for page in pages {
+        let page = page.as_ref();
+        for (_, _, term) in glossary.find_terms(&page.text) {
+            let hit = hits.entry(term.term.as_str()).or_insert_with(|| GlossaryHit {
+                term: term.term.clone(),
+                translation: term.translation.clone(),
+                count: 0,
+                pages: Vec::new(),
+            });
+            hit.count += 1;
+            if hit.pages.last() != Some(&page.page) {
+                hit.pages.push(page.page);
+            }
+        }
+    }
+    let mut hits: Vec<GlossaryHit> = hits.into_values().collect();
+    hits.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.term.cmp(&b.term)));
+    hits
+}
+
+pub fn document_hits(storage: &Storage, cache: &PdfTextCache, path: &Path) -> Result<Vec<GlossaryHit>, String> {
+    let glossary = load(storage)?;
+    if glossary.is_empty() {
+        return Ok(Vec::new());
+    }
+    let pages = ocr::document_text(storage, cache, path, false)?;
+    Ok(glossary_hits(&glossary, &pages))
+}
+
+#[tauri::command]
+pub fn add_glossary_term(
+    term: String,
+    translation: String,
+    note: Option<String>,
+    storage: tauri::State<'_, Storage>,
+) -> Result<GlossaryTerm, String> {
+    upsert_term(&storage, &term, &translation, note.as_deref().unwrap_or(""))
+}
+
+#[tauri::command]
+pub fn list_glossary(storage: tauri::State<'_, Storage>) -> Result<Vec<GlossaryTerm>, String> {
+    all_terms(&storage)
+}
+
+#[tauri::command]
+pub fn delete_glossary_term(term: String, storage: tauri::State<'_, Storage>) -> Result<(), String> {
+    storage.with_conn(|conn| {
+        conn.execute("DELETE FROM glossary WHERE term = ?1", params![term])?;
+        Ok(())
+    })
+}
+
+/// 当前文档中出现的术语
+#[tauri::command]
+pub async fn get_glossary_hits(document_id: String, app_handle: tauri::AppHandle) -> Result<Vec<GlossaryHit>, String> {
+    use tauri::Manager;
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let storage = app_handle.state::<Storage>();
+        let entry = library::find_entry(&storage, &document_id)?
+            .ok_or_else(|| format!("Document not in library: {}", document_id))?;
+        let path = app_handle.state::<FileScope>().resolve_pdf(&entry.path)?;
+        document_hits(&storage, &app_handle.state::<PdfTextCache>(), &path)
+    })
+    .await
+    .map_err(|e| format!("Glossary lookup failed: {}", e))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn term(term: &str, translation: &str) -> GlossaryTerm {
+        GlossaryTerm {
+            term: term.to_string(),
+            translation: translation.to_string(),
+            note: String::new(),
+            created_at: 0,
+            updated_at: 0,
+        }
+    }
+
+    #[test]
+    fn find_terms_prefers_longer_terms_and_whole_words() {
+        let glossary = Glossary::new(vec![
+            term("attention", "注意力"),
+            term("self-attention", "自注意力"),
+            term("GAN", "生成对抗网络"),
+            term("注意力", "attention"),
+        ]);
+        let text = "Self-Attention beats attention; GANs differ from a GAN. 注意力机制";
+        let found: Vec<(&str, &str)> = glossary
+            .find_terms(text)
+            .into_iter()
+            .map(|(start, end, t)| (&text[start..end], t.translation.as_str()))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                ("Self-Attention", "自注意力"),
+                ("attention", "注意力"),
+                ("GAN", "生成对抗网络"),
+                ("注意力", "attention"),
+            ]
+        );
+        assert!(Glossary::new(Vec::new()).find_terms(text).is_empty());
+    }
+
+    #[test]
+    fn load_recompiles_only_when_the_glossary_changes() {
+        let storage = Storage::open_in_memory().unwrap();
+        upsert_term(&storage, "transformer", "Transformer 模型", "").unwrap();
+        let first = load(&storage).unwrap();
+        assert_eq!(first.find_terms("a transformer").len(), 1);
+
+        upsert_term(&storage, "encoder", "编码器", "").unwrap();
+        let second = load(&storage).unwrap();
+        assert_eq!(second.find_terms("a transformer encoder").len(), 2);
+        assert_eq!(first.find_terms("a transformer encoder").len(), 1);
+    }
+}
@@ -0,0 +1,748 @@
+// 文献库：按内容指纹记录打开过的 PDF
+//
+// 文档 ID 为文件内容的 SHA-256，文件改名或移动后仍能对应到同一条记录。
+// 元数据优先取 XMP，其次是 Info 字典，最后从首页文字推断；
+// 同时记录最近打开时间和阅读位置。
+
+use lopdf::Document;
+use regex::Regex;
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+use std::time::UNIX_EPOCH;
+
+use crate::file_scope::{ensure_pdf, FileScope};
+use crate::outline;
+use crate::pdf_text::{decode_text_string, dict_get, resolve, stream_content, PdfTextCache};
+use crate::references::{find_arxiv_id, find_doi};
+use crate::search;
+use crate::storage::{now_secs, Storage};
+
+pub const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS documents (
+    id TEXT PRIMARY KEY,
+    path TEXT NOT NULL,
+    file_size INTEGER NOT NULL,
+    file_mtime INTEGER NOT NULL,
+    title TEXT,
+    authors TEXT NOT NULL DEFAULT '[]',
+    doi TEXT,
+    arxiv_id TEXT,
+    year TEXT,
+    page_count INTEGER NOT NULL DEFAULT 0,
+    added_at INTEGER NOT NULL,
+    last_opened_at INTEGER NOT NULL,
+    last_page INTEGER NOT NULL DEFAULT 1,
+    scroll_offset REAL NOT NULL DEFAULT 0,
+    zoom INTEGER NOT NULL DEFAULT 100
+);
+CREATE INDEX IF NOT EXISTS idx_documents_path ON documents(path);
+CREATE INDEX IF NOT EXISTS idx_documents_last_opened ON documents(last_opened_at);
+CREATE TABLE IF NOT EXISTS collections (
+    id TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    parent_id TEXT,
+    source TEXT NOT NULL DEFAULT 'papertalk'
+);
+CREATE TABLE IF NOT EXISTS document_collections (
+    document_id TEXT NOT NULL,
+    collection_id TEXT NOT NULL,
+    PRIMARY KEY (document_id, collection_id)
+);
+";
+
+const DOCUMENT_COLUMNS: &str = "id, path, title, authors, doi, arxiv_id, year, page_count,
+    added_at, last_opened_at, last_page, scroll_offset, zoom";
+
+// 查找移动过的文件时，文献库文件夹中最多检查的 PDF 数
+const MAX_SCAN_FILES: usize = 5000;
+
+// Info 字典里常见的无意义标题
+const JUNK_TITLES: &[&str] = &["untitled", "title", "paper", "main", "manuscript"];
+
+static XMP_TITLE_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?s)<dc:title>.*?<rdf:li[^>]*>(.*?)</rdf:li>").unwrap());
+static XMP_CREATORS_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?s)<dc:creator>(.*?)</dc:creator>").unwrap());
+static XMP_LI_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?s)<rdf:li[^>]*>(.*?)</rdf:li>").unwrap());
+static XMP_DOI_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?s)<(?:prism:doi|pdfx:doi|crossmark:DOI)>(.*?)</").unwrap());
+static PDF_DATE_YEAR_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^(?:D:)?((?:19|20)\d{2})").unwrap());
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LibraryEntry {
+    pub id: String,
+    pub path: String,
+    pub title: Option<String>,
+    pub authors: Vec<String>,
+    pub doi: Option<String>,
+    pub arxiv_id: Option<String>,
+    pub year: Option<String>,
+    pub page_count: u32,
+    pub added_at: i64,
+    pub last_opened_at: i64,
+    pub last_page: u32,
+    pub scroll_offset: f64,
+    pub zoom: u32,
+    // 文件已不在记录的位置
+    pub missing: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Collection {
+    pub id: String,
+    pub name: String,
+    pub parent_id: Option<String>,
+    // 来源：papertalk、zotero、bibtex
+    pub source: String,
+    pub document_count: u32,
+}
+
+/// 从其他文献管理工具导入的元数据，比从 PDF 推断的更可靠
+#[derive(Debug, Clone, Default)]
+pub struct ExternalMetadata {
+    pub title: Option<String>,
+    pub authors: Vec<String>,
+    pub doi: Option<String>,
+    pub arxiv_id: Option<String>,
+    pub year: Option<String>,
+}
+
+#[derive(Debug, Default)]
+struct Metadata {
+    title: Option<String>,
+    authors: Vec<String>,
+    doi: Option<String>,
+    arxiv_id: Option<String>,
+    year: Option<String>,
+    page_count: u32,
+}
+
+fn entry_from_row(row: &Row) -> rusqlite::Result<LibraryEntry> {
+    let path: String = row.get(1)?;
+    let authors: String = row.get(3)?;
+    Ok(LibraryEntry {
+        id: row.get(0)?,
+        missing: !Path::new(&path).exists(),
+        path,
+        title: row.get(2)?,
+        authors: serde_json::from_str(&authors).unwrap_or_default(),
+        doi: row.get(4)?,
+        arxiv_id: row.get(5)?,
+        year: row.get(6)?,
+        page_count: row.get(7)?,
+        added_at: row.get(8)?,
+        last_opened_at: row.get(9)?,
+        last_page: row.get(10)?,
+        scroll_offset: row.get(11)?,
+        zoom: row.get(12)?,
+    })
+}
+
+fn find_by_id(conn: &Connection, id: &str) -> rusqlite::Result<Option<LibraryEntry>> {
+    conn.query_row(
+        &format!("SELECT {} FROM documents WHERE id = ?1", DOCUMENT_COLUMNS),
+        params![id],
+        entry_from_row,
+    )
+    .optional()
+}
+
+pub fn find_entry(storage: &Storage, id: &str) -> Result<Option<LibraryEntry>, String> {
+    storage.with_conn(|conn| find_by_id(conn, id))
+}
+
+/* ---------------------------------
+ * 指纹
+ * --------------------------------- */
+
+pub fn file_stamp(path: &Path) -> Result<(u64, i64), String> {
+    let meta = std::fs::metadata(path).map_err(|e| format!("Cannot access {}: {}", path.display(), e))?;
+    let mtime = meta
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0);
+    Ok((meta.len(), mtime))
+}
+
+pub fn fingerprint(path: &Path) -> Result<String, String> {
+    let mut file = File::open(path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; 64 * 1024];
+    loop {
+        let n = file
+            .read(&mut buffer)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buffer[..n]);
+    }
+    Ok(hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+// 路径、大小、修改时间都没变时沿用已记录的指纹，避免每次打开都重新计算哈希
+fn cached_fingerprint(storage: &Storage, path: &str, size: u64, mtime: i64) -> Result<Option<String>, String> {
+    storage.with_conn(|conn| {
+        conn.query_row(
+            "SELECT id FROM documents WHERE path = ?1 AND file_size = ?2 AND file_mtime = ?3",
+            params![path, size as i64, mtime],
+            |row| row.get(0),
+        )
+        .optional()
+    })
+}
+
+/* ---------------------------------
+ * 元数据
+ * --------------------------------- */
+
+fn read_metadata(cache: &PdfTextCache, path: &Path) -> Metadata {
+    let mut meta = cache
+        .with_document(path, embedded_metadata)
+        .unwrap_or_default();
+
+    // 首页文字：补全标题，查找 DOI 与 arXiv 编号
+    if let Ok(first_page) = cache.page_text(path, 1) {
+        if meta.title.is_none() {
+            meta.title = outline::detect_title(std::slice::from_ref(&first_page));
+        }
+        if meta.doi.is_none() {
+            meta.doi = find_doi(&first_page.text);
+        }
+        if meta.arxiv_id.is_none() {
+            meta.arxiv_id = find_arxiv_id(&first_page.text);
+        }
+    }
+    meta
+}
+
+fn embedded_metadata(doc: &Document) -> Metadata {
+    let mut meta = Metadata {
+        page_count: doc.get_pages().len() as u32,
+        ..Metadata::default()
+    };
+
+    // XMP 元数据流
+    let catalog = doc
+        .trailer
+        .get(b"Root")
+        .ok()
+        .and_then(|o| resolve(doc, o).as_dict().ok());
+    if let Some(xmp) = catalog
+        .and_then(|c| dict_get(doc, c, b"Metadata"))
+        .and_then(|o| o.as_stream().ok())
+        .and_then(stream_content)
+    {
+        let xmp = String::from_utf8_lossy(&xmp);
+        meta.title = XMP_TITLE_RE
+            .captures(&xmp)
+            .map(|c| unescape_xml(&c[1]))
+            .filter(|t| plausible_title(t));
+        if let Some(creators) = XMP_CREATORS_RE.captures(&xmp) {
+            meta.authors = XMP_LI_RE
+                .captures_iter(&creators[1])
+                .map(|c| unescape_xml(&c[1]))
+                .filter(|a| !a.is_empty())
+                .collect();
+        }
+        meta.doi = XMP_DOI_RE.captures(&xmp).map(|c| unescape_xml(&c[1]));
+        if meta.arxiv_id.is_none() {
+            meta.arxiv_id = find_arxiv_id(&xmp);
+        }
+    }
+
+    // 文档信息字典
+    let Some(info) = doc
+        .trailer
+        .get(b"Info")
+        .ok()
+        .and_then(|o| resolve(doc, o).as_dict().ok())
+    else {
+        return meta;
+    };
+    let info_text = |key: &[u8]| {
+        dict_get(doc, info, key)
+            .and_then(|o| o.as_str().ok())
+            .map(|s| decode_text_string(s).trim().to_string())
+            .filter(|s| !s.is_empty())
+    };
+    if meta.title.is_none() {
+        meta.title = info_text(b"Title").filter(|t| plausible_title(t));
+    }
+    if meta.authors.is_empty() {
+        if let Some(author) = info_text(b"Author") {
+            meta.authors = author
+                .split([';', ','])
+                .flat_map(|a| a.split(" and "))
+                .map(|a| a.trim().to_string())
+                .filter(|a| !a.is_empty())
+                .collect();
+        }
+    }
+    if meta.doi.is_none() {
+        meta.doi = info_text(b"Subject")
+            .and_then(|s| find_doi(&s))
+            .or_else(|| info_text(b"doi"));
+    }
+    meta.year = info_text(b"CreationDate")
+        .and_then(|d| PDF_DATE_YEAR_RE.captures(&d).map(|c| c[1].to_string()));
+    meta
+}
+
+fn plausible_title(title: &str) -> bool {
+    let lower = title.trim().to_lowercase();
+    lower.chars().count() > 3
+        && !JUNK_TITLES.contains(&lower.as_str())
+        && !lower.starts_with("microsoft word")
+        && ![".doc", ".docx", ".tex", ".dvi", ".pdf"].iter().any(|ext| lower.ends_with(ext))
+}
+
+fn unescape_xml(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+        .trim()
+        .to_string()
+}
+
+/* ---------------------------------
+ * 文献库操作
+ * --------------------------------- */
+
+/// 登记文件：新文件计算指纹并读取元数据，已知文件更新路径；
+/// opened 为 true 时同时记为最近打开（批量导入时为 false）
+pub fn register_file(storage: &Storage, cache: &PdfTextCache, path: &Path, opened: bool) -> Result<LibraryEntry, String> {
+    let path_str = path.to_string_lossy().to_string();
+    let (size, mtime) = file_stamp(path)?;
+    let id = match cached_fingerprint(storage, &path_str, size, mtime)? {
+        Some(id) => id,
+        None => fingerprint(path)?,
+    };
+    let now = now_secs();
+    // 从未打开过的文档 last_opened_at 为 0
+    let opened_at = if opened { Some(now) } else { None };
+
+    let existing = storage.with_conn(|conn| find_by_id(conn, &id))?;
+    if let Some(entry) = existing {
+        if entry.path != path_str {
+            println!("📚 文献已移动: {} -> {}", entry.path, path_str);
+        }
+        storage.with_conn(|conn| {
+            conn.execute(
+                "UPDATE documents SET path = ?2, file_size = ?3, file_mtime = ?4,
+                    last_opened_at = COALESCE(?5, last_opened_at)
+                 WHERE id = ?1",
+                params![id, path_str, size as i64, mtime, opened_at],
+            )
+        })?;
+    } else {
+        let meta = read_metadata(cache, path);
+        println!("📚 加入文献库: {} ({})", meta.title.as_deref().unwrap_or("未识别标题"), path_str);
+        let authors = serde_json::to_string(&meta.authors).unwrap_or_else(|_| "[]".to_string());
+        storage.with_conn(|conn| {
+            conn.execute(
+                "INSERT INTO documents
+                    (id, path, file_size, file_mtime, title, authors, doi, arxiv_id, year, page_count, added_at, last_opened_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+                params![
+                    id,
+                    path_str,
+                    size as i64,
+                    mtime,
+                    meta.title,
+                    authors,
+                    meta.doi,
+                    meta.arxiv_id,
+                    meta.year,
+                    meta.page_count,
+                    now,
+                    opened_at.unwrap_or(0)
+                ],
+            )
+        })?;
+    }
+
+    storage
+        .with_conn(|conn| find_by_id(conn, &id))?
+        .ok_or_else(|| "Document disappeared from library".to_string())
+}
+
+/// 用导入的元数据覆盖对应字段（空字段保持不变）
+pub fn apply_external_metadata(storage: &Storage, id: &str, meta: &ExternalMetadata) -> Result<(), String> {
+    let authors = (!meta.authors.is_empty())
+        .then(|| serde_json::to_string(&meta.authors).unwrap_or_else(|_| "[]".to_string()));
+    storage.with_conn(|conn| {
+        conn.execute(
+            "UPDATE documents SET
+                title = COALESCE(?2, title), authors = COALESCE(?3, authors), doi = COALESCE(?4, doi),
+                arxiv_id = COALESCE(?5, arxiv_id), year = COALESCE(?6, year)
+             WHERE id = ?1",
+            params![id, meta.title, authors, meta.doi, meta.arxiv_id, meta.year],
+        )?;
+        Ok(())
+    })
+}
+
+pub fn find_by_doi(storage: &Storage, doi: &str) -> Result<Option<LibraryEntry>, String> {
+    storage.with_conn(|conn| {
+        conn.query_row(
+            &format!("SELECT {} FROM documents WHERE doi = ?1 COLLATE NOCASE", DOCUMENT_COLUMNS),
+            params![doi],
+            entry_from_row,
+        )
+        .optional()
+    })
+}
+
+/// 按标题精确匹配（不区分大小写），用于没有 DOI 的导入条目
+pub fn find_by_title(storage: &Storage, title: &str) -> Result<Option<LibraryEntry>, String> {
+    storage.with_conn(|conn| {
+        conn.query_row(
+            &format!("SELECT {} FROM documents WHERE title = ?1 COLLATE NOCASE LIMIT 1", DOCUMENT_COLUMNS),
+            params![title.trim()],
+            entry_from_row,
+        )
+        .optional()
+    })
+}
+
+pub fn upsert_collection(storage: &Storage, id: &str, name: &str, parent_id: Option<&str>, source: &str) -> Result<(), String> {
+    storage.with_conn(|conn| {
+        conn.execute(
+            "INSERT INTO collections (id, name, parent_id, source) VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT(id) DO UPDATE SET name = excluded.name, parent_id = excluded.parent_id",
+            params![id, name, parent_id, source],
+        )?;
+        Ok(())
+    })
+}
+
+pub fn add_to_collection(storage: &Storage, document_id: &str, collection_id: &str) -> Result<(), String> {
+    storage.with_conn(|conn| {
+        conn.execute(
+            "INSERT OR IGNORE INTO document_collections (document_id, collection_id) VALUES (?1, ?2)",
+            params![document_id, collection_id],
+        )?;
+        Ok(())
+    })
+}
+
+pub fn list_entries(storage: &Storage, limit: u32, offset: u32, opened_only: bool) -> Result<Vec<LibraryEntry>, String> {
+    let filter = if opened_only { "WHERE last_opened_at > 0" } else { "" };
+    storage.with_conn(|conn| {
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM documents {} ORDER BY last_opened_at DESC, added_at DESC LIMIT ?1 OFFSET ?2",
+            DOCUMENT_COLUMNS, filter
+        ))?;
+        let rows = stmt.query_map(params![limit, offset], entry_from_row)?;
+        rows.collect()
+    })
+}
+
+/// 按标题、作者、DOI、arXiv 编号或路径模糊查找
+pub fn search_entries(storage: &Storage, query: &str) -> Result<Vec<LibraryEntry>, String> {
+    let escaped = query.trim().replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+    let pattern = format!("%{}%", escaped);
+    storage.with_conn(|conn| {
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM documents
+             WHERE title LIKE ?1 ESCAPE '\\' OR authors LIKE ?1 ESCAPE '\\' OR doi LIKE ?1 ESCAPE '\\'
+                OR arxiv_id LIKE ?1 ESCAPE '\\' OR path LIKE ?1 ESCAPE '\\'
+             ORDER BY last_opened_at DESC",
+            DOCUMENT_COLUMNS
+        ))?;
+        let rows = stmt.query_map(params![pattern], entry_from_row)?;
+        rows.collect()
+    })
+}
+
+/// 在文献库文件夹中按大小和指纹找回移动过的文件，返回找回的数量
+pub fn relocate_missing(storage: &Storage, scope: &FileScope) -> Result<usize, String> {
+    let missing: Vec<(String, i64)> = storage.with_conn(|conn| {
+        let mut stmt = conn.prepare("SELECT id, path, file_size FROM documents")?;
+        let rows = stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get(2)?)))?;
+        Ok(rows
+            .filter_map(Result::ok)
+            .filter(|(_, path, _)| !Path::new(path).exists())
+            .map(|(id, _, size)| (id, size))
+            .collect())
+    })?;
+    if missing.is_empty() {
+        return Ok(0);
+    }
+
+    let mut relocated = 0;
+    for path in library_files(scope) {
+        let Ok((size, mtime)) = file_stamp(&path) else {
+            continue;
+        };
+        // 先按文件大小筛选，只对可能匹配的文件计算哈希
+        if !missing.iter().any(|(_, s)| *s == size as i64) {
+            continue;
+        }
+        let Ok(id) = fingerprint(&path) else {
+            continue;
+        };
+        if missing.iter().any(|(m, _)| *m == id) {
+            storage.with_conn(|conn| {
+                conn.execute(
+                    "UPDATE documents SET path = ?2, file_mtime = ?3 WHERE id = ?1",
+                    params![id, path.to_string_lossy(), mtime],
+                )
+            })?;
+            println!("📚 找回移动的文件: {}", path.display());
+            relocated += 1;
+        }
+    }
+    Ok(relocated)
+}
+
+/// 文献库文件夹中的全部 PDF
+pub fn library_files(scope: &FileScope) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    for folder in scope.folders() {
+        collect_pdfs(&folder, &mut files);
+    }
+    files
+}
+
+fn collect_pdfs(dir: &Path, out: &mut Vec<PathBuf>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        if out.len() >= MAX_SCAN_FILES {
+            return;
+        }
+        let path = entry.path();
+        // 不跟随符号链接，避免跳出授权文件夹或陷入循环
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+        if file_type.is_dir() {
+            collect_pdfs(&path, out);
+        } else if file_type.is_file()
+            && path
+                .extension()
+                .is_some_and(|ext| ext.eq_ignore_ascii_case("pdf"))
+            && ensure_pdf(&path).is_ok()
+        {
+            out.push(path);
+        }
+    }
+}
+
+/// 打开 PDF 时调用，返回文献库中的记录（含上次阅读位置）
+#[tauri::command]
+pub async fn open_document(path: String, app_handle: tauri::AppHandle) -> Result<LibraryEntry, String> {
+    use tauri::Manager;
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let canonical = app_handle.state::<FileScope>().resolve_pdf(&path)?;
+        let entry = register_file(
+            &app_handle.state::<Storage>(),
+            &app_handle.state::<PdfTextCache>(),
+            &canonical,
+            true,
+        )?;
+        search::index_in_background(&app_handle, entry.id.clone(), entry.path.clone());
+        Ok(entry)
+    })
+    .await
+    .map_err(|e| format!("Failed to open document: {}", e))?
+}
+
+#[tauri::command]
+pub fn list_documents(
+    limit: Option<u32>,
+    offset: Option<u32>,
+    storage: tauri::State<'_, Storage>,
+) -> Result<Vec<LibraryEntry>, String> {
+    list_entries(&storage, limit.unwrap_or(200), offset.unwrap_or(0), false)
+}
+
+#[tauri::command]
+pub fn recent_documents(limit: Option<u32>, storage: tauri::State<'_, Storage>) -> Result<Vec<LibraryEntry>, String> {
+    list_entries(&storage, limit.unwrap_or(10), 0, true)
+}
+
+#[tauri::command]
+pub fn search_documents(query: String, storage: tauri::State<'_, Storage>) -> Result<Vec<LibraryEntry>, String> {
+    search_entries(&storage, &query)
+}
+
+// 在一个事务中删除文档及其关联数据。用量记录和翻译记忆不随文档删除，只解除关联
+fn delete_document(storage: &Storage, id: &str) -> Result<(), String> {
+    storage.with_conn(|conn| {
+        let tx = conn.unchecked_transaction()?;
+        search::remove_document(&tx, id)?;
+        for table in [
+            "document_collections",
+            "summaries",
+            "notes",
+            "highlights",
+            "translations",
+            "translation_candidates",
+            "external_items",
+        ] {
+            tx.execute(&format!("DELETE FROM {} WHERE document_id = ?1", table), params![id])?;
+        }
+        tx.execute("UPDATE usage_records SET document_id = NULL WHERE document_id = ?1", params![id])?;
+        tx.execute("UPDATE translation_memory SET document_id = NULL WHERE document_id = ?1", params![id])?;
+        // OCR 结果按路径缓存，没有其他记录指向同一文件时才删除
+        tx.execute(
+            "DELETE FROM ocr_pages
+             WHERE path IN (SELECT path FROM documents WHERE id = ?1)
+               AND path NOT IN (SELECT path FROM documents WHERE id != ?1)",
+            params![id],
+        )?;
+        tx.execute("DELETE FROM documents WHERE id = ?1", params![id])?;
+        tx.commit()
+    })
+}
+
+/// 只从文献库中移除记录，不删除文件
+#[tauri::command]
+pub fn remove_document(id: String, storage: tauri::State<'_, Storage>) -> Result<(), String> {
+    delete_document(&storage, &id)
+}
+
+#[tauri::command]
+pub fn list_collections(storage: tauri::State<'_, Storage>) -> Result<Vec<Collection>, String> {
+    storage.with_conn(|conn| {
+        let mut stmt = conn.prepare(
+            "SELECT c.id, c.name, c.parent_id, c.source, COUNT(dc.document_id)
+             FROM collections c LEFT JOIN document_collections dc ON dc.collection_id = c.id
+             GROUP BY c.id ORDER BY c.name COLLATE NOCASE",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok(Collection {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                parent_id: row.get(2)?,
+                source: row.get(3)?,
+                document_count: row.get(4)?,
+            })
+        })?;
+        rows.collect()
+    })
+}
+
+#[tauri::command]
+pub fn collection_documents(collection_id: String, storage: tauri::State<'_, Storage>) -> Result<Vec<LibraryEntry>, String> {
+    storage.with_conn(|conn| {
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM documents
+             WHERE id IN (SELECT document_id FROM document_collections WHERE collection_id = ?1)
+             ORDER BY title COLLATE NOCASE",
+            DOCUMENT_COLUMNS
+        ))?;
+        let rows = stmt.query_map(params![collection_id], entry_from_row)?;
+        rows.collect()
+    })
+}
+
+#[tauri::command]
+pub fn save_reading_position(
+    id: String,
+    page: u32,
+    scroll_offset: Option<f64>,
+    zoom: Option<u32>,
+    storage: tauri::State<'_, Storage>,
+) -> Result<(), String> {
+    storage.with_conn(|conn| {
+        conn.execute(
+            "UPDATE documents SET last_page = ?2, scroll_offset = COALESCE(?3, scroll_offset), zoom = COALESCE(?4, zoom)
+             WHERE id = ?1",
+            params![id, page, scroll_offset, zoom],
+        )?;
+        Ok(())
+    })
+}
+
+#[tauri::command]
+pub async fn locate_missing_documents(app_handle: tauri::AppHandle) -> Result<usize, String> {
+    use tauri::Manager;
+
+    tauri::async_runtime::spawn_blocking(move || {
+        relocate_missing(&app_handle.state::<Storage>(), &app_handle.state::<FileScope>())
+    })
+    .await
+    .map_err(|e| format!("Failed to scan library folders: {}", e))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn count(storage: &Storage, sql: &str) -> i64 {
+        storage.with_conn(|conn| conn.query_row(sql, [], |row| row.get(0))).unwrap()
+    }
+
+    #[test]
+    fn removing_a_document_clears_its_data_in_one_go() {
+        let storage = Storage::open_in_memory().unwrap();
+        storage
+            .with_conn(|conn| {
+                conn.execute_batch(
+                    "INSERT INTO documents (id, path, file_size, file_mtime, added_at, last_opened_at)
+                     VALUES ('doc', '/papers/a.pdf', 1, 1, 0, 0), ('other', '/papers/b.pdf', 1, 1, 0, 0);
+                     INSERT INTO document_collections VALUES ('doc', 'c1');
+                     INSERT INTO summaries VALUES ('doc', 'ollama', 'qwen', 'zh', '{}', 0);
+                     INSERT INTO highlights VALUES ('h1', 'doc', 1, '[]', '#fff', 't', 0, 0);
+                     INSERT INTO notes (id, document_id, highlight_id, body, created_at, updated_at)
+                     VALUES ('n1', 'doc', 'h1', 'note', 0, 0);
+                     INSERT INTO translations (document_id, source, translation, provider, model, created_at)
+                     VALUES ('doc', 'a', 'b', 'ollama', 'qwen', 0), ('other', 'a', 'b', 'ollama', 'qwen', 0);
+                     INSERT INTO translation_candidates
+                         (comparison_id, document_id, source, provider, model, translation, created_at)
+                     VALUES ('cmp', 'doc', 'a', 'kimi', 'k', 'b', 0);
+                     INSERT INTO external_items VALUES ('zotero', 'K1', 'doc', 0);
+                     INSERT INTO ocr_pages VALUES ('/papers/a.pdf', 1, 1, 1, '{}', 0),
+                                                  ('/papers/b.pdf', 1, 1, 1, '{}', 0);
+                     INSERT INTO usage_records (created_at, document_id, provider, model, kind,
+                         prompt_tokens, completion_tokens, cost, currency)
+                     VALUES (0, 'doc', 'kimi', 'k', 'translation', 1, 1, 0.1, 'CNY');
+                     INSERT INTO translation_memory (source, target, source_key, document_id, created_at, updated_at)
+                     VALUES ('a', 'b', 'a', 'doc', 0, 0);
+                     INSERT INTO search_index (body, document_id, kind) VALUES ('a', 'doc', 'page');",
+                )
+            })
+            .unwrap();
+
+        delete_document(&storage, "doc").unwrap();
+
+        for table in [
+            "documents",
+            "document_collections",
+            "summaries",
+            "highlights",
+            "notes",
+            "translations",
+            "translation_candidates",
+            "external_items",
+            "search_index",
+        ] {
+            let left = if table == "documents" {
+                count(&storage, "SELECT COUNT(*) FROM documents WHERE id = 'doc'")
+            } else {
+                count(&storage, &format!("SELECT COUNT(*) FROM {} WHERE document_id = 'doc'", table))
+            };
+            assert_eq!(left, 0, "{} still has rows", table);
+        }
+        assert_eq!(count(&storage, "SELECT COUNT(*) FROM ocr_pages"), 1);
+        assert_eq!(count(&storage, "SELECT COUNT(*) FROM translations"), 1);
+        // 用量与翻译记忆保留，只解除关联
+        assert_eq!(count(&storage, "SELECT COUNT(*) FROM usage_records WHERE document_id IS NULL"), 1);
+        assert_eq!(count(&storage, "SELECT COUNT(*) FROM translation_memory WHERE document_id IS NULL"), 1);
+    }
+}
@@ -1,89 +1,96 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-use serde::{Deserialize, Serialize};
+mod annotations;
+mod compare;
+mod equations;
+mod export;
+mod file_scope;
+mod glossary;
+mod library;
+mod ocr;
+mod outline;
+mod pdf_annotations;
+mod pdf_protocol;
+mod pdf_text;
+mod providers;
+mod quality;
+mod references;
+mod retry;
+mod scheduler;
+mod search;
+mod segment;
+mod selection;
+mod storage;
+mod stream_filter;
+mod summary;
+mod text;
+mod translation_memory;
+mod translations;
+mod usage;
+mod vision;
+mod zotero;
+
+use file_scope::FileScope;
+use pdf_text::PdfTextCache;
+use quality::CheckedTranslation;
+use references::ReferenceCache;
+use providers::{
+    complete_with_fallback, Completion, CompletionRequest, ModelConfig, OllamaOptions, OllamaRequest, OllamaResponse,
+    OllamaStreamResponse, StreamDelta,
+};
+use serde::Serialize;
 use std::process::Command;
-use reqwest;
-use regex::Regex;
-use tauri::{Emitter, Manager};
-
-#[derive(Debug, Serialize, Deserialize)]
-struct OllamaResponse {
-    model: String,
-    created_at: String,
-    response: String,
-    done: bool,
+use scheduler::{Priority, Scheduler};
+use storage::Storage;
+use stream_filter::{strip_reasoning, FilterOutput, ThinkFilter};
+use tauri::{DragDropEvent, Emitter, Manager, WindowEvent};
+
+const TRANSLATE_SYSTEM_PROMPT: &str = "你是一个专业的翻译助手，请将英文文本准确翻译成中文，保持专业术语不变。";
+const CHAT_SYSTEM_PROMPT: &str = "你是一个专业的学术助手，请帮助用户解答关于论文的问题。";
+// 聊天时附带的论文上下文上限（估算 token），超出部分的正文截断
+const CHAT_CONTEXT_TOKENS: u64 = 6000;
+
+// 一类请求对应的前端事件名，kind 用于用量统计
+struct StreamEvents {
+    kind: &'static str,
+    chunk: &'static str,
+    reasoning: &'static str,
+    complete: &'static str,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct OllamaStreamResponse {
-    model: String,
-    created_at: String,
-    response: String,
-    done: bool,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-struct OllamaRequest {
-    model: String,
-    prompt: String,
-    stream: bool,
-    think: bool,
-    num_predict: Option<u32>,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-struct ModelConfig {
-    selected_model: String,
-    ollama: OllamaConfig,
-    deepseek: DeepSeekConfig,
-    kimi: KimiConfig,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-struct OllamaConfig {
-    base_url: String,
-    model: String,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-struct DeepSeekConfig {
-    api_key: String,
-    base_url: String,
-    model: String,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-struct KimiConfig {
-    api_key: String,
-    base_url: String,
-    model: String,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-struct DeepSeekMessage {
-    role: String,
-    content: String,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-struct DeepSeekRequest {
-    model: String,
-    messages: Vec<DeepSeekMessage>,
-    stream: bool,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-struct KimiMessage {
-    role: String,
-    content: String,
+const TRANSLATION_EVENTS: StreamEvents = StreamEvents {
+    kind: "translation",
+    chunk: "translation_chunk",
+    reasoning: "reasoning_chunk",
+    complete: "translation_complete",
+};
+
+const CHAT_EVENTS: StreamEvents = StreamEvents {
+    kind: "chat",
+    chunk: "chat_chunk",
+    reasoning: "chat_reasoning_chunk",
+    complete: "chat_complete",
+};
+
+// 发送事件到主窗口；"main" 窗口不存在时退回到第一个窗口
+fn emit_to_window<S: Serialize + Clone>(app_handle: &tauri::AppHandle, event: &str, payload: S) {
+    if let Some(window) = app_handle.get_webview_window("main") {
+        let _ = window.emit(event, payload);
+    } else if let Some(window) = app_handle.webview_windows().values().next() {
+        let _ = window.emit(event, payload);
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct KimiRequest {
-    model: String,
-    messages: Vec<KimiMessage>,
-    stream: bool,
+// 发送过滤后的正文片段，推理内容走单独的 reasoning_chunk 事件
+fn emit_filtered(app_handle: &tauri::AppHandle, chunk_event: &str, out: &FilterOutput, full_response: &mut String) {
+    if !out.reasoning.is_empty() {
+        emit_to_window(app_handle, "reasoning_chunk", &out.reasoning);
+    }
+    if !out.content.is_empty() {
+        full_response.push_str(&out.content);
+        emit_to_window(app_handle, chunk_event, &out.content);
+    }
 }
 
 #[tauri::command]
@@ -100,17 +107,21 @@ async fn translate_text(text: String) -> Result<String, String> {
     println!("🌐 收到翻译请求，文本：{}", text);
 
     let client = reqwest::Client::new();
+    let model = "qwen3:8b".to_string();
+    // 明确禁止输出思考标签或后缀
+    let prompt = format!(
+        "请将以下英文文本翻译成中文，保持专业术语不变。\
+         {}",
+        text
+    );
     let request = OllamaRequest {
-        model: "qwen3:8b".to_string(),
-        // 明确禁止输出思考标签或后缀
-        prompt: format!(
-            "请将以下英文文本翻译成中文，保持专业术语不变。\
-             {}",
-            text
-        ),
+        options: Some(OllamaOptions::for_prompt(&model, &prompt)),
+        model,
+        prompt,
+        system: None,
         stream: false,
         think: false,
-        num_predict: Some(1000), // 限制响应长度
+        images: Vec::new(),
     };
 
     let url = "http://127.0.0.1:11434/api/generate"; // 去掉尾部空格
@@ -124,8 +135,7 @@ async fn translate_text(text: String) -> Result<String, String> {
                 .map_err(|e| format!("Failed to parse response: {}", e))?;
 
             // 去掉 <think>…</think> 以及尾巴上的 /no_think /think
-            let re = Regex::new(r"(?s)<think>\s*.*?\s*</think>|\s*/no_think\s*/think\s*$").unwrap();
-            let cleaned = re.replace_all(&ollama_resp.response, "").trim().to_string();
+            let cleaned = strip_reasoning(&ollama_resp.response);
 
             println!("[translate_text] 翻译结果: {}", cleaned);
             Ok(cleaned)
@@ -146,16 +156,20 @@ async fn translate_text_stream(text: String, app_handle: tauri::AppHandle) -> Re
     println!("🌐 收到流式翻译请求，文本：{}", text);
 
     let client = reqwest::Client::new();
+    let model = "qwen3:8b".to_string();
+    let prompt = format!(
+        "请将以下英文文本翻译成中文，保持专业术语不变。\
+         {}",
+        text
+    );
     let request = OllamaRequest {
-        model: "qwen3:8b".to_string(),
-        prompt: format!(
-            "请将以下英文文本翻译成中文，保持专业术语不变。\
-             {}",
-            text
-        ),
+        options: Some(OllamaOptions::for_prompt(&model, &prompt)),
+        model,
+        prompt,
+        system: None,
         stream: true,
         think: false,
-        num_predict: Some(1000), // 限制响应长度
+        images: Vec::new(),
     };
 
     let url = "http://127.0.0.1:11434/api/generate";
@@ -164,6 +178,7 @@ async fn translate_text_stream(text: String, app_handle: tauri::AppHandle) -> Re
     match client.post(url).json(&request).send().await {
         Ok(mut resp) if resp.status().is_success() => {
             let mut full_response = String::new();
+            let mut filter = ThinkFilter::new(false);
             
             while let Some(chunk) = resp.chunk().await.map_err(|e| format!("Failed to read chunk: {}", e))? {
                 let chunk_str = String::from_utf8_lossy(&chunk);
@@ -176,33 +191,15 @@ async fn translate_text_stream(text: String, app_handle: tauri::AppHandle) -> Re
                     
                     match serde_json::from_str::<OllamaStreamResponse>(line) {
                         Ok(stream_resp) => {
-                            // 清理响应文本
-                            let re = Regex::new(r"(?s)<think>\s*.*?\s*</think>|\s*/no_think\s*/think\s*$|\s*/think\s*$").unwrap();
-                            let cleaned = re.replace_all(&stream_resp.response, "").trim().to_string();
-                            
-                            if !cleaned.is_empty() {
-                                full_response.push_str(&cleaned);
-                                
-                                // 发送流式更新到前端 - 获取主窗口并发送事件
-                                if let Some(window) = app_handle.get_webview_window("main") {
-                                    let _ = window.emit("translation_chunk", &cleaned);
-                                    println!("[translate_text_stream] 发送流式数据: {}", cleaned);
-                                } else {
-                                    // 如果 "main" 窗口不存在，尝试获取第一个窗口
-                                    if let Some(window) = app_handle.webview_windows().values().next() {
-                                        let _ = window.emit("translation_chunk", &cleaned);
-                                        println!("[translate_text_stream] 发送流式数据到第一个窗口: {}", cleaned);
-                                    }
-                                }
-                            }
+                            // 过滤推理块，标签可能跨 token
+                            let out = filter.push(&stream_resp.response);
+                            emit_filtered(&app_handle, "translation_chunk", &out, &mut full_response);
                             
                             if stream_resp.done {
+                                let out = filter.finish();
+                                emit_filtered(&app_handle, "translation_chunk", &out, &mut full_response);
                                 println!("[translate_text_stream] 流式翻译完成");
-                                if let Some(window) = app_handle.get_webview_window("main") {
-                                    let _ = window.emit("translation_complete", &full_response);
-                                } else if let Some(window) = app_handle.webview_windows().values().next() {
-                                    let _ = window.emit("translation_complete", &full_response);
-                                }
+                                emit_to_window(&app_handle, "translation_complete", &full_response);
                                 println!("[translate_text_stream] 返回完整结果: {}", full_response);
                                 return Ok(full_response);
                             }
@@ -214,6 +211,8 @@ async fn translate_text_stream(text: String, app_handle: tauri::AppHandle) -> Re
                 }
             }
             
+            let out = filter.finish();
+            emit_filtered(&app_handle, "translation_chunk", &out, &mut full_response);
             Ok(full_response)
         }
         Ok(resp) => {
@@ -251,14 +250,17 @@ fn open_ollama_website() -> Result<(), String> {
 
 
 #[tauri::command]
-async fn get_pdf_data(path: String) -> Result<Vec<u8>, String> {
+async fn get_pdf_data(path: String, scope: tauri::State<'_, FileScope>) -> Result<Vec<u8>, String> {
     use std::fs;
     
     println!("📁 Received request to read PDF: {}", path);
     
-    // Remove the protocol prefix if present (Tauri 2.x uses asset://)
-    let clean_path = path.replace("asset://localhost/", "");
-    println!("📁 Cleaned path: {}", clean_path);
+    // 只允许读取用户授权过的 PDF
+    let clean_path = scope.resolve_pdf(&path).map_err(|e| {
+        println!("❌ Rejected PDF request: {}", e);
+        e
+    })?;
+    println!("📁 Resolved path: {}", clean_path.display());
     
     match fs::read(&clean_path) {
         Ok(data) => {
@@ -272,597 +274,283 @@ async fn get_pdf_data(path: String) -> Result<Vec<u8>, String> {
     }
 }
 
+/// 翻译并检查译文质量，back_translate 为 true 时额外回译比较
 #[tauri::command]
-async fn translate_with_config(text: String, config: ModelConfig, app_handle: tauri::AppHandle) -> Result<String, String> {
+async fn translate_with_config(
+    text: String,
+    config: ModelConfig,
+    show_reasoning: Option<bool>,
+    document_id: Option<String>,
+    priority: Option<Priority>,
+    back_translate: Option<bool>,
+    app_handle: tauri::AppHandle,
+) -> Result<CheckedTranslation, String> {
     println!("🌐 收到多模型翻译请求，文本：{}，模型：{}", text, config.selected_model);
-    
-    match config.selected_model.as_str() {
-        "ollama" => {
-            println!("使用 Ollama 模型进行翻译");
-            translate_with_ollama(text, config.ollama, app_handle).await
-        }
-        "deepseek" => {
-            println!("使用 DeepSeek 模型进行翻译");
-            translate_with_deepseek(text, config.deepseek, app_handle).await
-        }
-        "kimi" => {
-            println!("使用 Kimi 模型进行翻译");
-            translate_with_kimi(text, config.kimi, app_handle).await
-        }
-        _ => {
-            println!("❌ 未知模型类型：{}", config.selected_model);
-            Err("Unknown model type".to_string())
-        }
+
+    let show_reasoning = show_reasoning.unwrap_or(false);
+    let priority = priority.unwrap_or_default();
+    let segments = segment::split_for(&config, &text, TRANSLATE_SYSTEM_PROMPT);
+    let completion = if segments.len() > 1 {
+        println!("✂️ 选区较长，分 {} 段翻译", segments.len());
+        translate_segments(&config, &segments, show_reasoning, priority, document_id.as_deref(), &app_handle).await?
+    } else {
+        // 翻译记忆中的相似句作为示例，保持术语和表达一致
+        let model = segment::tokenizer_model(&config);
+        let examples = translation_memory::few_shot_prompt(&app_handle.state::<Storage>(), &text, &model);
+        let request = CompletionRequest {
+            system: format!("{}{}", TRANSLATE_SYSTEM_PROMPT, examples),
+            prompt: text.clone(),
+            images: Vec::new(),
+            show_reasoning,
+            priority,
+        };
+        run_completion(&config, &request, &TRANSLATION_EVENTS, document_id.as_deref(), &app_handle).await?
+    };
+
+    let storage = app_handle.state::<Storage>();
+    if let Err(e) = translations::record_translation(&storage, document_id.as_deref(), &text, &completion) {
+        println!("⚠️ 保存翻译记录失败：{}", e);
     }
+    let checked = quality::checked(
+        &config,
+        &text,
+        completion,
+        back_translate.unwrap_or(false),
+        document_id.as_deref(),
+        &app_handle,
+    )
+    .await;
+    Ok(checked)
 }
 
 #[tauri::command]
-async fn chat_with_config(message: String, config: ModelConfig, app_handle: tauri::AppHandle) -> Result<String, String> {
+async fn chat_with_config(
+    message: String,
+    config: ModelConfig,
+    show_reasoning: Option<bool>,
+    document_id: Option<String>,
+    priority: Option<Priority>,
+    app_handle: tauri::AppHandle,
+) -> Result<Completion, String> {
     println!("💬 收到聊天请求，消息：{}，模型：{}", message, config.selected_model);
     
-    // 获取当前打开的PDF文件路径（如果有的话）
-    let current_pdf = std::env::current_dir()
-        .ok()
-        .and_then(|_dir| {
-            // 这里可以添加逻辑来获取当前打开的PDF文件
-            // 目前先返回一个占位符
-            Some("当前打开的论文内容".to_string())
-        })
-        .unwrap_or_default();
-    
-    // 构建包含论文上下文的提示词
-    let enhanced_message = if !current_pdf.is_empty() {
-        format!(
-            "用户正在阅读一篇论文，以下是论文的相关信息：{}\n\n用户的问题：{}",
-            current_pdf, message
-        )
+    // 当前文档的标题、摘要、章节和正文节选作为上下文；选中文字和当前页由前端拼在消息里
+    let context = match document_id.clone() {
+        Some(id) => {
+            let app_handle = app_handle.clone();
+            let model = segment::tokenizer_model(&config);
+            tauri::async_runtime::spawn_blocking(move || paper_context(&app_handle, &id, &model))
+                .await
+                .map_err(|e| format!("Text extraction failed: {}", e))?
+                .unwrap_or_else(|e| {
+                    println!("⚠️ 读取论文上下文失败：{}", e);
+                    String::new()
+                })
+        }
+        None => String::new(),
+    };
+    let system = if context.is_empty() {
+        CHAT_SYSTEM_PROMPT.to_string()
     } else {
-        message
+        format!("{}\n\n用户正在阅读的论文：\n{}", CHAT_SYSTEM_PROMPT, context)
     };
-    
-    match config.selected_model.as_str() {
-        "ollama" => {
-            println!("使用 Ollama 模型进行聊天");
-            chat_with_ollama(enhanced_message, config.ollama, app_handle).await
-        }
-        "deepseek" => {
-            println!("使用 DeepSeek 模型进行聊天");
-            chat_with_deepseek(enhanced_message, config.deepseek, app_handle).await
-        }
-        "kimi" => {
-            println!("使用 Kimi 模型进行聊天");
-            chat_with_kimi(enhanced_message, config.kimi, app_handle).await
-        }
-        _ => {
-            println!("❌ 未知模型类型：{}", config.selected_model);
-            Err("Unknown model type".to_string())
-        }
-    }
-}
 
-async fn translate_with_ollama(text: String, config: OllamaConfig, app_handle: tauri::AppHandle) -> Result<String, String> {
-    let client = reqwest::Client::new();
-    let request = OllamaRequest {
-        model: config.model,
-        prompt: format!(
-            "请将以下英文文本翻译成中文，保持专业术语不变。\
-             {}",
-            text
-        ),
-        stream: true,
-        think: false,
-        num_predict: Some(1000), // 限制响应长度
+    let request = CompletionRequest {
+        system,
+        prompt: message,
+        images: Vec::new(),
+        show_reasoning: show_reasoning.unwrap_or(false),
+        priority: priority.unwrap_or_default(),
     };
-
-    let url = format!("{}/api/generate", config.base_url);
-    println!("[translate_with_ollama] 请求 URL: {}", url);
-
-    match client.post(&url).json(&request).send().await {
-        Ok(mut resp) if resp.status().is_success() => {
-            let mut full_response = String::new();
-            
-            while let Some(chunk) = resp.chunk().await.map_err(|e| format!("Failed to read chunk: {}", e))? {
-                let chunk_str = String::from_utf8_lossy(&chunk);
-                
-                for line in chunk_str.lines() {
-                    if line.trim().is_empty() {
-                        continue;
-                    }
-                    
-                    match serde_json::from_str::<OllamaStreamResponse>(line) {
-                        Ok(stream_resp) => {
-                            let re = Regex::new(r"(?s)<think>\s*.*?\s*</think>|\s*/no_think\s*/think\s*$|\s*/think\s*$").unwrap();
-                            let cleaned = re.replace_all(&stream_resp.response, "").trim().to_string();
-                            
-                            if !cleaned.is_empty() {
-                                full_response.push_str(&cleaned);
-                                
-                                if let Some(window) = app_handle.get_webview_window("main") {
-                                    let _ = window.emit("translation_chunk", &cleaned);
-                                } else if let Some(window) = app_handle.webview_windows().values().next() {
-                                    let _ = window.emit("translation_chunk", &cleaned);
-                                }
-                            }
-                            
-                            if stream_resp.done {
-                                if let Some(window) = app_handle.get_webview_window("main") {
-                                    let _ = window.emit("translation_complete", &full_response);
-                                } else if let Some(window) = app_handle.webview_windows().values().next() {
-                                    let _ = window.emit("translation_complete", &full_response);
-                                }
-                                return Ok(full_response);
-                            }
-                        }
-                        Err(e) => {
-                            println!("[translate_with_ollama] JSON 解析错误: {}, 行: {}", e, line);
-                        }
-                    }
-                }
-            }
-            
-            Ok(full_response)
-        }
-        Ok(resp) => {
-            println!("[translate_with_ollama] ❌ 非 2xx 状态：{}", resp.status());
-            Err("Ollama translation failed".to_string())
-        }
-        Err(e) => {
-            println!("[translate_with_ollama] ❌ 网络错误: {}", e);
-            Err(format!("Network error: {}", e))
-        }
-    }
+    run_completion(&config, &request, &CHAT_EVENTS, document_id.as_deref(), &app_handle).await
 }
 
-async fn translate_with_deepseek(text: String, config: DeepSeekConfig, app_handle: tauri::AppHandle) -> Result<String, String> {
-    if config.api_key.is_empty() {
-        return Err("DeepSeek API key is required".to_string());
+// 文献库中文档的标题、摘要、章节列表和正文节选，正文按 CHAT_CONTEXT_TOKENS 截断
+fn paper_context(app_handle: &tauri::AppHandle, document_id: &str, model: &str) -> Result<String, String> {
+    let storage = app_handle.state::<Storage>();
+    let cache = app_handle.state::<PdfTextCache>();
+    let entry = library::find_entry(&storage, document_id)?
+        .ok_or_else(|| format!("Document not in library: {}", document_id))?;
+    let path = app_handle.state::<FileScope>().resolve_pdf(&entry.path)?;
+    let outline = outline::document_outline(&cache, &path)?;
+    let pages = ocr::document_text(&storage, &cache, &path, false)?;
+
+    let mut context = String::new();
+    if let Some(title) = outline.title.as_deref().or(entry.title.as_deref()) {
+        context.push_str(&format!("标题：{}\n", title));
     }
-
-    let client = reqwest::Client::new();
-    let request = DeepSeekRequest {
-        model: config.model,
-        messages: vec![
-            DeepSeekMessage {
-                role: "system".to_string(),
-                content: "你是一个专业的翻译助手，请将英文文本准确翻译成中文，保持专业术语不变。".to_string(),
-            },
-            DeepSeekMessage {
-                role: "user".to_string(),
-                content: text,
-            },
-        ],
-        stream: true,
-    };
-
-    let url = format!("{}/chat/completions", config.base_url);
-    println!("[translate_with_deepseek] 请求 URL: {}", url);
-
-    match client
-        .post(&url)
-        .header("Authorization", format!("Bearer {}", config.api_key))
-        .header("Content-Type", "application/json")
-        .json(&request)
-        .send()
-        .await
-    {
-        Ok(mut resp) if resp.status().is_success() => {
-            let mut full_response = String::new();
-            
-            while let Some(chunk) = resp.chunk().await.map_err(|e| format!("Failed to read chunk: {}", e))? {
-                let chunk_str = String::from_utf8_lossy(&chunk);
-                
-                for line in chunk_str.lines() {
-                    if line.trim().is_empty() || !line.starts_with("data: ") {
-                        continue;
-                    }
-                    
-                    let data_line = &line[6..]; // Remove "data: " prefix
-                    if data_line.trim() == "[DONE]" {
-                        if let Some(window) = app_handle.get_webview_window("main") {
-                            let _ = window.emit("translation_complete", &full_response);
-                        } else if let Some(window) = app_handle.webview_windows().values().next() {
-                            let _ = window.emit("translation_complete", &full_response);
-                        }
-                        return Ok(full_response);
-                    }
-                    
-                    match serde_json::from_str::<serde_json::Value>(data_line) {
-                        Ok(json) => {
-                            if let Some(choices) = json.get("choices").and_then(|c| c.as_array()) {
-                                if let Some(choice) = choices.first() {
-                                    if let Some(delta) = choice.get("delta") {
-                                        if let Some(content) = delta.get("content").and_then(|c| c.as_str()) {
-                                            if !content.is_empty() {
-                                                full_response.push_str(content);
-                                                
-                                                if let Some(window) = app_handle.get_webview_window("main") {
-                                                    let _ = window.emit("translation_chunk", content);
-                                                } else if let Some(window) = app_handle.webview_windows().values().next() {
-                                                    let _ = window.emit("translation_chunk", content);
-                                                }
-                                            }
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                        Err(e) => {
-                            println!("[translate_with_deepseek] JSON 解析错误: {}, 行: {}", e, data_line);
-                        }
-                    }
-                }
-            }
-            
-            Ok(full_response)
-        }
-        Ok(resp) => {
-            println!("[translate_with_deepseek] ❌ 非 2xx 状态：{}", resp.status());
-            Err("DeepSeek translation failed".to_string())
-        }
-        Err(e) => {
-            println!("[translate_with_deepseek] ❌ 网络错误: {}", e);
-            Err(format!("Network error: {}", e))
+    if let Some(abstract_text) = &outline.abstract_text {
+        context.push_str(&format!("摘要：{}\n", abstract_text));
+    }
+    if !outline.sections.is_empty() {
+        context.push_str("章节：\n");
+        let mut stack: Vec<&outline::OutlineEntry> = outline.sections.iter().rev().collect();
+        while let Some(section) = stack.pop() {
+            let indent = "  ".repeat(section.level.saturating_sub(1) as usize);
+            context.push_str(&format!("{}{}（第 {} 页）\n", indent, section.title, section.page));
+            stack.extend(section.children.iter().rev());
         }
     }
-}
 
-async fn translate_with_kimi(text: String, config: KimiConfig, app_handle: tauri::AppHandle) -> Result<String, String> {
-    if config.api_key.is_empty() {
-        return Err("Kimi API key is required".to_string());
+    // 正文从头开始，到参考文献为止，超出预算的部分截断
+    let end_page = outline.references.as_ref().map_or(u32::MAX, |r| r.page);
+    let mut remaining = CHAT_CONTEXT_TOKENS.saturating_sub(usage::estimate_tokens_for(model, &context));
+    let mut body = String::new();
+    for page in pages.iter().filter(|p| p.page <= end_page) {
+        let tokens = usage::estimate_tokens_for(model, &page.text);
+        if tokens > remaining {
+            let keep = (page.text.chars().count() as u64 * remaining / tokens.max(1)) as usize;
+            body.extend(page.text.chars().take(keep));
+            body.push_str("\n……（后文省略）");
+            break;
+        }
+        remaining -= tokens;
+        body.push_str(&page.text);
+        body.push('\n');
     }
-
-    let client = reqwest::Client::new();
-    let request = KimiRequest {
-        model: config.model,
-        messages: vec![
-            KimiMessage {
-                role: "system".to_string(),
-                content: "你是一个专业的翻译助手，请将英文文本准确翻译成中文，保持专业术语不变。".to_string(),
-            },
-            KimiMessage {
-                role: "user".to_string(),
-                content: text,
-            },
-        ],
-        stream: true,
-    };
-
-    let url = format!("{}/v1/chat/completions", config.base_url);
-    println!("[translate_with_kimi] 请求 URL: {}", url);
-
-    match client
-        .post(&url)
-        .header("Authorization", format!("Bearer {}", config.api_key))
-        .header("Content-Type", "application/json")
-        .json(&request)
-        .send()
-        .await
-    {
-        Ok(mut resp) if resp.status().is_success() => {
-            let mut full_response = String::new();
-            
-            while let Some(chunk) = resp.chunk().await.map_err(|e| format!("Failed to read chunk: {}", e))? {
-                let chunk_str = String::from_utf8_lossy(&chunk);
-                
-                for line in chunk_str.lines() {
-                    if line.trim().is_empty() || !line.starts_with("data: ") {
-                        continue;
-                    }
-                    
-                    let data_line = &line[6..]; // Remove "data: " prefix
-                    if data_line.trim() == "[DONE]" {
-                        if let Some(window) = app_handle.get_webview_window("main") {
-                            let _ = window.emit("translation_complete", &full_response);
-                        } else if let Some(window) = app_handle.webview_windows().values().next() {
-                            let _ = window.emit("translation_complete", &full_response);
-                        }
-                        return Ok(full_response);
-                    }
-                    
-                    match serde_json::from_str::<serde_json::Value>(data_line) {
-                        Ok(json) => {
-                            if let Some(choices) = json.get("choices").and_then(|c| c.as_array()) {
-                                if let Some(choice) = choices.first() {
-                                    if let Some(delta) = choice.get("delta") {
-                                        if let Some(content) = delta.get("content").and_then(|c| c.as_str()) {
-                                            if !content.is_empty() {
-                                                full_response.push_str(content);
-                                                
-                                                if let Some(window) = app_handle.get_webview_window("main") {
-                                                    let _ = window.emit("translation_chunk", content);
-                                                } else if let Some(window) = app_handle.webview_windows().values().next() {
-                                                    let _ = window.emit("translation_chunk", content);
-                                                }
-                                            }
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                        Err(e) => {
-                            println!("[translate_with_kimi] JSON 解析错误: {}, 行: {}", e, data_line);
-                        }
-                    }
-                }
-            }
-            
-            Ok(full_response)
-        }
-        Ok(resp) => {
-            println!("[translate_with_kimi] ❌ 非 2xx 状态：{}", resp.status());
-            Err("Kimi translation failed".to_string())
-        }
-        Err(e) => {
-            println!("[translate_with_kimi] ❌ 网络错误: {}", e);
-            Err(format!("Network error: {}", e))
-        }
+    if !body.trim().is_empty() {
+        context.push_str(&format!("正文：\n{}", body.trim_end()));
     }
+    Ok(context)
 }
 
-// 聊天功能实现
-async fn chat_with_ollama(message: String, config: OllamaConfig, app_handle: tauri::AppHandle) -> Result<String, String> {
-    let client = reqwest::Client::new();
-    let request = OllamaRequest {
-        model: config.model,
-        prompt: format!(
-            "你是一个专业的学术助手，请帮助用户解答关于论文的问题。\
-             用户的问题：{}",
-            message
-        ),
-        stream: true,
-        think: false,
-        num_predict: Some(1000), // 限制响应长度
-    };
-
-    let url = format!("{}/api/generate", config.base_url);
-    println!("[chat_with_ollama] 请求 URL: {}", url);
-
-    match client.post(&url).json(&request).send().await {
-        Ok(mut resp) if resp.status().is_success() => {
-            let mut full_response = String::new();
-            
-            while let Some(chunk) = resp.chunk().await.map_err(|e| format!("Failed to read chunk: {}", e))? {
-                let chunk_str = String::from_utf8_lossy(&chunk);
-                
-                for line in chunk_str.lines() {
-                    if line.trim().is_empty() {
-                        continue;
-                    }
-                    
-                    match serde_json::from_str::<OllamaStreamResponse>(line) {
-                        Ok(stream_resp) => {
-                            let re = Regex::new(r"(?s)<think>\s*.*?\s*</think>|\s*/no_think\s*/think\s*$|\s*/think\s*$").unwrap();
-                            let cleaned = re.replace_all(&stream_resp.response, "").trim().to_string();
-                            
-                            if !cleaned.is_empty() {
-                                full_response.push_str(&cleaned);
-                                
-                                // 发送聊天流式更新
-                                if let Some(window) = app_handle.get_webview_window("main") {
-                                    let _ = window.emit("chat_chunk", &cleaned);
-                                } else if let Some(window) = app_handle.webview_windows().values().next() {
-                                    let _ = window.emit("chat_chunk", &cleaned);
-                                }
-                            }
-                            
-                            if stream_resp.done {
-                                if let Some(window) = app_handle.get_webview_window("main") {
-                                    let _ = window.emit("chat_complete", &full_response);
-                                } else if let Some(window) = app_handle.webview_windows().values().next() {
-                                    let _ = window.emit("chat_complete", &full_response);
-                                }
-                                return Ok(full_response);
-                            }
-                        }
-                        Err(e) => {
-                            println!("[chat_with_ollama] JSON 解析错误: {}, 行: {}", e, line);
-                        }
-                    }
-                }
-            }
-            
-            Ok(full_response)
-        }
-        Ok(resp) => {
-            println!("[chat_with_ollama] ❌ 非 2xx 状态：{}", resp.status());
-            Err("Ollama chat failed".to_string())
-        }
-        Err(e) => {
-            println!("[chat_with_ollama] ❌ 网络错误: {}", e);
-            Err(format!("Network error: {}", e))
-        }
-    }
+// 按配置选择模型服务并把流式输出转发成前端事件
+async fn run_completion(
+    config: &ModelConfig,
+    request: &CompletionRequest,
+    events: &StreamEvents,
+    document_id: Option<&str>,
+    app_handle: &tauri::AppHandle,
+) -> Result<Completion, String> {
+    let completion = stream_and_record(config, request, events, document_id, app_handle).await?;
+    emit_to_window(app_handle, events.complete, &completion.content);
+    Ok(completion)
 }
 
-async fn chat_with_deepseek(message: String, config: DeepSeekConfig, app_handle: tauri::AppHandle) -> Result<String, String> {
-    if config.api_key.is_empty() {
-        return Err("DeepSeek API key is required".to_string());
-    }
-
-    let client = reqwest::Client::new();
-    let request = DeepSeekRequest {
-        model: config.model,
-        messages: vec![
-            DeepSeekMessage {
-                role: "system".to_string(),
-                content: "你是一个专业的学术助手，请帮助用户解答关于论文的问题。".to_string(),
-            },
-            DeepSeekMessage {
-                role: "user".to_string(),
-                content: message,
-            },
-        ],
-        stream: true,
+// 流式推送片段并记录用量，不发送完成事件
+async fn stream_and_record(
+    config: &ModelConfig,
+    request: &CompletionRequest,
+    events: &StreamEvents,
+    document_id: Option<&str>,
+    app_handle: &tauri::AppHandle,
+) -> Result<Completion, String> {
+    println!("使用模型服务顺序：{:?}", config.provider_chain());
+
+    let mut on_delta = |delta: StreamDelta| match delta {
+        StreamDelta::Content(text) => emit_to_window(app_handle, events.chunk, text),
+        StreamDelta::Reasoning(text) => emit_to_window(app_handle, events.reasoning, text),
+        StreamDelta::Fallback { from, to, reason } => {
+            println!("⚠️ {} 调用失败（{}），改用 {}", from, reason, to);
+            emit_to_window(
+                app_handle,
+                "provider_fallback",
+                serde_json::json!({ "from": from, "to": to, "reason": reason }),
+            );
+        }
     };
+    let scheduler = app_handle.state::<Scheduler>();
+    let completion = complete_with_fallback(config, request, &scheduler, &mut on_delta).await?;
 
-    let url = format!("{}/chat/completions", config.base_url);
-    println!("[chat_with_deepseek] 请求 URL: {}", url);
-
-    match client
-        .post(&url)
-        .header("Authorization", format!("Bearer {}", config.api_key))
-        .header("Content-Type", "application/json")
-        .json(&request)
-        .send()
-        .await
-    {
-        Ok(mut resp) if resp.status().is_success() => {
-            let mut full_response = String::new();
-            
-            while let Some(chunk) = resp.chunk().await.map_err(|e| format!("Failed to read chunk: {}", e))? {
-                let chunk_str = String::from_utf8_lossy(&chunk);
-                
-                for line in chunk_str.lines() {
-                    if line.trim().is_empty() || !line.starts_with("data: ") {
-                        continue;
-                    }
-                    
-                    let data_line = &line[6..]; // Remove "data: " prefix
-                    if data_line.trim() == "[DONE]" {
-                        if let Some(window) = app_handle.get_webview_window("main") {
-                            let _ = window.emit("chat_complete", &full_response);
-                        } else if let Some(window) = app_handle.webview_windows().values().next() {
-                            let _ = window.emit("chat_complete", &full_response);
-                        }
-                        return Ok(full_response);
-                    }
-                    
-                    match serde_json::from_str::<serde_json::Value>(data_line) {
-                        Ok(json) => {
-                            if let Some(choices) = json.get("choices").and_then(|c| c.as_array()) {
-                                if let Some(choice) = choices.first() {
-                                    if let Some(delta) = choice.get("delta") {
-                                        if let Some(content) = delta.get("content").and_then(|c| c.as_str()) {
-                                            if !content.is_empty() {
-                                                full_response.push_str(content);
-                                                
-                                                if let Some(window) = app_handle.get_webview_window("main") {
-                                                    let _ = window.emit("chat_chunk", content);
-                                                } else if let Some(window) = app_handle.webview_windows().values().next() {
-                                                    let _ = window.emit("chat_chunk", content);
-                                                }
-                                            }
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                        Err(e) => {
-                            println!("[chat_with_deepseek] JSON 解析错误: {}, 行: {}", e, data_line);
-                        }
-                    }
-                }
-            }
-            
-            Ok(full_response)
-        }
-        Ok(resp) => {
-            println!("[chat_with_deepseek] ❌ 非 2xx 状态：{}", resp.status());
-            Err("DeepSeek chat failed".to_string())
-        }
-        Err(e) => {
-            println!("[chat_with_deepseek] ❌ 网络错误: {}", e);
-            Err(format!("Network error: {}", e))
-        }
-    }
+    usage::record_completion(&app_handle.state::<Storage>(), document_id, events.kind, &completion);
+    Ok(completion)
 }
 
-async fn chat_with_kimi(message: String, config: KimiConfig, app_handle: tauri::AppHandle) -> Result<String, String> {
-    if config.api_key.is_empty() {
-        return Err("Kimi API key is required".to_string());
+// 长选区分段依次翻译：各段片段连续推送到 translation_chunk，全部完成后拼接成完整译文
+async fn translate_segments(
+    config: &ModelConfig,
+    segments: &[segment::Segment],
+    show_reasoning: bool,
+    priority: Priority,
+    document_id: Option<&str>,
+    app_handle: &tauri::AppHandle,
+) -> Result<Completion, String> {
+    let storage = app_handle.state::<Storage>();
+    let model = segment::tokenizer_model(config);
+    let mut result = Completion::default();
+    let mut total = usage::Usage::default();
+
+    for (index, segment) in segments.iter().enumerate() {
+        // 每段只带与本段相似的翻译记忆示例
+        let examples = translation_memory::few_shot_prompt(&storage, &segment.text, &model);
+        let request = CompletionRequest {
+            system: format!("{}{}", TRANSLATE_SYSTEM_PROMPT, examples),
+            prompt: segment.text.clone(),
+            images: Vec::new(),
+            show_reasoning,
+            priority,
+        };
+        if index > 0 {
+            emit_to_window(app_handle, TRANSLATION_EVENTS.chunk, segment.separator);
+        }
+        let part = stream_and_record(config, &request, &TRANSLATION_EVENTS, document_id, app_handle)
+            .await
+            .map_err(|e| format!("Segment {}/{} failed: {}", index + 1, segments.len(), e))?;
+
+        if index > 0 {
+            result.content.push_str(segment.separator);
+        }
+        result.content.push_str(part.content.trim());
+        result.reasoning.push_str(&part.reasoning);
+        if let Some(used) = &part.usage {
+            total.prompt_tokens += used.prompt_tokens;
+            total.completion_tokens += used.completion_tokens;
+            total.estimated |= used.estimated;
+        }
+        // 任何一段被截断都算整体被截断
+        if result.finish_reason.as_deref() != Some("length") {
+            result.finish_reason = part.finish_reason;
+        }
+        result.provider = part.provider;
+        result.model = part.model;
+        emit_to_window(
+            app_handle,
+            "translation_progress",
+            serde_json::json!({ "done": index + 1, "total": segments.len() }),
+        );
     }
 
-    let client = reqwest::Client::new();
-    let request = KimiRequest {
-        model: config.model,
-        messages: vec![
-            KimiMessage {
-                role: "system".to_string(),
-                content: "你是一个专业的学术助手，请帮助用户解答关于论文的问题。".to_string(),
-            },
-            KimiMessage {
-                role: "user".to_string(),
-                content: message,
-            },
-        ],
-        stream: true,
-    };
-
-    let url = format!("{}/v1/chat/completions", config.base_url);
-    println!("[chat_with_kimi] 请求 URL: {}", url);
-
-    match client
-        .post(&url)
-        .header("Authorization", format!("Bearer {}", config.api_key))
-        .header("Content-Type", "application/json")
-        .json(&request)
-        .send()
-        .await
-    {
-        Ok(mut resp) if resp.status().is_success() => {
-            let mut full_response = String::new();
-            
-            while let Some(chunk) = resp.chunk().await.map_err(|e| format!("Failed to read chunk: {}", e))? {
-                let chunk_str = String::from_utf8_lossy(&chunk);
-                
-                for line in chunk_str.lines() {
-                    if line.trim().is_empty() || !line.starts_with("data: ") {
-                        continue;
-                    }
-                    
-                    let data_line = &line[6..]; // Remove "data: " prefix
-                    if data_line.trim() == "[DONE]" {
-                        if let Some(window) = app_handle.get_webview_window("main") {
-                            let _ = window.emit("chat_complete", &full_response);
-                        } else if let Some(window) = app_handle.webview_windows().values().next() {
-                            let _ = window.emit("chat_complete", &full_response);
-                        }
-                        return Ok(full_response);
-                    }
-                    
-                    match serde_json::from_str::<serde_json::Value>(data_line) {
-                        Ok(json) => {
-                            if let Some(choices) = json.get("choices").and_then(|c| c.as_array()) {
-                                if let Some(choice) = choices.first() {
-                                    if let Some(delta) = choice.get("delta") {
-                                        if let Some(content) = delta.get("content").and_then(|c| c.as_str()) {
-                                            if !content.is_empty() {
-                                                full_response.push_str(content);
-                                                
-                                                if let Some(window) = app_handle.get_webview_window("main") {
-                                                    let _ = window.emit("chat_chunk", content);
-                                                } else if let Some(window) = app_handle.webview_windows().values().next() {
-                                                    let _ = window.emit("chat_chunk", content);
-                                                }
-                                            }
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                        Err(e) => {
-                            println!("[chat_with_kimi] JSON 解析错误: {}, 行: {}", e, data_line);
-                        }
-                    }
-                }
-            }
-            
-            Ok(full_response)
-        }
-        Ok(resp) => {
-            println!("[chat_with_kimi] ❌ 非 2xx 状态：{}", resp.status());
-            Err("Kimi chat failed".to_string())
-        }
-        Err(e) => {
-            println!("[chat_with_kimi] ❌ 网络错误: {}", e);
-            Err(format!("Network error: {}", e))
-        }
-    }
+    result.usage = Some(total);
+    emit_to_window(app_handle, TRANSLATION_EVENTS.complete, &result.content);
+    Ok(result)
 }
 
 fn main() {
     tauri::Builder::default()
         .plugin(tauri_plugin_dialog::init())
+        .manage(Scheduler::default())
+        .manage(PdfTextCache::default())
+        .manage(ReferenceCache::default())
+        .setup(|app| {
+            let data_dir = app.path().app_data_dir()?;
+            std::fs::create_dir_all(&data_dir)?;
+            let storage = Storage::open(&data_dir.join("papertalk.db"))?;
+            usage::seed_default_prices(&storage)?;
+            let file_scope = FileScope::load(&storage)?;
+            app.manage(storage);
+            app.manage(file_scope);
+            Ok(())
+        })
+        .register_asynchronous_uri_scheme_protocol(pdf_protocol::SCHEME, |ctx, request, responder| {
+            // 读文件放到阻塞线程池，避免卡住 webview
+            let app_handle = ctx.app_handle().clone();
+            tauri::async_runtime::spawn_blocking(move || {
+                let scope = app_handle.state::<FileScope>();
+                responder.respond(pdf_protocol::handle(&scope, &request));
+            });
+        })
+        .on_window_event(|window, event| {
+            // 拖拽到窗口上的 PDF 视为用户授权
+            if let WindowEvent::DragDrop(DragDropEvent::Drop { paths, .. }) = event {
+                let app_handle = window.app_handle();
+                let scope = app_handle.state::<FileScope>();
+                let storage = app_handle.state::<Storage>();
+                let accepted = file_scope::allow_dropped(&scope, &storage, paths);
+                if !accepted.is_empty() {
+                    emit_to_window(app_handle, "pdf_dropped", &accepted);
+                }
+            }
+        })
         .invoke_handler(tauri::generate_handler![
             check_ollama,
             translate_text,
@@ -870,7 +558,65 @@ fn main() {
             open_ollama_website,
             get_pdf_data,
             translate_with_config,
-            chat_with_config
+            chat_with_config,
+            selection::run_selection_action,
+            compare::compare_translations,
+            compare::pick_translation,
+            translation_memory::lookup_translation_memory,
+            translation_memory::add_translation_memory,
+            translation_memory::delete_translation_memory,
+            translation_memory::export_tmx,
+            translation_memory::import_tmx,
+            vision::translate_region,
+            equations::explain_equation,
+            summary::summarize_document,
+            summary::list_summaries,
+            usage::get_usage_report,
+            usage::get_price_table,
+            usage::set_model_price,
+            usage::delete_model_price,
+            file_scope::open_pdf_dialog,
+            file_scope::choose_library_folder,
+            file_scope::list_library_folders,
+            file_scope::remove_library_folder,
+            pdf_text::get_page_text,
+            ocr::ocr_status,
+            outline::get_document_outline,
+            references::get_references,
+            references::resolve_citation,
+            references::get_citation_links,
+            references::export_references_bibtex,
+            library::open_document,
+            library::list_documents,
+            library::recent_documents,
+            library::search_documents,
+            library::remove_document,
+            library::save_reading_position,
+            library::locate_missing_documents,
+            search::search_library,
+            search::index_library,
+            annotations::create_highlight,
+            annotations::update_highlight,
+            annotations::delete_highlight,
+            annotations::create_note,
+            annotations::update_note,
+            annotations::delete_note,
+            annotations::list_annotations,
+            annotations::list_notes_by_tag,
+            annotations::list_tags,
+            pdf_annotations::export_annotated_pdf,
+            pdf_annotations::import_pdf_annotations,
+            translations::list_translations,
+            glossary::add_glossary_term,
+            glossary::list_glossary,
+            glossary::delete_glossary_term,
+            glossary::get_glossary_hits,
+            export::export_markdown,
+            export::export_anki_deck,
+            library::list_collections,
+            library::collection_documents,
+            zotero::import_reference_library,
+            zotero::export_zotero_notes
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -0,0 +1,642 @@
+// OCR：扫描版 PDF 没有文本层时，调用本机安装的 Tesseract 识别页面文字
+//
+// 页面图像优先用 poppler 的 pdftoppm 按 300 DPI 渲染；没有 pdftoppm 时直接取出
+// 页面上最大的一张图片（扫描件通常整页就是一张图）。识别结果换算成与 PdfTextCache
+// 相同的坐标（PDF 点，左上角原点），按页存进数据库，文件修改后重新识别。
+
+use lopdf::{Document, Object};
+use rusqlite::{params, OptionalExtension};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, LazyLock};
+
+use crate::library;
+use crate::pdf_text::{self, PageText, PdfTextCache, TextSpan};
+use crate::storage::{now_secs, Storage};
+use crate::text::is_cjk_or_fullwidth;
+
+pub const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS ocr_pages (
+    path TEXT NOT NULL,
+    file_size INTEGER NOT NULL,
+    file_mtime INTEGER NOT NULL,
+    page INTEGER NOT NULL,
+    data TEXT NOT NULL,
+    created_at INTEGER NOT NULL,
+    PRIMARY KEY (path, page)
+);
+";
+
+// 按顺序尝试的识别语言，实际使用已安装的那部分
+const LANGUAGES: &[&str] = &["chi_sim", "eng"];
+const RENDER_DPI: u32 = 300;
+// 置信度低于这个值的词多半是噪点
+const MIN_CONFIDENCE: f32 = 30.0;
+
+#[derive(Debug, Clone)]
+struct OcrEngine {
+    tesseract: PathBuf,
+    languages: Vec<String>,
+    pdftoppm: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct OcrStatus {
+    pub available: bool,
+    pub tesseract: Option<String>,
+    pub languages: Vec<String>,
+    pub renderer: Option<String>,
+}
+
+static ENGINE: LazyLock<Option<OcrEngine>> = LazyLock::new(detect_engine);
+
+/* ---------------------------------
+ * 查找 OCR 引擎
+ * --------------------------------- */
+
+fn find_executable(name: &str) -> Option<PathBuf> {
+    let file_name = if cfg!(target_os = "windows") {
+        format!("{}.exe", name)
+    } else {
+        name.to_string()
+    };
+    let path = std::env::var_os("PATH")?;
+    std::env::split_paths(&path)
+        .map(|dir| dir.join(&file_name))
+        .find(|candidate| candidate.is_file())
+}
+
+fn find_tesseract() -> Option<PathBuf> {
+    // 允许用环境变量指定安装位置
+    if let Some(path) = std::env::var_os("PAPERTALK_TESSERACT").map(PathBuf::from) {
+        if path.is_file() {
+            return Some(path);
+        }
+        println!("⚠️ PAPERTALK_TESSERACT 指向的文件不存在: {}", path.display());
+    }
+    if let Some(path) = find_executable("tesseract") {
+        return Some(path);
+    }
+    // Windows 安装包默认不加入 PATH
+    let default = PathBuf::from(r"C:\Program Files\Tesseract-OCR\tesseract.exe");
+    (cfg!(target_os = "windows") && default.is_file()).then_some(default)
+}
+
+fn installed_languages(tesseract: &Path) -> Vec<String> {
+    let Ok(output) = Command::new(tesseract).arg("--list-langs").output() else {
+        return Vec::new();
+    };
+    // 第一行是 "List of available languages ..."，旧版本输出在 stderr
+    let listing = format!(
+        "{}\n{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let installed: Vec<&str> = listing.lines().map(str::trim).collect();
+    LANGUAGES
+        .iter()
+        .filter(|lang| installed.contains(lang))
+        .map(|lang| lang.to_string())
+        .collect()
+}
+
+fn detect_engine() -> Option<OcrEngine> {
+    let Some(tesseract) = find_tesseract() else {
+        println!("ℹ️ 未找到 Tesseract，扫描版 PDF 将没有可选中的文字");
+        return None;
+    };
+    let languages = installed_languages(&tesseract);
+    if languages.is_empty() {
+        println!("⚠️ Tesseract 未安装 chi_sim 或 eng 语言包: {}", tesseract.display());
+        return None;
+    }
+    let pdftoppm = find_executable("pdftoppm");
+    println!(
+        "🔤 OCR 引擎: {} ({})，页面渲染: {}",
+        tesseract.display(),
+        languages.join("+"),
+        pdftoppm.as_ref().map_or("内嵌图片".to_string(), |p| p.display().to_string())
+    );
+    Some(OcrEngine {
+        tesseract,
+        languages,
+        pdftoppm,
+    })
+}
+
+/* ---------------------------------
+ * 页面图像
+ * --------------------------------- */
+
+// 待识别的图像文件，以及从图像像素到页面坐标的缩放比例
+struct PageImage {
+    path: PathBuf,
+    dpi: u32,
+    scale_x: f32,
+    scale_y: f32,
+}
+
+// 临时文件，识别完成后删除
+struct TempFiles(Vec<PathBuf>);
+
+impl Drop for TempFiles {
+    fn drop(&mut self) {
+        for path in &self.0 {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+fn temp_path(page: u32, extension: &str) -> PathBuf {
+    static SEQ: AtomicUsize = AtomicUsize::new(0);
+    std::env::temp_dir().join(format!(
+        "papertalk-ocr-{}-{}-{}.{}",
+        std::process::id(),
+        SEQ.fetch_add(1, Ordering::Relaxed),
+        page,
+        extension
+    ))
+}
+
+fn render_with_pdftoppm(
+    pdftoppm: &Path,
+    path: &Path,
+    page: u32,
+    temp: &mut TempFiles,
+) -> Result<PageImage, String> {
+    let out = temp_path(page, "render");
+    let page_arg = page.to_string();
+    let output = Command::new(pdftoppm)
+        .args(["-f", &page_arg, "-l", &page_arg, "-r", &RENDER_DPI.to_string()])
+        .args(["-gray", "-singlefile", "-png"])
+        .arg(path)
+        .arg(&out)
+        .output()
+        .map_err(|e| format!("Failed to run pdftoppm: {}", e))?;
+    // pdftoppm 自己加扩展名
+    let png = out.with_extension("png");
+    temp.0.push(png.clone());
+    if !output.status.success() || !png.is_file() {
+        return Err(format!(
+            "pdftoppm failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    let scale = 72.0 / RENDER_DPI as f32;
+    Ok(PageImage {
+        path: png,
+        dpi: RENDER_DPI,
+        scale_x: scale,
+        scale_y: scale,
+    })
+}
+
+fn filters(doc: &Document, dict: &lopdf::Dictionary) -> Vec<Vec<u8>> {
+    match pdf_text::dict_get(doc, dict, b"Filter") {
+        Some(Object::Name(name)) => vec![name.clone()],
+        Some(Object::Array(items)) => items
+            .iter()
+            .filter_map(|o| pdf_text::resolve(doc, o).as_name().ok().map(|n| n.to_vec()))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// 把图片 XObject 转成 Tesseract 能读的文件，返回扩展名与内容
+fn encode_image(doc: &Document, stream: &lopdf::Stream, width: usize, height: usize) -> Option<(&'static str, Vec<u8>)> {
+    let dict = &stream.dict;
+    let filters = filters(doc, dict);
+    match filters.last().map(Vec::as_slice) {
+        Some(b"DCTDecode") if filters.len() == 1 => return Some(("jpg", stream.content.clone())),
+        Some(b"JPXDecode") if filters.len() == 1 => return Some(("jp2", stream.content.clone())),
+        // CCITT、JBIG2 等传真编码需要 pdftoppm
+        Some(b"CCITTFaxDecode") | Some(b"JBIG2Decode") | Some(b"DCTDecode") | Some(b"JPXDecode") => return None,
+        _ => {}
+    }
+
+    let data = pdf_text::stream_content(stream)?;
+    let bits = pdf_text::dict_get(doc, dict, b"BitsPerComponent")
+        .and_then(|o| o.as_i64().ok())
+        .unwrap_or(1);
+    let header = |magic: &str| format!("{}\n{} {}\n", magic, width, height).into_bytes();
+
+    match bits {
+        8 if data.len() >= width * height * 3 => {
+            let mut out = header("P6");
+            out.extend_from_slice(b"255\n");
+            out.extend_from_slice(&data[..width * height * 3]);
+            Some(("ppm", out))
+        }
+        8 if data.len() >= width * height => {
+            let mut out = header("P5");
+            out.extend_from_slice(b"255\n");
+            out.extend_from_slice(&data[..width * height]);
+            Some(("pgm", out))
+        }
+        1 if data.len() >= width.div_ceil(8) * height => {
+            // PDF 里 0 是黑，PBM 里 1 是黑；/Decode [1 0] 时正好相反
+            let inverted = pdf_text::dict_get(doc, dict, b"Decode")
+                .and_then(|o| o.as_array().ok())
+                .and_then(|a| a.first())
+                .and_then(|o| o.as_float().ok())
+                .is_some_and(|v| v >= 1.0);
+            let mut out = header("P4");
+            out.extend(
+                data[..width.div_ceil(8) * height]
+                    .iter()
+                    .map(|b| if inverted { *b } else { !*b }),
+            );
+            Some(("pbm", out))
+        }
+        _ => None,
+    }
+}
+
+/// 取出页面上面积最大的图片，认为它铺满整页
+fn extract_page_image(doc: &Document, page: u32, temp: &mut TempFiles) -> Result<PageImage, String> {
+    let pages = doc.get_pages();
+    let page_id = *pages.get(&page).ok_or_else(|| format!("Page {} out of range", page))?;
+    let media_box = pdf_text::page_media_box(doc, page_id);
+
+    let xobjects = pdf_text::inherited(doc, page_id, b"Resources")
+        .and_then(|o| o.as_dict().ok())
+        .and_then(|r| pdf_text::dict_get(doc, r, b"XObject"))
+        .and_then(|o| o.as_dict().ok())
+        .ok_or("No images on page (install pdftoppm to OCR vector pages)")?;
+
+    let size = |doc: &Document, stream: &lopdf::Stream| {
+        let get = |key: &[u8]| {
+            pdf_text::dict_get(doc, &stream.dict, key)
+                .and_then(|o| o.as_i64().ok())
+                .unwrap_or(0)
+                .max(0) as usize
+        };
+        (get(b"Width"), get(b"Height"))
+    };
+    let image = xobjects
+        .iter()
+        .filter_map(|(_, o)| pdf_text::resolve(doc, o).as_stream().ok())
+        .filter(|s| {
+            pdf_text::dict_get(doc, &s.dict, b"Subtype").and_then(|o| o.as_name().ok()) == Some(b"Image".as_slice())
+        })
+        .max_by_key(|s| {
+            let (w, h) = size(doc, s);
+            w * h
+        })
+        .ok_or("No images on page")?;
+
+    let (width, height) = size(doc, image);
+    if width == 0 || height == 0 {
+        return Err("Image has no size".to_string());
+    }
+    let (extension, bytes) = encode_image(doc, image, width, height)
+        .ok_or("Unsupported image encoding (install pdftoppm for full support)")?;
+    let out = temp_path(page, extension);
+    std::fs::write(&out, bytes).map_err(|e| format!("Failed to write page image: {}", e))?;
+    temp.0.push(out.clone());
+
+    let page_width = media_box.x1 - media_box.x0;
+    let page_height = media_box.y1 - media_box.y0;
+    Ok(PageImage {
+        path: out,
+        dpi: ((width as f32 / page_width * 72.0).round() as u32).max(1),
+        scale_x: page_width / width as f32,
+        scale_y: page_height / height as f32,
+    })
+}
+
+/* ---------------------------------
+ * 识别
+ * --------------------------------- */
+
+/// 解析 tesseract 的 TSV 输出，按行把词合成文字片段（中文词之间不加空格）
+fn parse_tsv(tsv: &str, image: &PageImage) -> Vec<(f32, TextSpan)> {
+    struct Word {
+        line: (u32, u32, u32),
+        left: f32,
+        top: f32,
+        width: f32,
+        height: f32,
+        text: String,
+    }
+
+    let words: Vec<Word> = tsv
+        .lines()
+        .skip(1)
+        .filter_map(|row| {
+            let cols: Vec<&str> = row.splitn(12, '\t').collect();
+            if cols.len() < 12 || cols[0] != "5" {
+                return None;
+            }
+            let text = cols[11].trim();
+            let conf: f32 = cols[10].parse().ok()?;
+            if text.is_empty() || conf < MIN_CONFIDENCE {
+                return None;
+            }
+            let num = |i: usize| cols[i].parse::<f32>().ok();
+            Some(Word {
+                line: (cols[2].parse().ok()?, cols[3].parse().ok()?, cols[4].parse().ok()?),
+                left: num(6)? * image.scale_x,
+                top: num(7)? * image.scale_y,
+                width: num(8)? * image.scale_x,
+                height: num(9)? * image.scale_y,
+                text: text.to_string(),
+            })
+        })
+        .collect();
+
+    // 同一行的字号取该行最高的词，保证分行时基线容差足够
+    let line_height = |line: (u32, u32, u32)| {
+        words
+            .iter()
+            .filter(|w| w.line == line)
+            .map(|w| w.height)
+            .fold(0.0, f32::max)
+    };
+
+    let mut spans: Vec<(f32, TextSpan)> = Vec::new();
+    let mut current_line = None;
+    for word in &words {
+        if let Some((baseline, span)) = spans.last_mut() {
+            let joins = current_line == Some(word.line)
+                && span.text.chars().last().is_some_and(is_cjk_or_fullwidth)
+                && word.text.chars().next().is_some_and(is_cjk_or_fullwidth);
+            if joins {
+                span.text.push_str(&word.text);
+                span.width = word.left + word.width - span.x;
+                span.height = span.height.max(word.height);
+                *baseline = baseline.max(word.top + word.height);
+                continue;
+            }
+        }
+        let size = line_height(word.line);
+        spans.push((
+            word.top + word.height,
+            TextSpan {
+                text: word.text.clone(),
+                x: word.left,
+                y: word.top,
+                width: word.width,
+                height: word.height,
+                font: "OCR".to_string(),
+                font_size: size,
+            },
+        ));
+        current_line = Some(word.line);
+    }
+    spans
+}
+
+fn recognize_page(engine: &OcrEngine, cache: &PdfTextCache, path: &Path, page: u32, native: &PageText) -> Result<PageText, String> {
+    let mut temp = TempFiles(Vec::new());
+    let image = match &engine.pdftoppm {
+        Some(pdftoppm) => render_with_pdftoppm(pdftoppm, path, page, &mut temp),
+        None => cache.with_document(path, |doc| extract_page_image(doc, page, &mut temp))?,
+    }?;
+
+    let output = Command::new(&engine.tesseract)
+        .arg(&image.path)
+        .arg("stdout")
+        .args(["-l", &engine.languages.join("+")])
+        .args(["--dpi", &image.dpi.to_string()])
+        .arg("tsv")
+        .output()
+        .map_err(|e| format!("Failed to run tesseract: {}", e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "Tesseract failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    let spans = parse_tsv(&String::from_utf8_lossy(&output.stdout), &image);
+    let mut text = pdf_text::layout_spans(page, native.width, native.height, spans);
+    text.ocr = true;
+    Ok(text)
+}
+
+/* ---------------------------------
+ * 缓存
+ * --------------------------------- */
+
+fn cached_page(storage: &Storage, path: &Path, page: u32) -> Result<Option<PageText>, String> {
+    let (size, mtime) = library::file_stamp(path)?;
+    let data: Option<String> = storage.with_conn(|conn| {
+        conn.query_row(
+            "SELECT data FROM ocr_pages WHERE path = ?1 AND page = ?2 AND file_size = ?3 AND file_mtime = ?4",
+            params![path.to_string_lossy(), page, size as i64, mtime],
+            |row| row.get(0),
+        )
+        .optional()
+    })?;
+    Ok(data.and_then(|d| serde_json::from_str(&d).ok()))
+}
+
+fn store_page(storage: &Storage, path: &Path, text: &PageText) -> Result<(), String> {
+    let (size, mtime) = library::file_stamp(path)?;
+    let data = serde_json::to_string(text).map_err(|e| format!("Failed to encode OCR result: {}", e))?;
+    storage.with_conn(|conn| {
+        conn.execute(
+            "INSERT OR REPLACE INTO ocr_pages (path, file_size, file_mtime, page, data, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![path.to_string_lossy(), size as i64, mtime, text.page, data, now_secs()],
+        )?;
+        Ok(())
+    })
+}
+
+/// 返回一页文字：有文本层时直接用，否则取 OCR 缓存；recognize 为 true 时没有缓存就现场识别
+fn resolve_page(
+    storage: &Storage,
+    cache: &PdfTextCache,
+    path: &Path,
+    native: Arc<PageText>,
+    recognize: bool,
+) -> Result<Arc<PageText>, String> {
+    if !native.text.trim().is_empty() {
+        return Ok(native);
+    }
+    if let Some(text) = cached_page(storage, path, native.page)? {
+        return Ok(Arc::new(text));
+    }
+    let Some(engine) = ENGINE.as_ref().filter(|_| recognize) else {
+        return Ok(native);
+    };
+
+    println!("🔤 OCR 识别第 {} 页: {}", native.page, path.display());
+    let text = recognize_page(engine, cache, path, native.page, &native)?;
+    // 识别不出文字的页也缓存，避免每次都重跑
+    store_page(storage, path, &text)?;
+    Ok(Arc::new(text))
+}
+
+pub fn page_text(storage: &Storage, cache: &PdfTextCache, path: &Path, page: u32) -> Result<Arc<PageText>, String> {
+    let native = cache.page_text(path, page)?;
+    resolve_page(storage, cache, path, native, true)
+}
+
+/// 整篇文档的文字，没有文本层的页用 OCR 结果代替；recognize 为 false 时只读缓存
+pub fn document_text(
+    storage: &Storage,
+    cache: &PdfTextCache,
+    path: &Path,
+    recognize: bool,
+) -> Result<Vec<Arc<PageText>>, String> {
+    let pages = cache.document_text(path)?;
+    Ok(pages
+        .into_iter()
+        .map(|native| {
+            let page = native.page;
+            resolve_page(storage, cache, path, native.clone(), recognize).unwrap_or_else(|e| {
+                println!("⚠️ 第 {} 页 OCR 失败：{}", page, e);
+                native
+            })
+        })
+        .collect())
+}
+
+#[tauri::command]
+pub fn ocr_status() -> OcrStatus {
+    let engine = ENGINE.as_ref();
+    OcrStatus {
+        available: engine.is_some(),
+        tesseract: engine.map(|e| e.tesseract.display().to_string()),
+        languages: engine.map(|e| e.languages.clone()).unwrap_or_default(),
+        renderer: engine.map(|e| {
+            e.pdftoppm
+                .as_ref()
+                .map_or("embedded-image".to_string(), |p| p.display().to_string())
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lopdf::{Dictionary, Stream};
+
+    const TSV_HEADER: &str = "level\tpage_num\tblock_num\tpar_num\tline_num\tword_num\tleft\ttop\twidth\theight\tconf\ttext";
+
+    fn image() -> PageImage {
+        // 300 DPI 渲染：每个像素对应 0.24 PDF 点
+        PageImage {
+            path: PathBuf::new(),
+            dpi: 300,
+            scale_x: 0.24,
+            scale_y: 0.24,
+        }
+    }
+
+    #[test]
+    fn parse_tsv_groups_words_into_spans() {
+        let tsv = [
+            TSV_HEADER,
+            "1\t1\t0\t0\t0\t0\t0\t0\t2550\t3300\t-1\t",
+            "4\t1\t1\t1\t1\t0\t100\t200\t900\t50\t-1\t",
+            "5\t1\t1\t1\t1\t1\t100\t200\t50\t50\t96.5\t深度",
+            "5\t1\t1\t1\t1\t2\t150\t200\t50\t50\t95.1\t学习",
+            "5\t1\t1\t1\t1\t3\t220\t205\t200\t40\t91.0\tmodels",
+            "5\t1\t1\t1\t1\t4\t430\t205\t50\t40\t12.0\t~",
+            "5\t1\t1\t1\t1\t5\t500\t200\t50\t50\t93.0\t模型",
+            "5\t1\t1\t1\t2\t1\t100\t300\t50\t50\t90.0\t方法",
+            "5\t1\t1\t1\t2\t2\t150\t300\tbad\t50\t90.0\t坏行",
+            "5\t1\t1\t1\t2\t3\t150\t300\t50\t50\t90.0\t ",
+            "5\t1\t1\t1\t2\t4",
+        ]
+        .join("\n");
+
+        let spans = parse_tsv(&tsv, &image());
+        let found: Vec<(&str, f32, f32, f32, f32)> = spans
+            .iter()
+            .map(|(baseline, s)| (s.text.as_str(), s.x, s.width, s.font_size, *baseline))
+            .collect();
+        let expected = [
+            // 同一行相邻的中文词合并
+            ("深度学习", 24.0, 24.0, 12.0, 60.0),
+            ("models", 52.8, 48.0, 12.0, 58.8),
+            // 低置信度的噪点被丢弃
+            ("模型", 120.0, 12.0, 12.0, 60.0),
+            // 换行后不与上一行合并
+            ("方法", 24.0, 12.0, 12.0, 84.0),
+        ];
+        assert_eq!(found.len(), expected.len(), "{:?}", found);
+        for (got, want) in found.iter().zip(expected) {
+            assert_eq!(got.0, want.0);
+            for (a, b) in [(got.1, want.1), (got.2, want.2), (got.3, want.3), (got.4, want.4)] {
+                assert!((a - b).abs() < 1e-3, "{:?} != {:?}", got, want);
+            }
+        }
+        assert!(spans.iter().all(|(_, s)| s.font == "OCR"));
+        assert!(parse_tsv(TSV_HEADER, &image()).is_empty());
+    }
+
+    fn image_stream(bits: i64, filter: Option<&str>, decode: Option<[f32; 2]>, content: Vec<u8>) -> Stream {
+        let mut dict = Dictionary::new();
+        dict.set("BitsPerComponent", Object::Integer(bits));
+        if let Some(filter) = filter {
+            dict.set("Filter", Object::Name(filter.as_bytes().to_vec()));
+        }
+        if let Some(decode) = decode {
+            dict.set("Decode", Object::Array(decode.iter().map(|v| Object::Real(*v)).collect()));
+        }
+        Stream::new(dict, content)
+    }
+
+    #[test]
+    fn encode_image_converts_raw_samples() {
+        let doc = Document::with_version("1.5");
+        let encode = |stream: Stream| encode_image(&doc, &stream, 2, 2);
+
+        let gray = encode(image_stream(8, None, None, vec![0, 64, 128, 255])).unwrap();
+        assert_eq!(gray, ("pgm", b"P5\n2 2\n255\n\x00\x40\x80\xff".to_vec()));
+
+        let rgb: Vec<u8> = (0..12).collect();
+        let (extension, data) = encode(image_stream(8, None, None, rgb.clone())).unwrap();
+        assert_eq!(extension, "ppm");
+        assert_eq!(data, [b"P6\n2 2\n255\n".to_vec(), rgb].concat());
+
+        // 1 位图：PDF 与 PBM 的黑白相反，/Decode [1 0] 时保持原样
+        let bits = vec![0b1000_0000, 0b0100_0000];
+        assert_eq!(encode(image_stream(1, None, None, bits.clone())).unwrap().1, b"P4\n2 2\n\x7f\xbf".to_vec());
+        assert_eq!(
+            encode(image_stream(1, None, Some([1.0, 0.0]), bits)).unwrap().1,
+            b"P4\n2 2\n\x80\x40".to_vec()
+        );
+
+        let jpeg = b"\xff\xd8\xff\xe0 not really a jpeg".to_vec();
+        assert_eq!(encode(image_stream(8, Some("DCTDecode"), None, jpeg.clone())).unwrap(), ("jpg", jpeg));
+        assert!(encode(image_stream(1, Some("CCITTFaxDecode"), None, vec![0; 4])).is_none());
+        // 数据不足一整幅图
+        assert!(encode(image_stream(8, None, None, vec![0; 3])).is_none());
+        assert!(encode(image_stream(4, None, None, vec![0; 16])).is_none());
+    }
+
+    #[test]
+    fn cached_pages_are_invalidated_when_the_file_changes() {
+        let storage = Storage::open_in_memory().unwrap();
+        let path = std::env::temp_dir().join(format!("papertalk-ocr-cache-{}.pdf", std::process::id()));
+        std::fs::write(&path, b"%PDF-1.4 scanned").unwrap();
+
+        let text = PageText {
+            page: 2,
+            width: 612.0,
+            height: 792.0,
+            text: "识别结果".to_string(),
+            lines: Vec::new(),
+            ocr: true,
+        };
+        assert!(cached_page(&storage, &path, 2).unwrap().is_none());
+        store_page(&storage, &path, &text).unwrap();
+        let cached = cached_page(&storage, &path, 2).unwrap().unwrap();
+        assert_eq!((cached.text.as_str(), cached.ocr), ("识别结果", true));
+        assert!(cached_page(&storage, &path, 1).unwrap().is_none());
+
+        // 文件大小变化后缓存失效
+        std::fs::write(&path, b"%PDF-1.4 scanned, then edited").unwrap();
+        assert!(cached_page(&storage, &path, 2).unwrap().is_none());
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
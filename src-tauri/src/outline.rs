@@ -0,0 +1,818 @@
+// 文档结构识别：标题、摘要、章节、图表标题、参考文献
+//
+// PDF 自带书签时优先使用书签作为章节目录；否则根据字号层级、编号格式
+// 以及 "Abstract"、"References" 等锚点推断。图表标题和摘要总是从正文中识别。
+
+use lopdf::{Dictionary, Document, Object, ObjectId};
+use regex::Regex;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, LazyLock};
+
+use crate::file_scope::FileScope;
+use crate::pdf_text::{decode_text_string, dict_get, resolve, PageText, PdfTextCache, TextLine};
+use crate::text::join_line;
+
+// 标题行一般不会太长
+const MAX_HEADING_CHARS: usize = 100;
+const MAX_ABSTRACT_CHARS: usize = 3000;
+const MAX_CAPTION_LINES: usize = 6;
+// 书签嵌套与兄弟节点数量上限，防止损坏文件中的环
+const MAX_BOOKMARK_DEPTH: usize = 16;
+const MAX_BOOKMARKS: usize = 2000;
+
+static NUMBERED_HEADING_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^((?:\d{1,2}|[A-Z])(?:\.\d{1,2}){0,3})\.?\s+(\S.*)$").unwrap()
+});
+static CHINESE_HEADING_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^(第[一二三四五六七八九十\d]+[章节]|[一二三四五六七八九十]+[、．.])\s*(\S.*)$").unwrap()
+});
+static ANCHOR_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"(?i)^(?:(?:\d{1,2}|[IVX]+)\.?\s+)?(abstract|摘\s*要|references|bibliography|参考文献|acknowledge?ments?|致\s*谢|appendix(?:\s+[A-Z])?|附\s*录)\s*$",
+    )
+    .unwrap()
+});
+// IEEE 等模板把摘要和正文写在同一行："Abstract—We propose ..."
+static INLINE_ABSTRACT_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?i)^(?:abstract|摘\s*要)\s*[—\-–.:：]\s*(.+)$").unwrap());
+static CAPTION_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^(?i:(figure|fig\.|table)\s*(\d+[a-z]?)\s*[.:|])\s*(.*)$|^([图表])\s*(\d+(?:[.\-]\d+)?)\s*[.:：]?\s+(.*)$")
+        .unwrap()
+});
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SectionKind {
+    Abstract,
+    Section,
+    References,
+    Acknowledgments,
+    Appendix,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct OutlineEntry {
+    pub title: String,
+    pub kind: SectionKind,
+    // 1 为最高层级
+    pub level: u32,
+    pub number: Option<String>,
+    pub page: u32,
+    // 距页面顶部的距离（PDF 点），书签没有给出位置时为空
+    pub y: Option<f32>,
+    pub children: Vec<OutlineEntry>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Caption {
+    // "figure" 或 "table"
+    pub kind: String,
+    // 例如 "Figure 3"、"表 2"
+    pub label: String,
+    pub text: String,
+    pub page: u32,
+    pub y: f32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DocumentOutline {
+    pub title: Option<String>,
+    #[serde(rename = "abstract")]
+    pub abstract_text: Option<String>,
+    // "bookmarks" 或 "heuristic"
+    pub source: String,
+    pub sections: Vec<OutlineEntry>,
+    pub captions: Vec<Caption>,
+    // 参考文献起始位置
+    pub references: Option<OutlineEntry>,
+}
+
+pub fn document_outline(cache: &PdfTextCache, path: &Path) -> Result<DocumentOutline, String> {
+    let pages = cache.document_text(path)?;
+    let bookmarks = cache.with_document(path, |doc| read_bookmarks(doc, &pages))?;
+    Ok(build_outline(&pages, bookmarks))
+}
+
+fn build_outline(pages: &[Arc<PageText>], bookmarks: Vec<OutlineEntry>) -> DocumentOutline {
+    let body_size = body_font_size(pages);
+    let headings = detect_headings(pages, body_size);
+
+    let references = headings
+        .iter()
+        .find(|h| h.kind == SectionKind::References)
+        .cloned();
+    let (source, sections) = if bookmarks.is_empty() {
+        ("heuristic", nest(headings.clone()))
+    } else {
+        ("bookmarks", bookmarks)
+    };
+
+    DocumentOutline {
+        title: detect_title(pages),
+        abstract_text: detect_abstract(pages, &headings),
+        source: source.to_string(),
+        sections,
+        captions: detect_captions(pages, body_size),
+        references,
+    }
+}
+
+/* ---------------------------------
+ * PDF 书签
+ * --------------------------------- */
+
+fn read_bookmarks(doc: &Document, pages: &[Arc<PageText>]) -> Vec<OutlineEntry> {
+    let Some(catalog) = doc
+        .trailer
+        .get(b"Root")
+        .ok()
+        .and_then(|o| resolve(doc, o).as_dict().ok())
+    else {
+        return Vec::new();
+    };
+    let Some(first) = dict_get(doc, catalog, b"Outlines")
+        .and_then(|o| o.as_dict().ok())
+        .and_then(|outlines| outlines.get(b"First").ok())
+    else {
+        return Vec::new();
+    };
+
+    let page_numbers: HashMap<ObjectId, u32> = doc.get_pages().into_iter().map(|(n, id)| (id, n)).collect();
+    let page_heights: HashMap<u32, f32> = pages.iter().map(|p| (p.page, p.height)).collect();
+    let reader = BookmarkReader {
+        doc,
+        catalog,
+        page_numbers,
+        page_heights,
+    };
+    let mut budget = MAX_BOOKMARKS;
+    reader.siblings(first, 1, &mut budget)
+}
+
+struct BookmarkReader<'a> {
+    doc: &'a Document,
+    catalog: &'a Dictionary,
+    page_numbers: HashMap<ObjectId, u32>,
+    page_heights: HashMap<u32, f32>,
+}
+
+impl<'a> BookmarkReader<'a> {
+    fn siblings(&self, first: &'a Object, level: u32, budget: &mut usize) -> Vec<OutlineEntry> {
+        let mut entries = Vec::new();
+        let mut current = Some(first);
+        while let Some(item) = current {
+            if *budget == 0 || level as usize > MAX_BOOKMARK_DEPTH {
+                break;
+            }
+            *budget -= 1;
+            let Ok(dict) = resolve(self.doc, item).as_dict() else {
+                break;
+            };
+
+            let title = dict_get(self.doc, dict, b"Title")
+                .and_then(|o| o.as_str().ok())
+                .map(|s| decode_text_string(s).trim().to_string())
+                .unwrap_or_default();
+            let children = dict
+                .get(b"First")
+                .map(|child| self.siblings(child, level + 1, budget))
+                .unwrap_or_default();
+
+            if let Some((page, y)) = self.destination(dict) {
+                let (number, kind) = classify_title(&title);
+                entries.push(OutlineEntry {
+                    title,
+                    kind,
+                    level,
+                    number,
+                    page,
+                    y,
+                    children,
+                });
+            } else {
+                // 没有目标页的书签只保留子项
+                entries.extend(children);
+            }
+            current = dict.get(b"Next").ok();
+        }
+        entries
+    }
+
+    // 书签目标可以写在 /Dest，也可以是 /A 中的 GoTo 动作；命名目标需要再查一次
+    fn destination(&self, item: &'a Dictionary) -> Option<(u32, Option<f32>)> {
+        let dest = match dict_get(self.doc, item, b"Dest") {
+            Some(dest) => dest,
+            None => {
+                let action = dict_get(self.doc, item, b"A")?.as_dict().ok()?;
+                dict_get(self.doc, action, b"D")?
+            }
+        };
+        let dest = match dest {
+            Object::String(name, _) | Object::Name(name) => self.named_destination(name)?,
+            other => other,
+        };
+        // 命名目标可能是 { /D [...] } 字典
+        let dest = match dest {
+            Object::Dictionary(d) => dict_get(self.doc, d, b"D")?,
+            other => other,
+        };
+
+        let items = dest.as_array().ok()?;
+        let page = match items.first()? {
+            Object::Reference(id) => *self.page_numbers.get(id)?,
+            // 部分生成器写的是从 0 开始的页序号；负数或越界的序号视为无效目标
+            Object::Integer(index) => u32::try_from(*index).ok()?.checked_add(1)?,
+            _ => return None,
+        };
+        // [page /XYZ left top zoom] 或 [page /FitH top]
+        let kind = items.get(1).and_then(|o| o.as_name().ok()).unwrap_or(b"Fit");
+        let top = match kind {
+            b"XYZ" => items.get(3),
+            b"FitH" | b"FitBH" => items.get(2),
+            _ => None,
+        }
+        .and_then(|o| o.as_float().ok());
+        let y = top.zip(self.page_heights.get(&page)).map(|(top, height)| (height - top).max(0.0));
+        Some((page, y))
+    }
+
+    fn named_destination(&self, name: &[u8]) -> Option<&'a Object> {
+        // PDF 1.1 的 /Dests 字典
+        if let Some(dest) = dict_get(self.doc, self.catalog, b"Dests")
+            .and_then(|o| o.as_dict().ok())
+            .and_then(|d| dict_get(self.doc, d, name))
+        {
+            return Some(dest);
+        }
+        // PDF 1.2 之后的名字树 /Names /Dests
+        let tree = dict_get(self.doc, self.catalog, b"Names")
+            .and_then(|o| o.as_dict().ok())
+            .and_then(|n| dict_get(self.doc, n, b"Dests"))
+            .and_then(|o| o.as_dict().ok())?;
+        self.name_tree_lookup(tree, name, 0)
+    }
+
+    fn name_tree_lookup(&self, node: &'a Dictionary, name: &[u8], depth: usize) -> Option<&'a Object> {
+        if depth > MAX_BOOKMARK_DEPTH {
+            return None;
+        }
+        if let Some(names) = dict_get(self.doc, node, b"Names").and_then(|o| o.as_array().ok()) {
+            for pair in names.chunks(2) {
+                if let [key, value] = pair {
+                    if resolve(self.doc, key).as_str().ok() == Some(name) {
+                        return Some(resolve(self.doc, value));
+                    }
+                }
+            }
+        }
+        let kids = dict_get(self.doc, node, b"Kids").and_then(|o| o.as_array().ok())?;
+        kids.iter()
+            .filter_map(|kid| resolve(self.doc, kid).as_dict().ok())
+            .find_map(|kid| self.name_tree_lookup(kid, name, depth + 1))
+    }
+}
+
+/* ---------------------------------
+ * 正文启发式
+ * --------------------------------- */
+
+fn line_font_size(line: &TextLine) -> f32 {
+    line.spans.iter().map(|s| s.font_size).fold(0.0, f32::max)
+}
+
+fn is_bold(line: &TextLine) -> bool {
+    line.spans.iter().all(|s| {
+        let font = s.font.to_lowercase();
+        font.contains("bold") || font.contains("black") || font.contains("heavy") || font.contains("semibold")
+    })
+}
+
+// 正文字号：按字数加权出现最多的字号（取 0.5 pt 精度）
+fn body_font_size(pages: &[Arc<PageText>]) -> f32 {
+    let mut counts: HashMap<i32, usize> = HashMap::new();
+    for span in pages.iter().flat_map(|p| &p.lines).flat_map(|l| &l.spans) {
+        *counts.entry((span.font_size * 2.0).round() as i32).or_default() += span.text.chars().count();
+    }
+    counts
+        .into_iter()
+        .max_by_key(|(_, count)| *count)
+        .map(|(size, _)| size as f32 / 2.0)
+        .unwrap_or(10.0)
+}
+
+// 拆出章节编号；单个字母只接受 "A." 这种带点的写法，否则 "A Study of ..." 也会被当成编号
+fn numbered(text: &str) -> Option<(String, &str)> {
+    let caps = NUMBERED_HEADING_RE.captures(text)?;
+    let number = caps.get(1)?.as_str();
+    if number.starts_with(|c: char| c.is_ascii_uppercase()) && !text[1..].starts_with('.') {
+        return None;
+    }
+    Some((number.to_string(), caps.get(2)?.as_str()))
+}
+
+/// 根据标题文字判断编号和特殊章节
+fn classify_title(title: &str) -> (Option<String>, SectionKind) {
+    let number = numbered(title)
+        .map(|(number, _)| number)
+        .or_else(|| CHINESE_HEADING_RE.captures(title).map(|c| c[1].to_string()));
+
+    let lower = title.to_lowercase();
+    let kind = if ANCHOR_RE.is_match(title) || lower.starts_with("appendix") {
+        anchor_kind(&lower)
+    } else {
+        SectionKind::Section
+    };
+    (number, kind)
+}
+
+fn anchor_kind(lower: &str) -> SectionKind {
+    if lower.contains("abstract") || lower.contains('摘') {
+        SectionKind::Abstract
+    } else if lower.contains("reference") || lower.contains("bibliography") || lower.contains("参考文献") {
+        SectionKind::References
+    } else if lower.contains("acknowledg") || lower.contains('致') {
+        SectionKind::Acknowledgments
+    } else if lower.contains("appendix") || lower.contains("附") {
+        SectionKind::Appendix
+    } else {
+        SectionKind::Section
+    }
+}
+
+/// 按阅读顺序返回从正文中识别出的全部标题（扁平列表）
+pub fn headings(pages: &[Arc<PageText>]) -> Vec<OutlineEntry> {
+    detect_headings(pages, body_font_size(pages))
+}
+
+fn detect_headings(pages: &[Arc<PageText>], body_size: f32) -> Vec<OutlineEntry> {
+    let mut headings = Vec::new();
+    for page in pages {
+        for line in &page.lines {
+            let text = line.text.trim();
+            let chars = text.chars().count();
+            if !(2..=MAX_HEADING_CHARS).contains(&chars) {
+                continue;
+            }
+            let size = line_font_size(line);
+            if size < body_size * 0.95 {
+                continue;
+            }
+
+            if let Some(caps) = ANCHOR_RE.captures(text) {
+                headings.push(heading(text, anchor_kind(&caps[1].to_lowercase()), 1, None, page.page, line));
+                continue;
+            }
+
+            // 编号标题还要求加粗或字号大于正文，避免把正文中的编号列表当成标题
+            let emphasized = is_bold(line) || size > body_size * 1.1;
+            if !emphasized || text.ends_with(['.', ',', ';', '，', '。', '；']) {
+                continue;
+            }
+            if let Some((number, rest)) = numbered(text) {
+                if rest.chars().filter(|c| c.is_alphabetic()).count() < 2 {
+                    continue;
+                }
+                let level = number.split('.').count() as u32;
+                headings.push(heading(text, SectionKind::Section, level, Some(number), page.page, line));
+            } else if let Some(caps) = CHINESE_HEADING_RE.captures(text) {
+                let number = caps[1].to_string();
+                let level = if number.starts_with('第') && number.ends_with('节') { 2 } else { 1 };
+                headings.push(heading(text, SectionKind::Section, level, Some(number), page.page, line));
+            } else if page.page > 1 && size > body_size * 1.15 {
+                // 没有编号但明显放大的短行，层级稍后按字号排定
+                headings.push(heading(text, SectionKind::Section, 0, None, page.page, line));
+            }
+        }
+    }
+    assign_unnumbered_levels(&mut headings, pages);
+    headings
+}
+
+fn heading(title: &str, kind: SectionKind, level: u32, number: Option<String>, page: u32, line: &TextLine) -> OutlineEntry {
+    OutlineEntry {
+        title: title.to_string(),
+        kind,
+        level,
+        number,
+        page,
+        y: Some(line.y),
+        children: Vec::new(),
+    }
+}
+
+// 无编号标题按字号从大到小依次定为 1、2、3 级
+fn assign_unnumbered_levels(headings: &mut [OutlineEntry], pages: &[Arc<PageText>]) {
+    let size_of = |h: &OutlineEntry| -> f32 {
+        pages
+            .iter()
+            .find(|p| p.page == h.page)
+            .and_then(|p| p.lines.iter().find(|l| Some(l.y) == h.y))
+            .map(line_font_size)
+            .unwrap_or(0.0)
+    };
+    let mut sizes: Vec<i32> = headings
+        .iter()
+        .filter(|h| h.level == 0)
+        .map(|h| (size_of(h) * 2.0).round() as i32)
+        .collect();
+    sizes.sort_unstable_by(|a, b| b.cmp(a));
+    sizes.dedup();
+
+    for h in headings.iter_mut().filter(|h| h.level == 0) {
+        let size = (size_of(h) * 2.0).round() as i32;
+        let rank = sizes.iter().position(|s| *s == size).unwrap_or(0);
+        h.level = (rank as u32 + 1).min(3);
+    }
+}
+
+/// 把按阅读顺序排列的扁平标题列表按层级组织成树
+fn nest(headings: Vec<OutlineEntry>) -> Vec<OutlineEntry> {
+    let mut roots: Vec<OutlineEntry> = Vec::new();
+    for entry in headings {
+        insert_entry(&mut roots, entry);
+    }
+    roots
+}
+
+fn insert_entry(siblings: &mut Vec<OutlineEntry>, entry: OutlineEntry) {
+    match siblings.last_mut() {
+        Some(last) if entry.level > last.level => insert_entry(&mut last.children, entry),
+        _ => siblings.push(entry),
+    }
+}
+
+// 首页上半部分字号最大的连续几行
+pub fn detect_title(pages: &[Arc<PageText>]) -> Option<String> {
+    let first = pages.first()?;
+    let top: Vec<&TextLine> = first
+        .lines
+        .iter()
+        .filter(|l| l.y < first.height * 0.5 && l.text.chars().count() > 3)
+        .collect();
+    let max_size = top.iter().map(|l| line_font_size(l)).fold(0.0, f32::max);
+    if max_size <= 0.0 {
+        return None;
+    }
+
+    let mut title_lines: Vec<&TextLine> = top
+        .into_iter()
+        .filter(|l| (line_font_size(l) - max_size).abs() < 0.5)
+        .collect();
+    title_lines.sort_by(|a, b| a.y.total_cmp(&b.y));
+    // 只取相邻的几行，避免把页眉等同字号内容也拼进来
+    let mut picked = vec![*title_lines.first()?];
+    for line in title_lines.iter().skip(1) {
+        let prev = picked[picked.len() - 1];
+        if line.y - (prev.y + prev.height) > max_size * 1.5 {
+            break;
+        }
+        picked.push(line);
+    }
+    let title = picked.iter().map(|l| l.text.as_str()).collect::<Vec<_>>().join(" ");
+    Some(title.trim().to_string()).filter(|t| !t.is_empty())
+}
+
+fn detect_abstract(pages: &[Arc<PageText>], headings: &[OutlineEntry]) -> Option<String> {
+    // 摘要从 Abstract 锚点开始，到下一个标题为止
+    let lines: Vec<(u32, &TextLine)> = pages
+        .iter()
+        .take(3)
+        .flat_map(|p| p.lines.iter().map(move |l| (p.page, l)))
+        .collect();
+
+    let (start, first_text) = lines.iter().enumerate().find_map(|(i, (_, line))| {
+        let text = line.text.trim();
+        if let Some(caps) = INLINE_ABSTRACT_RE.captures(text) {
+            return Some((i + 1, caps[1].to_string()));
+        }
+        ANCHOR_RE
+            .captures(text)
+            .filter(|c| anchor_kind(&c[1].to_lowercase()) == SectionKind::Abstract)
+            .map(|_| (i + 1, String::new()))
+    })?;
+
+    let mut text = first_text;
+    for (page, line) in &lines[start..] {
+        let is_heading = headings
+            .iter()
+            .any(|h| h.page == *page && h.y == Some(line.y) && h.kind != SectionKind::Abstract);
+        if is_heading || text.chars().count() > MAX_ABSTRACT_CHARS {
+            break;
+        }
+        join_line(&mut text, &line.text);
+    }
+    let text = text.trim().to_string();
+    (!text.is_empty()).then_some(text)
+}
+
+fn detect_captions(pages: &[Arc<PageText>], body_size: f32) -> Vec<Caption> {
+    let mut captions = Vec::new();
+    for page in pages {
+        let mut i = 0;
+        while i < page.lines.len() {
+            let line = &page.lines[i];
+            let Some(caps) = CAPTION_RE.captures(line.text.trim()) else {
+                i += 1;
+                continue;
+            };
+            let (kind, label, first) = match (caps.get(1), caps.get(4)) {
+                (Some(word), _) => {
+                    let kind = if word.as_str().to_lowercase().starts_with("tab") { "table" } else { "figure" };
+                    (kind, format!("{} {}", word.as_str(), &caps[2]), caps[3].to_string())
+                }
+                (None, Some(word)) => {
+                    let kind = if word.as_str() == "表" { "table" } else { "figure" };
+                    (kind, format!("{} {}", word.as_str(), &caps[5]), caps[6].to_string())
+                }
+                _ => {
+                    i += 1;
+                    continue;
+                }
+            };
+
+            // 续行：字号相同、行距正常、且不是下一个图表标题
+            let size = line_font_size(line);
+            let mut text = first;
+            let mut prev = line;
+            let mut j = i + 1;
+            while j < page.lines.len() && j - i < MAX_CAPTION_LINES {
+                let next = &page.lines[j];
+                let close = next.y - (prev.y + prev.height) < size * 0.8 && next.y > prev.y;
+                if !close || (line_font_size(next) - size).abs() > 0.5 || CAPTION_RE.is_match(next.text.trim()) {
+                    break;
+                }
+                join_line(&mut text, &next.text);
+                prev = next;
+                j += 1;
+            }
+
+            // 正文里的 "Figure 3: ..." 一般字号不会小于正文，这里只排除明显更大的标题行
+            if size <= body_size * 1.2 {
+                captions.push(Caption {
+                    kind: kind.to_string(),
+                    label,
+                    text: text.trim().to_string(),
+                    page: page.page,
+                    y: line.y,
+                });
+            }
+            i = j;
+        }
+    }
+    captions
+}
+
+/// 返回文档目录：书签或推断出的章节树、标题、摘要、图表标题与参考文献位置
+#[tauri::command]
+pub async fn get_document_outline(path: String, app_handle: tauri::AppHandle) -> Result<DocumentOutline, String> {
+    use tauri::Manager;
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let canonical = app_handle.state::<FileScope>().resolve_pdf(&path)?;
+        document_outline(&app_handle.state::<PdfTextCache>(), &canonical)
+    })
+    .await
+    .map_err(|e| format!("Outline detection failed: {}", e))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pdf_text::TextSpan;
+    use lopdf::StringFormat;
+
+    fn line(text: &str, y: f32, font: &str, font_size: f32) -> TextLine {
+        let width = text.chars().count() as f32 * font_size * 0.5;
+        TextLine {
+            text: text.to_string(),
+            x: 50.0,
+            y,
+            width,
+            height: font_size,
+            spans: vec![TextSpan {
+                text: text.to_string(),
+                x: 50.0,
+                y,
+                width,
+                height: font_size,
+                font: font.to_string(),
+                font_size,
+            }],
+        }
+    }
+
+    // (文本, 字体, 字号)，依次排在 y = 60, 80, 100, ...
+    fn page(number: u32, lines: &[(&str, &str, f32)]) -> Arc<PageText> {
+        let lines: Vec<TextLine> = lines
+            .iter()
+            .enumerate()
+            .map(|(i, (text, font, size))| line(text, 60.0 + i as f32 * 20.0, font, *size))
+            .collect();
+        Arc::new(PageText {
+            page: number,
+            width: 600.0,
+            height: 800.0,
+            text: lines.iter().map(|l| l.text.as_str()).collect::<Vec<_>>().join("\n"),
+            lines,
+            ocr: false,
+        })
+    }
+
+    const BODY: &str = "Times-Roman";
+    const BOLD: &str = "Times-Bold";
+    const FILLER: &str = "This is an ordinary line of body text used to set the dominant font size.";
+
+    #[test]
+    fn classify_title_reads_numbers_and_anchors() {
+        let cases = [
+            ("1 Introduction", Some("1"), SectionKind::Section),
+            ("3.2.1 Training Details", Some("3.2.1"), SectionKind::Section),
+            ("A. Proofs", Some("A"), SectionKind::Section),
+            ("A Study of Attention", None, SectionKind::Section),
+            ("Appendix B", None, SectionKind::Appendix),
+            ("5 References", Some("5"), SectionKind::References),
+            ("Bibliography", None, SectionKind::References),
+            ("Abstract", None, SectionKind::Abstract),
+            ("Acknowledgements", None, SectionKind::Acknowledgments),
+            ("第三章 实验方法", Some("第三章"), SectionKind::Section),
+            ("二、相关工作", Some("二、"), SectionKind::Section),
+            ("致 谢", None, SectionKind::Acknowledgments),
+        ];
+        for (title, number, kind) in cases {
+            let (got_number, got_kind) = classify_title(title);
+            assert_eq!((got_number.as_deref(), got_kind), (number, kind), "{:?}", title);
+        }
+    }
+
+    #[test]
+    fn detect_headings_uses_emphasis_numbering_and_size() {
+        let pages = vec![
+            page(
+                1,
+                &[
+                    ("A Large Title Line", BOLD, 18.0),
+                    ("Abstract", BODY, 10.0),
+                    (FILLER, BODY, 10.0),
+                    ("1 Introduction", BOLD, 10.0),
+                    (FILLER, BODY, 10.0),
+                    // 正文中的编号列表：没有加粗
+                    ("2 items are listed here", BODY, 10.0),
+                    // 加粗但以句号结尾
+                    ("3 Results are shown in Table 3.", BOLD, 10.0),
+                    // 编号后几乎没有文字
+                    ("4 12", BOLD, 12.0),
+                    // 脚注字号太小
+                    ("5 Code is available online", BOLD, 8.0),
+                ],
+            ),
+            page(
+                2,
+                &[
+                    ("2.1 Setup", BOLD, 10.0),
+                    (FILLER, BODY, 10.0),
+                    ("Related Work", BODY, 14.0),
+                    ("Discussion", BODY, 12.0),
+                    ("Small Caps Line", BODY, 10.5),
+                    ("二、相关工作", BOLD, 10.0),
+                    ("References", BODY, 10.0),
+                ],
+            ),
+        ];
+        let headings = headings(&pages);
+        let found: Vec<(&str, u32, Option<&str>, SectionKind, u32)> = headings
+            .iter()
+            .map(|h| (h.title.as_str(), h.level, h.number.as_deref(), h.kind, h.page))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                ("Abstract", 1, None, SectionKind::Abstract, 1),
+                ("1 Introduction", 1, Some("1"), SectionKind::Section, 1),
+                ("2.1 Setup", 2, Some("2.1"), SectionKind::Section, 2),
+                ("Related Work", 1, None, SectionKind::Section, 2),
+                ("Discussion", 2, None, SectionKind::Section, 2),
+                ("二、相关工作", 1, Some("二、"), SectionKind::Section, 2),
+                ("References", 1, None, SectionKind::References, 2),
+            ]
+        );
+
+        let tree = nest(headings);
+        let titles: Vec<(&str, Vec<&str>)> = tree
+            .iter()
+            .map(|h| (h.title.as_str(), h.children.iter().map(|c| c.title.as_str()).collect()))
+            .collect();
+        assert_eq!(
+            titles,
+            vec![
+                ("Abstract", vec![]),
+                ("1 Introduction", vec!["2.1 Setup"]),
+                ("Related Work", vec!["Discussion"]),
+                ("二、相关工作", vec![]),
+                ("References", vec![]),
+            ]
+        );
+    }
+
+    fn literal(text: &str) -> Object {
+        Object::String(text.as_bytes().to_vec(), StringFormat::Literal)
+    }
+
+    fn name(value: &str) -> Object {
+        Object::Name(value.as_bytes().to_vec())
+    }
+
+    #[test]
+    fn bookmarks_resolve_destinations_and_reject_bad_ones() {
+        let mut doc = Document::with_version("1.5");
+        let pages_id = doc.new_object_id();
+        let page_ids: Vec<ObjectId> = (0..2)
+            .map(|_| {
+                let mut page = Dictionary::new();
+                page.set("Type", name("Page"));
+                page.set("Parent", Object::Reference(pages_id));
+                doc.add_object(page)
+            })
+            .collect();
+        let mut pages = Dictionary::new();
+        pages.set("Type", name("Pages"));
+        pages.set("Count", Object::Integer(2));
+        pages.set("Kids", Object::Array(page_ids.iter().map(|id| Object::Reference(*id)).collect()));
+        doc.objects.insert(pages_id, Object::Dictionary(pages));
+
+        let page_ref = |i: usize| Object::Reference(page_ids[i]);
+        let xyz = |i: usize, top: f32| {
+            Object::Array(vec![page_ref(i), name("XYZ"), Object::Integer(0), Object::Real(top), Object::Null])
+        };
+        let index_dest = |index: i64| Object::Array(vec![Object::Integer(index), name("Fit")]);
+
+        let mut goto = Dictionary::new();
+        goto.set("S", name("GoTo"));
+        goto.set("D", Object::Array(vec![Object::Integer(1), name("FitH"), Object::Real(500.0)]));
+        let mut child = Dictionary::new();
+        child.set("Title", literal("2.1 Child"));
+        child.set("Dest", Object::Array(vec![page_ref(1), name("Fit")]));
+        let child_id = doc.add_object(child);
+
+        // (标题, 目标键, 目标, 第一个子项)
+        let items: Vec<(&str, &str, Object, Option<ObjectId>)> = vec![
+            ("1 Introduction", "Dest", xyz(0, 700.0), None),
+            ("2 Method", "A", Object::Dictionary(goto), None),
+            ("Negative index", "Dest", index_dest(-1), Some(child_id)),
+            ("Overflowing index", "Dest", index_dest(u32::MAX as i64), None),
+            ("Huge index", "Dest", index_dest(i64::MAX), None),
+            ("Missing page", "Dest", Object::Array(vec![Object::Reference((999, 0))]), None),
+            ("References", "Dest", literal("refs"), None),
+        ];
+        let ids: Vec<ObjectId> = items.iter().map(|_| doc.new_object_id()).collect();
+        for (i, (title, key, dest, first)) in items.into_iter().enumerate() {
+            let mut item = Dictionary::new();
+            item.set("Title", literal(title));
+            item.set(key, dest);
+            if let Some(first) = first {
+                item.set("First", Object::Reference(first));
+            }
+            if let Some(next) = ids.get(i + 1) {
+                item.set("Next", Object::Reference(*next));
+            }
+            doc.objects.insert(ids[i], Object::Dictionary(item));
+        }
+        let mut outlines = Dictionary::new();
+        outlines.set("First", Object::Reference(ids[0]));
+
+        // 命名目标放在 /Names /Dests 名字树的子节点中
+        let mut leaf = Dictionary::new();
+        leaf.set("Names", Object::Array(vec![literal("refs"), xyz(1, 100.0)]));
+        let leaf_id = doc.add_object(leaf);
+        let mut tree = Dictionary::new();
+        tree.set("Kids", Object::Array(vec![Object::Reference(leaf_id)]));
+        let mut names = Dictionary::new();
+        names.set("Dests", Object::Dictionary(tree));
+
+        let mut catalog = Dictionary::new();
+        catalog.set("Type", name("Catalog"));
+        catalog.set("Pages", Object::Reference(pages_id));
+        catalog.set("Outlines", Object::Dictionary(outlines));
+        catalog.set("Names", Object::Dictionary(names));
+        let catalog_id = doc.add_object(catalog);
+        doc.trailer.set("Root", Object::Reference(catalog_id));
+
+        let text = vec![page(1, &[]), page(2, &[])];
+        let bookmarks = read_bookmarks(&doc, &text);
+        let found: Vec<(&str, u32, u32, Option<f32>, SectionKind)> = bookmarks
+            .iter()
+            .map(|b| (b.title.as_str(), b.level, b.page, b.y, b.kind))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                ("1 Introduction", 1, 1, Some(100.0), SectionKind::Section),
+                ("2 Method", 1, 2, Some(300.0), SectionKind::Section),
+                // 目标无效的书签只保留子项
+                ("2.1 Child", 2, 2, None, SectionKind::Section),
+                ("References", 1, 2, Some(700.0), SectionKind::References),
+            ]
+        );
+    }
+}
@@ -0,0 +1,775 @@
+// PDF 标准批注的导出与导入
+//
+// 导出：把高亮写成 /Highlight 批注、笔记写成 /Text + /Popup 批注，
+// 以增量更新的方式追加到文件副本末尾，原文件不动，其他阅读器也能看到。
+// 导入：读取别处标注过的 PDF 中的 Highlight / Text / FreeText 批注。
+// 导出时批注的 /NM 即 PaperTalk 中的 ID；导入时 ID 为「文档 ID:/NM」，同一份副本
+// 换成另一个文档导入也不会与原记录冲突，重复导出、导入都不会产生重复记录。
+
+use lopdf::{Dictionary, IncrementalDocument, Object, ObjectId, StringFormat};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+use crate::annotations::{
+    self, extract_tags, Annotations, Highlight, Note, Quad, DEFAULT_HIGHLIGHT_COLOR,
+};
+use crate::file_scope::{self, FileScope};
+use crate::library;
+use crate::pdf_text::{decode_text_string, dict_get, page_media_box, resolve, MediaBox, PdfTextCache};
+use crate::storage::{now_secs, Storage};
+
+// 作者字段（/T）
+const ANNOTATION_AUTHOR: &str = "PaperTalk";
+// 笔记图标的边长与弹出框大小（PDF 点）
+const NOTE_ICON_SIZE: f32 = 20.0;
+const POPUP_WIDTH: f32 = 220.0;
+const POPUP_HEIGHT: f32 = 120.0;
+// 没有关联高亮的笔记沿页面左边距依次排列
+const NOTE_MARGIN: f32 = 12.0;
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct AnnotationImport {
+    pub highlights: usize,
+    pub notes: usize,
+    // 已导入过或内容为空而跳过的批注
+    pub skipped: usize,
+}
+
+/* ---------------------------------
+ * 坐标与取值
+ * --------------------------------- */
+
+// PaperTalk 坐标原点在左上角，PDF 用户空间原点在左下角
+fn to_pdf_point(media_box: &MediaBox, x: f32, y: f32) -> (f32, f32) {
+    (x + media_box.x0, media_box.y1 - y)
+}
+
+fn from_pdf_point(media_box: &MediaBox, x: f32, y: f32) -> (f32, f32) {
+    (x - media_box.x0, media_box.y1 - y)
+}
+
+fn quad_bounds(quad: &Quad) -> (f32, f32, f32, f32) {
+    let xs = [quad[0], quad[2], quad[4], quad[6]];
+    let ys = [quad[1], quad[3], quad[5], quad[7]];
+    (
+        xs.iter().copied().fold(f32::MAX, f32::min),
+        ys.iter().copied().fold(f32::MAX, f32::min),
+        xs.iter().copied().fold(f32::MIN, f32::max),
+        ys.iter().copied().fold(f32::MIN, f32::max),
+    )
+}
+
+/// "#rrggbb" 转成 /C 需要的 RGB 分量
+fn color_components(color: &str) -> Vec<Object> {
+    let hex = color.trim_start_matches('#');
+    (0..3)
+        .map(|i| {
+            let value = hex
+                .get(i * 2..i * 2 + 2)
+                .and_then(|h| u8::from_str_radix(h, 16).ok())
+                .unwrap_or(0);
+            Object::Real(value as f32 / 255.0)
+        })
+        .collect()
+}
+
+fn color_hex(doc: &lopdf::Document, object: Option<&Object>) -> Option<String> {
+    let values: Vec<f32> = object?
+        .as_array()
+        .ok()?
+        .iter()
+        .filter_map(|o| resolve(doc, o).as_float().ok())
+        .collect();
+    let [r, g, b] = values[..] else {
+        return None;
+    };
+    let byte = |v: f32| (v.clamp(0.0, 1.0) * 255.0).round() as u8;
+    Some(format!("#{:02x}{:02x}{:02x}", byte(r), byte(g), byte(b)))
+}
+
+/// 编码为 PDF 文本字符串：纯 ASCII 用字面量，其余用带 BOM 的 UTF-16BE
+fn text_string(text: &str) -> Object {
+    if text.is_ascii() {
+        return Object::String(text.as_bytes().to_vec(), StringFormat::Literal);
+    }
+    let mut bytes = vec![0xFE, 0xFF];
+    for unit in text.encode_utf16() {
+        bytes.extend_from_slice(&unit.to_be_bytes());
+    }
+    Object::String(bytes, StringFormat::Hexadecimal)
+}
+
+fn name(value: &str) -> Object {
+    Object::Name(value.as_bytes().to_vec())
+}
+
+fn rect(x0: f32, y0: f32, x1: f32, y1: f32) -> Object {
+    Object::Array(vec![
+        Object::Real(x0.min(x1)),
+        Object::Real(y0.min(y1)),
+        Object::Real(x0.max(x1)),
+        Object::Real(y0.max(y1)),
+    ])
+}
+
+/// 导入的批注在 PaperTalk 中的 ID：/NM 按文档区分
+fn imported_id(document_id: &str, nm: &str) -> String {
+    format!("{}:{}", document_id, nm)
+}
+
+/// 写入 PDF 的 /NM：导入的批注还原成原来的 /NM，其余直接用 ID
+fn pdf_name<'a>(document_id: &str, id: &'a str) -> &'a str {
+    id.strip_prefix(document_id)
+        .and_then(|rest| rest.strip_prefix(':'))
+        .unwrap_or(id)
+}
+
+fn annotation_string(doc: &lopdf::Document, dict: &Dictionary, key: &[u8]) -> Option<String> {
+    let text = decode_text_string(dict_get(doc, dict, key)?.as_str().ok()?);
+    let text = text.trim().replace('\r', "\n");
+    (!text.is_empty()).then_some(text)
+}
+
+/* ---------------------------------
+ * 导出
+ * --------------------------------- */
+
+fn lopdf_error(e: lopdf::Error) -> String {
+    format!("Failed to write PDF: {}", e)
+}
+
+// 文件中已有批注的 /NM（没有 /NM 的用导入时相同的方式生成），避免重复导出
+fn existing_names(inc: &IncrementalDocument, document_id: &str, page: u32, page_id: ObjectId) -> HashSet<String> {
+    let doc = inc.get_prev_documents();
+    let Some(annots) = doc
+        .get_dictionary(page_id)
+        .ok()
+        .and_then(|page| dict_get(doc, page, b"Annots"))
+        .and_then(|a| a.as_array().ok())
+    else {
+        return HashSet::new();
+    };
+    annots
+        .iter()
+        .filter_map(|a| resolve(doc, a).as_dict().ok())
+        .filter_map(|a| {
+            annotation_string(doc, a, b"NM").or_else(|| {
+                let subtype = dict_get(doc, a, b"Subtype")?.as_name().ok()?;
+                let contents = annotation_string(doc, a, b"Contents");
+                Some(derived_id(document_id, page, subtype, &numbers(doc, a, b"Rect"), contents.as_deref()))
+            })
+        })
+        .collect()
+}
+
+// 高亮上的批注文字导入时另存为 "{ID}-note"，高亮已在文件中时这条笔记也在
+fn already_written(existing: &HashSet<String>, name: &str) -> bool {
+    existing.contains(name) || name.strip_suffix("-note").is_some_and(|h| existing.contains(h))
+}
+
+/// 把新批注追加到页面的 /Annots（可能是直接数组，也可能是间接对象）
+fn append_annots(inc: &mut IncrementalDocument, page_id: ObjectId, ids: Vec<ObjectId>) -> Result<(), String> {
+    let refs: Vec<Object> = ids.into_iter().map(Object::Reference).collect();
+    inc.opt_clone_object_to_new_document(page_id).map_err(lopdf_error)?;
+    let current = inc
+        .new_document
+        .get_dictionary(page_id)
+        .map_err(lopdf_error)?
+        .get(b"Annots")
+        .ok()
+        .cloned();
+
+    match current {
+        Some(Object::Reference(array_id)) => {
+            inc.opt_clone_object_to_new_document(array_id).map_err(lopdf_error)?;
+            inc.new_document
+                .get_object_mut(array_id)
+                .and_then(Object::as_array_mut)
+                .map_err(lopdf_error)?
+                .extend(refs);
+        }
+        current => {
+            let mut annots = match current {
+                Some(Object::Array(items)) => items,
+                _ => Vec::new(),
+            };
+            annots.extend(refs);
+            inc.new_document
+                .get_object_mut(page_id)
+                .and_then(Object::as_dict_mut)
+                .map_err(lopdf_error)?
+                .set("Annots", Object::Array(annots));
+        }
+    }
+    Ok(())
+}
+
+fn highlight_annotation(highlight: &Highlight, media_box: &MediaBox, page_id: ObjectId) -> Dictionary {
+    let mut points = Vec::with_capacity(highlight.quads.len() * 8);
+    let (mut x0, mut y0, mut x1, mut y1) = (f32::MAX, f32::MAX, f32::MIN, f32::MIN);
+    for quad in &highlight.quads {
+        for pair in quad.chunks(2) {
+            let (x, y) = to_pdf_point(media_box, pair[0], pair[1]);
+            points.push(Object::Real(x));
+            points.push(Object::Real(y));
+            x0 = x0.min(x);
+            y0 = y0.min(y);
+            x1 = x1.max(x);
+            y1 = y1.max(y);
+        }
+    }
+
+    let mut dict = Dictionary::new();
+    dict.set("Type", name("Annot"));
+    dict.set("Subtype", name("Highlight"));
+    dict.set("Rect", rect(x0, y0, x1, y1));
+    dict.set("QuadPoints", Object::Array(points));
+    dict.set("C", Object::Array(color_components(&highlight.color)));
+    dict.set("NM", text_string(pdf_name(&highlight.document_id, &highlight.id)));
+    dict.set("T", text_string(ANNOTATION_AUTHOR));
+    dict.set("P", Object::Reference(page_id));
+    // Print
+    dict.set("F", Object::Integer(4));
+    dict
+}
+
+/// 写入一条笔记：/Text 图标加一个默认收起的 /Popup
+fn add_note_annotations(
+    inc: &mut IncrementalDocument,
+    note: &Note,
+    anchor: (f32, f32),
+    media_box: &MediaBox,
+    page_id: ObjectId,
+) -> Vec<ObjectId> {
+    let (x, y) = to_pdf_point(media_box, anchor.0, anchor.1);
+
+    let mut text = Dictionary::new();
+    text.set("Type", name("Annot"));
+    text.set("Subtype", name("Text"));
+    text.set("Rect", rect(x, y - NOTE_ICON_SIZE, x + NOTE_ICON_SIZE, y));
+    text.set("Contents", text_string(&note.body));
+    text.set("Name", name("Comment"));
+    text.set("NM", text_string(pdf_name(&note.document_id, &note.id)));
+    text.set("T", text_string(ANNOTATION_AUTHOR));
+    text.set("C", Object::Array(color_components(DEFAULT_HIGHLIGHT_COLOR)));
+    text.set("P", Object::Reference(page_id));
+    text.set("F", Object::Integer(4));
+    let text_id = inc.new_document.add_object(text);
+
+    let popup_x = (x + NOTE_ICON_SIZE + 4.0).min(media_box.x1 - POPUP_WIDTH).max(media_box.x0);
+    let mut popup = Dictionary::new();
+    popup.set("Type", name("Annot"));
+    popup.set("Subtype", name("Popup"));
+    popup.set("Rect", rect(popup_x, y - POPUP_HEIGHT, popup_x + POPUP_WIDTH, y));
+    popup.set("Parent", Object::Reference(text_id));
+    popup.set("Open", Object::Boolean(false));
+    popup.set("P", Object::Reference(page_id));
+    let popup_id = inc.new_document.add_object(popup);
+
+    if let Ok(text) = inc.new_document.get_object_mut(text_id).and_then(Object::as_dict_mut) {
+        text.set("Popup", Object::Reference(popup_id));
+    }
+    vec![text_id, popup_id]
+}
+
+/// 把批注以增量更新的方式写入 source 的副本 dest，返回写入的批注数
+pub fn write_annotated_copy(
+    document_id: &str,
+    source: &Path,
+    dest: &Path,
+    annotations: &Annotations,
+) -> Result<usize, String> {
+    let mut inc = IncrementalDocument::load(source).map_err(|e| format!("Failed to parse PDF: {}", e))?;
+    let pages = inc.get_prev_documents().get_pages();
+    let highlights: HashMap<&str, &Highlight> = annotations.highlights.iter().map(|h| (h.id.as_str(), h)).collect();
+
+    let mut written = 0;
+    for (&page, &page_id) in &pages {
+        let media_box = page_media_box(inc.get_prev_documents(), page_id);
+        let existing = existing_names(&inc, document_id, page, page_id);
+        let mut ids = Vec::new();
+
+        for highlight in annotations.highlights.iter().filter(|h| h.page == page) {
+            if already_written(&existing, pdf_name(&highlight.document_id, &highlight.id)) || highlight.quads.is_empty() {
+                continue;
+            }
+            ids.push(inc.new_document.add_object(highlight_annotation(highlight, &media_box, page_id)));
+            written += 1;
+        }
+
+        let mut margin_y = NOTE_MARGIN;
+        for note in annotations.notes.iter().filter(|n| n.page.unwrap_or(1) == page) {
+            if already_written(&existing, pdf_name(&note.document_id, &note.id)) {
+                continue;
+            }
+            // 关联高亮的笔记放在高亮右上角，其余沿左边距往下排
+            let anchor = match note.highlight_id.as_deref().and_then(|id| highlights.get(id)) {
+                Some(highlight) if !highlight.quads.is_empty() => {
+                    let (_, top, right, _) = quad_bounds(&highlight.quads[0]);
+                    (right + 2.0, top)
+                }
+                _ => {
+                    let anchor = (NOTE_MARGIN, margin_y);
+                    margin_y += NOTE_ICON_SIZE + 4.0;
+                    anchor
+                }
+            };
+            ids.extend(add_note_annotations(&mut inc, note, anchor, &media_box, page_id));
+            written += 1;
+        }
+
+        if !ids.is_empty() {
+            append_annots(&mut inc, page_id, ids)?;
+        }
+    }
+
+    // 先写临时文件再改名，写到一半失败不会留下损坏的 PDF
+    let tmp = dest.with_extension("pdf.part");
+    inc.save(&tmp).map_err(|e| format!("Failed to write PDF: {}", e))?;
+    std::fs::rename(&tmp, dest).map_err(|e| {
+        let _ = std::fs::remove_file(&tmp);
+        format!("Failed to save {}: {}", dest.display(), e)
+    })?;
+    Ok(written)
+}
+
+/* ---------------------------------
+ * 导入
+ * --------------------------------- */
+
+struct RawAnnotation {
+    id: String,
+    page: u32,
+    subtype: Vec<u8>,
+    quads: Vec<Quad>,
+    color: Option<String>,
+    contents: Option<String>,
+    // 回复（/IRT）所指批注的 ID
+    reply_to: Option<String>,
+}
+
+/// 没有 /NM 的批注用内容生成稳定的 ID，重复导入时能识别出来
+fn derived_id(document_id: &str, page: u32, subtype: &[u8], rect: &[f32], contents: Option<&str>) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(document_id.as_bytes());
+    hasher.update(page.to_be_bytes());
+    hasher.update(subtype);
+    for v in rect {
+        hasher.update(((v * 10.0).round() as i64).to_be_bytes());
+    }
+    hasher.update(contents.unwrap_or("").as_bytes());
+    let digest = hasher.finalize();
+    let hex: String = digest[..8].iter().map(|b| format!("{:02x}", b)).collect();
+    format!("pdf-{}", hex)
+}
+
+fn numbers(doc: &lopdf::Document, dict: &Dictionary, key: &[u8]) -> Vec<f32> {
+    dict_get(doc, dict, key)
+        .and_then(|o| o.as_array().ok())
+        .map(|items| items.iter().filter_map(|o| resolve(doc, o).as_float().ok()).collect())
+        .unwrap_or_default()
+}
+
+fn read_raw_annotations(doc: &lopdf::Document, document_id: &str) -> Vec<RawAnnotation> {
+    let mut raw = Vec::new();
+    let mut ids_by_object: HashMap<ObjectId, String> = HashMap::new();
+    let mut replies: Vec<(usize, ObjectId)> = Vec::new();
+
+    for (page, page_id) in doc.get_pages() {
+        let media_box = page_media_box(doc, page_id);
+        let Some(annots) = doc
+            .get_dictionary(page_id)
+            .ok()
+            .and_then(|p| dict_get(doc, p, b"Annots"))
+            .and_then(|a| a.as_array().ok())
+        else {
+            continue;
+        };
+
+        for annot in annots {
+            let Ok(dict) = resolve(doc, annot).as_dict() else {
+                continue;
+            };
+            let Some(subtype) = dict_get(doc, dict, b"Subtype").and_then(|s| s.as_name().ok()) else {
+                continue;
+            };
+            if !matches!(subtype, b"Highlight" | b"Text" | b"FreeText") {
+                continue;
+            }
+
+            let rect_values = numbers(doc, dict, b"Rect");
+            let contents = annotation_string(doc, dict, b"Contents");
+            let id = match annotation_string(doc, dict, b"NM") {
+                Some(nm) => imported_id(document_id, &nm),
+                None => derived_id(document_id, page, subtype, &rect_values, contents.as_deref()),
+            };
+
+            // QuadPoints 缺失时用 Rect 代替
+            let mut points = numbers(doc, dict, b"QuadPoints");
+            if points.len() < 8 {
+                if let [x0, y0, x1, y1] = rect_values[..] {
+                    points = vec![x0, y1, x1, y1, x0, y0, x1, y0];
+                }
+            }
+            let quads = points
+                .chunks_exact(8)
+                .map(|c| {
+                    let mut quad = [0.0; 8];
+                    for i in 0..4 {
+                        let (x, y) = from_pdf_point(&media_box, c[i * 2], c[i * 2 + 1]);
+                        quad[i * 2] = x;
+                        quad[i * 2 + 1] = y;
+                    }
+                    quad
+                })
+                .collect();
+
+            if let Ok(object_id) = annot.as_reference() {
+                ids_by_object.insert(object_id, id.clone());
+            }
+            if let Some(Ok(target)) = dict.get(b"IRT").ok().map(Object::as_reference) {
+                replies.push((raw.len(), target));
+            }
+            raw.push(RawAnnotation {
+                id,
+                page,
+                subtype: subtype.to_vec(),
+                quads,
+                color: color_hex(doc, dict_get(doc, dict, b"C")),
+                contents,
+                reply_to: None,
+            });
+        }
+    }
+
+    for (index, target) in replies {
+        raw[index].reply_to = ids_by_object.get(&target).cloned();
+    }
+    raw
+}
+
+/// 高亮本身通常不保存文字，按 QuadPoints 从页面文本中取出被覆盖的片段
+fn covered_text(cache: &PdfTextCache, path: &Path, page: u32, quads: &[Quad]) -> String {
+    let Ok(page_text) = cache.page_text(path, page) else {
+        return String::new();
+    };
+    let bounds: Vec<(f32, f32, f32, f32)> = quads.iter().map(quad_bounds).collect();
+    let mut parts = Vec::new();
+    for line in &page_text.lines {
+        for span in &line.spans {
+            let cx = span.x + span.width / 2.0;
+            let cy = span.y + span.height / 2.0;
+            if bounds
+                .iter()
+                .any(|&(x0, y0, x1, y1)| cx >= x0 - 1.0 && cx <= x1 + 1.0 && cy >= y0 - 2.0 && cy <= y1 + 2.0)
+            {
+                parts.push(span.text.trim());
+            }
+        }
+    }
+    parts.join(" ")
+}
+
+/// 读取 PDF 中已有的批注并存入 PaperTalk
+pub fn import_annotations(
+    storage: &Storage,
+    cache: &PdfTextCache,
+    document_id: &str,
+    path: &Path,
+) -> Result<AnnotationImport, String> {
+    let raw = cache.with_document(path, |doc| read_raw_annotations(doc, document_id))?;
+    let now = now_secs();
+    let mut summary = AnnotationImport::default();
+    let mut highlight_ids = HashSet::new();
+
+    for annot in raw.iter().filter(|a| a.subtype == b"Highlight") {
+        if annot.quads.is_empty() {
+            summary.skipped += 1;
+            continue;
+        }
+        let highlight = Highlight {
+            id: annot.id.clone(),
+            document_id: document_id.to_string(),
+            page: annot.page,
+            text: covered_text(cache, path, annot.page, &annot.quads),
+            quads: annot.quads.clone(),
+            color: annot.color.clone().unwrap_or_else(|| DEFAULT_HIGHLIGHT_COLOR.to_string()),
+            created_at: now,
+            updated_at: now,
+        };
+        highlight_ids.insert(highlight.id.clone());
+        if annotations::insert_highlight(storage, &highlight)? {
+            summary.highlights += 1;
+        } else {
+            summary.skipped += 1;
+        }
+
+        // 高亮上的批注文字作为关联笔记
+        if let Some(body) = &annot.contents {
+            let note = Note {
+                id: format!("{}-note", annot.id),
+                document_id: document_id.to_string(),
+                highlight_id: Some(annot.id.clone()),
+                page: Some(annot.page),
+                body: body.clone(),
+                tags: extract_tags(body),
+                created_at: now,
+                updated_at: now,
+            };
+            if annotations::insert_note(storage, &note)? {
+                summary.notes += 1;
+            }
+        }
+    }
+
+    for annot in raw.iter().filter(|a| a.subtype != b"Highlight") {
+        let Some(body) = &annot.contents else {
+            summary.skipped += 1;
+            continue;
+        };
+        let highlight_id = annot.reply_to.clone().filter(|id| highlight_ids.contains(id));
+        let note = Note {
+            id: annot.id.clone(),
+            document_id: document_id.to_string(),
+            highlight_id,
+            page: Some(annot.page),
+            body: body.clone(),
+            tags: extract_tags(body),
+            created_at: now,
+            updated_at: now,
+        };
+        if annotations::insert_note(storage, &note)? {
+            summary.notes += 1;
+        } else {
+            summary.skipped += 1;
+        }
+    }
+    Ok(summary)
+}
+
+/* ---------------------------------
+ * 命令
+ * --------------------------------- */
+
+/// 选择保存位置后，把文档的高亮和笔记写入一份 PDF 副本，返回副本路径
+#[tauri::command]
+pub async fn export_annotated_pdf(document_id: String, app_handle: tauri::AppHandle) -> Result<Option<String>, String> {
+    use tauri::Manager;
+
+    let (source, annotations) = {
+        let storage = app_handle.state::<Storage>();
+        let entry = library::find_entry(&storage, &document_id)?
+            .ok_or_else(|| format!("Document not in library: {}", document_id))?;
+        let source = app_handle.state::<FileScope>().resolve_pdf(&entry.path)?;
+        (source, annotations::document_annotations(&storage, &document_id)?)
+    };
+    if annotations.highlights.is_empty() && annotations.notes.is_empty() {
+        return Err("This document has no annotations to export".to_string());
+    }
+
+    let stem = source
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(|| "document".to_string());
+    let file_name = format!("{}-annotated.pdf", stem);
+    let Some(dest) = file_scope::save_dialog(&app_handle, "PDF", &["pdf"], file_name).await? else {
+        return Ok(None);
+    };
+    if dest.canonicalize().is_ok_and(|d| d == source) {
+        return Err("Choose a different file; the original PDF is never modified".to_string());
+    }
+
+    let written = {
+        let dest = dest.clone();
+        tauri::async_runtime::spawn_blocking(move || write_annotated_copy(&document_id, &source, &dest, &annotations))
+            .await
+            .map_err(|e| format!("Annotation export failed: {}", e))??
+    };
+    println!("📝 已写入 {} 条批注: {}", written, dest.display());
+
+    // 导出的副本可以直接在 PaperTalk 中打开
+    let storage = app_handle.state::<Storage>();
+    let canonical = app_handle.state::<FileScope>().allow_file(&storage, &dest)?;
+    Ok(Some(canonical.to_string_lossy().to_string()))
+}
+
+#[tauri::command]
+pub async fn import_pdf_annotations(document_id: String, app_handle: tauri::AppHandle) -> Result<AnnotationImport, String> {
+    use tauri::Manager;
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let storage = app_handle.state::<Storage>();
+        let entry = library::find_entry(&storage, &document_id)?
+            .ok_or_else(|| format!("Document not in library: {}", document_id))?;
+        let path = app_handle.state::<FileScope>().resolve_pdf(&entry.path)?;
+        let summary = import_annotations(&storage, &app_handle.state::<PdfTextCache>(), &document_id, &path)?;
+        println!(
+            "📥 导入批注: {} 条高亮, {} 条笔记, 跳过 {} 条",
+            summary.highlights, summary.notes, summary.skipped
+        );
+        Ok(summary)
+    })
+    .await
+    .map_err(|e| format!("Annotation import failed: {}", e))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lopdf::{Document, Stream};
+    use std::path::PathBuf;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("papertalk-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn reals(values: &[f32]) -> Object {
+        Object::Array(values.iter().map(|v| Object::Real(*v)).collect())
+    }
+
+    // 两页的 PDF：第 1 页 /Annots 是直接数组，里面有一条别的阅读器写的高亮；
+    // 第 2 页的 MediaBox 不从原点开始，/Annots 是间接引用的数组，里面有一条没有 /NM 的笔记
+    fn write_fixture(path: &Path) {
+        let mut doc = Document::with_version("1.5");
+        let pages_id = doc.new_object_id();
+        let content_id = doc.add_object(Stream::new(Dictionary::new(), Vec::new()));
+
+        let mut highlight = Dictionary::new();
+        highlight.set("Type", name("Annot"));
+        highlight.set("Subtype", name("Highlight"));
+        highlight.set("Rect", reals(&[72.0, 680.0, 300.0, 692.0]));
+        highlight.set("QuadPoints", reals(&[72.0, 692.0, 300.0, 692.0, 72.0, 680.0, 300.0, 680.0]));
+        highlight.set("NM", text_string("ext-1"));
+        highlight.set("Contents", text_string("from another reader"));
+        let highlight_id = doc.add_object(highlight);
+
+        let mut note = Dictionary::new();
+        note.set("Type", name("Annot"));
+        note.set("Subtype", name("Text"));
+        note.set("Rect", reals(&[20.0, 780.0, 40.0, 800.0]));
+        note.set("Contents", text_string("margin note"));
+        let note_id = doc.add_object(note);
+        let annots_id = doc.add_object(Object::Array(vec![Object::Reference(note_id)]));
+
+        let mut kids = Vec::new();
+        for (media_box, annots) in [
+            ([0.0, 0.0, 612.0, 792.0], Object::Array(vec![Object::Reference(highlight_id)])),
+            ([10.0, 20.0, 622.0, 812.0], Object::Reference(annots_id)),
+        ] {
+            let mut page = Dictionary::new();
+            page.set("Type", name("Page"));
+            page.set("Parent", Object::Reference(pages_id));
+            page.set("MediaBox", reals(&media_box));
+            page.set("Contents", Object::Reference(content_id));
+            page.set("Annots", annots);
+            kids.push(Object::Reference(doc.add_object(page)));
+        }
+        let mut pages = Dictionary::new();
+        pages.set("Type", name("Pages"));
+        pages.set("Count", Object::Integer(kids.len() as i64));
+        pages.set("Kids", Object::Array(kids));
+        doc.objects.insert(pages_id, Object::Dictionary(pages));
+
+        let mut catalog = Dictionary::new();
+        catalog.set("Type", name("Catalog"));
+        catalog.set("Pages", Object::Reference(pages_id));
+        let catalog_id = doc.add_object(catalog);
+        doc.trailer.set("Root", Object::Reference(catalog_id));
+        doc.save(path).unwrap();
+    }
+
+    fn page_annots(path: &Path) -> Vec<(u32, Dictionary)> {
+        let doc = Document::load(path).unwrap();
+        let mut annots = Vec::new();
+        for (page, page_id) in doc.get_pages() {
+            let page_dict = doc.get_dictionary(page_id).unwrap();
+            let Some(items) = dict_get(&doc, page_dict, b"Annots").and_then(|a| a.as_array().ok()) else {
+                continue;
+            };
+            for item in items {
+                annots.push((page, resolve(&doc, item).as_dict().unwrap().clone()));
+            }
+        }
+        annots
+    }
+
+    fn count(annots: &[(u32, Dictionary)], page: u32, subtype: &[u8]) -> usize {
+        annots
+            .iter()
+            .filter(|(p, a)| *p == page && a.get(b"Subtype").and_then(Object::as_name).ok() == Some(subtype))
+            .count()
+    }
+
+    #[test]
+    fn page_coordinates_round_trip_through_pdf_space() {
+        let media_box = MediaBox {
+            x0: 10.0,
+            y0: 20.0,
+            x1: 622.0,
+            y1: 812.0,
+        };
+        for (x, y) in [(0.0, 0.0), (72.0, 100.0), (612.0, 792.0)] {
+            let (px, py) = to_pdf_point(&media_box, x, y);
+            assert_eq!(from_pdf_point(&media_box, px, py), (x, y));
+        }
+        // 左上角原点的 y 越大，PDF 坐标越靠下
+        assert_eq!(to_pdf_point(&media_box, 0.0, 0.0), (10.0, 812.0));
+        assert_eq!(to_pdf_point(&media_box, 72.0, 100.0), (82.0, 712.0));
+        assert_eq!(pdf_name("doc", "doc:ext-1"), "ext-1");
+        assert_eq!(pdf_name("doc", "other:ext-1"), "other:ext-1");
+        assert_eq!(pdf_name("doc", "h-1"), "h-1");
+    }
+
+    #[test]
+    fn export_appends_to_existing_annots_and_reexport_does_not_duplicate() {
+        let dir = temp_dir("pdf-annotations");
+        let source = dir.join("paper.pdf");
+        let copy = dir.join("paper-annotated.pdf");
+        let again = dir.join("paper-annotated-again.pdf");
+        write_fixture(&source);
+
+        let storage = Storage::open_in_memory().unwrap();
+        let quad = [72.0, 100.0, 300.0, 100.0, 72.0, 112.0, 300.0, 112.0];
+        let highlight = annotations::add_highlight(&storage, "doc", 1, vec![quad], None, "attention").unwrap();
+        annotations::add_note(&storage, "doc", "see eq. 3", None, Some(2)).unwrap();
+        let original = annotations::document_annotations(&storage, "doc").unwrap();
+
+        assert_eq!(write_annotated_copy("doc", &source, &copy, &original).unwrap(), 2);
+        let annots = page_annots(&copy);
+        // 第 1 页的直接数组、第 2 页的间接数组都保留了原有批注
+        assert_eq!(count(&annots, 1, b"Highlight"), 2);
+        assert_eq!(count(&annots, 2, b"Text"), 2);
+        assert_eq!(count(&annots, 2, b"Popup"), 1);
+        let written = annots
+            .iter()
+            .find(|(_, a)| a.get(b"NM").ok().and_then(|n| n.as_str().ok()) == Some(highlight.id.as_bytes()))
+            .map(|(_, a)| a.clone())
+            .unwrap();
+        let doc = Document::new();
+        assert_eq!(numbers(&doc, &written, b"QuadPoints")[..2], [72.0, 692.0]);
+
+        // 同样的批注再导出一次不会重复写入
+        assert_eq!(write_annotated_copy("doc", &copy, &again, &original).unwrap(), 0);
+        assert_eq!(page_annots(&again).len(), annots.len());
+
+        // 副本作为另一个文档导入：/NM 与原文档相同也不会被跳过
+        let cache = PdfTextCache::default();
+        let imported = import_annotations(&storage, &cache, "copy", &copy).unwrap();
+        assert_eq!((imported.highlights, imported.notes, imported.skipped), (2, 3, 0));
+        let copied = annotations::document_annotations(&storage, "copy").unwrap();
+        assert!(copied.highlights.iter().any(|h| h.id == format!("copy:{}", highlight.id)));
+        assert_eq!(copied.highlights[0].quads[0][..2], [72.0, 100.0]);
+        assert_eq!(annotations::document_annotations(&storage, "doc").unwrap().highlights.len(), 1);
+
+        // 重复导入全部跳过；把导入的批注导出回同一份文件也不会重复
+        let again_import = import_annotations(&storage, &cache, "copy", &copy).unwrap();
+        assert_eq!((again_import.highlights, again_import.notes), (0, 0));
+        assert_eq!(write_annotated_copy("copy", &copy, &again, &copied).unwrap(), 0);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,226 @@
+// pdf:// 自定义协议：按 HTTP Range 分段提供 PDF 内容
+//
+// 前端用 convertFileSrc(path, 'pdf') 得到地址，pdf.js 只按需请求用到的字节段，
+// 不必把整个文件读进内存再经 IPC 序列化成 JSON 数组。
+
+use percent_encoding::percent_decode_str;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+use tauri::http::{header, Method, Request, Response, StatusCode};
+
+use crate::file_scope::FileScope;
+
+pub const SCHEME: &str = "pdf";
+
+// 单次 Range 请求最多返回的字节数，超出部分由前端继续请求
+const MAX_RANGE_LEN: u64 = 8 * 1024 * 1024;
+
+pub fn handle(scope: &FileScope, request: &Request<Vec<u8>>) -> Response<Vec<u8>> {
+    if request.method() == Method::OPTIONS {
+        return cors(Response::builder().status(StatusCode::NO_CONTENT))
+            .body(Vec::new())
+            .unwrap();
+    }
+
+    let encoded = request.uri().path().trim_start_matches('/');
+    let raw_path = percent_decode_str(encoded).decode_utf8_lossy();
+    let path = match scope.resolve_pdf(&raw_path) {
+        Ok(path) => path,
+        Err(e) => {
+            println!("❌ [pdf_protocol] {}", e);
+            return error_response(StatusCode::FORBIDDEN, &e);
+        }
+    };
+
+    match serve(&path, request) {
+        Ok(response) => response,
+        Err(e) => {
+            println!("❌ [pdf_protocol] 读取失败 {}: {}", path.display(), e);
+            error_response(StatusCode::INTERNAL_SERVER_ERROR, &e)
+        }
+    }
+}
+
+fn serve(path: &Path, request: &Request<Vec<u8>>) -> Result<Response<Vec<u8>>, String> {
+    let mut file = File::open(path).map_err(|e| e.to_string())?;
+    let size = file.metadata().map_err(|e| e.to_string())?.len();
+
+    let builder = cors(Response::builder())
+        .header(header::CONTENT_TYPE, "application/pdf")
+        .header(header::ACCEPT_RANGES, "bytes");
+
+    // HEAD 只返回长度，pdf.js 据此决定分段请求
+    if request.method() == Method::HEAD {
+        return Ok(builder
+            .header(header::CONTENT_LENGTH, size)
+            .body(Vec::new())
+            .unwrap());
+    }
+
+    let range = request
+        .headers()
+        .get(header::RANGE)
+        .and_then(|v| v.to_str().ok());
+
+    // 没有 Range 头时按 HTTP 语义返回 200 和完整内容，上限只用于分段请求
+    let Some(range) = range else {
+        let mut data = Vec::with_capacity(size as usize);
+        file.read_to_end(&mut data).map_err(|e| e.to_string())?;
+        return Ok(builder
+            .header(header::CONTENT_LENGTH, data.len())
+            .body(data)
+            .unwrap());
+    };
+
+    let Some((start, end)) = parse_range(range, size) else {
+        return Ok(cors(Response::builder())
+            .status(StatusCode::RANGE_NOT_SATISFIABLE)
+            .header(header::CONTENT_RANGE, format!("bytes */{}", size))
+            .body(Vec::new())
+            .unwrap());
+    };
+    // 超出上限时返回较短的 206，Content-Range 标明实际范围
+    read_range(&mut file, builder, start, end.min(start + MAX_RANGE_LEN - 1), size)
+}
+
+fn read_range(
+    file: &mut File,
+    builder: tauri::http::response::Builder,
+    start: u64,
+    end: u64,
+    size: u64,
+) -> Result<Response<Vec<u8>>, String> {
+    let mut data = vec![0; (end - start + 1) as usize];
+    file.seek(SeekFrom::Start(start)).map_err(|e| e.to_string())?;
+    file.read_exact(&mut data).map_err(|e| e.to_string())?;
+
+    Ok(builder
+        .status(StatusCode::PARTIAL_CONTENT)
+        .header(header::CONTENT_RANGE, format!("bytes {}-{}/{}", start, end, size))
+        .header(header::CONTENT_LENGTH, data.len())
+        .body(data)
+        .unwrap())
+}
+
+/// 解析单段 Range：bytes=a-b、bytes=a-、bytes=-n；返回闭区间
+fn parse_range(value: &str, size: u64) -> Option<(u64, u64)> {
+    let spec = value.trim().strip_prefix("bytes=")?;
+    // 多段 Range 只取第一段
+    let spec = spec.split(',').next()?.trim();
+    let (start, end) = spec.split_once('-')?;
+
+    if size == 0 {
+        return None;
+    }
+    let (start, end) = match (start.trim(), end.trim()) {
+        ("", suffix) => {
+            let len: u64 = suffix.parse().ok()?;
+            if len == 0 {
+                return None;
+            }
+            (size.saturating_sub(len), size - 1)
+        }
+        (start, "") => (start.parse().ok()?, size - 1),
+        (start, end) => (start.parse().ok()?, end.parse::<u64>().ok()?.min(size - 1)),
+    };
+    if start > end || start >= size {
+        return None;
+    }
+    Some((start, end))
+}
+
+// 自定义协议与页面不同源，需要放开跨域读取以及 Range 相关的响应头
+fn cors(builder: tauri::http::response::Builder) -> tauri::http::response::Builder {
+    builder
+        .header(header::ACCESS_CONTROL_ALLOW_ORIGIN, "*")
+        .header(header::ACCESS_CONTROL_ALLOW_METHODS, "GET, HEAD, OPTIONS")
+        .header(header::ACCESS_CONTROL_ALLOW_HEADERS, "Range")
+        .header(
+            header::ACCESS_CONTROL_EXPOSE_HEADERS,
+            "Accept-Ranges, Content-Length, Content-Range",
+        )
+}
+
+fn error_response(status: StatusCode, message: &str) -> Response<Vec<u8>> {
+    cors(Response::builder())
+        .status(status)
+        .header(header::CONTENT_TYPE, "text/plain; charset=utf-8")
+        .body(message.as_bytes().to_vec())
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_range_handles_single_ranges() {
+        let cases = [
+            ("bytes=0-99", 1000, Some((0, 99))),
+            ("bytes=100-", 1000, Some((100, 999))),
+            ("bytes=-100", 1000, Some((900, 999))),
+            // 后缀长度超过文件大小时返回整个文件
+            ("bytes=-5000", 1000, Some((0, 999))),
+            ("bytes=900-5000", 1000, Some((900, 999))),
+            (" bytes= 10 - 20 ", 1000, Some((10, 20))),
+            // 多段只取第一段
+            ("bytes=0-9, 20-29", 1000, Some((0, 9))),
+            ("bytes=-10,0-5", 1000, Some((990, 999))),
+            ("bytes=999-999", 1000, Some((999, 999))),
+            // 越界、反向和无效写法
+            ("bytes=1000-", 1000, None),
+            ("bytes=1000-1100", 1000, None),
+            ("bytes=50-10", 1000, None),
+            ("bytes=-0", 1000, None),
+            ("bytes=-", 1000, None),
+            ("bytes=a-b", 1000, None),
+            ("bytes=10", 1000, None),
+            ("items=0-10", 1000, None),
+            ("", 1000, None),
+            // 空文件没有可返回的范围
+            ("bytes=0-", 0, None),
+            ("bytes=-10", 0, None),
+        ];
+        for (value, size, expected) in cases {
+            assert_eq!(parse_range(value, size), expected, "{:?} / {}", value, size);
+        }
+    }
+    #[test]
+    fn serve_returns_full_body_without_range_and_caps_ranged_reads() {
+        let path = std::env::temp_dir().join(format!("papertalk-protocol-{}.pdf", std::process::id()));
+        let size = MAX_RANGE_LEN + 1024;
+        let content: Vec<u8> = (0..size).map(|i| (i % 251) as u8).collect();
+        std::fs::write(&path, &content).unwrap();
+
+        let get = |range: Option<&str>| {
+            let mut builder = Request::builder().method(Method::GET).uri("pdf://localhost/x");
+            if let Some(range) = range {
+                builder = builder.header(header::RANGE, range);
+            }
+            serve(&path, &builder.body(Vec::new()).unwrap()).unwrap()
+        };
+
+        let full = get(None);
+        assert_eq!(full.status(), StatusCode::OK);
+        assert_eq!(full.body().len() as u64, size);
+        assert!(full.headers().get(header::CONTENT_RANGE).is_none());
+
+        let capped = get(Some("bytes=0-"));
+        assert_eq!(capped.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(capped.body().len() as u64, MAX_RANGE_LEN);
+        assert_eq!(
+            capped.headers()[header::CONTENT_RANGE],
+            format!("bytes 0-{}/{}", MAX_RANGE_LEN - 1, size).as_str()
+        );
+
+        let tail = get(Some("bytes=-10"));
+        assert_eq!(tail.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(tail.body().as_slice(), &content[content.len() - 10..]);
+
+        let outside = get(Some(&format!("bytes={}-", size)));
+        assert_eq!(outside.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
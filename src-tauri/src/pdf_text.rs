@@ -0,0 +1,1353 @@
+// PDF 文本提取：按页给出带位置、字号的文本，并整理成阅读顺序
+//
+// 用 lopdf 解析内容流，自己维护文本矩阵来计算每段文字的位置。
+// 坐标单位为 PDF 点，原点在页面左上角，与 pdf.js 在 scale = 1 时的视口一致
+// （暂不处理 /Rotate）。解析结果按文档缓存，文件未修改时不重复解析。
+
+use lopdf::content::Content;
+use lopdf::{Dictionary, Document, Object, ObjectId, Stream};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use crate::file_scope::FileScope;
+
+// 同时缓存的文档数，超过后丢弃最久未用的
+const MAX_CACHED_DOCUMENTS: usize = 4;
+// Form XObject 嵌套上限，防止循环引用
+const MAX_FORM_DEPTH: usize = 8;
+// TJ 数组中超过这个负偏移（千分之一字号）视为词间空格
+const TJ_SPACE_THRESHOLD: f32 = 200.0;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TextSpan {
+    pub text: String,
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+    pub font: String,
+    pub font_size: f32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TextLine {
+    pub text: String,
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+    pub spans: Vec<TextSpan>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PageText {
+    // 页码从 1 开始，与 pdf.js 一致
+    pub page: u32,
+    pub width: f32,
+    pub height: f32,
+    pub text: String,
+    // 已按阅读顺序排列：双栏页面先左栏后右栏
+    pub lines: Vec<TextLine>,
+    // 文字来自 OCR 识别而非 PDF 文本层
+    #[serde(default)]
+    pub ocr: bool,
+}
+
+/* ---------------------------------
+ * 文档缓存
+ * --------------------------------- */
+
+struct LoadedPdf {
+    doc: Document,
+    fonts: Mutex<HashMap<ObjectId, Arc<Font>>>,
+    pages: Mutex<HashMap<u32, Arc<PageText>>>,
+}
+
+struct CacheEntry {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    pdf: Arc<LoadedPdf>,
+}
+
+#[derive(Default)]
+pub struct PdfTextCache {
+    // 最近使用的排在最后
+    docs: Mutex<Vec<CacheEntry>>,
+}
+
+impl PdfTextCache {
+    pub fn page_text(&self, path: &Path, page: u32) -> Result<Arc<PageText>, String> {
+        self.load(path)?.page_text(page)
+    }
+
+    /// 按页顺序返回整篇文档的文本，无法解析的页会被跳过
+    pub fn document_text(&self, path: &Path) -> Result<Vec<Arc<PageText>>, String> {
+        let pdf = self.load(path)?;
+        let count = pdf.doc.get_pages().len() as u32;
+        Ok((1..=count)
+            .filter_map(|page| match pdf.page_text(page) {
+                Ok(text) => Some(text),
+                Err(e) => {
+                    println!("⚠️ 跳过第 {} 页：{}", page, e);
+                    None
+                }
+            })
+            .collect())
+    }
+
+    /// 直接访问已解析的 lopdf 文档，用于读取书签、元数据等
+    pub fn with_document<T>(&self, path: &Path, f: impl FnOnce(&Document) -> T) -> Result<T, String> {
+        let pdf = self.load(path)?;
+        Ok(f(&pdf.doc))
+    }
+
+    fn load(&self, path: &Path) -> Result<Arc<LoadedPdf>, String> {
+        let modified = std::fs::metadata(path).and_then(|m| m.modified()).ok();
+        {
+            let mut docs = self.docs.lock().unwrap_or_else(|e| e.into_inner());
+            if let Some(pos) = docs.iter().position(|d| d.path == path && d.modified == modified) {
+                let entry = docs.remove(pos);
+                let pdf = entry.pdf.clone();
+                docs.push(entry);
+                return Ok(pdf);
+            }
+        }
+
+        // 解析放在锁外，大文件不阻塞其他文档的查询
+        println!("📄 解析 PDF 文本: {}", path.display());
+        let doc = Document::load(path).map_err(|e| format!("Failed to parse PDF: {}", e))?;
+        let pdf = Arc::new(LoadedPdf {
+            doc,
+            fonts: Mutex::new(HashMap::new()),
+            pages: Mutex::new(HashMap::new()),
+        });
+
+        let mut docs = self.docs.lock().unwrap_or_else(|e| e.into_inner());
+        docs.retain(|d| d.path != path);
+        docs.push(CacheEntry {
+            path: path.to_path_buf(),
+            modified,
+            pdf: pdf.clone(),
+        });
+        if docs.len() > MAX_CACHED_DOCUMENTS {
+            docs.remove(0);
+        }
+        Ok(pdf)
+    }
+}
+
+impl LoadedPdf {
+    fn page_text(&self, page: u32) -> Result<Arc<PageText>, String> {
+        if let Some(cached) = self.pages.lock().unwrap_or_else(|e| e.into_inner()).get(&page) {
+            return Ok(cached.clone());
+        }
+
+        let pages = self.doc.get_pages();
+        let page_id = *pages
+            .get(&page)
+            .ok_or_else(|| format!("Page {} out of range (1-{})", page, pages.len()))?;
+
+        let media_box = page_media_box(&self.doc, page_id);
+        let mut interpreter = Interpreter {
+            pdf: self,
+            spans: Vec::new(),
+        };
+        let content = self
+            .doc
+            .get_page_content(page_id)
+            .map_err(|e| format!("Failed to read page {}: {}", page, e))?;
+        let resources = inherited(&self.doc, page_id, b"Resources").and_then(|o| o.as_dict().ok());
+        interpreter.run(&content, resources, GraphicsState::default(), 0);
+
+        let text = Arc::new(layout_page(page, media_box, interpreter.spans));
+        self.pages
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(page, text.clone());
+        Ok(text)
+    }
+
+    fn font(&self, resources: Option<&Dictionary>, name: &[u8]) -> Option<Arc<Font>> {
+        let fonts = resources
+            .and_then(|r| r.get(b"Font").ok())
+            .and_then(|f| resolve(&self.doc, f).as_dict().ok())?;
+        let entry = fonts.get(name).ok()?;
+
+        // 间接引用的字体按对象号缓存，ToUnicode 只解析一次
+        let Ok(id) = entry.as_reference() else {
+            let dict = entry.as_dict().ok()?;
+            return Some(Arc::new(Font::load(&self.doc, dict)));
+        };
+        if let Some(font) = self.fonts.lock().unwrap_or_else(|e| e.into_inner()).get(&id) {
+            return Some(font.clone());
+        }
+        let font = Arc::new(Font::load(&self.doc, self.doc.get_dictionary(id).ok()?));
+        self.fonts
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(id, font.clone());
+        Some(font)
+    }
+}
+
+pub fn resolve<'a>(doc: &'a Document, object: &'a Object) -> &'a Object {
+    doc.dereference(object).map(|(_, o)| o).unwrap_or(object)
+}
+
+/// 流的解码内容；没有 /Filter 的流直接返回原始字节（lopdf 对这种流会报错）
+pub fn stream_content(stream: &Stream) -> Option<Vec<u8>> {
+    if stream.dict.has(b"Filter") {
+        stream.decompressed_content().ok()
+    } else {
+        Some(stream.content.clone())
+    }
+}
+
+pub fn dict_get<'a>(doc: &'a Document, dict: &'a Dictionary, key: &[u8]) -> Option<&'a Object> {
+    dict.get(key).ok().map(|o| resolve(doc, o))
+}
+
+// Resources、MediaBox 等属性可以从父节点继承
+pub fn inherited<'a>(doc: &'a Document, page_id: ObjectId, key: &[u8]) -> Option<&'a Object> {
+    let mut dict = doc.get_dictionary(page_id).ok()?;
+    for _ in 0..32 {
+        if let Some(value) = dict_get(doc, dict, key) {
+            return Some(value);
+        }
+        dict = dict_get(doc, dict, b"Parent")?.as_dict().ok()?;
+    }
+    None
+}
+
+fn numbers(doc: &Document, object: &Object) -> Vec<f32> {
+    object
+        .as_array()
+        .map(|items| items.iter().filter_map(|o| resolve(doc, o).as_float().ok()).collect())
+        .unwrap_or_default()
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct MediaBox {
+    pub x0: f32,
+    pub y0: f32,
+    pub x1: f32,
+    pub y1: f32,
+}
+
+pub fn page_media_box(doc: &Document, page_id: ObjectId) -> MediaBox {
+    let values = inherited(doc, page_id, b"MediaBox")
+        .map(|o| numbers(doc, o))
+        .unwrap_or_default();
+    match values[..] {
+        [a, b, c, d] => MediaBox {
+            x0: a.min(c),
+            y0: b.min(d),
+            x1: a.max(c),
+            y1: b.max(d),
+        },
+        // US Letter
+        _ => MediaBox {
+            x0: 0.0,
+            y0: 0.0,
+            x1: 612.0,
+            y1: 792.0,
+        },
+    }
+}
+
+/* ---------------------------------
+ * 字体：字符编码与字宽
+ * --------------------------------- */
+
+struct Font {
+    name: String,
+    // 单字节（简单字体）或双字节（Type0 / CID 字体）
+    code_len: usize,
+    to_unicode: HashMap<u32, String>,
+    // 简单字体 /Encoding /Differences 中的字形名
+    differences: HashMap<u32, String>,
+    // 字宽，单位为千分之一字号
+    widths: HashMap<u32, f32>,
+    default_width: f32,
+}
+
+impl Font {
+    fn load(doc: &Document, dict: &Dictionary) -> Font {
+        let name = dict_get(doc, dict, b"BaseFont")
+            .and_then(|o| o.as_name().ok())
+            .map(|n| String::from_utf8_lossy(n).to_string())
+            .unwrap_or_default();
+        let is_type0 = dict_get(doc, dict, b"Subtype")
+            .and_then(|o| o.as_name().ok())
+            .is_some_and(|n| n == b"Type0");
+
+        let mut font = Font {
+            name,
+            code_len: if is_type0 { 2 } else { 1 },
+            to_unicode: HashMap::new(),
+            differences: HashMap::new(),
+            widths: HashMap::new(),
+            default_width: if is_type0 { 1000.0 } else { 500.0 },
+        };
+
+        if let Some(data) = dict_get(doc, dict, b"ToUnicode")
+            .and_then(|o| o.as_stream().ok())
+            .and_then(stream_content)
+        {
+            let cmap = parse_cmap(&data);
+            if let Some(len) = cmap.code_len {
+                font.code_len = len;
+            }
+            font.to_unicode = cmap.map;
+        }
+
+        if is_type0 {
+            font.load_cid_widths(doc, dict);
+        } else {
+            font.load_simple_widths(doc, dict);
+            font.load_differences(doc, dict);
+        }
+        font
+    }
+
+    fn load_simple_widths(&mut self, doc: &Document, dict: &Dictionary) {
+        if let Some(missing) = dict_get(doc, dict, b"FontDescriptor")
+            .and_then(|o| o.as_dict().ok())
+            .and_then(|d| dict_get(doc, d, b"MissingWidth"))
+            .and_then(|o| o.as_float().ok())
+        {
+            self.default_width = missing;
+        }
+        // 负的 FirstChar 无法对应任何字符码，整组字宽丢弃
+        let Some(first) = dict_get(doc, dict, b"FirstChar")
+            .and_then(|o| o.as_i64().ok())
+            .map_or(Some(0), |n| u32::try_from(n).ok())
+        else {
+            return;
+        };
+        if let Some(widths) = dict_get(doc, dict, b"Widths") {
+            for (code, w) in (first..=u32::MAX).zip(numbers(doc, widths)) {
+                self.widths.insert(code, w);
+            }
+        }
+    }
+
+    // CID 字体的 /W 数组有两种写法：c [w1 w2 ...] 与 c_first c_last w
+    fn load_cid_widths(&mut self, doc: &Document, dict: &Dictionary) {
+        let Some(descendant) = dict_get(doc, dict, b"DescendantFonts")
+            .and_then(|o| o.as_array().ok())
+            .and_then(|a| a.first())
+            .and_then(|o| resolve(doc, o).as_dict().ok())
+        else {
+            return;
+        };
+        if let Some(dw) = dict_get(doc, descendant, b"DW").and_then(|o| o.as_float().ok()) {
+            self.default_width = dw;
+        }
+        let Some(items) = dict_get(doc, descendant, b"W").and_then(|o| o.as_array().ok()) else {
+            return;
+        };
+
+        let mut i = 0;
+        while i < items.len() {
+            // 字符码不能为负，遇到损坏的数组就停止解析
+            let Some(start) = resolve(doc, &items[i]).as_i64().ok().and_then(|n| u32::try_from(n).ok()) else {
+                break;
+            };
+            match items.get(i + 1).map(|o| resolve(doc, o)) {
+                Some(list @ Object::Array(_)) => {
+                    for (code, w) in (start..=u32::MAX).zip(numbers(doc, list)) {
+                        self.widths.insert(code, w);
+                    }
+                    i += 2;
+                }
+                Some(end) => {
+                    let end = end.as_i64().ok().and_then(|n| u32::try_from(n).ok());
+                    let (Some(end), Some(Ok(w))) = (end, items.get(i + 2).map(|o| resolve(doc, o).as_float())) else {
+                        break;
+                    };
+                    for code in start..=end.min(start.saturating_add(0xFFFF)) {
+                        self.widths.insert(code, w);
+                    }
+                    i += 3;
+                }
+                None => break,
+            }
+        }
+    }
+
+    fn load_differences(&mut self, doc: &Document, dict: &Dictionary) {
+        let Some(differences) = dict_get(doc, dict, b"Encoding")
+            .and_then(|o| o.as_dict().ok())
+            .and_then(|d| dict_get(doc, d, b"Differences"))
+            .and_then(|o| o.as_array().ok())
+        else {
+            return;
+        };
+        // 负数或越界的起始码之后的字形名没有对应的字符码，跳过直到下一个整数
+        let mut code = Some(0u32);
+        for item in differences {
+            match resolve(doc, item) {
+                Object::Integer(n) => code = u32::try_from(*n).ok(),
+                Object::Name(glyph) => {
+                    if let (Some(code), Some(text)) = (code, glyph_to_text(&String::from_utf8_lossy(glyph))) {
+                        self.differences.insert(code, text);
+                    }
+                    code = code.and_then(|c| c.checked_add(1));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn codes(&self, bytes: &[u8]) -> Vec<u32> {
+        bytes
+            .chunks(self.code_len)
+            .map(|chunk| chunk.iter().fold(0u32, |acc, b| (acc << 8) | *b as u32))
+            .collect()
+    }
+
+    fn text(&self, code: u32) -> Option<String> {
+        if let Some(text) = self.to_unicode.get(&code) {
+            return Some(text.clone());
+        }
+        if let Some(text) = self.differences.get(&code) {
+            return Some(text.clone());
+        }
+        // 没有 ToUnicode 的 CID 字体无法还原字符
+        if self.code_len > 1 {
+            return None;
+        }
+        win_ansi(code as u8).map(String::from)
+    }
+
+    fn width(&self, code: u32) -> f32 {
+        self.widths.get(&code).copied().unwrap_or(self.default_width)
+    }
+}
+
+struct CMap {
+    code_len: Option<usize>,
+    map: HashMap<u32, String>,
+}
+
+/// 解析 ToUnicode CMap 中的 codespacerange、bfchar 与 bfrange
+fn parse_cmap(data: &[u8]) -> CMap {
+    let tokens = cmap_tokens(&String::from_utf8_lossy(data));
+    let mut cmap = CMap {
+        code_len: None,
+        map: HashMap::new(),
+    };
+
+    let mut i = 0;
+    while i < tokens.len() {
+        match tokens[i].as_str() {
+            "begincodespacerange" => {
+                if let Some(hex) = tokens.get(i + 1).and_then(|t| t.strip_prefix('<')) {
+                    cmap.code_len = Some(hex.len().div_ceil(2).clamp(1, 4));
+                }
+                i += 1;
+            }
+            "beginbfchar" => {
+                i += 1;
+                while i + 1 < tokens.len() && tokens[i] != "endbfchar" {
+                    if let (Some(code), Some(text)) = (hex_code(&tokens[i]), hex_text(&tokens[i + 1])) {
+                        cmap.map.insert(code, text);
+                    }
+                    i += 2;
+                }
+            }
+            "beginbfrange" => {
+                i += 1;
+                while i + 2 < tokens.len() && tokens[i] != "endbfrange" {
+                    let (Some(lo), Some(hi)) = (hex_code(&tokens[i]), hex_code(&tokens[i + 1])) else {
+                        i += 1;
+                        continue;
+                    };
+                    let hi = hi.min(lo.saturating_add(0xFFFF));
+                    if tokens[i + 2] == "[" {
+                        // <lo> <hi> [<dst1> <dst2> ...]，多出的目标丢弃
+                        let mut j = i + 3;
+                        let mut codes = lo..=hi;
+                        while j < tokens.len() && tokens[j] != "]" {
+                            if let (Some(code), Some(text)) = (codes.next(), hex_text(&tokens[j])) {
+                                cmap.map.insert(code, text);
+                            }
+                            j += 1;
+                        }
+                        i = j + 1;
+                    } else {
+                        // <lo> <hi> <dst>：目标的最后一个码元依次递增
+                        if let Some(start) = hex_units(&tokens[i + 2]) {
+                            for (offset, code) in (lo..=hi).enumerate() {
+                                let mut units = start.clone();
+                                if let Some(last) = units.last_mut() {
+                                    *last = last.wrapping_add(offset as u16);
+                                }
+                                cmap.map.insert(code, String::from_utf16_lossy(&units));
+                            }
+                        }
+                        i += 3;
+                    }
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    cmap
+}
+
+// 只保留 CMap 里需要的记号：<十六进制>、方括号和关键字
+fn cmap_tokens(text: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '<' => {
+                let mut hex = String::from("<");
+                for c in chars.by_ref() {
+                    if c == '>' {
+                        break;
+                    }
+                    if c.is_ascii_hexdigit() {
+                        hex.push(c);
+                    }
+                }
+                tokens.push(hex);
+            }
+            '[' | ']' => tokens.push(c.to_string()),
+            '%' => {
+                for c in chars.by_ref() {
+                    if c == '\n' || c == '\r' {
+                        break;
+                    }
+                }
+            }
+            c if c.is_whitespace() => {}
+            c => {
+                let mut word = c.to_string();
+                while let Some(&next) = chars.peek() {
+                    if next.is_whitespace() || matches!(next, '<' | '[' | ']' | '%') {
+                        break;
+                    }
+                    word.push(next);
+                    chars.next();
+                }
+                tokens.push(word);
+            }
+        }
+    }
+    tokens
+}
+
+fn hex_code(token: &str) -> Option<u32> {
+    let hex = token.strip_prefix('<')?;
+    u32::from_str_radix(hex, 16).ok()
+}
+
+fn hex_units(token: &str) -> Option<Vec<u16>> {
+    let hex = token.strip_prefix('<')?;
+    if hex.is_empty() {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(4)
+        .map(|i| u16::from_str_radix(&hex[i..(i + 4).min(hex.len())], 16).ok())
+        .collect()
+}
+
+fn hex_text(token: &str) -> Option<String> {
+    hex_units(token).map(|units| String::from_utf16_lossy(&units))
+}
+
+/// 解码 PDF 文本字符串（书签标题、文档信息等）：UTF-16BE、UTF-8 或 PDFDocEncoding
+pub fn decode_text_string(bytes: &[u8]) -> String {
+    if let Some(rest) = bytes.strip_prefix(&[0xFE, 0xFF]) {
+        let units: Vec<u16> = rest
+            .chunks_exact(2)
+            .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+            .collect();
+        return String::from_utf16_lossy(&units);
+    }
+    if let Some(rest) = bytes.strip_prefix(&[0xEF, 0xBB, 0xBF]) {
+        return String::from_utf8_lossy(rest).to_string();
+    }
+    // PDFDocEncoding 的可打印部分与 WinAnsi 基本一致
+    bytes
+        .iter()
+        .filter_map(|b| match b {
+            b'\t' | b'\n' | b'\r' => Some(*b as char),
+            _ => win_ansi(*b),
+        })
+        .collect()
+}
+
+// WinAnsiEncoding：0x80-0x9F 之外与 Latin-1 一致
+fn win_ansi(byte: u8) -> Option<char> {
+    match byte {
+        0x20..=0x7E | 0xA0..=0xFF => Some(byte as char),
+        0x85 => Some('…'),
+        0x91 => Some('‘'),
+        0x92 => Some('’'),
+        0x93 => Some('“'),
+        0x94 => Some('”'),
+        0x95 => Some('•'),
+        0x96 => Some('–'),
+        0x97 => Some('—'),
+        _ => None,
+    }
+}
+
+// 常见字形名（LaTeX 生成的 Type1 字体经常只有 Differences 没有 ToUnicode）
+fn glyph_to_text(glyph: &str) -> Option<String> {
+    if glyph.len() == 1 && glyph.is_ascii() {
+        return Some(glyph.to_string());
+    }
+    if let Some(hex) = glyph.strip_prefix("uni") {
+        return hex_text(&format!("<{}", hex));
+    }
+    let text = match glyph {
+        "space" => " ",
+        "zero" => "0",
+        "one" => "1",
+        "two" => "2",
+        "three" => "3",
+        "four" => "4",
+        "five" => "5",
+        "six" => "6",
+        "seven" => "7",
+        "eight" => "8",
+        "nine" => "9",
+        "ff" => "ff",
+        "fi" => "fi",
+        "fl" => "fl",
+        "ffi" => "ffi",
+        "ffl" => "ffl",
+        "period" => ".",
+        "comma" => ",",
+        "colon" => ":",
+        "semicolon" => ";",
+        "hyphen" => "-",
+        "endash" => "–",
+        "emdash" => "—",
+        "quoteleft" => "‘",
+        "quoteright" => "’",
+        "quotedblleft" => "“",
+        "quotedblright" => "”",
+        "quotesingle" => "'",
+        "quotedbl" => "\"",
+        "parenleft" => "(",
+        "parenright" => ")",
+        "bracketleft" => "[",
+        "bracketright" => "]",
+        "braceleft" => "{",
+        "braceright" => "}",
+        "slash" => "/",
+        "backslash" => "\\",
+        "exclam" => "!",
+        "question" => "?",
+        "percent" => "%",
+        "ampersand" => "&",
+        "asterisk" => "*",
+        "plus" => "+",
+        "minus" => "−",
+        "equal" => "=",
+        "less" => "<",
+        "greater" => ">",
+        "numbersign" => "#",
+        "dollar" => "$",
+        "at" => "@",
+        "underscore" => "_",
+        "bullet" => "•",
+        "dagger" => "†",
+        "daggerdbl" => "‡",
+        "section" => "§",
+        "degree" => "°",
+        "multiply" => "×",
+        "ellipsis" => "…",
+        _ => return None,
+    };
+    Some(text.to_string())
+}
+
+/* ---------------------------------
+ * 内容流解释
+ * --------------------------------- */
+
+#[derive(Debug, Clone, Copy)]
+struct Matrix([f32; 6]);
+
+impl Matrix {
+    const IDENTITY: Matrix = Matrix([1.0, 0.0, 0.0, 1.0, 0.0, 0.0]);
+
+    fn translate(tx: f32, ty: f32) -> Matrix {
+        Matrix([1.0, 0.0, 0.0, 1.0, tx, ty])
+    }
+
+    fn from_operands(operands: &[Object]) -> Option<Matrix> {
+        let values: Vec<f32> = operands.iter().filter_map(|o| o.as_float().ok()).collect();
+        let values: [f32; 6] = values.try_into().ok()?;
+        Some(Matrix(values))
+    }
+
+    // self × other（PDF 使用行向量约定）
+    fn mul(self, other: Matrix) -> Matrix {
+        let [a, b, c, d, e, f] = self.0;
+        let [a2, b2, c2, d2, e2, f2] = other.0;
+        Matrix([
+            a * a2 + b * c2,
+            a * b2 + b * d2,
+            c * a2 + d * c2,
+            c * b2 + d * d2,
+            e * a2 + f * c2 + e2,
+            e * b2 + f * d2 + f2,
+        ])
+    }
+
+    fn apply(self, x: f32, y: f32) -> (f32, f32) {
+        let [a, b, c, d, e, f] = self.0;
+        (x * a + y * c + e, x * b + y * d + f)
+    }
+
+    // 竖直方向的缩放，用来换算实际字号
+    fn vertical_scale(self) -> f32 {
+        self.0[2].hypot(self.0[3])
+    }
+}
+
+#[derive(Clone)]
+struct GraphicsState {
+    ctm: Matrix,
+    font: Option<Arc<Font>>,
+    font_size: f32,
+    char_spacing: f32,
+    word_spacing: f32,
+    horizontal_scale: f32,
+    leading: f32,
+    rise: f32,
+}
+
+impl Default for GraphicsState {
+    fn default() -> Self {
+        GraphicsState {
+            ctm: Matrix::IDENTITY,
+            font: None,
+            font_size: 0.0,
+            char_spacing: 0.0,
+            word_spacing: 0.0,
+            horizontal_scale: 1.0,
+            leading: 0.0,
+            rise: 0.0,
+        }
+    }
+}
+
+// 用户空间坐标下的一段文字，y 为基线
+#[derive(Debug, Clone)]
+struct RawSpan {
+    text: String,
+    x0: f32,
+    x1: f32,
+    baseline: f32,
+    size: f32,
+    font: String,
+}
+
+struct Interpreter<'a> {
+    pdf: &'a LoadedPdf,
+    spans: Vec<RawSpan>,
+}
+
+impl Interpreter<'_> {
+    fn run(&mut self, content: &[u8], resources: Option<&Dictionary>, mut state: GraphicsState, depth: usize) {
+        let Ok(content) = Content::decode(content) else {
+            return;
+        };
+        let mut stack: Vec<GraphicsState> = Vec::new();
+        let mut tm = Matrix::IDENTITY;
+        let mut tlm = Matrix::IDENTITY;
+
+        for op in &content.operations {
+            let operands = &op.operands;
+            let num = |i: usize| operands.get(i).and_then(|o| o.as_float().ok()).unwrap_or(0.0);
+
+            match op.operator.as_str() {
+                "q" => stack.push(state.clone()),
+                "Q" => {
+                    if let Some(saved) = stack.pop() {
+                        state = saved;
+                    }
+                }
+                "cm" => {
+                    if let Some(m) = Matrix::from_operands(operands) {
+                        state.ctm = m.mul(state.ctm);
+                    }
+                }
+                "BT" => {
+                    tm = Matrix::IDENTITY;
+                    tlm = Matrix::IDENTITY;
+                }
+                "Tf" => {
+                    if let Some(name) = operands.first().and_then(|o| o.as_name().ok()) {
+                        state.font = self.pdf.font(resources, name);
+                    }
+                    state.font_size = num(1);
+                }
+                "Tc" => state.char_spacing = num(0),
+                "Tw" => state.word_spacing = num(0),
+                "Tz" => state.horizontal_scale = num(0) / 100.0,
+                "TL" => state.leading = num(0),
+                "Ts" => state.rise = num(0),
+                "Td" | "TD" => {
+                    if op.operator == "TD" {
+                        state.leading = -num(1);
+                    }
+                    tlm = Matrix::translate(num(0), num(1)).mul(tlm);
+                    tm = tlm;
+                }
+                "Tm" => {
+                    if let Some(m) = Matrix::from_operands(operands) {
+                        tlm = m;
+                        tm = m;
+                    }
+                }
+                "T*" => {
+                    tlm = Matrix::translate(0.0, -state.leading).mul(tlm);
+                    tm = tlm;
+                }
+                "Tj" | "'" | "\"" => {
+                    // ' 与 " 先换行再显示，" 还同时设置词距和字距
+                    let text_index = if op.operator == "\"" { 2 } else { 0 };
+                    if op.operator == "\"" {
+                        state.word_spacing = num(0);
+                        state.char_spacing = num(1);
+                    }
+                    if op.operator != "Tj" {
+                        tlm = Matrix::translate(0.0, -state.leading).mul(tlm);
+                        tm = tlm;
+                    }
+                    if let Some(bytes) = operands.get(text_index).and_then(|o| o.as_str().ok()) {
+                        let mut span = SpanBuilder::default();
+                        self.show(&state, &mut tm, bytes, &mut span);
+                        self.finish_span(&state, tm, span);
+                    }
+                }
+                "TJ" => {
+                    let Some(items) = operands.first().and_then(|o| o.as_array().ok()) else {
+                        continue;
+                    };
+                    let mut span = SpanBuilder::default();
+                    for item in items {
+                        match item {
+                            Object::String(bytes, _) => self.show(&state, &mut tm, bytes, &mut span),
+                            other => {
+                                let Ok(adjust) = other.as_float() else {
+                                    continue;
+                                };
+                                let tx = -adjust / 1000.0 * state.font_size * state.horizontal_scale;
+                                tm = Matrix::translate(tx, 0.0).mul(tm);
+                                if -adjust > TJ_SPACE_THRESHOLD && !span.text.ends_with(char::is_whitespace) {
+                                    span.text.push(' ');
+                                }
+                            }
+                        }
+                    }
+                    self.finish_span(&state, tm, span);
+                }
+                "Do" if depth < MAX_FORM_DEPTH => {
+                    if let Some(name) = operands.first().and_then(|o| o.as_name().ok()) {
+                        self.run_form(resources, name, &state, depth);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn run_form(&mut self, resources: Option<&Dictionary>, name: &[u8], state: &GraphicsState, depth: usize) {
+        let doc = &self.pdf.doc;
+        let Some(stream) = resources
+            .and_then(|r| dict_get(doc, r, b"XObject"))
+            .and_then(|o| o.as_dict().ok())
+            .and_then(|x| dict_get(doc, x, name))
+            .and_then(|o| o.as_stream().ok())
+        else {
+            return;
+        };
+        let is_form = dict_get(doc, &stream.dict, b"Subtype")
+            .and_then(|o| o.as_name().ok())
+            .is_some_and(|n| n == b"Form");
+        if !is_form {
+            return;
+        }
+        let Some(content) = stream_content(stream) else {
+            return;
+        };
+
+        let mut form_state = state.clone();
+        if let Some(matrix) = dict_get(doc, &stream.dict, b"Matrix")
+            .and_then(|o| o.as_array().ok())
+            .and_then(|m| Matrix::from_operands(m))
+        {
+            form_state.ctm = matrix.mul(form_state.ctm);
+        }
+        // 表单没有自己的资源时沿用页面资源
+        let form_resources = dict_get(doc, &stream.dict, b"Resources")
+            .and_then(|o| o.as_dict().ok())
+            .or(resources);
+        self.run(&content, form_resources, form_state, depth + 1);
+    }
+
+    fn show(&self, state: &GraphicsState, tm: &mut Matrix, bytes: &[u8], span: &mut SpanBuilder) {
+        let Some(font) = &state.font else {
+            return;
+        };
+        for code in font.codes(bytes) {
+            if span.start.is_none() {
+                let m = tm.mul(state.ctm);
+                span.start = Some(m.apply(0.0, state.rise));
+                span.size = state.font_size * m.vertical_scale();
+                span.font = font.name.clone();
+            }
+            if let Some(text) = font.text(code) {
+                span.text.push_str(&text);
+            }
+            let word_spacing = if font.code_len == 1 && code == 32 {
+                state.word_spacing
+            } else {
+                0.0
+            };
+            let tx = (font.width(code) / 1000.0 * state.font_size + state.char_spacing + word_spacing)
+                * state.horizontal_scale;
+            *tm = Matrix::translate(tx, 0.0).mul(*tm);
+        }
+    }
+
+    fn finish_span(&mut self, state: &GraphicsState, tm: Matrix, span: SpanBuilder) {
+        let Some((x0, baseline)) = span.start else {
+            return;
+        };
+        if span.text.trim().is_empty() {
+            return;
+        }
+        let (x1, _) = tm.mul(state.ctm).apply(0.0, state.rise);
+        self.spans.push(RawSpan {
+            text: span.text,
+            x0: x0.min(x1),
+            x1: x0.max(x1),
+            baseline,
+            size: span.size.abs().max(1.0),
+            font: span.font,
+        });
+    }
+}
+
+#[derive(Default)]
+struct SpanBuilder {
+    text: String,
+    start: Option<(f32, f32)>,
+    size: f32,
+    font: String,
+}
+
+/* ---------------------------------
+ * 版面整理：分栏、成行、阅读顺序
+ * --------------------------------- */
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Column {
+    Left,
+    Right,
+    Full,
+}
+
+fn layout_page(page: u32, media_box: MediaBox, raw: Vec<RawSpan>) -> PageText {
+    let width = media_box.x1 - media_box.x0;
+    let height = media_box.y1 - media_box.y0;
+
+    // 转成左上角原点；字高按 0.8 em 上伸、0.2 em 下伸估算
+    let spans: Vec<(f32, TextSpan)> = raw
+        .into_iter()
+        .map(|s| {
+            let baseline = height - (s.baseline - media_box.y0);
+            let span = TextSpan {
+                text: s.text,
+                x: s.x0 - media_box.x0,
+                y: baseline - s.size * 0.8,
+                width: s.x1 - s.x0,
+                height: s.size,
+                font: s.font,
+                font_size: s.size,
+            };
+            (baseline, span)
+        })
+        .collect();
+
+    layout_spans(page, width, height, spans)
+}
+
+/// 把左上角原点坐标的文字片段（附基线位置）整理成行，并按阅读顺序排列
+pub fn layout_spans(page: u32, width: f32, height: f32, spans: Vec<(f32, TextSpan)>) -> PageText {
+    let gutter = find_gutter(&spans, width);
+    let mut grouped: Vec<(Column, Vec<(f32, TextSpan)>)> = Vec::new();
+    for column in [Column::Left, Column::Right, Column::Full] {
+        let members: Vec<(f32, TextSpan)> = spans
+            .iter()
+            .filter(|(_, s)| classify(s, gutter) == column)
+            .cloned()
+            .collect();
+        grouped.push((column, members));
+    }
+
+    let mut left = Vec::new();
+    let mut right = Vec::new();
+    let mut full = Vec::new();
+    for (column, members) in grouped {
+        let lines = group_lines(members);
+        match column {
+            Column::Left => left = lines,
+            Column::Right => right = lines,
+            Column::Full => full = lines,
+        }
+    }
+
+    // 通栏的行（标题、摘要、跨栏图注）把页面切成若干段，每段内先左栏后右栏
+    let mut lines = Vec::new();
+    let mut left = left.into_iter().peekable();
+    let mut right = right.into_iter().peekable();
+    for full_line in full {
+        while let Some(line) = left.next_if(|l| l.y < full_line.y) {
+            lines.push(line);
+        }
+        while let Some(line) = right.next_if(|l| l.y < full_line.y) {
+            lines.push(line);
+        }
+        lines.push(full_line);
+    }
+    lines.extend(left);
+    lines.extend(right);
+
+    let text = lines.iter().map(|l| l.text.as_str()).collect::<Vec<_>>().join("\n");
+    PageText {
+        page,
+        width,
+        height,
+        text,
+        lines,
+        ocr: false,
+    }
+}
+
+fn classify(span: &TextSpan, gutter: Option<f32>) -> Column {
+    match gutter {
+        Some(g) if span.x + span.width <= g => Column::Left,
+        Some(g) if span.x >= g => Column::Right,
+        _ => Column::Full,
+    }
+}
+
+/// 在页面中部寻找几乎没有文字跨过的竖直空白带，找到则认为是双栏排版
+fn find_gutter(spans: &[(f32, TextSpan)], width: f32) -> Option<f32> {
+    if spans.len() < 10 || width <= 0.0 {
+        return None;
+    }
+    let bins = width.ceil() as usize;
+    let mut coverage = vec![0usize; bins + 1];
+    for (_, s) in spans {
+        let start = s.x.max(0.0) as usize;
+        let end = ((s.x + s.width).max(0.0) as usize).min(bins);
+        for c in coverage.iter_mut().take(end + 1).skip(start) {
+            *c += 1;
+        }
+    }
+
+    let allowed = spans.len() / 5;
+    let lo = (width * 0.3) as usize;
+    let hi = ((width * 0.7) as usize).min(bins);
+    let mut best: Option<(usize, usize)> = None;
+    let mut x = lo;
+    while x <= hi {
+        if coverage[x] > allowed {
+            x += 1;
+            continue;
+        }
+        let start = x;
+        while x <= hi && coverage[x] <= allowed {
+            x += 1;
+        }
+        if best.is_none_or(|(s, e)| x - start > e - s) {
+            best = Some((start, x));
+        }
+    }
+
+    let (start, end) = best.filter(|(s, e)| e - s >= 6)?;
+    let center = (start + end) as f32 / 2.0;
+    let left = spans.iter().filter(|(_, s)| s.x + s.width <= center).count();
+    let right = spans.iter().filter(|(_, s)| s.x >= center).count();
+    let enough = spans.len() / 5;
+    (left >= enough && right >= enough).then_some(center)
+}
+
+// 基线接近的文字归为同一行，行内按横坐标排序
+fn group_lines(mut spans: Vec<(f32, TextSpan)>) -> Vec<TextLine> {
+    spans.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.x.total_cmp(&b.1.x)));
+
+    let mut rows: Vec<(f32, Vec<TextSpan>)> = Vec::new();
+    for (baseline, span) in spans {
+        match rows.last_mut() {
+            Some((row_baseline, members)) if (baseline - *row_baseline).abs() < span.font_size * 0.5 => {
+                members.push(span)
+            }
+            _ => rows.push((baseline, vec![span])),
+        }
+    }
+
+    rows.into_iter()
+        .map(|(_, mut spans)| {
+            spans.sort_by(|a, b| a.x.total_cmp(&b.x));
+            let mut text = String::new();
+            let mut prev_end: Option<f32> = None;
+            for span in &spans {
+                if let Some(end) = prev_end {
+                    let gap = span.x - end;
+                    if gap > span.font_size * 0.15
+                        && !text.ends_with(char::is_whitespace)
+                        && !span.text.starts_with(char::is_whitespace)
+                    {
+                        text.push(' ');
+                    }
+                }
+                text.push_str(&span.text);
+                prev_end = Some(span.x + span.width);
+            }
+
+            let x = spans.iter().map(|s| s.x).fold(f32::MAX, f32::min);
+            let y = spans.iter().map(|s| s.y).fold(f32::MAX, f32::min);
+            let right = spans.iter().map(|s| s.x + s.width).fold(f32::MIN, f32::max);
+            let bottom = spans.iter().map(|s| s.y + s.height).fold(f32::MIN, f32::max);
+            TextLine {
+                text: text.trim().to_string(),
+                x,
+                y,
+                width: right - x,
+                height: bottom - y,
+                spans,
+            }
+        })
+        .collect()
+}
+
+/// 返回某一页的文本与位置信息，页码从 1 开始；扫描页返回 OCR 识别结果
+#[tauri::command]
+pub async fn get_page_text(path: String, page: u32, app_handle: tauri::AppHandle) -> Result<PageText, String> {
+    use tauri::Manager;
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let canonical = app_handle.state::<FileScope>().resolve_pdf(&path)?;
+        let text = crate::ocr::page_text(
+            &app_handle.state::<crate::storage::Storage>(),
+            &app_handle.state::<PdfTextCache>(),
+            &canonical,
+            page,
+        )?;
+        Ok((*text).clone())
+    })
+    .await
+    .map_err(|e| format!("Text extraction failed: {}", e))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn name(value: &str) -> Object {
+        Object::Name(value.as_bytes().to_vec())
+    }
+
+    fn ints(values: &[i64]) -> Object {
+        Object::Array(values.iter().map(|v| Object::Integer(*v)).collect())
+    }
+
+    fn type0_font(widths: Vec<Object>) -> Font {
+        let mut descendant = Dictionary::new();
+        descendant.set("DW", Object::Integer(1000));
+        descendant.set("W", Object::Array(widths));
+        let mut dict = Dictionary::new();
+        dict.set("Subtype", name("Type0"));
+        dict.set("DescendantFonts", Object::Array(vec![Object::Dictionary(descendant)]));
+        Font::load(&Document::new(), &dict)
+    }
+
+    fn span(text: &str, x0: f32, x1: f32, baseline: f32) -> RawSpan {
+        RawSpan {
+            text: text.to_string(),
+            x0,
+            x1,
+            baseline,
+            size: 10.0,
+            font: "F1".to_string(),
+        }
+    }
+
+    #[test]
+    fn to_unicode_cmap_maps_bfchar_and_both_bfrange_forms() {
+        let cmap = parse_cmap(
+            b"/CIDInit /ProcSet findresource begin\n\
+              1 begincodespacerange <0000> <FFFF> endcodespacerange\n\
+              2 beginbfchar <0003> <0041> <0004> <D835DC00> endbfchar % comment <0005> <0042>\n\
+              2 beginbfrange <0010> <0012> <4E00>\n\
+              <0020> <0021> [<0066006C> <00660069> <0078>] endbfrange\n\
+              endcmap",
+        );
+        assert_eq!(cmap.code_len, Some(2));
+        assert_eq!(cmap.map.get(&0x03).map(String::as_str), Some("A"));
+        assert_eq!(cmap.map.get(&0x04).map(String::as_str), Some("𝐀"));
+        assert!(!cmap.map.contains_key(&0x05));
+        assert_eq!(cmap.map.get(&0x10).map(String::as_str), Some("一"));
+        assert_eq!(cmap.map.get(&0x12).map(String::as_str), Some("丂"));
+        assert_eq!(cmap.map.get(&0x20).map(String::as_str), Some("fl"));
+        assert_eq!(cmap.map.get(&0x21).map(String::as_str), Some("fi"));
+        // 数组比区间长时多出的目标不占用区间外的字符码
+        assert!(!cmap.map.contains_key(&0x22));
+    }
+
+    #[test]
+    fn cmap_ranges_at_the_top_of_the_code_space_do_not_overflow() {
+        let cmap = parse_cmap(
+            b"begincodespacerange <00000000> <FFFFFFFFFFFF> endcodespacerange\n\
+              beginbfrange <FFFFFFFD> <FFFFFFFE> <0041>\n\
+              <FFFFFFFF> <FFFFFFFF> [<0061> <0062>] endbfrange",
+        );
+        assert_eq!(cmap.code_len, Some(4));
+        assert_eq!(cmap.map.len(), 3);
+        assert_eq!(cmap.map.get(&0xFFFF_FFFE).map(String::as_str), Some("B"));
+        assert_eq!(cmap.map.get(&u32::MAX).map(String::as_str), Some("a"));
+    }
+
+    #[test]
+    fn uncompressed_to_unicode_stream_decodes_two_byte_codes() {
+        let cmap = b"begincodespacerange <0000> <FFFF> endcodespacerange\n\
+                     2 beginbfchar <0001> <8BBA> <0002> <6587> endbfchar";
+        let mut dict = Dictionary::new();
+        dict.set("Subtype", name("Type0"));
+        dict.set("BaseFont", name("SimSun"));
+        dict.set("ToUnicode", Object::Stream(Stream::new(Dictionary::new(), cmap.to_vec())));
+        let font = Font::load(&Document::new(), &dict);
+
+        let codes = font.codes(&[0x00, 0x01, 0x00, 0x02, 0x00, 0x09]);
+        assert_eq!(codes, vec![1, 2, 9]);
+        let text: Vec<Option<String>> = codes.iter().map(|c| font.text(*c)).collect();
+        assert_eq!(text, vec![Some("论".to_string()), Some("文".to_string()), None]);
+    }
+
+    #[test]
+    fn cid_widths_read_both_forms_and_stop_at_negative_codes() {
+        let font = type0_font(vec![
+            Object::Integer(1),
+            ints(&[500, 600]),
+            Object::Integer(10),
+            Object::Integer(12),
+            Object::Integer(700),
+            Object::Integer(i64::from(u32::MAX)),
+            ints(&[100, 200]),
+            Object::Integer(i64::from(u32::MAX) - 1),
+            Object::Integer(i64::from(u32::MAX)),
+            Object::Integer(50),
+            Object::Integer(-5),
+            ints(&[300]),
+            Object::Integer(20),
+            ints(&[400]),
+        ]);
+        assert_eq!(font.width(1), 500.0);
+        assert_eq!(font.width(2), 600.0);
+        assert_eq!(font.width(3), 1000.0);
+        assert_eq!(font.width(10), 700.0);
+        assert_eq!(font.width(12), 700.0);
+        assert_eq!(font.width(13), 1000.0);
+        assert_eq!(font.width(u32::MAX - 1), 50.0);
+        assert_eq!(font.width(u32::MAX), 50.0);
+        // 负的起始码之后的条目全部忽略
+        assert_eq!(font.width(20), 1000.0);
+    }
+
+    #[test]
+    fn simple_font_widths_and_differences_reject_negative_codes() {
+        let mut encoding = Dictionary::new();
+        encoding.set(
+            "Differences",
+            Object::Array(vec![
+                Object::Integer(-1),
+                name("x"),
+                name("y"),
+                Object::Integer(65),
+                name("uni03B1"),
+                name("B"),
+            ]),
+        );
+        let mut dict = Dictionary::new();
+        dict.set("Subtype", name("Type1"));
+        dict.set("FirstChar", Object::Integer(32));
+        dict.set("Widths", ints(&[250, 333]));
+        dict.set("Encoding", Object::Dictionary(encoding));
+        let font = Font::load(&Document::new(), &dict);
+
+        assert_eq!(font.width(32), 250.0);
+        assert_eq!(font.width(33), 333.0);
+        assert_eq!(font.width(34), 500.0);
+        assert_eq!(font.text(65).as_deref(), Some("α"));
+        assert_eq!(font.text(66).as_deref(), Some("B"));
+        assert!(!font.differences.values().any(|t| t == "x" || t == "y"));
+
+        dict.set("FirstChar", Object::Integer(-3));
+        let font = Font::load(&Document::new(), &dict);
+        assert!(font.widths.is_empty());
+    }
+
+    #[test]
+    fn two_column_page_reads_left_column_before_right() {
+        let media_box = MediaBox {
+            x0: 0.0,
+            y0: 0.0,
+            x1: 600.0,
+            y1: 800.0,
+        };
+        let mut raw = vec![
+            span("A Title Across Both Columns", 50.0, 550.0, 750.0),
+            span("Footnote across the page", 50.0, 550.0, 60.0),
+        ];
+        for (i, baseline) in [700.0, 685.0, 670.0, 655.0, 640.0].into_iter().enumerate() {
+            // 右栏先写入，确认顺序与内容流顺序无关
+            raw.push(span(&format!("R{}", i + 1), 320.0, 550.0, baseline));
+            raw.push(span(&format!("L{}", i + 1), 50.0, 200.0, baseline));
+        }
+        raw.push(span("tail", 210.0, 240.0, 640.0));
+
+        let page = layout_page(3, media_box, raw);
+        let order: Vec<&str> = page.lines.iter().map(|l| l.text.as_str()).collect();
+        assert_eq!(
+            order,
+            vec![
+                "A Title Across Both Columns",
+                "L1",
+                "L2",
+                "L3",
+                "L4",
+                "L5 tail",
+                "R1",
+                "R2",
+                "R3",
+                "R4",
+                "R5",
+                "Footnote across the page",
+            ]
+        );
+        assert_eq!(page.page, 3);
+        // 转换为左上角原点：标题基线 750 → y = 800 - 750 - 0.8 em
+        assert_eq!(page.lines[0].y, 42.0);
+        assert!(page.text.starts_with("A Title Across Both Columns\nL1\n"));
+    }
+}
@@ -0,0 +1,689 @@
+// 模型服务调用层：Ollama 与 OpenAI 兼容接口（DeepSeek、Kimi）的流式请求
+//
+// 这里只负责请求与流解析，事件怎么发给前端由调用方通过回调决定。
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::retry::{is_retryable_status, parse_retry_after, RetryPolicy};
+use crate::scheduler::{Priority, ProviderLimits, Scheduler};
+use crate::segment;
+use crate::stream_filter::{FilterOutput, ThinkFilter};
+use crate::usage::{estimate_tokens_for, Usage};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ModelConfig {
+    pub selected_model: String,
+    pub ollama: OllamaConfig,
+    pub deepseek: DeepSeekConfig,
+    pub kimi: KimiConfig,
+    #[serde(default)]
+    pub retry: RetryPolicy,
+    // selected_model 失败后依次尝试的服务
+    #[serde(default)]
+    pub fallback_chain: Vec<String>,
+    // PRD 中的“在线翻译兜底”开关：未开启时不会回退到云端服务
+    #[serde(default)]
+    pub allow_cloud_fallback: bool,
+    // 按服务覆盖默认的速率与并发限制
+    #[serde(default)]
+    pub rate_limits: HashMap<String, ProviderLimits>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OllamaConfig {
+    pub base_url: String,
+    pub model: String,
+    // 识别图片时使用的多模态模型，如 llava、qwen2.5vl
+    #[serde(default)]
+    pub vision_model: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeepSeekConfig {
+    pub api_key: String,
+    pub base_url: String,
+    pub model: String,
+    #[serde(default)]
+    pub vision_model: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct KimiConfig {
+    pub api_key: String,
+    pub base_url: String,
+    pub model: String,
+    #[serde(default)]
+    pub vision_model: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OllamaRequest {
+    pub model: String,
+    pub prompt: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub system: Option<String>,
+    pub stream: bool,
+    pub think: bool,
+    // num_ctx、num_predict 只有放在 options 里才生效
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub options: Option<OllamaOptions>,
+    // base64 编码的图片，多模态模型使用
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub images: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OllamaOptions {
+    pub num_ctx: u32,
+    pub num_predict: u32,
+}
+
+impl OllamaOptions {
+    /// 按模型预算设置上下文长度，输出上限扣除提示词占用
+    pub fn for_prompt(model: &str, prompt: &str) -> Self {
+        let budget = segment::model_budget("ollama", model);
+        OllamaOptions {
+            num_ctx: budget.context_tokens,
+            num_predict: budget.max_output(estimate_tokens_for(model, prompt)),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OllamaResponse {
+    pub model: String,
+    pub created_at: String,
+    pub response: String,
+    pub done: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OllamaStreamResponse {
+    pub model: String,
+    pub created_at: String,
+    pub response: String,
+    // 新版 Ollama 在 think 为 true 时把推理内容放在单独的字段里
+    #[serde(default)]
+    pub thinking: Option<String>,
+    pub done: bool,
+    // 以下字段只出现在最后一行
+    #[serde(default)]
+    pub prompt_eval_count: Option<u64>,
+    #[serde(default)]
+    pub eval_count: Option<u64>,
+    // "stop" 为正常结束，"length" 表示达到 num_predict 被截断
+    #[serde(default)]
+    pub done_reason: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ChatMessage {
+    role: String,
+    content: MessageContent,
+}
+
+// 纯文本消息直接是字符串，带图片时是 text / image_url 分段数组
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+enum MessageContent {
+    Text(String),
+    Parts(Vec<serde_json::Value>),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ChatCompletionRequest {
+    model: String,
+    messages: Vec<ChatMessage>,
+    stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stream_options: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_tokens: Option<u32>,
+}
+
+/// 解析后的调用目标：用哪个服务、哪个模型
+#[derive(Debug, Clone)]
+pub struct ProviderTarget {
+    pub provider: String,
+    pub base_url: String,
+    pub api_key: String,
+    pub model: String,
+}
+
+impl ModelConfig {
+    pub fn target(&self, provider: &str) -> Result<ProviderTarget, String> {
+        match provider {
+            "ollama" => Ok(ProviderTarget {
+                provider: provider.to_string(),
+                base_url: self.ollama.base_url.clone(),
+                api_key: String::new(),
+                model: self.ollama.model.clone(),
+            }),
+            "deepseek" => {
+                if self.deepseek.api_key.is_empty() {
+                    return Err("DeepSeek API key is required".to_string());
+                }
+                Ok(ProviderTarget {
+                    provider: provider.to_string(),
+                    base_url: self.deepseek.base_url.clone(),
+                    api_key: self.deepseek.api_key.clone(),
+                    model: self.deepseek.model.clone(),
+                })
+            }
+            "kimi" => {
+                if self.kimi.api_key.is_empty() {
+                    return Err("Kimi API key is required".to_string());
+                }
+                Ok(ProviderTarget {
+                    provider: provider.to_string(),
+                    base_url: self.kimi.base_url.clone(),
+                    api_key: self.kimi.api_key.clone(),
+                    model: self.kimi.model.clone(),
+                })
+            }
+            _ => Err("Unknown model type".to_string()),
+        }
+    }
+
+    /// 带图片的请求改用该服务的视觉模型，没有配置时视为不可用
+    pub fn vision_target(&self, provider: &str) -> Result<ProviderTarget, String> {
+        let mut target = self.target(provider)?;
+        let vision_model = match provider {
+            "ollama" => &self.ollama.vision_model,
+            "deepseek" => &self.deepseek.vision_model,
+            "kimi" => &self.kimi.vision_model,
+            _ => return Err("Unknown model type".to_string()),
+        };
+        if vision_model.trim().is_empty() {
+            return Err(format!("{} has no vision model configured", target.display_name()));
+        }
+        target.model = vision_model.trim().to_string();
+        Ok(target)
+    }
+
+    /// 实际尝试的服务顺序：先 selected_model，再按 fallback_chain
+    pub fn provider_chain(&self) -> Vec<String> {
+        let mut chain = vec![self.selected_model.clone()];
+        for provider in &self.fallback_chain {
+            if chain.contains(provider) {
+                continue;
+            }
+            if provider != "ollama" && !self.allow_cloud_fallback {
+                println!("[provider_chain] 未开启在线翻译兜底，跳过 {}", provider);
+                continue;
+            }
+            chain.push(provider.clone());
+        }
+        chain
+    }
+}
+
+impl ProviderTarget {
+    pub fn display_name(&self) -> &str {
+        match self.provider.as_str() {
+            "ollama" => "Ollama",
+            "deepseek" => "DeepSeek",
+            "kimi" => "Kimi",
+            other => other,
+        }
+    }
+
+    // Kimi 在最后一个 choice 中自带 usage，DeepSeek 需要显式开启
+    fn stream_options(&self) -> Option<serde_json::Value> {
+        match self.provider.as_str() {
+            "kimi" => None,
+            _ => Some(serde_json::json!({ "include_usage": true })),
+        }
+    }
+
+    fn chat_completions_url(&self) -> String {
+        match self.provider.as_str() {
+            "kimi" => format!("{}/v1/chat/completions", self.base_url),
+            _ => format!("{}/chat/completions", self.base_url),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct CompletionRequest {
+    pub system: String,
+    pub prompt: String,
+    // base64 编码的 PNG 图片，非空时使用视觉模型
+    pub images: Vec<String>,
+    pub show_reasoning: bool,
+    pub priority: Priority,
+}
+
+/// 一次调用的完整结果，推理内容与回答分开保存
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct Completion {
+    pub content: String,
+    pub reasoning: String,
+    pub provider: String,
+    pub model: String,
+    pub usage: Option<Usage>,
+    // 服务返回的结束原因，"length" 表示输出被截断
+    pub finish_reason: Option<String>,
+}
+
+pub enum StreamDelta<'a> {
+    Content(&'a str),
+    Reasoning(&'a str),
+    // 当前服务失败，改用下一个服务
+    Fallback { from: &'a str, to: &'a str, reason: &'a str },
+}
+
+#[derive(Debug)]
+pub struct ProviderError {
+    pub message: String,
+    pub retryable: bool,
+    pub retry_after: Option<Duration>,
+}
+
+impl ProviderError {
+    fn fatal(message: String) -> Self {
+        ProviderError {
+            message,
+            retryable: false,
+            retry_after: None,
+        }
+    }
+
+    fn transient(message: String) -> Self {
+        ProviderError {
+            message,
+            retryable: true,
+            retry_after: None,
+        }
+    }
+
+    fn from_status(name: &str, resp: &reqwest::Response) -> Self {
+        let status = resp.status();
+        let retry_after = resp
+            .headers()
+            .get(reqwest::header::RETRY_AFTER)
+            .and_then(|v| v.to_str().ok())
+            .and_then(parse_retry_after);
+        ProviderError {
+            message: format!("{} request failed: {}", name, status),
+            retryable: is_retryable_status(status),
+            retry_after,
+        }
+    }
+}
+
+pub type DeltaCallback<'a> = dyn FnMut(StreamDelta) + Send + 'a;
+
+/// 按 provider_chain 依次调用，每个服务内部按 RetryPolicy 重试
+///
+/// 一旦已经向前端输出了内容，就不再重试或回退，避免译文重复。
+pub async fn complete_with_fallback(
+    config: &ModelConfig,
+    request: &CompletionRequest,
+    scheduler: &Scheduler,
+    on_delta: &mut DeltaCallback<'_>,
+) -> Result<Completion, String> {
+    scheduler.configure(&config.rate_limits);
+    let chain = config.provider_chain();
+    let mut failures: Vec<(String, String)> = Vec::new();
+
+    for (index, provider) in chain.iter().enumerate() {
+        let target = if request.images.is_empty() {
+            config.target(provider)
+        } else {
+            config.vision_target(provider)
+        };
+        let target = match target {
+            Ok(target) => target,
+            Err(e) => {
+                println!("[complete_with_fallback] ❌ 跳过 {}：{}", provider, e);
+                failures.push((provider.clone(), e));
+                continue;
+            }
+        };
+
+        let mut streamed = false;
+        let mut attempt = 0;
+        let error = loop {
+            attempt += 1;
+            // 每次尝试单独排队，退避等待期间不占用并发名额
+            let result = {
+                let _permit = scheduler.acquire(&target.provider, request.priority).await;
+                let mut tracked = |delta: StreamDelta| {
+                    streamed = true;
+                    on_delta(delta)
+                };
+                stream_completion(&target, request, &mut tracked).await
+            };
+
+            match result {
+                Ok(completion) => return Ok(completion),
+                Err(e) if e.retryable && !streamed && attempt < config.retry.max_attempts => {
+                    let Some(wait) = config.retry.delay(attempt, e.retry_after) else {
+                        println!(
+                            "[complete_with_fallback] {} 要求等待 {:?}，超过上限，不再重试",
+                            target.display_name(),
+                            e.retry_after.unwrap_or_default()
+                        );
+                        break e;
+                    };
+                    println!(
+                        "[complete_with_fallback] {} 第 {} 次失败：{}，{:?} 后重试",
+                        target.display_name(),
+                        attempt,
+                        e.message,
+                        wait
+                    );
+                    tokio::time::sleep(wait).await;
+                }
+                Err(e) => break e,
+            }
+        };
+
+        println!("[complete_with_fallback] ❌ {} 失败：{}", target.display_name(), error.message);
+        if streamed {
+            return Err(error.message);
+        }
+        failures.push((provider.clone(), error.message.clone()));
+
+        if let Some(next) = chain.get(index + 1) {
+            on_delta(StreamDelta::Fallback {
+                from: provider,
+                to: next,
+                reason: &error.message,
+            });
+        }
+    }
+
+    if failures.len() == 1 {
+        return Err(failures.remove(0).1);
+    }
+    let summary: Vec<String> = failures
+        .iter()
+        .map(|(provider, message)| format!("{}: {}", provider, message))
+        .collect();
+    Err(format!("All providers failed: {}", summary.join("; ")))
+}
+
+pub async fn stream_completion(
+    target: &ProviderTarget,
+    request: &CompletionRequest,
+    on_delta: &mut DeltaCallback<'_>,
+) -> Result<Completion, ProviderError> {
+    let mut completion = match target.provider.as_str() {
+        "ollama" => stream_ollama(target, request, on_delta).await?,
+        _ => stream_openai_compatible(target, request, on_delta).await?,
+    };
+    completion.provider = target.provider.clone();
+    completion.model = target.model.clone();
+    if completion.usage.is_none() {
+        let prompt = format!("{}{}", request.system, request.prompt);
+        completion.usage = Some(Usage::estimate(&target.model, &prompt, &completion.content));
+    }
+    Ok(completion)
+}
+
+async fn stream_ollama(
+    target: &ProviderTarget,
+    request: &CompletionRequest,
+    on_delta: &mut DeltaCallback<'_>,
+) -> Result<Completion, ProviderError> {
+    let client = reqwest::Client::new();
+    let body = OllamaRequest {
+        model: target.model.clone(),
+        prompt: request.prompt.clone(),
+        system: Some(request.system.clone()),
+        stream: true,
+        think: request.show_reasoning,
+        options: Some(OllamaOptions::for_prompt(
+            &target.model,
+            &format!("{}{}", request.system, request.prompt),
+        )),
+        images: request.images.clone(),
+    };
+
+    let url = format!("{}/api/generate", target.base_url);
+    println!("[stream_ollama] 请求 URL: {}", url);
+
+    let mut resp = match client.post(&url).json(&body).send().await {
+        Ok(resp) if resp.status().is_success() => resp,
+        Ok(resp) => {
+            println!("[stream_ollama] ❌ 非 2xx 状态：{}", resp.status());
+            return Err(ProviderError::from_status("Ollama", &resp));
+        }
+        Err(e) => {
+            println!("[stream_ollama] ❌ 网络错误: {}", e);
+            return Err(network_error(e));
+        }
+    };
+
+    let mut completion = Completion::default();
+    let mut filter = ThinkFilter::new(request.show_reasoning);
+    let mut lines = LineBuffer::default();
+
+    loop {
+        let chunk = resp
+            .chunk()
+            .await
+            .map_err(|e| ProviderError::transient(format!("Failed to read chunk: {}", e)))?;
+        let batch = match &chunk {
+            Some(bytes) => lines.push(bytes),
+            None => lines.finish(),
+        };
+
+        for line in batch {
+            match serde_json::from_str::<OllamaStreamResponse>(&line) {
+                Ok(stream_resp) => {
+                    if let Some(thinking) = stream_resp.thinking.as_deref() {
+                        if request.show_reasoning && !thinking.is_empty() {
+                            completion.reasoning.push_str(thinking);
+                            on_delta(StreamDelta::Reasoning(thinking));
+                        }
+                    }
+                    apply_filtered(filter.push(&stream_resp.response), &mut completion, on_delta);
+
+                    if stream_resp.done {
+                        apply_filtered(filter.finish(), &mut completion, on_delta);
+                        completion.usage =
+                            Usage::from_ollama(stream_resp.prompt_eval_count, stream_resp.eval_count);
+                        completion.finish_reason = stream_resp.done_reason;
+                        return Ok(completion);
+                    }
+                }
+                Err(e) => {
+                    println!("[stream_ollama] JSON 解析错误: {}, 行: {}", e, line);
+                }
+            }
+        }
+
+        if chunk.is_none() {
+            break;
+        }
+    }
+
+    apply_filtered(filter.finish(), &mut completion, on_delta);
+    Ok(completion)
+}
+
+async fn stream_openai_compatible(
+    target: &ProviderTarget,
+    request: &CompletionRequest,
+    on_delta: &mut DeltaCallback<'_>,
+) -> Result<Completion, ProviderError> {
+    let client = reqwest::Client::new();
+    let user_content = if request.images.is_empty() {
+        MessageContent::Text(request.prompt.clone())
+    } else {
+        let mut parts = vec![serde_json::json!({ "type": "text", "text": request.prompt })];
+        parts.extend(request.images.iter().map(|image| {
+            serde_json::json!({
+                "type": "image_url",
+                "image_url": { "url": format!("data:image/png;base64,{}", image) },
+            })
+        }));
+        MessageContent::Parts(parts)
+    };
+    let body = ChatCompletionRequest {
+        model: target.model.clone(),
+        messages: vec![
+            ChatMessage {
+                role: "system".to_string(),
+                content: MessageContent::Text(request.system.clone()),
+            },
+            ChatMessage {
+                role: "user".to_string(),
+                content: user_content,
+            },
+        ],
+        stream: true,
+        stream_options: target.stream_options(),
+        // 不设置时部分服务默认只输出很短的内容
+        max_tokens: Some(
+            segment::model_budget(&target.provider, &target.model)
+                .max_output(estimate_tokens_for(&target.model, &format!("{}{}", request.system, request.prompt))),
+        ),
+    };
+
+    let url = target.chat_completions_url();
+    let name = target.display_name();
+    println!("[stream_openai_compatible] {} 请求 URL: {}", name, url);
+
+    let mut resp = match client
+        .post(&url)
+        .header("Authorization", format!("Bearer {}", target.api_key))
+        .header("Content-Type", "application/json")
+        .json(&body)
+        .send()
+        .await
+    {
+        Ok(resp) if resp.status().is_success() => resp,
+        Ok(resp) => {
+            println!("[stream_openai_compatible] ❌ {} 非 2xx 状态：{}", name, resp.status());
+            return Err(ProviderError::from_status(name, &resp));
+        }
+        Err(e) => {
+            println!("[stream_openai_compatible] ❌ {} 网络错误: {}", name, e);
+            return Err(network_error(e));
+        }
+    };
+
+    let mut completion = Completion::default();
+    let mut filter = ThinkFilter::new(request.show_reasoning);
+    let mut lines = LineBuffer::default();
+
+    loop {
+        let chunk = resp
+            .chunk()
+            .await
+            .map_err(|e| ProviderError::transient(format!("Failed to read chunk: {}", e)))?;
+        let batch = match &chunk {
+            Some(bytes) => lines.push(bytes),
+            None => lines.finish(),
+        };
+
+        for line in batch {
+            let Some(data_line) = line.strip_prefix("data: ") else {
+                continue;
+            };
+            if data_line.trim() == "[DONE]" {
+                apply_filtered(filter.finish(), &mut completion, on_delta);
+                return Ok(completion);
+            }
+
+            match serde_json::from_str::<serde_json::Value>(data_line) {
+                Ok(json) => {
+                    if let Some(usage) = Usage::from_openai_chunk(&json) {
+                        completion.usage = Some(usage);
+                    }
+
+                    let choice = json
+                        .get("choices")
+                        .and_then(|c| c.as_array())
+                        .and_then(|choices| choices.first());
+                    if let Some(reason) = choice.and_then(|c| c.get("finish_reason")).and_then(|r| r.as_str()) {
+                        completion.finish_reason = Some(reason.to_string());
+                    }
+                    let delta = choice.and_then(|choice| choice.get("delta"));
+                    let Some(delta) = delta else {
+                        continue;
+                    };
+
+                    // deepseek-reasoner 等推理模型把思考过程放在 reasoning_content 中
+                    if let Some(reasoning) = delta.get("reasoning_content").and_then(|c| c.as_str()) {
+                        if request.show_reasoning && !reasoning.is_empty() {
+                            completion.reasoning.push_str(reasoning);
+                            on_delta(StreamDelta::Reasoning(reasoning));
+                        }
+                    }
+                    if let Some(content) = delta.get("content").and_then(|c| c.as_str()) {
+                        apply_filtered(filter.push(content), &mut completion, on_delta);
+                    }
+                }
+                Err(e) => {
+                    println!("[stream_openai_compatible] JSON 解析错误: {}, 行: {}", e, data_line);
+                }
+            }
+        }
+
+        if chunk.is_none() {
+            break;
+        }
+    }
+
+    apply_filtered(filter.finish(), &mut completion, on_delta);
+    Ok(completion)
+}
+
+// 连接失败、超时、连接被重置都值得重试；构造请求本身出错则不必
+fn network_error(e: reqwest::Error) -> ProviderError {
+    let message = format!("Network error: {}", e);
+    if e.is_builder() {
+        ProviderError::fatal(message)
+    } else {
+        ProviderError::transient(message)
+    }
+}
+
+fn apply_filtered(out: FilterOutput, completion: &mut Completion, on_delta: &mut DeltaCallback<'_>) {
+    if !out.reasoning.is_empty() {
+        completion.reasoning.push_str(&out.reasoning);
+        on_delta(StreamDelta::Reasoning(&out.reasoning));
+    }
+    if !out.content.is_empty() {
+        completion.content.push_str(&out.content);
+        on_delta(StreamDelta::Content(&out.content));
+    }
+}
+
+/// 按行切分字节流；网络 chunk 可能截断在一行 JSON 或一个多字节字符中间
+#[derive(Default)]
+struct LineBuffer {
+    buf: Vec<u8>,
+}
+
+impl LineBuffer {
+    fn push(&mut self, bytes: &[u8]) -> Vec<String> {
+        self.buf.extend_from_slice(bytes);
+        let mut lines = Vec::new();
+        while let Some(pos) = self.buf.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = self.buf.drain(..=pos).collect();
+            let line = String::from_utf8_lossy(&line).trim().to_string();
+            if !line.is_empty() {
+                lines.push(line);
+            }
+        }
+        lines
+    }
+
+    fn finish(&mut self) -> Vec<String> {
+        let line = String::from_utf8_lossy(&self.buf).trim().to_string();
+        self.buf.clear();
+        if line.is_empty() {
+            Vec::new()
+        } else {
+            vec![line]
+        }
+    }
+}
@@ -0,0 +1,376 @@
+// 译文质量检查：每次翻译完成后做一遍规则检查，结果作为警告随译文返回
+//
+// 检查项：输出是否被截断、数字与单位是否一致、是否遵守术语库、长度比例是否异常、
+// 是否残留大段未翻译的英文；可选回译，比较回译与原文的相似度。
+
+use regex::Regex;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::sync::LazyLock;
+
+use crate::glossary;
+use crate::providers::{complete_with_fallback, Completion, CompletionRequest, ModelConfig, StreamDelta};
+use crate::scheduler::{Priority, Scheduler};
+use crate::storage::Storage;
+use crate::text::is_cjk;
+use crate::usage;
+
+// 太短的原文不检查长度比例
+const MIN_RATIO_CHARS: usize = 40;
+// 英文原文译成中文后，汉字数与原文字符数之比的正常范围
+const MIN_LENGTH_RATIO: f64 = 0.15;
+const MAX_LENGTH_RATIO: f64 = 1.0;
+// 译文中连续这么多个英文单词视为漏译
+const UNTRANSLATED_WORDS: usize = 6;
+// 回译与原文的词重合度低于这个值时提示
+const MIN_BACK_SIMILARITY: f64 = 0.4;
+
+const BACK_TRANSLATE_SYSTEM_PROMPT: &str =
+    "You are a professional translator. Translate the Chinese text into English literally, keeping technical terms.";
+
+static NUMBER_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\d+(?:,\d{3})*(?:\.\d+)?").unwrap());
+static QUANTITY_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"(\d+(?:\.\d+)?)\s*(%|°C|(?:ms|μs|us|ns|s|min|h|kHz|MHz|GHz|Hz|KB|MB|GB|TB|kg|mg|g|km|cm|mm|nm|μm|m|dB|kW|mW|W|mV|V|mA|K|M|B)\b)",
+    )
+    .unwrap()
+});
+static ENGLISH_RUN_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(&format!(r"(?:[A-Za-z][A-Za-z'-]*[\s,;:]+){{{}}}[A-Za-z]", UNTRANSLATED_WORDS - 1)).unwrap()
+});
+static WORD_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"[A-Za-z][A-Za-z-]{2,}").unwrap());
+
+#[derive(Debug, Clone, Serialize)]
+pub struct QualityWarning {
+    // truncated / empty / number / unit / glossary / length / untranslated / back_translation
+    pub kind: String,
+    pub message: String,
+}
+
+/// 翻译结果：Completion 的字段平铺，附带质量检查结果
+#[derive(Debug, Clone, Serialize)]
+pub struct CheckedTranslation {
+    #[serde(flatten)]
+    pub completion: Completion,
+    pub warnings: Vec<QualityWarning>,
+    pub back_translation: Option<String>,
+    pub back_similarity: Option<f64>,
+}
+
+fn warning(kind: &str, message: String) -> QualityWarning {
+    QualityWarning {
+        kind: kind.to_string(),
+        message,
+    }
+}
+
+/* ---------------------------------
+ * 规则检查
+ * --------------------------------- */
+
+fn numbers(text: &str) -> HashMap<String, usize> {
+    let mut counts = HashMap::new();
+    for m in NUMBER_RE.find_iter(text) {
+        *counts.entry(m.as_str().replace(',', "")).or_insert(0) += 1;
+    }
+    counts
+}
+
+fn check_numbers(source: &str, translation: &str) -> Vec<QualityWarning> {
+    let expected = numbers(source);
+    let actual = numbers(translation);
+    let mut missing: Vec<&str> = expected
+        .iter()
+        .filter(|(n, count)| actual.get(*n).copied().unwrap_or(0) < **count)
+        .map(|(n, _)| n.as_str())
+        .collect();
+    let mut extra: Vec<&str> = actual
+        .keys()
+        .filter(|n| !expected.contains_key(*n))
+        .map(|n| n.as_str())
+        .collect();
+    missing.sort();
+    extra.sort();
+
+    let mut warnings = Vec::new();
+    if !missing.is_empty() {
+        warnings.push(warning("number", format!("原文中的数字未出现在译文中：{}", missing.join("、"))));
+    }
+    if !extra.is_empty() {
+        warnings.push(warning("number", format!("译文中出现了原文没有的数字：{}", extra.join("、"))));
+    }
+    warnings
+}
+
+// 同一个数字在译文中带了不同的单位，例如 5 ms 变成 5 s
+fn check_units(source: &str, translation: &str) -> Vec<QualityWarning> {
+    let translated: Vec<(String, String)> = QUANTITY_RE
+        .captures_iter(translation)
+        .map(|c| (c[1].to_string(), c[2].to_string()))
+        .collect();
+    let mut warnings = Vec::new();
+    for c in QUANTITY_RE.captures_iter(source) {
+        let (value, unit) = (&c[1], &c[2]);
+        let units: Vec<&str> = translated
+            .iter()
+            .filter(|(v, _)| v == value)
+            .map(|(_, u)| u.as_str())
+            .collect();
+        if !units.is_empty() && !units.contains(&unit) {
+            warnings.push(warning(
+                "unit",
+                format!("原文 {} {} 在译文中的单位变成了 {}", value, unit, units.join("、")),
+            ));
+        }
+    }
+    warnings
+}
+
+fn check_glossary(storage: &Storage, source: &str, translation: &str) -> Vec<QualityWarning> {
+    let glossary = match glossary::load(storage) {
+        Ok(glossary) => glossary,
+        Err(e) => {
+            println!("⚠️ 读取术语库失败：{}", e);
+            return Vec::new();
+        }
+    };
+    let mut seen = HashSet::new();
+    let mut warnings = Vec::new();
+    for (_, _, term) in glossary.find_terms(source) {
+        if !seen.insert(term.term.to_lowercase()) || translation.contains(&term.translation) {
+            continue;
+        }
+        warnings.push(warning(
+            "glossary",
+            format!("术语「{}」未使用术语库译法「{}」", term.term, term.translation),
+        ));
+    }
+    warnings
+}
+
+// 只检查英译中：原文以拉丁字母为主、译文以汉字为主。只数汉字本身，
+// 中文标点和全角字符不算，译文里保留的英文术语按词计
+fn check_length(source: &str, translation: &str) -> Option<QualityWarning> {
+    let source_chars = source.chars().filter(|c| !c.is_whitespace()).count();
+    if source_chars < MIN_RATIO_CHARS {
+        return None;
+    }
+    let source_cjk = source.chars().filter(|c| is_cjk(*c)).count();
+    let translation_cjk = translation.chars().filter(|c| is_cjk(*c)).count();
+    let translation_words = translation
+        .split(|c: char| !c.is_ascii_alphabetic())
+        .filter(|w| !w.is_empty())
+        .count();
+    if source_cjk * 2 > source_chars || translation_cjk < translation_words * 2 {
+        return None;
+    }
+    let ratio = translation_cjk as f64 / source_chars as f64;
+    if ratio < MIN_LENGTH_RATIO {
+        Some(warning("length", format!("译文明显短于原文（长度比 {:.2}），可能有遗漏", ratio)))
+    } else if ratio > MAX_LENGTH_RATIO {
+        Some(warning("length", format!("译文明显长于原文（长度比 {:.2}），可能添加了额外内容", ratio)))
+    } else {
+        None
+    }
+}
+
+fn check_untranslated(source: &str, translation: &str) -> Option<QualityWarning> {
+    // 原文本身是中文时不检查
+    if source.chars().filter(|c| is_cjk(*c)).count() * 2 > source.chars().count() {
+        return None;
+    }
+    let run = ENGLISH_RUN_RE.find(translation)?;
+    let preview: String = translation[run.start()..].chars().take(60).collect();
+    Some(warning("untranslated", format!("译文中有未翻译的英文：{}…", preview.trim())))
+}
+
+/// 对一次翻译做规则检查，返回发现的问题
+pub fn check_translation(storage: &Storage, source: &str, completion: &Completion) -> Vec<QualityWarning> {
+    let translation = completion.content.trim();
+    if translation.is_empty() {
+        return vec![warning("empty", "模型没有返回译文".to_string())];
+    }
+
+    let mut warnings = Vec::new();
+    if completion.finish_reason.as_deref() == Some("length") {
+        warnings.push(warning(
+            "truncated",
+            format!("{} 的输出达到长度上限被截断，译文不完整", completion.model),
+        ));
+    }
+    warnings.extend(check_numbers(source, translation));
+    warnings.extend(check_units(source, translation));
+    warnings.extend(check_glossary(storage, source, translation));
+    warnings.extend(check_length(source, translation));
+    warnings.extend(check_untranslated(source, translation));
+    warnings
+}
+
+/* ---------------------------------
+ * 回译
+ * --------------------------------- */
+
+fn word_set(text: &str) -> HashSet<String> {
+    WORD_RE.find_iter(text).map(|m| m.as_str().to_lowercase()).collect()
+}
+
+/// 回译与原文的词重合度（Dice 系数），不要求措辞一致
+pub fn back_similarity(source: &str, back: &str) -> f64 {
+    let a = word_set(source);
+    let b = word_set(back);
+    if a.is_empty() || b.is_empty() {
+        return 0.0;
+    }
+    2.0 * a.intersection(&b).count() as f64 / (a.len() + b.len()) as f64
+}
+
+/// 把译文再译回英文，不向前端推送片段，只记录用量
+pub async fn back_translate(
+    config: &ModelConfig,
+    translation: &str,
+    document_id: Option<&str>,
+    app_handle: &tauri::AppHandle,
+) -> Result<String, String> {
+    use tauri::Manager;
+
+    let request = CompletionRequest {
+        system: BACK_TRANSLATE_SYSTEM_PROMPT.to_string(),
+        prompt: translation.to_string(),
+        images: Vec::new(),
+        show_reasoning: false,
+        priority: Priority::Background,
+    };
+    let mut on_delta = |_: StreamDelta| {};
+    let scheduler = app_handle.state::<Scheduler>();
+    let completion = complete_with_fallback(config, &request, &scheduler, &mut on_delta).await?;
+    usage::record_completion(&app_handle.state::<Storage>(), document_id, "back_translation", &completion);
+    Ok(completion.content.trim().to_string())
+}
+
+/// 检查译文，back_translate 为 true 时额外做一次回译比较
+pub async fn checked(
+    config: &ModelConfig,
+    source: &str,
+    completion: Completion,
+    with_back_translation: bool,
+    document_id: Option<&str>,
+    app_handle: &tauri::AppHandle,
+) -> CheckedTranslation {
+    use tauri::Manager;
+
+    let mut warnings = check_translation(&app_handle.state::<Storage>(), source, &completion);
+    let mut result = CheckedTranslation {
+        completion,
+        warnings: Vec::new(),
+        back_translation: None,
+        back_similarity: None,
+    };
+
+    let translation = result.completion.content.trim();
+    if with_back_translation && !translation.is_empty() {
+        match back_translate(config, translation, document_id, app_handle).await {
+            Ok(back) => {
+                let similarity = back_similarity(source, &back);
+                if similarity < MIN_BACK_SIMILARITY {
+                    warnings.push(warning(
+                        "back_translation",
+                        format!("回译与原文差异较大（相似度 {:.0}%），译文可能偏离原意", similarity * 100.0),
+                    ));
+                }
+                result.back_translation = Some(back);
+                result.back_similarity = Some(similarity);
+            }
+            Err(e) => println!("⚠️ 回译失败：{}", e),
+        }
+    }
+
+    if !warnings.is_empty() {
+        println!("⚠️ 译文质量检查发现 {} 个问题", warnings.len());
+    }
+    result.warnings = warnings;
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(warnings: &[QualityWarning]) -> Vec<&str> {
+        warnings.iter().map(|w| w.kind.as_str()).collect()
+    }
+
+    #[test]
+    fn check_numbers_ignores_thousands_separators() {
+        let cases: &[(&str, &str, &[&str])] = &[
+            ("Trained on 1,281,167 images.", "在 1281167 张图像上训练。", &[]),
+            ("We use 10000 steps.", "我们训练 10,000 步。", &[]),
+            ("Accuracy rose from 76.1 to 78.5.", "准确率从 76.1 提高到 78.5。", &[]),
+            ("Accuracy rose from 76.1 to 78.5.", "准确率从 76.1 提高到 87.5。", &["原文中的数字未出现在译文中：78.5", "译文中出现了原文没有的数字：87.5"]),
+            // 同一个数字出现两次，译文只出现一次
+            ("Layers 3 and 3 again.", "第 3 层。", &["原文中的数字未出现在译文中：3"]),
+            ("Batch size 1,024.", "批大小 1024。", &[]),
+        ];
+        for (source, translation, expected) in cases {
+            let messages: Vec<String> = check_numbers(source, translation).into_iter().map(|w| w.message).collect();
+            assert_eq!(messages, *expected, "{:?} → {:?}", source, translation);
+        }
+    }
+
+    #[test]
+    fn check_units_flags_changed_units_only() {
+        let cases: &[(&str, &str, usize)] = &[
+            ("Latency is 5 ms.", "延迟为 5 ms。", 0),
+            ("Latency is 5 ms.", "延迟为 5 s。", 1),
+            ("Accuracy improves by 3%.", "准确率提高了 3%。", 0),
+            ("Runs at 2.4 GHz with 16 GB.", "运行在 2.4 MHz，内存 16 GB。", 1),
+            // 译文换成中文单位时不比较
+            ("It takes 3 h.", "需要 3 小时。", 0),
+            ("Trained at 25 °C for 10 min.", "在 25°C 下训练 10 min。", 0),
+        ];
+        for (source, translation, expected) in cases {
+            let warnings = check_units(source, translation);
+            assert_eq!(warnings.len(), *expected, "{:?} → {:?}", source, translation);
+            assert!(kinds(&warnings).iter().all(|k| *k == "unit"));
+        }
+    }
+
+    #[test]
+    fn check_length_counts_only_chinese_letters() {
+        let source = "The proposed method significantly outperforms all baselines on three benchmarks.";
+        let cases: &[(&str, Option<&str>)] = &[
+            ("所提出的方法在三个基准上显著优于所有基线方法。", None),
+            ("方法更好。", Some("length")),
+            // 标点和全角字符不算汉字
+            ("方法更好！！！！！！！！！！！！！！！！！！！！", Some("length")),
+            ("该方法很好。（详见下文）。。。。。。。。。。。。。。。。。。。。。。。。。。。。", Some("length")),
+            // 保留英文术语的译文仍然检查
+            ("所提出的 Transformer 方法在 GLUE 等三个基准上显著优于所有基线方法。", None),
+            // 译文不是中文时不检查
+            ("The method is better.", None),
+        ];
+        for (translation, expected) in cases {
+            let kind = check_length(source, translation).map(|w| w.kind);
+            assert_eq!(kind.as_deref(), *expected, "{:?}", translation);
+        }
+        // 原文太短或本身是中文时不检查
+        assert!(check_length("Short.", "短。").is_none());
+        assert!(check_length("所提出的方法在三个基准上显著优于所有的基线方法，并且训练速度更快、占用的显存更少。", "好。").is_none());
+    }
+
+    #[test]
+    fn check_untranslated_allows_kept_english_terms() {
+        let source = "We fine-tune BERT and Vision Transformer (ViT) models on ImageNet using AdamW with cosine decay.";
+        let cases: &[(&str, bool)] = &[
+            ("我们使用 AdamW 和余弦衰减，在 ImageNet 上微调 BERT 和 Vision Transformer (ViT) 模型。", false),
+            ("我们在 ImageNet 上微调 BERT 模型，using AdamW with cosine decay and warmup.", true),
+            ("We fine-tune BERT and Vision Transformer models on ImageNet.", true),
+            ("我们微调了模型。", false),
+        ];
+        for (translation, expected) in cases {
+            let warned = check_untranslated(source, translation).is_some();
+            assert_eq!(warned, *expected, "{:?}", translation);
+        }
+        // 原文是中文时不检查
+        assert!(check_untranslated("我们提出了一种新的方法。", "We propose a brand new method for this task.").is_none());
+    }
+}
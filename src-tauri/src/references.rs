@@ -0,0 +1,834 @@
+// 参考文献解析与引用定位
+//
+// 从 References 标题之后的文字中切分出每一条文献，抽取作者、标题、出处、
+// 年份、DOI 与 arXiv 编号；正文中的 [23]、Vaswani et al. (2017) 之类的
+// 引用标记据此对应到具体条目，并可导出为 BibTeX。
+
+use regex::Regex;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, LazyLock, Mutex};
+
+use crate::file_scope::FileScope;
+use crate::outline::{self, SectionKind};
+use crate::pdf_text::{PageText, PdfTextCache, TextLine};
+use crate::text::{self, join_line};
+
+// 单个引用标记里展开的编号上限，防止 [1-9999] 这类误识别
+const MAX_MARKER_RANGE: u32 = 50;
+// 同时缓存参考文献列表的文档数，与 PdfTextCache 一致
+const MAX_CACHED_DOCUMENTS: usize = 4;
+
+static NUMERIC_LABEL_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^(?:\[(\d{1,3})\]\s*|(\d{1,3})\.\s+)").unwrap());
+static DOI_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?i)(?:doi:\s*|doi\.org/)?(10\.\d{4,9}/[^\s\x22<>]+)").unwrap());
+static ARXIV_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)(?:arxiv:\s*|arxiv\.org/(?:abs|pdf)/)(\d{4}\.\d{4,5}|[a-z\-]+(?:\.[A-Z]{2})?/\d{7})(?:v\d+)?").unwrap()
+});
+static URL_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"https?://\S+").unwrap());
+static YEAR_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\b((?:19|20)\d{2})([a-z]?)\b").unwrap());
+static QUOTED_TITLE_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"[“"]([^”"]{8,})[”"]"#).unwrap());
+static NUMERIC_CITATION_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\[(\d{1,3}(?:\s*[,;–\-]\s*\d{1,3})*)\]").unwrap());
+static AUTHOR_YEAR_CITATION_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(\p{Lu}[\p{L}'\-]+)(?:\s+et\s+al\.?|\s+(?:and|&)\s+\p{Lu}[\p{L}'\-]+)?,?\s*\(?((?:19|20)\d{2}[a-z]?)\)?")
+        .unwrap()
+});
+static EMPTY_FIELD_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\s*,(?:\s*,)+\s*").unwrap());
+static INITIALS_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^(?:\p{Lu}\.?\s*-?\s*)+$").unwrap());
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Reference {
+    // 在参考文献列表中的顺序，从 0 开始
+    pub index: usize,
+    // 编号式文献的编号（"23"），作者-年份式为空
+    pub label: Option<String>,
+    pub raw: String,
+    pub authors: Vec<String>,
+    pub title: Option<String>,
+    pub venue: Option<String>,
+    pub year: Option<String>,
+    pub doi: Option<String>,
+    pub arxiv_id: Option<String>,
+    pub page: u32,
+    pub y: f32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CitationLink {
+    pub marker: String,
+    pub page: u32,
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+    // 对应的文献在列表中的 index
+    pub references: Vec<usize>,
+}
+
+/* ---------------------------------
+ * 切分参考文献
+ * --------------------------------- */
+
+pub fn parse_references(pages: &[Arc<PageText>]) -> Vec<Reference> {
+    let headings = outline::headings(pages);
+    let Some(start) = headings.iter().position(|h| h.kind == SectionKind::References) else {
+        return Vec::new();
+    };
+    let heading = &headings[start];
+    // 参考文献之后的附录、致谢等一级标题是列表的结尾
+    let end = headings[start + 1..]
+        .iter()
+        .find(|h| h.level == 1 && h.kind != SectionKind::References)
+        .map(|h| (h.page, h.y));
+
+    let mut lines: Vec<(u32, &TextLine)> = Vec::new();
+    let mut started = false;
+    'pages: for page in pages.iter().filter(|p| p.page >= heading.page) {
+        for line in &page.lines {
+            if !started {
+                started = page.page == heading.page && Some(line.y) == heading.y && line.text.trim() == heading.title;
+                continue;
+            }
+            if end == Some((page.page, Some(line.y))) {
+                break 'pages;
+            }
+            // 跳过页码
+            if line.text.trim().chars().all(|c| c.is_ascii_digit()) {
+                continue;
+            }
+            lines.push((page.page, line));
+        }
+    }
+
+    split_entries(&lines)
+        .into_iter()
+        .enumerate()
+        .map(|(index, (label, page, y, raw))| parse_entry(index, label, page, y, raw))
+        .collect()
+}
+
+// 编号式按 [n] / n. 切分；作者-年份式按悬挂缩进切分
+fn split_entries(lines: &[(u32, &TextLine)]) -> Vec<(Option<String>, u32, f32, String)> {
+    let numbered = lines
+        .iter()
+        .filter(|(_, l)| NUMERIC_LABEL_RE.is_match(l.text.trim()))
+        .count();
+    let use_labels = numbered * 3 >= lines.len().clamp(1, 30) && numbered >= 2;
+
+    let mut entries: Vec<(Option<String>, u32, f32, String)> = Vec::new();
+    for (i, (page, line)) in lines.iter().enumerate() {
+        let text = line.text.trim();
+        if use_labels {
+            if let Some(caps) = NUMERIC_LABEL_RE.captures(text) {
+                let label = caps.get(1).or(caps.get(2)).map(|m| m.as_str().to_string());
+                entries.push((label, *page, line.y, text[caps[0].len()..].to_string()));
+                continue;
+            }
+        } else if starts_entry(lines, i) {
+            entries.push((None, *page, line.y, text.to_string()));
+            continue;
+        }
+        match entries.last_mut() {
+            Some((_, _, _, raw)) => join_line(raw, text),
+            None => entries.push((None, *page, line.y, text.to_string())),
+        }
+    }
+    entries
+}
+
+fn starts_entry(lines: &[(u32, &TextLine)], i: usize) -> bool {
+    let (page, line) = lines[i];
+    let Some((prev_page, prev)) = i.checked_sub(1).map(|p| lines[p]) else {
+        return true;
+    };
+    // 换页或换栏后的第一行：下一行有悬挂缩进，或上一条已经结束
+    if prev_page != page || line.y < prev.y {
+        let next_indented = lines
+            .get(i + 1)
+            .is_some_and(|(p, next)| *p == page && next.y > line.y && next.x > line.x + 3.0);
+        return next_indented || line_ends_entry(prev);
+    }
+    // 悬挂缩进：比上一行更靠左则是新条目
+    if line.x + 3.0 < prev.x {
+        return true;
+    }
+    if line.x > prev.x + 3.0 {
+        return false;
+    }
+    // 没有缩进的排版：上一行以句号结束且这一行像作者名开头
+    line_ends_entry(prev) && looks_like_author_start(&line.text)
+}
+
+fn line_ends_entry(line: &TextLine) -> bool {
+    let text = line.text.trim_end();
+    text.ends_with('.') && !text.ends_with(" et al.")
+}
+
+fn looks_like_author_start(text: &str) -> bool {
+    let first = text.split_whitespace().next().unwrap_or("");
+    first.starts_with(|c: char| c.is_uppercase()) && (first.ends_with(',') || first.len() > 1)
+}
+
+/* ---------------------------------
+ * 解析单条文献
+ * --------------------------------- */
+
+fn parse_entry(index: usize, label: Option<String>, page: u32, y: f32, raw: String) -> Reference {
+    let doi = find_doi(&raw);
+    let arxiv_id = find_arxiv_id(&raw);
+
+    // 去掉链接和编号后再找年份，避免把 arXiv 编号的前四位当成年份
+    let cleaned = URL_RE.replace_all(&raw, "");
+    let cleaned = DOI_RE.replace_all(&cleaned, "");
+    let cleaned = ARXIV_RE.replace_all(&cleaned, "").to_string();
+    let year_match = YEAR_RE.captures(&cleaned);
+    let year = year_match.as_ref().map(|c| format!("{}{}", &c[1], &c[2]));
+
+    let (authors_part, title, venue) = if let Some(caps) = QUOTED_TITLE_RE.captures(&cleaned) {
+        // IEEE：A. Author, "Title," in Venue, 2020.
+        let whole = caps.get(0).unwrap();
+        let title = caps[1].trim().trim_end_matches(',').to_string();
+        let venue = cleaned[whole.end()..].trim_start_matches([',', '.', ' ']).to_string();
+        (cleaned[..whole.start()].to_string(), Some(title), Some(venue))
+    } else {
+        let sentences = split_sentences(&cleaned);
+        let mut rest = sentences.iter().map(|s| s.as_str()).filter(|s| !is_year_only(s));
+        let authors = rest.next().unwrap_or("").to_string();
+        let title = rest.next().map(str::to_string);
+        let venue = rest.collect::<Vec<_>>().join(". ");
+        (authors, title, Some(venue))
+    };
+
+    let venue = venue
+        .map(|v| clean_venue(&v))
+        .filter(|v| !v.is_empty());
+    let title = title
+        .map(|t| t.trim().trim_end_matches(['.', ',']).to_string())
+        .filter(|t| !t.is_empty());
+
+    Reference {
+        index,
+        label,
+        authors: parse_authors(&authors_part),
+        title,
+        venue,
+        year,
+        doi,
+        arxiv_id,
+        page,
+        y,
+        raw,
+    }
+}
+
+/// 文本中的第一个 DOI
+pub fn find_doi(text: &str) -> Option<String> {
+    DOI_RE
+        .captures(text)
+        .map(|c| c[1].trim_end_matches(['.', ',', ';', ')']).to_string())
+}
+
+/// 文本中的第一个 arXiv 编号（不含版本号）
+pub fn find_arxiv_id(text: &str) -> Option<String> {
+    ARXIV_RE.captures(text).map(|c| c[1].to_string())
+}
+
+fn is_year_only(sentence: &str) -> bool {
+    let s = sentence.trim().trim_matches(['(', ')', '.', ',']);
+    YEAR_RE.find(s).is_some_and(|m| m.as_str().len() == s.len())
+}
+
+// 按 ". " 分句，但作者缩写（"J. Smith"）和常见缩写后的句点不算句末；句末的句点去掉
+fn split_sentences(text: &str) -> Vec<String> {
+    const ABBREVIATIONS: &[&str] = &["al", "Proc", "Conf", "Vol", "vol", "pp", "eds", "ed", "Int", "J", "Trans", "No", "no"];
+    text::split_sentences_by(text, |sentence, _| {
+        let word: String = sentence
+            .trim_end_matches('.')
+            .chars()
+            .rev()
+            .take_while(|c| c.is_alphanumeric())
+            .collect::<Vec<_>>()
+            .into_iter()
+            .rev()
+            .collect();
+        let is_initial = word.chars().count() == 1 && word.chars().all(char::is_uppercase);
+        !is_initial && !ABBREVIATIONS.contains(&word.as_str())
+    })
+    .into_iter()
+    .map(|s| s.trim_end_matches('.').to_string())
+    .collect()
+}
+
+fn clean_venue(venue: &str) -> String {
+    let venue = venue.trim().trim_start_matches("In ").trim_start_matches("in ");
+    let venue = YEAR_RE.replace_all(venue, "");
+    // 去掉年份后留下的 ", ," 合并成一个逗号
+    let venue = EMPTY_FIELD_RE.replace_all(&venue, ", ");
+    venue
+        .trim()
+        .trim_matches([',', '.', ' ', '(', ')'])
+        .chars()
+        .take(200)
+        .collect()
+}
+
+// "Smith, J., Doe, A." 与 "John Smith, Jane Doe and Bob Lee" 两种写法
+fn parse_authors(text: &str) -> Vec<String> {
+    let text = YEAR_RE.replace_all(text, "");
+    let text = text
+        .replace(" et al.", "")
+        .replace(" et al", "")
+        .replace(['(', ')'], "");
+    let parts: Vec<String> = text
+        .split([';', '&'])
+        .flat_map(|chunk| chunk.split(" and "))
+        .flat_map(|chunk| chunk.split(','))
+        .map(|p| p.trim().to_string())
+        .filter(|p| !p.is_empty())
+        .collect();
+
+    let mut authors = Vec::new();
+    let mut i = 0;
+    while i < parts.len() {
+        let next_is_initials = parts.get(i + 1).is_some_and(|n| INITIALS_RE.is_match(n));
+        if next_is_initials && !INITIALS_RE.is_match(&parts[i]) {
+            authors.push(format!("{}, {}", parts[i], parts[i + 1]));
+            i += 2;
+        } else {
+            authors.push(parts[i].clone());
+            i += 1;
+        }
+    }
+    authors.retain(|a| a.chars().any(char::is_alphabetic));
+    authors
+}
+
+/// 作者姓氏：“Smith, J.” 取逗号前，“John Smith” 取最后一个词
+pub fn surname(author: &str) -> String {
+    match author.split_once(',') {
+        Some((last, _)) => last.trim().to_string(),
+        None => author.split_whitespace().last().unwrap_or("").to_string(),
+    }
+}
+
+/* ---------------------------------
+ * 引用标记
+ * --------------------------------- */
+
+/// 把一个引用标记解析成对应的文献：支持 [3]、[1, 4-6]、Vaswani et al. (2017) 等写法
+pub fn resolve_marker(references: &[Reference], marker: &str) -> Vec<usize> {
+    let numbers = marker_numbers(marker);
+    if !numbers.is_empty() {
+        return numbers
+            .iter()
+            .filter_map(|n| {
+                let label = n.to_string();
+                references.iter().find(|r| r.label.as_deref() == Some(label.as_str()))
+            })
+            .map(|r| r.index)
+            .collect();
+    }
+
+    AUTHOR_YEAR_CITATION_RE
+        .captures_iter(marker)
+        .filter_map(|caps| match_author_year(references, &caps[1], &caps[2]))
+        .collect()
+}
+
+fn marker_numbers(marker: &str) -> Vec<u32> {
+    let inner = marker.trim().trim_start_matches('[').trim_end_matches(']');
+    if !inner.chars().all(|c| c.is_ascii_digit() || " ,;–-".contains(c)) {
+        return Vec::new();
+    }
+    let mut numbers = Vec::new();
+    for part in inner.split([',', ';']) {
+        let bounds: Vec<u32> = part
+            .split(['-', '–'])
+            .filter_map(|n| n.trim().parse().ok())
+            .collect();
+        match bounds[..] {
+            [n] => numbers.push(n),
+            [a, b] if a <= b && b - a <= MAX_MARKER_RANGE => numbers.extend(a..=b),
+            _ => {}
+        }
+    }
+    numbers
+}
+
+fn match_author_year(references: &[Reference], name: &str, year: &str) -> Option<usize> {
+    references
+        .iter()
+        .find(|r| {
+            r.year.as_deref() == Some(year)
+                && r.authors
+                    .first()
+                    .is_some_and(|a| surname(a).eq_ignore_ascii_case(name))
+        })
+        .map(|r| r.index)
+}
+
+/// 在参考文献之前的正文中找出引用标记及其位置
+pub fn citation_links(pages: &[Arc<PageText>], references: &[Reference]) -> Vec<CitationLink> {
+    let Some(first) = references.first() else {
+        return Vec::new();
+    };
+    let numbered = references.iter().any(|r| r.label.is_some());
+    let surnames: HashSet<String> = references
+        .iter()
+        .filter_map(|r| r.authors.first())
+        .map(|a| surname(a).to_lowercase())
+        .collect();
+
+    let mut links = Vec::new();
+    for page in pages.iter().filter(|p| p.page <= first.page) {
+        for line in &page.lines {
+            if page.page == first.page && line.y >= first.y {
+                continue;
+            }
+            let matches: Vec<(String, Vec<usize>)> = if numbered {
+                NUMERIC_CITATION_RE
+                    .find_iter(&line.text)
+                    .map(|m| (m.as_str().to_string(), resolve_marker(references, m.as_str())))
+                    .collect()
+            } else {
+                AUTHOR_YEAR_CITATION_RE
+                    .captures_iter(&line.text)
+                    .filter(|c| surnames.contains(&c[1].to_lowercase()))
+                    .map(|c| {
+                        // "(Vaswani et al., 2017)" 只匹配到右括号，去掉不成对的括号
+                        let marker = c[0].strip_suffix(')').filter(|m| !m.contains('(')).unwrap_or(&c[0]);
+                        (marker.to_string(), resolve_marker(references, marker))
+                    })
+                    .collect()
+            };
+
+            for (marker, indices) in matches {
+                if indices.is_empty() {
+                    continue;
+                }
+                // 标记落在某个 span 内时用 span 的位置，否则退回整行
+                let span = line.spans.iter().find(|s| s.text.contains(&marker));
+                let (x, y, width, height) = match span {
+                    Some(s) => (s.x, s.y, s.width, s.height),
+                    None => (line.x, line.y, line.width, line.height),
+                };
+                links.push(CitationLink {
+                    marker,
+                    page: page.page,
+                    x,
+                    y,
+                    width,
+                    height,
+                    references: indices,
+                });
+            }
+        }
+    }
+    links
+}
+
+/* ---------------------------------
+ * BibTeX 导出
+ * --------------------------------- */
+
+pub fn to_bibtex(references: &[Reference]) -> String {
+    let mut used_keys: HashSet<String> = HashSet::new();
+    let mut out = String::new();
+    for reference in references {
+        let key = unique_key(bibtex_key(reference), &mut used_keys);
+        let venue = reference.venue.as_deref().unwrap_or("");
+        let lower = venue.to_lowercase();
+        let entry_type = if reference.arxiv_id.is_some() && (venue.is_empty() || lower.contains("arxiv")) {
+            "misc"
+        } else if ["proceedings", "conference", "workshop", "symposium", "proc."]
+            .iter()
+            .any(|w| lower.contains(w))
+        {
+            "inproceedings"
+        } else if venue.is_empty() {
+            "misc"
+        } else {
+            "article"
+        };
+
+        let mut fields: Vec<(&str, String)> = Vec::new();
+        if !reference.authors.is_empty() {
+            fields.push(("author", reference.authors.join(" and ")));
+        }
+        if let Some(title) = &reference.title {
+            fields.push(("title", title.clone()));
+        }
+        if !venue.is_empty() {
+            let field = match entry_type {
+                "inproceedings" => "booktitle",
+                "article" => "journal",
+                _ => "howpublished",
+            };
+            fields.push((field, venue.to_string()));
+        }
+        if let Some(year) = &reference.year {
+            fields.push(("year", year.trim_end_matches(|c: char| c.is_alphabetic()).to_string()));
+        }
+        if let Some(doi) = &reference.doi {
+            fields.push(("doi", doi.clone()));
+        }
+        if let Some(arxiv) = &reference.arxiv_id {
+            fields.push(("eprint", arxiv.clone()));
+            fields.push(("archivePrefix", "arXiv".to_string()));
+        }
+        if fields.is_empty() || reference.title.is_none() {
+            fields.push(("note", reference.raw.clone()));
+        }
+
+        out.push_str(&format!("@{}{{{},\n", entry_type, key));
+        for (name, value) in fields {
+            out.push_str(&format!("  {} = {{{}}},\n", name, escape_bibtex(&value)));
+        }
+        out.push_str("}\n\n");
+    }
+    out
+}
+
+fn bibtex_key(reference: &Reference) -> String {
+    let author: String = reference
+        .authors
+        .first()
+        .map(|a| surname(a))
+        .unwrap_or_else(|| "ref".to_string())
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .collect::<String>()
+        .to_lowercase();
+    let year = reference
+        .year
+        .as_deref()
+        .map(|y| y.trim_end_matches(|c: char| c.is_alphabetic()))
+        .unwrap_or("");
+    let word: String = reference
+        .title
+        .as_deref()
+        .and_then(|t| {
+            t.split_whitespace()
+                .map(|w| w.chars().filter(|c| c.is_alphanumeric()).collect::<String>())
+                .find(|w| w.chars().count() > 3)
+        })
+        .unwrap_or_default()
+        .to_lowercase();
+    format!("{}{}{}", author, year, word)
+}
+
+fn unique_key(key: String, used: &mut HashSet<String>) -> String {
+    let mut candidate = key.clone();
+    let mut suffix = b'a';
+    while used.contains(&candidate) && suffix <= b'z' {
+        candidate = format!("{}{}", key, suffix as char);
+        suffix += 1;
+    }
+    used.insert(candidate.clone());
+    candidate
+}
+
+fn escape_bibtex(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '&' | '%' | '$' | '#' | '_' | '{' | '}') {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+/* ---------------------------------
+ * 缓存
+ * --------------------------------- */
+
+/// 一篇文档的页面文本与从中解析出的参考文献
+pub struct DocumentReferences {
+    // 解析时用的页面；PdfTextCache 因文件修改重新解析后页面对象不同，缓存随之失效
+    pub pages: Vec<Arc<PageText>>,
+    pub references: Vec<Reference>,
+}
+
+struct CacheEntry {
+    path: PathBuf,
+    parsed: Arc<DocumentReferences>,
+}
+
+/// 按文档缓存解析好的参考文献列表，引用跳转、标记定位和导出不必每次重新解析
+#[derive(Default)]
+pub struct ReferenceCache {
+    // 最近使用的排在最后
+    docs: Mutex<Vec<CacheEntry>>,
+}
+
+impl ReferenceCache {
+    pub fn references(&self, text_cache: &PdfTextCache, path: &Path) -> Result<Arc<DocumentReferences>, String> {
+        let pages = text_cache.document_text(path)?;
+        {
+            let mut docs = self.docs.lock().unwrap_or_else(|e| e.into_inner());
+            if let Some(pos) = docs.iter().position(|d| d.path == path && same_pages(&d.parsed.pages, &pages)) {
+                let entry = docs.remove(pos);
+                let parsed = entry.parsed.clone();
+                docs.push(entry);
+                return Ok(parsed);
+            }
+        }
+
+        let references = parse_references(&pages);
+        let parsed = Arc::new(DocumentReferences { pages, references });
+        let mut docs = self.docs.lock().unwrap_or_else(|e| e.into_inner());
+        docs.retain(|d| d.path != path);
+        docs.push(CacheEntry {
+            path: path.to_path_buf(),
+            parsed: parsed.clone(),
+        });
+        if docs.len() > MAX_CACHED_DOCUMENTS {
+            docs.remove(0);
+        }
+        Ok(parsed)
+    }
+}
+
+fn same_pages(a: &[Arc<PageText>], b: &[Arc<PageText>]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).all(|(x, y)| Arc::ptr_eq(x, y))
+}
+
+fn load_references(app_handle: &tauri::AppHandle, path: &str) -> Result<Arc<DocumentReferences>, String> {
+    use tauri::Manager;
+
+    let canonical = app_handle.state::<FileScope>().resolve_pdf(path)?;
+    app_handle
+        .state::<ReferenceCache>()
+        .references(&app_handle.state::<PdfTextCache>(), &canonical)
+}
+
+#[tauri::command]
+pub async fn get_references(path: String, app_handle: tauri::AppHandle) -> Result<Vec<Reference>, String> {
+    tauri::async_runtime::spawn_blocking(move || load_references(&app_handle, &path).map(|parsed| parsed.references.clone()))
+        .await
+        .map_err(|e| format!("Reference parsing failed: {}", e))?
+}
+
+/// 返回引用标记对应的文献条目，例如 marker = "[23]" 或 "Vaswani et al., 2017"
+#[tauri::command]
+pub async fn resolve_citation(path: String, marker: String, app_handle: tauri::AppHandle) -> Result<Vec<Reference>, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let parsed = load_references(&app_handle, &path)?;
+        Ok(resolve_marker(&parsed.references, &marker)
+            .into_iter()
+            .map(|i| parsed.references[i].clone())
+            .collect())
+    })
+    .await
+    .map_err(|e| format!("Citation lookup failed: {}", e))?
+}
+
+#[tauri::command]
+pub async fn get_citation_links(path: String, app_handle: tauri::AppHandle) -> Result<Vec<CitationLink>, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let parsed = load_references(&app_handle, &path)?;
+        Ok(citation_links(&parsed.pages, &parsed.references))
+    })
+    .await
+    .map_err(|e| format!("Citation lookup failed: {}", e))?
+}
+
+#[tauri::command]
+pub async fn export_references_bibtex(path: String, app_handle: tauri::AppHandle) -> Result<String, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let parsed = load_references(&app_handle, &path)?;
+        Ok(to_bibtex(&parsed.references))
+    })
+    .await
+    .map_err(|e| format!("BibTeX export failed: {}", e))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pdf_text::TextSpan;
+
+    fn line(text: &str, x: f32, y: f32) -> TextLine {
+        let width = text.chars().count() as f32 * 5.0;
+        TextLine {
+            text: text.to_string(),
+            x,
+            y,
+            width,
+            height: 10.0,
+            spans: vec![TextSpan {
+                text: text.to_string(),
+                x,
+                y,
+                width,
+                height: 10.0,
+                font: "Times-Roman".to_string(),
+                font_size: 10.0,
+            }],
+        }
+    }
+
+    // 每页的行依次排在 y = 60, 75, 90, ...；(缩进, 文本)
+    fn page(number: u32, lines: &[(f32, &str)]) -> Arc<PageText> {
+        let lines: Vec<TextLine> = lines
+            .iter()
+            .enumerate()
+            .map(|(i, (indent, text))| line(text, 50.0 + indent, 60.0 + i as f32 * 15.0))
+            .collect();
+        Arc::new(PageText {
+            page: number,
+            width: 600.0,
+            height: 800.0,
+            text: lines.iter().map(|l| l.text.as_str()).collect::<Vec<_>>().join("\n"),
+            lines,
+            ocr: false,
+        })
+    }
+
+    fn numbered_paper() -> Vec<Arc<PageText>> {
+        vec![
+            page(
+                1,
+                &[
+                    (0.0, "Transformers [1] replaced recurrence, and residual"),
+                    (0.0, "connections [2, 3] made deep models trainable [1-3]."),
+                    (0.0, "Unknown work [9] is not linked."),
+                ],
+            ),
+            page(
+                2,
+                &[
+                    (0.0, "References"),
+                    (0.0, "[1] A. Vaswani, N. Shazeer, and N. Parmar, “Attention is all you"),
+                    (0.0, "need,” in Advances in Neural Information Processing Systems, 2017."),
+                    (0.0, "[2] K. He, X. Zhang, S. Ren, and J. Sun. Deep residual learning for image"),
+                    (0.0, "recognition. In Proc. CVPR, 2016. https://doi.org/"),
+                    (0.0, "10.1109/CVPR.2016.90."),
+                    (0.0, "[3] J. Devlin. BERT: Pre-training of deep bidirectional trans-"),
+                    (0.0, "formers. arXiv:1810.04805v2, 2018."),
+                    (0.0, "4"),
+                ],
+            ),
+        ]
+    }
+
+    #[test]
+    fn numbered_references_are_split_and_parsed() {
+        let references = parse_references(&numbered_paper());
+        assert_eq!(references.len(), 3);
+
+        let ieee = &references[0];
+        assert_eq!(ieee.label.as_deref(), Some("1"));
+        assert_eq!(ieee.authors, vec!["A. Vaswani", "N. Shazeer", "N. Parmar"]);
+        assert_eq!(ieee.title.as_deref(), Some("Attention is all you need"));
+        assert_eq!(ieee.venue.as_deref(), Some("Advances in Neural Information Processing Systems"));
+        assert_eq!(ieee.year.as_deref(), Some("2017"));
+
+        // 跨行的 DOI 链接拼接时中间不加空格
+        let he = &references[1];
+        assert_eq!(he.authors, vec!["K. He", "X. Zhang", "S. Ren", "J. Sun"]);
+        assert_eq!(he.title.as_deref(), Some("Deep residual learning for image recognition"));
+        assert_eq!(he.venue.as_deref(), Some("Proc. CVPR"));
+        assert_eq!(he.doi.as_deref(), Some("10.1109/CVPR.2016.90"));
+
+        // 行尾连字符去掉；arXiv 编号去掉版本号，且不被当成年份
+        let bert = &references[2];
+        assert_eq!(bert.title.as_deref(), Some("BERT: Pre-training of deep bidirectional transformers"));
+        assert_eq!(bert.arxiv_id.as_deref(), Some("1810.04805"));
+        assert_eq!(bert.year.as_deref(), Some("2018"));
+        // 页码行不并入最后一条
+        assert!(!bert.raw.ends_with('4'));
+    }
+
+    #[test]
+    fn author_year_references_split_on_hanging_indent() {
+        let pages = vec![
+            page(1, &[(0.0, "Attention (Vaswani et al., 2017) and Smith and Doe (2019a) help.")]),
+            page(
+                2,
+                &[
+                    (0.0, "Bibliography"),
+                    (0.0, "Smith, J. and Doe, B. (2019a). Deep nets for everything. In Proc."),
+                    (12.0, "ICML, pages 1–10."),
+                    (0.0, "Vaswani, A., Shazeer, N., et al. (2017). Attention is all you need."),
+                    (12.0, "NeurIPS."),
+                ],
+            ),
+        ];
+        let references = parse_references(&pages);
+        assert_eq!(references.len(), 2);
+        assert!(references.iter().all(|r| r.label.is_none()));
+        assert_eq!(references[0].authors, vec!["Smith, J.", "Doe, B."]);
+        assert_eq!(references[0].year.as_deref(), Some("2019a"));
+        assert_eq!(references[0].title.as_deref(), Some("Deep nets for everything"));
+        assert_eq!(references[0].venue.as_deref(), Some("Proc. ICML, pages 1–10"));
+        assert_eq!(references[1].authors, vec!["Vaswani, A.", "Shazeer, N."]);
+        assert_eq!(references[1].title.as_deref(), Some("Attention is all you need"));
+
+        let links = citation_links(&pages, &references);
+        let found: Vec<(&str, &[usize])> = links.iter().map(|l| (l.marker.as_str(), &l.references[..])).collect();
+        assert_eq!(found, vec![("Vaswani et al., 2017", &[1][..]), ("Smith and Doe (2019a)", &[0][..])]);
+    }
+
+    #[test]
+    fn markers_resolve_lists_ranges_and_author_year() {
+        let pages = numbered_paper();
+        let references = parse_references(&pages);
+        let cases: &[(&str, &[usize])] = &[
+            ("[2]", &[1]),
+            ("[1, 3]", &[0, 2]),
+            ("[1–3]", &[0, 1, 2]),
+            ("[2; 9]", &[1]),
+            // 范围过大视为误识别
+            ("[1-999]", &[]),
+            ("He et al. (2016)", &[1]),
+            ("Devlin, 2018", &[2]),
+            ("Devlin, 2019", &[]),
+        ];
+        for (marker, expected) in cases {
+            assert_eq!(resolve_marker(&references, marker), *expected, "{}", marker);
+        }
+
+        // 只在参考文献之前的正文里找标记，找不到对应文献的标记不输出
+        let links = citation_links(&pages, &references);
+        let found: Vec<(&str, u32, &[usize])> = links
+            .iter()
+            .map(|l| (l.marker.as_str(), l.page, &l.references[..]))
+            .collect();
+        assert_eq!(
+            found,
+            vec![("[1]", 1, &[0][..]), ("[2, 3]", 1, &[1, 2][..]), ("[1-3]", 1, &[0, 1, 2][..])]
+        );
+        assert_eq!(links[1].y, 75.0);
+    }
+
+    #[test]
+    fn reference_sentences_keep_initials_and_abbreviations() {
+        assert_eq!(
+            split_sentences("Smith, J. A. and Doe, B. (2019). Deep nets. In Proc. Int. Conf. on ML, pp. 1–10."),
+            vec!["Smith, J. A. and Doe, B. (2019)", "Deep nets", "In Proc. Int. Conf. on ML, pp. 1–10"]
+        );
+    }
+
+    #[test]
+    fn bibtex_export_picks_entry_types_and_unique_keys() {
+        let mut references = parse_references(&numbered_paper());
+        let mut duplicate = references[1].clone();
+        duplicate.index = 3;
+        references.push(duplicate);
+
+        let bibtex = to_bibtex(&references);
+        assert!(bibtex.contains("@article{vaswani2017attention,"));
+        assert!(bibtex.contains("@inproceedings{he2016deep,\n"));
+        assert!(bibtex.contains("@inproceedings{he2016deepa,\n"));
+        assert!(bibtex.contains("  booktitle = {Proc. CVPR},\n"));
+        assert!(bibtex.contains("@misc{devlin2018bert,"));
+        assert!(bibtex.contains("  eprint = {1810.04805},\n  archivePrefix = {arXiv},\n"));
+        assert_eq!(escape_bibtex("R&D_100%"), "R\\&D\\_100\\%");
+    }
+}
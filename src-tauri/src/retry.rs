@@ -0,0 +1,167 @@
+// 重试策略：指数退避，服务端给出 Retry-After 时优先遵守，等待过久则换下一个服务
+
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetryPolicy {
+    // 包含第一次请求在内的最大尝试次数
+    pub max_attempts: u32,
+    pub initial_backoff_ms: u64,
+    pub max_backoff_ms: u64,
+    pub multiplier: f64,
+    pub respect_retry_after: bool,
+    // Retry-After 超过这个时长时不再等待，直接换下一个服务
+    pub max_retry_after_ms: u64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            initial_backoff_ms: 500,
+            max_backoff_ms: 8_000,
+            multiplier: 2.0,
+            respect_retry_after: true,
+            max_retry_after_ms: 60_000,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// 第 attempt 次失败（从 1 开始）之后应等待的时间；
+    /// 服务端要求的等待超过 max_retry_after_ms 时返回 None，调用方应放弃重试
+    pub fn delay(&self, attempt: u32, retry_after: Option<Duration>) -> Option<Duration> {
+        let max = Duration::from_millis(self.max_backoff_ms);
+        if self.respect_retry_after {
+            if let Some(wait) = retry_after {
+                // Retry-After 是服务端的明确要求，不受 max_backoff 限制，但不能无限等下去
+                return (wait <= Duration::from_millis(self.max_retry_after_ms)).then_some(wait);
+            }
+        }
+
+        let exp = self.multiplier.max(1.0).powi(attempt.saturating_sub(1) as i32);
+        let base = (self.initial_backoff_ms as f64 * exp).min(self.max_backoff_ms as f64);
+        // 加上最多 20% 的抖动，避免多个请求同时重试
+        let jitter = base * 0.2 * jitter_fraction();
+        Some(Duration::from_millis((base + jitter) as u64).min(max + max / 5))
+    }
+}
+
+/// 解析 Retry-After 头：秒数或 HTTP 日期（RFC 9110 的 IMF-fixdate，
+/// 如 "Sun, 06 Nov 1994 08:49:37 GMT"；已废弃的 RFC 850 与 asctime 格式不支持）
+pub fn parse_retry_after(value: &str) -> Option<Duration> {
+    parse_retry_after_at(value, SystemTime::now())
+}
+
+fn parse_retry_after_at(value: &str, now: SystemTime) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let at = UNIX_EPOCH + Duration::from_secs(parse_http_date(value)?);
+    // 日期已过时立即重试
+    Some(at.duration_since(now).unwrap_or(Duration::ZERO))
+}
+
+// IMF-fixdate 转成 Unix 时间戳（秒）
+fn parse_http_date(value: &str) -> Option<u64> {
+    const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+    let (_weekday, rest) = value.split_once(", ")?;
+    let parts: Vec<&str> = rest.split(' ').collect();
+    let [day, month, year, time, "GMT"] = parts[..] else {
+        return None;
+    };
+    let day: u64 = day.parse().ok().filter(|d| (1..=31).contains(d))?;
+    let month = MONTHS.iter().position(|m| *m == month)? as u64 + 1;
+    let year: u64 = year.parse().ok().filter(|y| *y >= 1970)?;
+    let clock: Vec<u64> = time.split(':').map(|p| p.parse().ok()).collect::<Option<_>>()?;
+    let [hour, minute, second] = clock[..] else {
+        return None;
+    };
+    if hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+
+    // 公历日期到 1970-01-01 的天数（Howard Hinnant 的 days_from_civil）
+    let (y, m) = if month <= 2 { (year - 1, month + 9) } else { (year, month - 3) };
+    let era = y / 400;
+    let yoe = y - era * 400;
+    let doy = (153 * m + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = (era * 146_097 + doe).checked_sub(719_468)?;
+    Some(days * 86_400 + hour * 3_600 + minute * 60 + second)
+}
+
+/// 429 与 5xx 视为暂时性错误
+pub fn is_retryable_status(status: reqwest::StatusCode) -> bool {
+    status == reqwest::StatusCode::TOO_MANY_REQUESTS
+        || status == reqwest::StatusCode::REQUEST_TIMEOUT
+        || status.is_server_error()
+}
+
+fn jitter_fraction() -> f64 {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.subsec_nanos())
+        .unwrap_or(0);
+    (nanos % 1000) as f64 / 1000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delay_grows_exponentially_up_to_the_cap() {
+        let policy = RetryPolicy::default();
+        let cases = [(1, 500), (2, 1_000), (3, 2_000), (5, 8_000), (10, 8_000)];
+        for (attempt, base) in cases {
+            let wait = policy.delay(attempt, None).unwrap();
+            assert!(wait >= Duration::from_millis(base), "attempt {}: {:?}", attempt, wait);
+            assert!(wait <= Duration::from_millis(base * 6 / 5), "attempt {}: {:?}", attempt, wait);
+        }
+    }
+
+    #[test]
+    fn delay_honours_retry_after_up_to_the_ceiling() {
+        let policy = RetryPolicy::default();
+        // 超过 max_backoff 也照等
+        assert_eq!(policy.delay(1, Some(Duration::from_secs(30))), Some(Duration::from_secs(30)));
+        assert_eq!(policy.delay(1, Some(Duration::from_secs(60))), Some(Duration::from_secs(60)));
+        // 超过上限时放弃重试
+        assert_eq!(policy.delay(1, Some(Duration::from_secs(3_600))), None);
+
+        let ignore = RetryPolicy {
+            respect_retry_after: false,
+            ..RetryPolicy::default()
+        };
+        assert!(ignore.delay(1, Some(Duration::from_secs(3_600))).unwrap() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn parse_retry_after_seconds_and_http_dates() {
+        // 1994-11-06 08:49:37 UTC
+        let now = UNIX_EPOCH + Duration::from_secs(784_111_777);
+        let cases = [
+            ("120", Some(Duration::from_secs(120))),
+            (" 0 ", Some(Duration::ZERO)),
+            ("Sun, 06 Nov 1994 08:49:37 GMT", Some(Duration::ZERO)),
+            ("Sun, 06 Nov 1994 08:51:07 GMT", Some(Duration::from_secs(90))),
+            ("Mon, 07 Nov 1994 08:49:37 GMT", Some(Duration::from_secs(86_400))),
+            ("Thu, 01 Mar 1996 00:00:00 GMT", Some(Duration::from_secs(825_638_400 - 784_111_777))),
+            // 已经过去的日期立即重试
+            ("Sat, 05 Nov 1994 08:49:37 GMT", Some(Duration::ZERO)),
+            ("Sunday, 06-Nov-94 08:49:37 GMT", None),
+            ("Sun Nov  6 08:49:37 1994", None),
+            ("Sun, 06 Nov 1994 25:00:00 GMT", None),
+            ("-5", None),
+            ("soon", None),
+        ];
+        for (value, expected) in cases {
+            assert_eq!(parse_retry_after_at(value, now), expected, "{:?}", value);
+        }
+    }
+}
@@ -0,0 +1,418 @@
+// 请求调度：按服务限制请求速率与并发数
+//
+// 每个服务一个令牌桶（每分钟请求数）加一个并发上限。
+// 有交互式请求（划词翻译、聊天）在排队时，后台批量任务让路。
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    #[default]
+    Interactive,
+    Background,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProviderLimits {
+    // 0 表示不限速
+    pub requests_per_minute: u32,
+    // 令牌桶容量，允许的瞬时突发请求数
+    pub burst: u32,
+    pub max_in_flight: u32,
+}
+
+impl Default for ProviderLimits {
+    fn default() -> Self {
+        ProviderLimits {
+            requests_per_minute: 0,
+            burst: 1,
+            max_in_flight: 4,
+        }
+    }
+}
+
+impl ProviderLimits {
+    fn defaults_for(provider: &str) -> Self {
+        match provider {
+            // 本地模型同一时间只跑一个请求，避免拖垮笔记本
+            "ollama" => ProviderLimits {
+                requests_per_minute: 0,
+                burst: 1,
+                max_in_flight: 1,
+            },
+            "deepseek" => ProviderLimits {
+                requests_per_minute: 60,
+                burst: 5,
+                max_in_flight: 4,
+            },
+            "kimi" => ProviderLimits {
+                requests_per_minute: 20,
+                burst: 3,
+                max_in_flight: 3,
+            },
+            _ => ProviderLimits::default(),
+        }
+    }
+}
+
+struct Slot {
+    limits: ProviderLimits,
+    tokens: f64,
+    last_refill: Instant,
+    in_flight: u32,
+    interactive_waiting: u32,
+}
+
+impl Slot {
+    fn new(limits: ProviderLimits) -> Self {
+        Slot {
+            tokens: limits.burst.max(1) as f64,
+            limits,
+            last_refill: Instant::now(),
+            in_flight: 0,
+            interactive_waiting: 0,
+        }
+    }
+
+    fn refill(&mut self) {
+        let now = Instant::now();
+        if self.limits.requests_per_minute > 0 {
+            let per_sec = self.limits.requests_per_minute as f64 / 60.0;
+            let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+            self.tokens = (self.tokens + elapsed * per_sec).min(self.limits.burst.max(1) as f64);
+        }
+        self.last_refill = now;
+    }
+
+    // 距离下一个令牌可用还要多久；不限速时为 None
+    fn token_wait(&self) -> Option<Duration> {
+        if self.limits.requests_per_minute == 0 || self.tokens >= 1.0 {
+            return None;
+        }
+        let per_sec = self.limits.requests_per_minute as f64 / 60.0;
+        Some(Duration::from_secs_f64((1.0 - self.tokens) / per_sec))
+    }
+
+    fn has_token(&self) -> bool {
+        self.limits.requests_per_minute == 0 || self.tokens >= 1.0
+    }
+}
+
+#[derive(Default)]
+pub struct Scheduler {
+    slots: Mutex<HashMap<String, Slot>>,
+    notify: Notify,
+}
+
+/// 持有期间占用一个并发名额，drop 时归还
+pub struct Permit<'a> {
+    scheduler: &'a Scheduler,
+    provider: String,
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        if let Ok(mut slots) = self.scheduler.slots.lock() {
+            if let Some(slot) = slots.get_mut(&self.provider) {
+                slot.in_flight = slot.in_flight.saturating_sub(1);
+            }
+        }
+        self.scheduler.notify.notify_waiters();
+    }
+}
+
+// 交互式请求排队期间计数，请求被取消时也能正确归还
+struct WaitingGuard<'a> {
+    scheduler: &'a Scheduler,
+    provider: String,
+}
+
+impl Drop for WaitingGuard<'_> {
+    fn drop(&mut self) {
+        if let Ok(mut slots) = self.scheduler.slots.lock() {
+            if let Some(slot) = slots.get_mut(&self.provider) {
+                slot.interactive_waiting = slot.interactive_waiting.saturating_sub(1);
+            }
+        }
+        self.scheduler.notify.notify_waiters();
+    }
+}
+
+impl Scheduler {
+    /// 用配置中的限制覆盖默认值，不重置已有的令牌与并发计数；
+    /// 配置中去掉的服务恢复默认限制
+    pub fn configure(&self, overrides: &HashMap<String, ProviderLimits>) {
+        let Ok(mut slots) = self.slots.lock() else {
+            return;
+        };
+        for (provider, slot) in slots.iter_mut() {
+            let limits = overrides
+                .get(provider)
+                .cloned()
+                .unwrap_or_else(|| ProviderLimits::defaults_for(provider));
+            slot.tokens = slot.tokens.min(limits.burst.max(1) as f64);
+            slot.limits = limits;
+        }
+        for (provider, limits) in overrides {
+            if !slots.contains_key(provider) {
+                slots.insert(provider.clone(), Slot::new(limits.clone()));
+            }
+        }
+        drop(slots);
+        self.notify.notify_waiters();
+    }
+
+    pub async fn acquire(&self, provider: &str, priority: Priority) -> Permit<'_> {
+        let mut waiting: Option<WaitingGuard> = None;
+
+        loop {
+            // 先注册通知再检查状态，避免错过检查与等待之间的唤醒
+            let notified = self.notify.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+
+            let token_wait = {
+                let mut slots = self.slots.lock().unwrap_or_else(|e| e.into_inner());
+                let slot = slots
+                    .entry(provider.to_string())
+                    .or_insert_with(|| Slot::new(ProviderLimits::defaults_for(provider)));
+                slot.refill();
+
+                let yield_to_interactive = priority == Priority::Background && slot.interactive_waiting > 0;
+                let has_capacity = slot.in_flight < slot.limits.max_in_flight.max(1);
+
+                if has_capacity && slot.has_token() && !yield_to_interactive {
+                    if slot.limits.requests_per_minute > 0 {
+                        slot.tokens -= 1.0;
+                    }
+                    slot.in_flight += 1;
+                    drop(slots);
+                    drop(waiting);
+                    return Permit {
+                        scheduler: self,
+                        provider: provider.to_string(),
+                    };
+                }
+
+                if priority == Priority::Interactive && waiting.is_none() {
+                    slot.interactive_waiting += 1;
+                    waiting = Some(WaitingGuard {
+                        scheduler: self,
+                        provider: provider.to_string(),
+                    });
+                }
+
+                if has_capacity {
+                    slot.token_wait()
+                } else {
+                    None
+                }
+            };
+
+            match token_wait {
+                Some(wait) => {
+                    tokio::select! {
+                        _ = &mut notified => {}
+                        _ = tokio::time::sleep(wait) => {}
+                    }
+                }
+                None => notified.await,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::{complete_with_fallback, CompletionRequest, ModelConfig, StreamDelta};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    const SHORT: Duration = Duration::from_millis(50);
+
+    fn limited(provider: &str, requests_per_minute: u32, burst: u32, max_in_flight: u32) -> Scheduler {
+        let scheduler = Scheduler::default();
+        scheduler.configure(&HashMap::from([(
+            provider.to_string(),
+            ProviderLimits {
+                requests_per_minute,
+                burst,
+                max_in_flight,
+            },
+        )]));
+        scheduler
+    }
+
+    async fn acquired_within(scheduler: &Scheduler, provider: &str, priority: Priority, wait: Duration) -> bool {
+        tokio::time::timeout(wait, scheduler.acquire(provider, priority)).await.is_ok()
+    }
+
+    #[tokio::test]
+    async fn max_in_flight_blocks_until_a_permit_is_dropped() {
+        let scheduler = limited("kimi", 0, 1, 2);
+        let first = scheduler.acquire("kimi", Priority::Interactive).await;
+        let _second = scheduler.acquire("kimi", Priority::Interactive).await;
+        assert!(!acquired_within(&scheduler, "kimi", Priority::Interactive, SHORT).await);
+
+        drop(first);
+        assert!(acquired_within(&scheduler, "kimi", Priority::Interactive, SHORT).await);
+        // 其他服务不受影响
+        assert!(acquired_within(&scheduler, "deepseek", Priority::Interactive, SHORT).await);
+    }
+
+    // 本地的假 Ollama 服务：每个请求都返回 503，要求 1 秒后重试
+    async fn failing_ollama(requests: Arc<AtomicUsize>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let requests = requests.clone();
+                tokio::spawn(async move {
+                    // 读完请求头和请求体再回复，避免客户端收到连接重置
+                    let mut buf = Vec::new();
+                    let mut chunk = [0u8; 4096];
+                    loop {
+                        match socket.read(&mut chunk).await {
+                            Ok(0) | Err(_) => return,
+                            Ok(n) => buf.extend_from_slice(&chunk[..n]),
+                        }
+                        let text = String::from_utf8_lossy(&buf).to_lowercase();
+                        let Some(end) = text.find("\r\n\r\n") else {
+                            continue;
+                        };
+                        let length: usize = text[..end]
+                            .lines()
+                            .find_map(|line| line.strip_prefix("content-length:"))
+                            .and_then(|value| value.trim().parse().ok())
+                            .unwrap_or(0);
+                        if buf.len() >= end + 4 + length {
+                            break;
+                        }
+                    }
+                    requests.fetch_add(1, Ordering::SeqCst);
+                    let _ = socket
+                        .write_all(b"HTTP/1.1 503 Service Unavailable\r\nRetry-After: 1\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+                        .await;
+                });
+            }
+        });
+        format!("http://{}", addr)
+    }
+
+    #[tokio::test]
+    async fn slot_is_free_while_a_failed_attempt_backs_off() {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let base_url = failing_ollama(requests.clone()).await;
+        let config: ModelConfig = serde_json::from_value(serde_json::json!({
+            "selected_model": "ollama",
+            "ollama": { "base_url": base_url, "model": "stub" },
+            "deepseek": { "api_key": "", "base_url": "", "model": "" },
+            "kimi": { "api_key": "", "base_url": "", "model": "" },
+            "retry": { "max_attempts": 2 },
+            "rate_limits": { "ollama": { "requests_per_minute": 0, "burst": 1, "max_in_flight": 1 } }
+        }))
+        .unwrap();
+        let request = CompletionRequest {
+            system: String::new(),
+            prompt: "hello".to_string(),
+            images: Vec::new(),
+            show_reasoning: false,
+            priority: Priority::Background,
+        };
+
+        let scheduler = Arc::new(Scheduler::default());
+        let retrying = {
+            let scheduler = scheduler.clone();
+            tokio::spawn(async move {
+                let mut on_delta = |_: StreamDelta| {};
+                complete_with_fallback(&config, &request, &scheduler, &mut on_delta).await
+            })
+        };
+
+        // 等第一次尝试失败，进入 1 秒的退避
+        let start = Instant::now();
+        while requests.load(Ordering::SeqCst) == 0 {
+            assert!(start.elapsed() < Duration::from_secs(5), "stub provider was never called");
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        {
+            let permit = tokio::time::timeout(
+                Duration::from_millis(500),
+                scheduler.acquire("ollama", Priority::Interactive),
+            )
+            .await;
+            assert!(permit.is_ok(), "backoff should not hold the only slot");
+            assert_eq!(requests.load(Ordering::SeqCst), 1);
+        }
+
+        let error = retrying.await.unwrap().unwrap_err();
+        assert!(error.contains("503"), "{}", error);
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn removing_an_override_restores_the_default_limit() {
+        let scheduler = limited("ollama", 0, 1, 3);
+        let _a = scheduler.acquire("ollama", Priority::Interactive).await;
+        let _b = scheduler.acquire("ollama", Priority::Interactive).await;
+        assert!(acquired_within(&scheduler, "ollama", Priority::Interactive, SHORT).await);
+
+        // 去掉覆盖后恢复 Ollama 默认的单并发
+        scheduler.configure(&HashMap::new());
+        assert!(!acquired_within(&scheduler, "ollama", Priority::Interactive, SHORT).await);
+        let slots = scheduler.slots.lock().unwrap();
+        assert_eq!(slots["ollama"].limits.max_in_flight, 1);
+    }
+
+    #[tokio::test]
+    async fn token_bucket_allows_burst_then_waits_for_refill() {
+        // 每秒 10 个令牌，容量 2
+        let scheduler = limited("deepseek", 600, 2, 10);
+        let start = Instant::now();
+        let _a = scheduler.acquire("deepseek", Priority::Interactive).await;
+        let _b = scheduler.acquire("deepseek", Priority::Interactive).await;
+        assert!(start.elapsed() < SHORT, "burst should be immediate");
+
+        let _c = scheduler.acquire("deepseek", Priority::Interactive).await;
+        let waited = start.elapsed();
+        assert!(waited >= Duration::from_millis(80), "third request waited only {:?}", waited);
+        assert!(waited < Duration::from_millis(500), "third request waited {:?}", waited);
+    }
+
+    #[tokio::test]
+    async fn interactive_requests_jump_ahead_of_background() {
+        let scheduler = Arc::new(limited("kimi", 0, 1, 1));
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let held = scheduler.acquire("kimi", Priority::Interactive).await;
+
+        let spawn_waiter = |priority: Priority, label: &'static str| {
+            let scheduler = scheduler.clone();
+            let order = order.clone();
+            tokio::spawn(async move {
+                let _permit = scheduler.acquire("kimi", priority).await;
+                order.lock().unwrap().push(label);
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            })
+        };
+
+        // 后台请求先排队，交互式请求后到
+        let background = spawn_waiter(Priority::Background, "background");
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        let interactive = spawn_waiter(Priority::Interactive, "interactive");
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        drop(held);
+        interactive.await.unwrap();
+        background.await.unwrap();
+        assert_eq!(*order.lock().unwrap(), vec!["interactive", "background"]);
+    }
+}
@@ -0,0 +1,386 @@
+// 全文检索：基于 SQLite FTS5 的文献库索引
+//
+// 索引内容包括每页正文、笔记、高亮和翻译结果。unicode61 分词器会把连续的汉字
+// 当成一个词，因此写入和查询前都在汉字之间插入空格（按字切分），
+// 中文查询词再以短语形式匹配，效果接近按词检索。
+
+use rusqlite::{params, Connection};
+use serde::Serialize;
+use std::path::Path;
+
+use crate::file_scope::FileScope;
+use crate::library;
+use crate::ocr;
+use crate::pdf_text::PdfTextCache;
+use crate::storage::{now_secs, Storage};
+use crate::text::is_cjk;
+
+pub const SCHEMA: &str = "
+CREATE VIRTUAL TABLE IF NOT EXISTS search_index USING fts5(
+    body,
+    document_id UNINDEXED,
+    kind UNINDEXED,
+    ref_id UNINDEXED,
+    page UNINDEXED,
+    tokenize = 'unicode61 remove_diacritics 2'
+);
+CREATE TABLE IF NOT EXISTS indexed_documents (
+    document_id TEXT PRIMARY KEY,
+    indexed_at INTEGER NOT NULL
+);
+";
+
+const SNIPPET_START: &str = "<mark>";
+const SNIPPET_END: &str = "</mark>";
+// 摘要片段包含的词数（汉字按字计）
+const SNIPPET_TOKENS: i32 = 24;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchHit {
+    pub document_id: String,
+    pub title: Option<String>,
+    pub path: Option<String>,
+    // "page"、"note"、"highlight" 或 "translation"
+    pub kind: String,
+    pub ref_id: Option<String>,
+    pub page: Option<u32>,
+    // 命中词用 <mark></mark> 包裹
+    pub snippet: String,
+    pub score: f64,
+}
+
+/// 在每个汉字两侧插入空格，让 unicode61 分词器按字切分
+pub fn segment(text: &str) -> String {
+    let mut out = String::with_capacity(text.len() * 2);
+    // 上一个字符是汉字，其后已经插入了空格
+    let mut spaced = false;
+    for c in text.chars() {
+        if is_cjk(c) {
+            if !out.ends_with(' ') && !out.is_empty() {
+                out.push(' ');
+            }
+            out.push(c);
+            out.push(' ');
+            spaced = true;
+        } else {
+            // 原文中紧跟汉字的空格不再重复，否则 unsegment 后会多出一个空格
+            if !(spaced && c == ' ') {
+                out.push(c);
+            }
+            spaced = false;
+        }
+    }
+    out
+}
+
+/// 去掉 segment 插入的空格：两个汉字之间（允许隔着高亮标记）的空格删除
+fn unsegment(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        if c == ' ' {
+            let prev_cjk = last_visible_char(&out).is_some_and(is_cjk);
+            let next_cjk = next_visible_char(rest.trim_start_matches(' ')).is_some_and(is_cjk);
+            if prev_cjk && next_cjk {
+                rest = rest.trim_start_matches(' ');
+                continue;
+            }
+        }
+        out.push(c);
+        rest = &rest[c.len_utf8()..];
+    }
+    out
+}
+
+fn last_visible_char(text: &str) -> Option<char> {
+    let text = text.strip_suffix(SNIPPET_START).unwrap_or(text);
+    let text = text.strip_suffix(SNIPPET_END).unwrap_or(text);
+    text.chars().last()
+}
+
+fn next_visible_char(text: &str) -> Option<char> {
+    let text = text.strip_prefix(SNIPPET_START).unwrap_or(text);
+    let text = text.strip_prefix(SNIPPET_END).unwrap_or(text);
+    text.chars().next()
+}
+
+/// 把用户输入转成 FTS5 查询：每个词都加引号避免语法错误，多个词之间为 AND
+fn build_query(query: &str) -> Option<String> {
+    let terms: Vec<String> = query
+        .split_whitespace()
+        .map(|term| {
+            let segmented = segment(term);
+            let words: Vec<&str> = segmented
+                .split(|c: char| !c.is_alphanumeric() && !is_cjk(c))
+                .filter(|w| !w.is_empty())
+                .collect();
+            words.join(" ")
+        })
+        .filter(|term| !term.is_empty())
+        .map(|term| format!("\"{}\"", term.replace('"', "")))
+        .collect();
+    (!terms.is_empty()).then(|| terms.join(" "))
+}
+
+/// 写入一条索引记录；同一 (document_id, kind, ref_id) 的旧记录先删除
+pub fn index_text(
+    storage: &Storage,
+    document_id: &str,
+    kind: &str,
+    ref_id: Option<&str>,
+    page: Option<u32>,
+    text: &str,
+) -> Result<(), String> {
+    storage.with_conn(|conn| {
+        if let Some(ref_id) = ref_id {
+            conn.execute(
+                "DELETE FROM search_index WHERE document_id = ?1 AND kind = ?2 AND ref_id = ?3",
+                params![document_id, kind, ref_id],
+            )?;
+        }
+        conn.execute(
+            "INSERT INTO search_index (body, document_id, kind, ref_id, page) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![segment(text), document_id, kind, ref_id, page],
+        )?;
+        Ok(())
+    })
+}
+
+pub fn remove_text(storage: &Storage, document_id: &str, kind: &str, ref_id: &str) -> Result<(), String> {
+    storage.with_conn(|conn| {
+        conn.execute(
+            "DELETE FROM search_index WHERE document_id = ?1 AND kind = ?2 AND ref_id = ?3",
+            params![document_id, kind, ref_id],
+        )?;
+        Ok(())
+    })
+}
+
+/// 删除文档的全部索引（从文献库移除时调用）
+/// 删除文档的索引，在调用方的事务中执行
+pub fn remove_document(conn: &Connection, document_id: &str) -> rusqlite::Result<()> {
+    conn.execute("DELETE FROM search_index WHERE document_id = ?1", params![document_id])?;
+    conn.execute("DELETE FROM indexed_documents WHERE document_id = ?1", params![document_id])?;
+    Ok(())
+}
+
+pub fn is_indexed(storage: &Storage, document_id: &str) -> Result<bool, String> {
+    storage.with_conn(|conn| {
+        conn.query_row(
+            "SELECT COUNT(*) FROM indexed_documents WHERE document_id = ?1",
+            params![document_id],
+            |row| row.get::<_, i64>(0),
+        )
+        .map(|n| n > 0)
+    })
+}
+
+/// 把文档每一页的正文写入索引，返回写入的页数
+pub fn index_document(storage: &Storage, cache: &PdfTextCache, document_id: &str, path: &Path) -> Result<usize, String> {
+    // 扫描页在这里完成 OCR，之后划词、对话直接读缓存
+    let pages = ocr::document_text(storage, cache, path, true)?;
+    let rows: Vec<(u32, String)> = pages
+        .iter()
+        .filter(|p| !p.text.trim().is_empty())
+        .map(|p| (p.page, segment(&p.text)))
+        .collect();
+
+    storage.with_conn(|conn| {
+        let tx = conn.unchecked_transaction()?;
+        tx.execute(
+            "DELETE FROM search_index WHERE document_id = ?1 AND kind = 'page'",
+            params![document_id],
+        )?;
+        {
+            let mut stmt = tx.prepare(
+                "INSERT INTO search_index (body, document_id, kind, ref_id, page) VALUES (?1, ?2, 'page', NULL, ?3)",
+            )?;
+            for (page, body) in &rows {
+                stmt.execute(params![body, document_id, page])?;
+            }
+        }
+        tx.execute(
+            "INSERT INTO indexed_documents (document_id, indexed_at) VALUES (?1, ?2)
+             ON CONFLICT(document_id) DO UPDATE SET indexed_at = excluded.indexed_at",
+            params![document_id, now_secs()],
+        )?;
+        tx.commit()
+    })?;
+    Ok(rows.len())
+}
+
+pub fn search(storage: &Storage, query: &str, limit: u32) -> Result<Vec<SearchHit>, String> {
+    let Some(fts_query) = build_query(query) else {
+        return Ok(Vec::new());
+    };
+
+    let hits: Vec<SearchHit> = storage.with_conn(|conn| {
+        let mut stmt = conn.prepare(
+            "SELECT s.document_id, d.title, d.path, s.kind, s.ref_id, s.page,
+                    snippet(search_index, 0, ?2, ?3, '…', ?4), bm25(search_index)
+             FROM search_index s
+             LEFT JOIN documents d ON d.id = s.document_id
+             WHERE search_index MATCH ?1
+             ORDER BY bm25(search_index)
+             LIMIT ?5",
+        )?;
+        let rows = stmt.query_map(
+            params![fts_query, SNIPPET_START, SNIPPET_END, SNIPPET_TOKENS, limit],
+            |row| {
+                Ok(SearchHit {
+                    document_id: row.get(0)?,
+                    title: row.get(1)?,
+                    path: row.get(2)?,
+                    kind: row.get(3)?,
+                    ref_id: row.get(4)?,
+                    page: row.get(5)?,
+                    snippet: row.get(6)?,
+                    // bm25 越小越相关，取反后越大越好
+                    score: -row.get::<_, f64>(7)?,
+                })
+            },
+        )?;
+        rows.collect()
+    })?;
+
+    Ok(hits
+        .into_iter()
+        .map(|hit| SearchHit {
+            snippet: unsegment(&hit.snippet).trim().to_string(),
+            ..hit
+        })
+        .collect())
+}
+
+/// 打开文档后在后台建立索引（已索引过的跳过）
+pub fn index_in_background(app_handle: &tauri::AppHandle, document_id: String, path: String) {
+    use tauri::Manager;
+
+    let app_handle = app_handle.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let storage = app_handle.state::<Storage>();
+        if is_indexed(&storage, &document_id).unwrap_or(false) {
+            return;
+        }
+        let cache = app_handle.state::<PdfTextCache>();
+        match index_document(&storage, &cache, &document_id, Path::new(&path)) {
+            Ok(pages) => println!("🔎 已索引 {} 页: {}", pages, path),
+            Err(e) => println!("⚠️ 建立索引失败 {}: {}", path, e),
+        }
+    });
+}
+
+#[tauri::command]
+pub fn search_library(query: String, limit: Option<u32>, storage: tauri::State<'_, Storage>) -> Result<Vec<SearchHit>, String> {
+    search(&storage, &query, limit.unwrap_or(50))
+}
+
+/// 把文献库文件夹中的所有 PDF 加入文献库并建立索引，进度通过 library_index_progress 事件发送
+#[tauri::command]
+pub async fn index_library(app_handle: tauri::AppHandle) -> Result<usize, String> {
+    use tauri::Manager;
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let storage = app_handle.state::<Storage>();
+        let cache = app_handle.state::<PdfTextCache>();
+        let scope = app_handle.state::<FileScope>();
+
+        let files = library::library_files(&scope);
+        let total = files.len();
+        let mut indexed = 0;
+        for (i, path) in files.iter().enumerate() {
+            let result = library::register_file(&storage, &cache, path, false).and_then(|entry| {
+                if is_indexed(&storage, &entry.id)? {
+                    return Ok(false);
+                }
+                index_document(&storage, &cache, &entry.id, path).map(|_| true)
+            });
+            match result {
+                Ok(true) => indexed += 1,
+                Ok(false) => {}
+                Err(e) => println!("⚠️ 索引失败 {}: {}", path.display(), e),
+            }
+            crate::emit_to_window(
+                &app_handle,
+                "library_index_progress",
+                serde_json::json!({ "done": i + 1, "total": total, "path": path.to_string_lossy() }),
+            );
+        }
+        Ok(indexed)
+    })
+    .await
+    .map_err(|e| format!("Library indexing failed: {}", e))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn segment_spaces_out_cjk_only() {
+        let cases = [
+            ("深度学习", "深 度 学 习 "),
+            ("BERT模型", "BERT 模 型 "),
+            ("深度learning模型", "深 度 learning 模 型 "),
+            ("attention is all", "attention is all"),
+            ("图 3：结果", "图 3： 结 果 "),
+            ("中文 English", "中 文 English"),
+            ("", ""),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(segment(input), expected, "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn unsegment_joins_cjk_across_marks() {
+        let cases = [
+            ("深 度 学 习 ", "深度学习 "),
+            ("深 度 learning 模 型", "深度 learning 模型"),
+            ("<mark>深 度</mark> 学 习", "<mark>深度</mark>学习"),
+            ("学 <mark>习</mark> 方 法", "学<mark>习</mark>方法"),
+            ("the <mark>Transformer</mark> 模 型", "the <mark>Transformer</mark> 模型"),
+            ("a  b", "a  b"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(unsegment(input), expected, "{:?}", input);
+        }
+        assert_eq!(unsegment(&segment("使用 BERT 模型")), "使用 BERT 模型 ");
+    }
+
+    #[test]
+    fn build_query_quotes_every_term() {
+        let cases = [
+            ("深度学习 transformer", Some(r#""深 度 学 习" "transformer""#)),
+            ("BERT模型", Some(r#""BERT 模 型""#)),
+            // 引号、前缀通配符、布尔运算符和减号都按普通文字处理
+            (r#""attention" AND self-attention* -rnn"#, Some(r#""attention" "AND" "self attention" "rnn""#)),
+            ("NEAR(a b)", Some(r#""NEAR a" "b""#)),
+            (r#"* - " ( ) ^"#, None),
+            ("   ", None),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(build_query(input).as_deref(), expected, "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn search_handles_operator_input_and_cjk_snippets() {
+        let storage = Storage::open_in_memory().unwrap();
+        index_text(&storage, "doc-1", "note", Some("1"), None, "我们提出了新的深度学习模型 AND attention").unwrap();
+        index_text(&storage, "doc-2", "page", None, Some(3), "Self-attention replaces recurrence").unwrap();
+
+        let hits = search(&storage, "深度学习", 10).unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].document_id, "doc-1");
+        assert!(hits[0].snippet.contains("新的<mark>深度学习</mark>模型 AND"), "{}", hits[0].snippet);
+
+        let hits = search(&storage, "self-attention*", 10).unwrap();
+        assert_eq!(hits.iter().map(|h| h.page).collect::<Vec<_>>(), vec![Some(3)]);
+
+        for query in ["AND", "\"", "-recurrence", "attention OR", "(", "NOT *"] {
+            assert!(search(&storage, query, 10).is_ok(), "{:?}", query);
+        }
+        assert_eq!(search(&storage, "AND", 10).unwrap().len(), 1);
+    }
+}
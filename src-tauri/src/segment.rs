@@ -0,0 +1,272 @@
+// 长文本分段：按模型的上下文和输出上限把长选区切成若干段，依次翻译后拼接
+//
+// 先按段落切分，段落超出预算时再按句子切分，单句仍超出时按字符硬切；
+// 相邻的小段会合并，尽量减少请求次数。
+
+use crate::providers::{ModelConfig, ProviderTarget};
+use crate::text::split_sentences;
+use crate::translation_memory::FEW_SHOT_TOKENS;
+use crate::usage::estimate_tokens_for;
+
+// 输出预留：为翻译结果按原文 token 数的这个倍数预留空间
+const OUTPUT_EXPANSION: f64 = 2.0;
+// 单段原文的上限，段太长时模型容易漏译，流式输出也更慢
+const MAX_SEGMENT_TOKENS: u64 = 1500;
+// 最小的段，预算算出来更小时也按这个切
+const MIN_SEGMENT_TOKENS: u64 = 200;
+// 最少留给输出的 token 数
+const MIN_OUTPUT_TOKENS: u32 = 256;
+
+/// 模型的上下文长度和单次输出上限（token）
+#[derive(Debug, Clone, Copy)]
+pub struct ModelBudget {
+    pub context_tokens: u32,
+    pub output_tokens: u32,
+}
+
+// 按模型名关键字匹配，排在前面的优先
+const CLOUD_BUDGETS: &[(&str, ModelBudget)] = &[
+    ("deepseek-reasoner", ModelBudget { context_tokens: 65_536, output_tokens: 32_768 }),
+    ("deepseek", ModelBudget { context_tokens: 65_536, output_tokens: 8_192 }),
+    ("moonshot-v1-8k", ModelBudget { context_tokens: 8_192, output_tokens: 4_096 }),
+    ("moonshot-v1-32k", ModelBudget { context_tokens: 32_768, output_tokens: 8_192 }),
+    ("moonshot-v1-128k", ModelBudget { context_tokens: 131_072, output_tokens: 8_192 }),
+    ("moonshot-v1-auto", ModelBudget { context_tokens: 131_072, output_tokens: 8_192 }),
+    ("kimi", ModelBudget { context_tokens: 131_072, output_tokens: 8_192 }),
+];
+
+const DEFAULT_CLOUD_BUDGET: ModelBudget = ModelBudget {
+    context_tokens: 32_768,
+    output_tokens: 4_096,
+};
+
+// Ollama 的上下文越大占用显存越多，本地模型统一用 8K
+const OLLAMA_BUDGET: ModelBudget = ModelBudget {
+    context_tokens: 8_192,
+    output_tokens: 4_096,
+};
+
+pub fn model_budget(provider: &str, model: &str) -> ModelBudget {
+    if provider == "ollama" {
+        return OLLAMA_BUDGET;
+    }
+    let model = model.to_lowercase();
+    CLOUD_BUDGETS
+        .iter()
+        .find(|(key, _)| model.contains(key))
+        .map(|(_, budget)| *budget)
+        .unwrap_or(DEFAULT_CLOUD_BUDGET)
+}
+
+impl ModelBudget {
+    /// 扣除提示词后本次请求可用的输出上限
+    pub fn max_output(&self, prompt_tokens: u64) -> u32 {
+        let room = (self.context_tokens as u64).saturating_sub(prompt_tokens) as u32;
+        self.output_tokens.min(room).max(MIN_OUTPUT_TOKENS)
+    }
+
+    // 单段原文的 token 上限：输出能容纳译文，上下文能容纳提示词加译文
+    fn segment_tokens(&self, reserved_prompt_tokens: u64) -> u64 {
+        let by_output = (self.output_tokens as f64 / OUTPUT_EXPANSION) as u64;
+        let by_context = ((self.context_tokens as u64).saturating_sub(reserved_prompt_tokens) as f64
+            / (1.0 + OUTPUT_EXPANSION)) as u64;
+        by_output.min(by_context)
+    }
+}
+
+/// 按 targets 中最紧的预算计算单段原文的 token 上限
+pub fn segment_budget(targets: &[ProviderTarget], reserved_prompt_tokens: u64) -> u64 {
+    targets
+        .iter()
+        .map(|target| model_budget(&target.provider, &target.model).segment_tokens(reserved_prompt_tokens))
+        .min()
+        .unwrap_or(MAX_SEGMENT_TOKENS)
+        .clamp(MIN_SEGMENT_TOKENS, MAX_SEGMENT_TOKENS)
+}
+
+/// 估算 token 时使用的分词器：selected_model 对应服务的模型
+pub fn tokenizer_model(config: &ModelConfig) -> String {
+    config
+        .target(&config.selected_model)
+        .map(|target| target.model)
+        .unwrap_or_default()
+}
+
+/// 一段待译原文，separator 为拼接译文时放在这一段前面的分隔符
+#[derive(Debug, Clone)]
+pub struct Segment {
+    pub text: String,
+    pub separator: &'static str,
+}
+
+// 单句超出预算时按字符硬切，尽量切在空白处；单个字符就超出预算时原样保留
+fn hard_split(sentence: &str, budget: u64, measure: &impl Fn(&str) -> u64) -> Vec<String> {
+    let mut pieces = Vec::new();
+    let mut current = String::new();
+    for c in sentence.chars() {
+        current.push(c);
+        if measure(&current) > budget && current.trim().chars().count() > 1 {
+            let cut = current.trim_end().rfind(char::is_whitespace).filter(|i| *i > 0);
+            let rest = match cut {
+                Some(i) => current.split_off(i),
+                None => current.pop().map(String::from).unwrap_or_default(),
+            };
+            let piece = std::mem::take(&mut current).trim().to_string();
+            if !piece.is_empty() {
+                pieces.push(piece);
+            }
+            current = rest.trim_start().to_string();
+        }
+    }
+    if !current.trim().is_empty() {
+        pieces.push(current.trim().to_string());
+    }
+    pieces
+}
+
+/// 把长文本切成不超过 budget 个 token 的段，measure 为 token 估算函数
+pub fn split_segments(text: &str, budget: u64, measure: impl Fn(&str) -> u64) -> Vec<Segment> {
+    let mut segments: Vec<Segment> = Vec::new();
+    // PDF 选区的段落之间是空行，段内换行只是排版
+    let paragraphs = text
+        .split("\n\n")
+        .map(|p| p.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|p| !p.is_empty());
+
+    for paragraph in paragraphs {
+        let units: Vec<String> = if measure(&paragraph) <= budget {
+            vec![paragraph]
+        } else {
+            split_sentences(&paragraph)
+                .into_iter()
+                .flat_map(|s| if measure(&s) <= budget { vec![s] } else { hard_split(&s, budget, &measure) })
+                .collect()
+        };
+
+        for (i, unit) in units.into_iter().enumerate() {
+            let separator = if i == 0 { "\n\n" } else { "" };
+            // 与上一段合并：同一段落内用空格连接，跨段落保留空行
+            if let Some(last) = segments.last_mut() {
+                let joiner = if separator.is_empty() { " " } else { "\n\n" };
+                let merged = format!("{}{}{}", last.text, joiner, unit);
+                if measure(&merged) <= budget {
+                    last.text = merged;
+                    continue;
+                }
+            }
+            segments.push(Segment { text: unit, separator });
+        }
+    }
+
+    if let Some(first) = segments.first_mut() {
+        first.separator = "";
+    }
+    segments
+}
+
+/// 切分翻译原文：预算取 targets 中最紧的，系统提示词（另加翻译记忆示例的预留）和原文都用 model 的分词器估算
+pub fn split_for_targets(targets: &[ProviderTarget], model: &str, text: &str, system_prompt: &str) -> Vec<Segment> {
+    let reserved = estimate_tokens_for(model, system_prompt) + FEW_SHOT_TOKENS;
+    let budget = segment_budget(targets, reserved);
+    split_segments(text, budget, |s| estimate_tokens_for(model, s))
+}
+
+/// 按回退链上的所有服务切分翻译原文
+pub fn split_for(config: &ModelConfig, text: &str, system_prompt: &str) -> Vec<Segment> {
+    let targets: Vec<ProviderTarget> = config
+        .provider_chain()
+        .iter()
+        .filter_map(|provider| config.target(provider).ok())
+        .collect();
+    split_for_targets(&targets, &tokenizer_model(config), text, system_prompt)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chars(text: &str) -> u64 {
+        text.chars().count() as u64
+    }
+
+    fn split(text: &str, budget: u64) -> Vec<(&'static str, String)> {
+        split_segments(text, budget, chars)
+            .into_iter()
+            .map(|segment| (segment.separator, segment.text))
+            .collect()
+    }
+
+    fn owned(segments: &[(&'static str, &str)]) -> Vec<(&'static str, String)> {
+        segments.iter().map(|(sep, text)| (*sep, text.to_string())).collect()
+    }
+
+    #[test]
+    fn short_paragraphs_are_merged_and_long_ones_split_by_paragraph() {
+        let text = "Para one\nwraps here.\n\n\nPara two.";
+        assert_eq!(split(text, 100), owned(&[("", "Para one wraps here.\n\nPara two.")]));
+        assert_eq!(split(text, 20), owned(&[("", "Para one wraps here."), ("\n\n", "Para two.")]));
+        assert!(split(" \n\n ", 20).is_empty());
+    }
+
+    #[test]
+    fn long_paragraphs_fall_back_to_sentences_then_hard_splits() {
+        let text = "First sentence here. Second sentence here. Third one.";
+        assert_eq!(
+            split(text, 25),
+            owned(&[("", "First sentence here."), ("", "Second sentence here."), ("", "Third one.")])
+        );
+        // 同一段落内的句子在预算内合并
+        assert_eq!(
+            split(text, 35),
+            owned(&[("", "First sentence here."), ("", "Second sentence here. Third one.")])
+        );
+        // 单句超出预算时按空白硬切
+        assert_eq!(
+            split("alpha beta gamma delta epsilon", 12),
+            owned(&[("", "alpha beta"), ("", "gamma delta"), ("", "epsilon")])
+        );
+        // 没有空白时按字符切
+        assert_eq!(split("注意力机制很重要", 3), owned(&[("", "注意力"), ("", "机制很"), ("", "重要")]));
+    }
+
+    #[test]
+    fn segments_stay_within_budget() {
+        let paragraph = "Transformers rely on attention. ".repeat(12) + &"x".repeat(90);
+        let text = [paragraph.as_str(), "Short.", "Another short one.", "注意力机制。".repeat(20).as_str()].join("\n\n");
+        for budget in [10, 25, 40, 80, 200] {
+            let segments = split(&text, budget);
+            assert!(!segments.is_empty());
+            for (_, segment) in &segments {
+                assert!(!segment.is_empty());
+                assert!(chars(segment) <= budget, "budget {}: {:?}", budget, segment);
+            }
+        }
+    }
+
+    #[test]
+    fn hard_split_never_emits_empty_pieces() {
+        for (text, budget) in [("a    b     c", 1), ("    ", 2), ("abc", 0), ("word", 1), ("a b", 2), ("长文本 切分", 2)] {
+            let pieces = hard_split(text, budget, &chars);
+            assert!(pieces.iter().all(|p| !p.trim().is_empty()), "{:?} -> {:?}", text, pieces);
+            let kept: String = pieces.concat();
+            let expected: String = text.chars().filter(|c| !c.is_whitespace()).collect();
+            assert_eq!(kept.replace(' ', ""), expected, "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn separators_rebuild_the_paragraph_structure() {
+        let text = "One. Two is longer. Three.\n\nFour.\n\nFive is here. Six.";
+        let segments = split(text, 12);
+        assert_eq!(segments[0].0, "");
+        // 段首的段用空行分隔，段内的句子之间不加分隔（中文译文直接相连）
+        let rebuilt: String = segments
+            .iter()
+            .map(|(sep, text)| format!("{}{}", if sep.is_empty() { " " } else { sep }, text))
+            .collect::<String>()
+            .trim_start()
+            .to_string();
+        assert_eq!(rebuilt, text);
+        assert_eq!(segments.iter().filter(|(sep, _)| *sep == "\n\n").count(), 2);
+    }
+}
@@ -0,0 +1,195 @@
+// 划词的其他操作：通俗解释、术语释义、英文润色、中译英学术改写
+//
+// 与 translate_with_config 走同一套模型服务与回退逻辑，每种操作有自己的提示词和事件名，
+// 前端可以同时监听而不会串台。
+
+use serde::Deserialize;
+
+use crate::glossary;
+use crate::providers::{Completion, CompletionRequest, ModelConfig};
+use crate::scheduler::Priority;
+use crate::storage::Storage;
+use crate::{run_completion, StreamEvents};
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SelectionAction {
+    // 用通俗的中文解释
+    Explain,
+    // 解释选中文字中的术语
+    Define,
+    // 润色英文，用于论文写作
+    Polish,
+    // 中文改写成学术英文
+    AcademicEnglish,
+}
+
+const EXPLAIN_EVENTS: StreamEvents = StreamEvents {
+    kind: "explain",
+    chunk: "explain_chunk",
+    reasoning: "explain_reasoning_chunk",
+    complete: "explain_complete",
+};
+
+const DEFINE_EVENTS: StreamEvents = StreamEvents {
+    kind: "define",
+    chunk: "define_chunk",
+    reasoning: "define_reasoning_chunk",
+    complete: "define_complete",
+};
+
+const POLISH_EVENTS: StreamEvents = StreamEvents {
+    kind: "polish",
+    chunk: "polish_chunk",
+    reasoning: "polish_reasoning_chunk",
+    complete: "polish_complete",
+};
+
+const ACADEMIC_ENGLISH_EVENTS: StreamEvents = StreamEvents {
+    kind: "academic_english",
+    chunk: "academic_english_chunk",
+    reasoning: "academic_english_reasoning_chunk",
+    complete: "academic_english_complete",
+};
+
+impl SelectionAction {
+    fn events(self) -> &'static StreamEvents {
+        match self {
+            SelectionAction::Explain => &EXPLAIN_EVENTS,
+            SelectionAction::Define => &DEFINE_EVENTS,
+            SelectionAction::Polish => &POLISH_EVENTS,
+            SelectionAction::AcademicEnglish => &ACADEMIC_ENGLISH_EVENTS,
+        }
+    }
+
+    fn system_prompt(self) -> &'static str {
+        match self {
+            SelectionAction::Explain => "你是一个耐心的学术导师，擅长把论文中晦涩的内容讲得通俗易懂。",
+            SelectionAction::Define => "你是一个学术术语专家，熟悉各学科的专业术语及其标准中文译名。",
+            SelectionAction::Polish => "You are an experienced academic editor who polishes English for journal and conference papers.",
+            SelectionAction::AcademicEnglish => "你是一个学术写作专家，擅长把中文内容改写成地道、规范的学术英文。",
+        }
+    }
+
+    fn prompt(self, text: &str) -> String {
+        match self {
+            SelectionAction::Explain => format!(
+                "请用通俗易懂的中文解释下面这段论文内容：先用一两句话说清它在讲什么，\
+                 再解释其中的关键概念和推理，必要时举一个简单的例子。\n\n{}",
+                text
+            ),
+            SelectionAction::Define => format!(
+                "请找出下面这段文字中的专业术语，逐个给出：术语原文、标准中文译名、一句话定义，\
+                 以及它在这段文字中的具体含义。按 Markdown 列表输出。\n\n{}",
+                text
+            ),
+            SelectionAction::Polish => format!(
+                "Polish the following text for an academic paper. Fix grammar, improve clarity and flow, \
+                 and keep the original meaning and technical terms. Output the polished text first, \
+                 then list the main changes briefly in Chinese.\n\n{}",
+                text
+            ),
+            SelectionAction::AcademicEnglish => format!(
+                "请把下面的中文改写成适合发表在国际期刊上的学术英文：用词准确、句式规范，\
+                 专业术语使用领域内的通用英文表达，不要逐字直译。只输出英文结果。\n\n{}",
+                text
+            ),
+        }
+    }
+}
+
+// 术语释义时附上术语库里已有的译法，保证前后一致
+fn glossary_hint(storage: &Storage, text: &str) -> String {
+    let Ok(glossary) = glossary::load(storage) else {
+        return String::new();
+    };
+    let mut known: Vec<String> = Vec::new();
+    for (_, _, term) in glossary.find_terms(text) {
+        let line = format!("- {}：{}", term.term, term.translation);
+        if !known.contains(&line) {
+            known.push(line);
+        }
+    }
+    if known.is_empty() {
+        return String::new();
+    }
+    format!("\n\n以下术语请使用术语库中的译法：\n{}", known.join("\n"))
+}
+
+/// 对选中的文字执行翻译以外的操作，输出通过各操作自己的事件流式推送
+#[tauri::command]
+pub async fn run_selection_action(
+    action: SelectionAction,
+    text: String,
+    config: ModelConfig,
+    show_reasoning: Option<bool>,
+    document_id: Option<String>,
+    priority: Option<Priority>,
+    app_handle: tauri::AppHandle,
+) -> Result<Completion, String> {
+    use tauri::Manager;
+
+    let text = text.trim();
+    if text.is_empty() {
+        return Err("Select some text first".to_string());
+    }
+    println!("✏️ 收到划词操作：{:?}，文本：{}，模型：{}", action, text, config.selected_model);
+
+    let mut prompt = action.prompt(text);
+    if let SelectionAction::Define = action {
+        prompt.push_str(&glossary_hint(&app_handle.state::<Storage>(), text));
+    }
+    let request = CompletionRequest {
+        system: action.system_prompt().to_string(),
+        prompt,
+        images: Vec::new(),
+        show_reasoning: show_reasoning.unwrap_or(false),
+        priority: priority.unwrap_or_default(),
+    };
+    run_completion(&config, &request, action.events(), document_id.as_deref(), &app_handle).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ACTIONS: [(&str, SelectionAction); 4] = [
+        ("explain", SelectionAction::Explain),
+        ("define", SelectionAction::Define),
+        ("polish", SelectionAction::Polish),
+        ("academic_english", SelectionAction::AcademicEnglish),
+    ];
+
+    #[test]
+    fn actions_deserialize_and_use_their_own_events() {
+        let mut seen = Vec::new();
+        for (name, action) in ACTIONS {
+            let parsed: SelectionAction = serde_json::from_value(serde_json::json!(name)).unwrap();
+            let events = parsed.events();
+            assert_eq!(events.kind, action.events().kind);
+            assert_eq!(events.kind, name);
+            assert_eq!(events.chunk, format!("{}_chunk", name));
+            assert_eq!(events.reasoning, format!("{}_reasoning_chunk", name));
+            assert_eq!(events.complete, format!("{}_complete", name));
+            assert!(parsed.prompt("SELECTED TEXT").ends_with("\n\nSELECTED TEXT"));
+            seen.extend([events.chunk, events.reasoning, events.complete]);
+        }
+        let count = seen.len();
+        seen.sort_unstable();
+        seen.dedup();
+        assert_eq!(seen.len(), count);
+        assert!(serde_json::from_value::<SelectionAction>(serde_json::json!("translate")).is_err());
+    }
+
+    #[test]
+    fn glossary_hint_lists_known_terms_once() {
+        let storage = Storage::open_in_memory().unwrap();
+        assert_eq!(glossary_hint(&storage, "Self-attention is everywhere."), "");
+
+        glossary::upsert_term(&storage, "self-attention", "自注意力", "").unwrap();
+        glossary::upsert_term(&storage, "dropout", "随机失活", "").unwrap();
+        let hint = glossary_hint(&storage, "Self-attention layers stack self-attention with residuals.");
+        assert_eq!(hint, "\n\n以下术语请使用术语库中的译法：\n- self-attention：自注意力");
+        assert_eq!(glossary_hint(&storage, "No known terms here."), "");
+    }
+}
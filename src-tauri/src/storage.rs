@@ -0,0 +1,133 @@
+// 本地存储：应用数据目录下的 SQLite 数据库
+//
+// 各功能模块提供自己的建表语句，打开数据库时统一执行。
+
+use rusqlite::Connection;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{
+    annotations, compare, file_scope, glossary, library, ocr, search, summary, translation_memory, translations, usage,
+    zotero,
+};
+
+const SCHEMAS: &[&str] = &[
+    usage::SCHEMA,
+    file_scope::SCHEMA,
+    library::SCHEMA,
+    annotations::SCHEMA,
+    translations::SCHEMA,
+    compare::SCHEMA,
+    translation_memory::SCHEMA,
+    glossary::SCHEMA,
+    zotero::SCHEMA,
+    ocr::SCHEMA,
+    summary::SCHEMA,
+    search::SCHEMA,
+];
+
+// 建表之后新增的列：新库由建表语句创建，旧库打开时补上缺少的列
+const ADDED_COLUMNS: &[(&str, &str, &str)] = &[
+    ("translations", "reasoning", "TEXT"),
+    ("translation_candidates", "reasoning", "TEXT"),
+    ("translation_candidates", "translation_id", "INTEGER"),
+];
+
+pub struct Storage {
+    conn: Mutex<Connection>,
+}
+
+impl Storage {
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let conn = Connection::open(path).map_err(|e| format!("Failed to open database: {}", e))?;
+        Self::init(conn)
+    }
+
+    #[cfg(test)]
+    pub fn open_in_memory() -> Result<Self, String> {
+        let conn = Connection::open_in_memory().map_err(|e| format!("Failed to open database: {}", e))?;
+        Self::init(conn)
+    }
+
+    fn init(conn: Connection) -> Result<Self, String> {
+        conn.execute_batch("PRAGMA journal_mode = WAL; PRAGMA foreign_keys = ON;")
+            .map_err(|e| format!("Failed to configure database: {}", e))?;
+        for schema in SCHEMAS {
+            conn.execute_batch(schema)
+                .map_err(|e| format!("Failed to migrate database: {}", e))?;
+        }
+        for (table, column, definition) in ADDED_COLUMNS {
+            add_column(&conn, table, column, definition)
+                .map_err(|e| format!("Failed to migrate database: {}", e))?;
+        }
+        Ok(Storage {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    pub fn with_conn<T>(&self, f: impl FnOnce(&Connection) -> rusqlite::Result<T>) -> Result<T, String> {
+        let conn = self.conn.lock().map_err(|_| "Database lock poisoned".to_string())?;
+        f(&conn).map_err(|e| format!("Database error: {}", e))
+    }
+}
+
+fn add_column(conn: &Connection, table: &str, column: &str, definition: &str) -> rusqlite::Result<()> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table))?;
+    let exists = stmt
+        .query_map([], |row| row.get::<_, String>(1))?
+        .collect::<rusqlite::Result<Vec<_>>>()?
+        .iter()
+        .any(|name| name == column);
+    if !exists {
+        conn.execute_batch(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition))?;
+        println!("🗄️ 数据表 {} 新增列 {}", table, column);
+    }
+    Ok(())
+}
+
+pub fn now_secs() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn opening_an_old_database_adds_missing_columns() {
+        let dir = std::env::temp_dir().join(format!("papertalk-storage-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("papertalk.db");
+        let _ = std::fs::remove_file(&path);
+        Connection::open(&path)
+            .unwrap()
+            .execute_batch(
+                "CREATE TABLE translations (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    document_id TEXT,
+                    source TEXT NOT NULL,
+                    translation TEXT NOT NULL,
+                    provider TEXT NOT NULL,
+                    model TEXT NOT NULL,
+                    created_at INTEGER NOT NULL
+                );
+                INSERT INTO translations (source, translation, provider, model, created_at)
+                VALUES ('attention', '注意力', 'ollama', 'qwen', 0);",
+            )
+            .unwrap();
+
+        // 重复打开不会重复加列
+        Storage::open(&path).unwrap();
+        let storage = Storage::open(&path).unwrap();
+        let reasoning: Option<String> = storage
+            .with_conn(|conn| conn.query_row("SELECT reasoning FROM translations", [], |row| row.get(0)))
+            .unwrap();
+        assert_eq!(reasoning, None);
+        drop(storage);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,298 @@
+// 流式输出过滤：在 token 流中识别 <think>…</think> 推理块
+//
+// 推理块的起止标签经常被拆到多个 token 里（例如 "<th" + "ink>"），
+// 逐 token 做正则替换无法识别，因此这里用一个带状态的过滤器，
+// 把可能是标签前缀的尾部暂存起来，等下一个 chunk 到达后再判断。
+
+use regex::Regex;
+use std::sync::LazyLock;
+
+const THINK_OPEN: &str = "<think>";
+const THINK_CLOSE: &str = "</think>";
+
+// qwen3 偶尔会把提示词里的控制标记回显出来。只去掉正文开头和结尾的完整标记，
+// 正文中间出现的（例如讲解 qwen3 用法时）原样保留
+const CONTROL_MARKERS: [&str; 2] = ["/no_think", "/think"];
+
+static THINK_BLOCK_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?s)<think>\s*.*?\s*</think>").unwrap());
+static CONTROL_EDGE_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^\s*(?:(?:/no_think|/think)(?:\s+|$))+|(?:^|\s)(?:/no_think|/think)(?:\s+(?:/no_think|/think))*\s*$")
+        .unwrap()
+});
+
+/// 一次 push 之后可以发送给前端的内容
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct FilterOutput {
+    pub content: String,
+    pub reasoning: String,
+}
+
+pub struct ThinkFilter {
+    forward_reasoning: bool,
+    in_think: bool,
+    // 正文开头（以及推理块结束后）的空白不输出
+    skip_leading_ws: bool,
+    pending: String,
+}
+
+impl ThinkFilter {
+    pub fn new(forward_reasoning: bool) -> Self {
+        ThinkFilter {
+            forward_reasoning,
+            in_think: false,
+            skip_leading_ws: true,
+            pending: String::new(),
+        }
+    }
+
+    pub fn push(&mut self, chunk: &str) -> FilterOutput {
+        let mut buf = std::mem::take(&mut self.pending);
+        buf.push_str(chunk);
+
+        let mut out = FilterOutput::default();
+        let mut rest = buf.as_str();
+
+        loop {
+            if self.in_think {
+                if let Some(idx) = rest.find(THINK_CLOSE) {
+                    self.emit_reasoning(&mut out, &rest[..idx]);
+                    rest = &rest[idx + THINK_CLOSE.len()..];
+                    self.in_think = false;
+                    self.skip_leading_ws = true;
+                    continue;
+                }
+                let hold = partial_marker_len(rest, &[THINK_CLOSE]);
+                self.emit_reasoning(&mut out, &rest[..rest.len() - hold]);
+                self.pending = rest[rest.len() - hold..].to_string();
+                break;
+            }
+
+            if let Some((idx, marker)) = find_first_marker(rest) {
+                self.emit_content(&mut out, &rest[..idx]);
+                rest = &rest[idx + marker.len()..];
+                if marker == THINK_OPEN {
+                    self.in_think = true;
+                }
+                continue;
+            }
+
+            let hold = partial_marker_len(rest, &[THINK_OPEN, THINK_CLOSE]).max(control_tail_len(rest, true));
+            self.emit_content(&mut out, &rest[..rest.len() - hold]);
+            self.pending = rest[rest.len() - hold..].to_string();
+            break;
+        }
+
+        out
+    }
+
+    /// 流结束时调用，输出暂存的尾部
+    pub fn finish(&mut self) -> FilterOutput {
+        let pending = std::mem::take(&mut self.pending);
+        let mut out = FilterOutput::default();
+        if self.in_think {
+            self.emit_reasoning(&mut out, &pending);
+        } else {
+            // 结尾的控制标记丢弃，不完整的标签前缀按正文输出
+            let tail = control_tail_len(&pending, false);
+            self.emit_content(&mut out, &pending[..pending.len() - tail]);
+        }
+        out
+    }
+
+    fn emit_content(&mut self, out: &mut FilterOutput, text: &str) {
+        let text = if self.skip_leading_ws {
+            strip_leading_controls(text)
+        } else {
+            text
+        };
+        if !text.is_empty() {
+            self.skip_leading_ws = false;
+            out.content.push_str(text);
+        }
+    }
+
+    fn emit_reasoning(&self, out: &mut FilterOutput, text: &str) {
+        if self.forward_reasoning {
+            out.reasoning.push_str(text);
+        }
+    }
+}
+
+/// 非流式响应的清理：去掉完整的 <think>…</think> 以及首尾的 /no_think /think
+pub fn strip_reasoning(text: &str) -> String {
+    let without_think = THINK_BLOCK_RE.replace_all(text, "");
+    CONTROL_EDGE_RE
+        .replace_all(&without_think, "")
+        .trim()
+        .to_string()
+}
+
+// 去掉开头的空白和完整的控制标记；标记后面必须是空白，"/thinking" 不算
+fn strip_leading_controls(mut text: &str) -> &str {
+    loop {
+        text = text.trim_start();
+        let rest = CONTROL_MARKERS
+            .iter()
+            .filter_map(|marker| text.strip_prefix(marker))
+            .find(|rest| rest.starts_with(char::is_whitespace));
+        match rest {
+            Some(rest) => text = rest,
+            None => return text,
+        }
+    }
+}
+
+// 文本末尾由空白分隔的控制标记（连同前面的空白）的长度。流式输出时暂存这部分，
+// 后面还有正文就原样输出，流结束时丢弃；allow_partial 时末尾只到一半的标记也暂存
+fn control_tail_len(text: &str, allow_partial: bool) -> usize {
+    let mut keep = text.len();
+    let mut last_word = true;
+    loop {
+        let head = &text[..keep];
+        let trimmed = head.trim_end();
+        let start = trimmed
+            .char_indices()
+            .rev()
+            .find(|(_, c)| c.is_whitespace())
+            .map(|(i, c)| i + c.len_utf8())
+            .unwrap_or(0);
+        let word = &trimmed[start..];
+        let partial = allow_partial
+            && last_word
+            && trimmed.len() == head.len()
+            && CONTROL_MARKERS.iter().any(|marker| marker.starts_with(word));
+        if word.is_empty() || !(CONTROL_MARKERS.contains(&word) || partial) {
+            break;
+        }
+        keep = trimmed[..start].trim_end().len();
+        last_word = false;
+    }
+    text.len() - keep
+}
+
+// 找到最早出现的标签；同一位置时取最长的
+fn find_first_marker(text: &str) -> Option<(usize, &'static str)> {
+    let mut best: Option<(usize, &'static str)> = None;
+    for marker in [THINK_OPEN, THINK_CLOSE] {
+        if let Some(idx) = text.find(marker) {
+            best = match best {
+                Some((b, m)) if b < idx || (b == idx && m.len() >= marker.len()) => Some((b, m)),
+                _ => Some((idx, marker)),
+            };
+        }
+    }
+    best
+}
+
+// 文本末尾与任一标记前缀重合的最大长度，这部分需要等下一个 chunk
+fn partial_marker_len(text: &str, markers: &[&str]) -> usize {
+    let mut hold = 0;
+    for marker in markers {
+        for len in (1..marker.len()).rev() {
+            if len <= hold {
+                break;
+            }
+            if text.len() >= len
+                && text.is_char_boundary(text.len() - len)
+                && text.ends_with(&marker[..len])
+            {
+                hold = len;
+                break;
+            }
+        }
+    }
+    hold
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(chunks: &[&str], forward_reasoning: bool) -> FilterOutput {
+        let mut filter = ThinkFilter::new(forward_reasoning);
+        let mut all = FilterOutput::default();
+        for chunk in chunks {
+            let out = filter.push(chunk);
+            all.content.push_str(&out.content);
+            all.reasoning.push_str(&out.reasoning);
+        }
+        let out = filter.finish();
+        all.content.push_str(&out.content);
+        all.reasoning.push_str(&out.reasoning);
+        all
+    }
+
+    #[test]
+    fn think_block_split_at_every_offset() {
+        let input = "<think>\n先看公式</think>\n\nThe answer is 42.";
+        for i in (0..=input.len()).filter(|i| input.is_char_boundary(*i)) {
+            let out = run(&[&input[..i], &input[i..]], true);
+            assert_eq!(out.content, "The answer is 42.", "split at {}", i);
+            assert_eq!(out.reasoning, "\n先看公式", "split at {}", i);
+        }
+        // 逐字符推送
+        let chars: Vec<String> = input.chars().map(String::from).collect();
+        let chunks: Vec<&str> = chars.iter().map(String::as_str).collect();
+        assert_eq!(run(&chunks, false).content, "The answer is 42.");
+    }
+
+    #[test]
+    fn reasoning_is_forwarded_or_dropped() {
+        let chunks = ["<think>check units", "</think>5 ms"];
+        assert_eq!(
+            run(&chunks, true),
+            FilterOutput {
+                content: "5 ms".to_string(),
+                reasoning: "check units".to_string(),
+            }
+        );
+        assert_eq!(
+            run(&chunks, false),
+            FilterOutput {
+                content: "5 ms".to_string(),
+                reasoning: String::new(),
+            }
+        );
+    }
+
+    #[test]
+    fn unclosed_think_at_end_of_stream_is_reasoning() {
+        let out = run(&["<think>still ", "thinking"], true);
+        assert_eq!(out.content, "");
+        assert_eq!(out.reasoning, "still thinking");
+        assert_eq!(run(&["<think>still ", "thinking"], false), FilterOutput::default());
+    }
+
+    #[test]
+    fn partial_marker_is_held_back_then_flushed() {
+        let mut filter = ThinkFilter::new(false);
+        assert_eq!(filter.push("a < b and <thi").content, "a < b and ");
+        assert_eq!(filter.finish().content, "<thi");
+
+        let mut filter = ThinkFilter::new(false);
+        assert_eq!(filter.push("Use /thi").content, "Use");
+        assert_eq!(filter.finish().content, " /thi");
+    }
+
+    #[test]
+    fn control_markers_are_stripped_only_at_the_edges() {
+        assert_eq!(run(&["/no_think ", "Answer"], false).content, "Answer");
+        assert_eq!(run(&["Answer /no_think", " /think\n"], false).content, "Answer");
+        assert_eq!(run(&["</think>Answer"], false).content, "Answer");
+        // 正文中间的标记保留，跨 chunk 也一样
+        assert_eq!(
+            run(&["Append /no", "_think to the prompt"], false).content,
+            "Append /no_think to the prompt"
+        );
+        assert_eq!(run(&["Run /think", "ing mode"], false).content, "Run /thinking mode");
+        assert_eq!(run(&["path/think is kept"], false).content, "path/think is kept");
+    }
+
+    #[test]
+    fn strip_reasoning_for_complete_responses() {
+        assert_eq!(strip_reasoning("<think>\nhmm\n</think>\n\n注意力 /no_think /think"), "注意力");
+        assert_eq!(strip_reasoning("/no_think 注意力"), "注意力");
+        assert_eq!(strip_reasoning("Add /think to enable it."), "Add /think to enable it.");
+    }
+}
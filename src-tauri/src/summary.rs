@@ -0,0 +1,481 @@
+// 论文摘要：按章节分块做 map-reduce，生成问题、方法、结果、局限、贡献五部分
+//
+// 先把正文按识别出的章节切块（过长的章节再按行切分），逐块提炼要点；
+// 要点合起来仍超出预算时分组再压缩一轮，最后一次调用输出结构化结果并流式推送。
+// 结果按文档、所选模型、语言保存，换模型可以重新生成，互不覆盖；
+// 所选模型不可用而由备用模型生成时，记录里保留实际生成的模型。
+
+use rusqlite::{params, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::file_scope::FileScope;
+use crate::library;
+use crate::ocr;
+use crate::outline::{self, SectionKind};
+use crate::pdf_text::{PageText, PdfTextCache};
+use crate::providers::{complete_with_fallback, Completion, CompletionRequest, ModelConfig, StreamDelta};
+use crate::scheduler::{Priority, Scheduler};
+use crate::storage::{now_secs, Storage};
+use crate::usage;
+use crate::{run_completion, StreamEvents};
+
+pub const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS summaries (
+    document_id TEXT NOT NULL,
+    provider TEXT NOT NULL,
+    model TEXT NOT NULL,
+    language TEXT NOT NULL,
+    data TEXT NOT NULL,
+    created_at INTEGER NOT NULL,
+    PRIMARY KEY (document_id, provider, model, language)
+);
+";
+
+// 每次 map 调用送入的正文上限（估算 token），留出提示词和输出的余量
+const CHUNK_TOKENS: u64 = 3000;
+// reduce 阶段一次合并的要点上限
+const REDUCE_TOKENS: u64 = 4000;
+const DEFAULT_LANGUAGE: &str = "中文";
+
+const SUMMARY_SYSTEM_PROMPT: &str = "你是一个专业的学术助手，擅长快速阅读并概括论文。";
+
+const SUMMARY_EVENTS: StreamEvents = StreamEvents {
+    kind: "summary",
+    chunk: "summary_chunk",
+    reasoning: "reasoning_chunk",
+    complete: "summary_complete",
+};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DocumentSummary {
+    pub document_id: String,
+    pub problem: String,
+    pub method: String,
+    pub results: String,
+    pub limitations: String,
+    pub contributions: Vec<String>,
+    pub language: String,
+    pub provider: String,
+    pub model: String,
+    pub created_at: i64,
+}
+
+// 送去提炼要点的一块正文
+#[derive(Debug, Clone, PartialEq)]
+struct Chunk {
+    title: String,
+    text: String,
+}
+
+/* ---------------------------------
+ * 切分章节
+ * --------------------------------- */
+
+/// 按正文标题把文档切成章节，参考文献、致谢之后的内容不参与摘要
+fn split_sections(pages: &[Arc<PageText>]) -> Vec<Chunk> {
+    let headings = outline::headings(pages);
+    let mut sections = vec![Chunk {
+        title: String::new(),
+        text: String::new(),
+    }];
+
+    'pages: for page in pages {
+        for line in &page.lines {
+            let heading = headings
+                .iter()
+                .find(|h| h.page == page.page && h.y == Some(line.y) && h.title == line.text.trim());
+            if let Some(heading) = heading {
+                if matches!(heading.kind, SectionKind::References | SectionKind::Acknowledgments) {
+                    break 'pages;
+                }
+                sections.push(Chunk {
+                    title: heading.title.clone(),
+                    text: String::new(),
+                });
+                continue;
+            }
+            if let Some(current) = sections.last_mut() {
+                current.text.push_str(&line.text);
+                current.text.push('\n');
+            }
+        }
+    }
+    sections.retain(|s| !s.text.trim().is_empty());
+    sections
+}
+
+/// 把章节装进不超过预算的块：短章节合并，长章节按行切开
+fn pack_chunks(sections: Vec<Chunk>, budget: u64) -> Vec<Chunk> {
+    let mut chunks: Vec<Chunk> = Vec::new();
+    let mut current: Option<(Chunk, u64)> = None;
+
+    let mut flush = |current: &mut Option<(Chunk, u64)>| {
+        if let Some((chunk, _)) = current.take() {
+            chunks.push(chunk);
+        }
+    };
+
+    for section in sections {
+        let tokens = usage::estimate_tokens(&section.text);
+        if tokens > budget {
+            flush(&mut current);
+            // 长章节按行切成若干块，标题带上序号
+            let mut part = String::new();
+            let mut part_tokens = 0;
+            let mut index = 1;
+            for line in section.text.lines() {
+                let line_tokens = usage::estimate_tokens(line) + 1;
+                if part_tokens + line_tokens > budget && !part.is_empty() {
+                    current = Some((
+                        Chunk {
+                            title: format!("{}（{}）", section.title, index),
+                            text: std::mem::take(&mut part),
+                        },
+                        part_tokens,
+                    ));
+                    flush(&mut current);
+                    part_tokens = 0;
+                    index += 1;
+                }
+                part.push_str(line);
+                part.push('\n');
+                part_tokens += line_tokens;
+            }
+            if !part.trim().is_empty() {
+                current = Some((
+                    Chunk {
+                        title: format!("{}（{}）", section.title, index),
+                        text: part,
+                    },
+                    part_tokens,
+                ));
+                flush(&mut current);
+            }
+            continue;
+        }
+
+        match current.as_mut() {
+            Some((chunk, used)) if *used + tokens <= budget => {
+                chunk.title = [chunk.title.as_str(), section.title.as_str()]
+                    .iter()
+                    .filter(|t| !t.is_empty())
+                    .copied()
+                    .collect::<Vec<_>>()
+                    .join(" / ");
+                chunk.text.push_str(&section.text);
+                *used += tokens;
+            }
+            _ => {
+                flush(&mut current);
+                current = Some((section, tokens));
+            }
+        }
+    }
+    flush(&mut current);
+    chunks
+}
+
+/* ---------------------------------
+ * 解析结果
+ * --------------------------------- */
+
+const SUMMARY_FORMAT: &str = "请严格按以下格式输出，每个标记单独一行，不要输出其他内容：
+[PROBLEM]
+研究要解决的问题与动机
+[METHOD]
+提出的方法或技术路线
+[RESULTS]
+主要实验结果与结论
+[LIMITATIONS]
+局限性与未来工作（论文未提及时写“未提及”）
+[CONTRIBUTIONS]
+每行一条主要贡献，以 - 开头";
+
+fn parse_summary(content: &str) -> Option<(String, String, String, String, Vec<String>)> {
+    let mut sections: Vec<(String, String)> = Vec::new();
+    for line in content.lines() {
+        let marker = line.trim().to_ascii_uppercase();
+        if let Some(name) = marker.strip_prefix('[').and_then(|m| m.strip_suffix(']')) {
+            if ["PROBLEM", "METHOD", "RESULTS", "LIMITATIONS", "CONTRIBUTIONS"].contains(&name) {
+                sections.push((name.to_string(), String::new()));
+                continue;
+            }
+        }
+        if let Some((_, body)) = sections.last_mut() {
+            body.push_str(line);
+            body.push('\n');
+        }
+    }
+    if sections.is_empty() {
+        return None;
+    }
+    let section = |name: &str| {
+        sections
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, body)| body.trim().to_string())
+            .unwrap_or_default()
+    };
+    let contributions = section("CONTRIBUTIONS")
+        .lines()
+        .map(|l| l.trim().trim_start_matches(['-', '*', '•']).trim().to_string())
+        .filter(|l| !l.is_empty())
+        .collect();
+    Some((
+        section("PROBLEM"),
+        section("METHOD"),
+        section("RESULTS"),
+        section("LIMITATIONS"),
+        contributions,
+    ))
+}
+
+/* ---------------------------------
+ * 存取
+ * --------------------------------- */
+
+fn load_summary(storage: &Storage, document_id: &str, provider: &str, model: &str, language: &str) -> Result<Option<DocumentSummary>, String> {
+    let data: Option<String> = storage.with_conn(|conn| {
+        conn.query_row(
+            "SELECT data FROM summaries WHERE document_id = ?1 AND provider = ?2 AND model = ?3 AND language = ?4",
+            params![document_id, provider, model, language],
+            |row| row.get(0),
+        )
+        .optional()
+    })?;
+    Ok(data.and_then(|d| serde_json::from_str(&d).ok()))
+}
+
+// provider、model 是请求时选择的模型，作为缓存键；summary 里记录实际生成的模型
+fn save_summary(storage: &Storage, provider: &str, model: &str, summary: &DocumentSummary) -> Result<(), String> {
+    let data = serde_json::to_string(summary).map_err(|e| format!("Failed to encode summary: {}", e))?;
+    storage.with_conn(|conn| {
+        conn.execute(
+            "INSERT OR REPLACE INTO summaries (document_id, provider, model, language, data, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                summary.document_id,
+                provider,
+                model,
+                summary.language,
+                data,
+                summary.created_at
+            ],
+        )?;
+        Ok(())
+    })
+}
+
+pub fn document_summaries(storage: &Storage, document_id: &str) -> Result<Vec<DocumentSummary>, String> {
+    let rows: Vec<String> = storage.with_conn(|conn| {
+        let mut stmt = conn.prepare("SELECT data FROM summaries WHERE document_id = ?1 ORDER BY created_at DESC")?;
+        let rows = stmt.query_map(params![document_id], |row| row.get(0))?;
+        rows.collect()
+    })?;
+    Ok(rows.iter().filter_map(|d| serde_json::from_str(d).ok()).collect())
+}
+
+/* ---------------------------------
+ * map-reduce
+ * --------------------------------- */
+
+// 中间步骤不向前端推送片段，只记录用量
+async fn complete_quietly(
+    config: &ModelConfig,
+    prompt: String,
+    document_id: &str,
+    app_handle: &tauri::AppHandle,
+) -> Result<Completion, String> {
+    use tauri::Manager;
+
+    let request = CompletionRequest {
+        system: SUMMARY_SYSTEM_PROMPT.to_string(),
+        prompt,
+        images: Vec::new(),
+        show_reasoning: false,
+        priority: Priority::Background,
+    };
+    let mut on_delta = |_: StreamDelta| {};
+    let scheduler = app_handle.state::<Scheduler>();
+    let completion = complete_with_fallback(config, &request, &scheduler, &mut on_delta).await?;
+    usage::record_completion(&app_handle.state::<Storage>(), Some(document_id), SUMMARY_EVENTS.kind, &completion);
+    Ok(completion)
+}
+
+fn load_chunks(app_handle: &tauri::AppHandle, path: &Path) -> Result<Vec<Chunk>, String> {
+    use tauri::Manager;
+
+    let storage = app_handle.state::<Storage>();
+    let cache = app_handle.state::<PdfTextCache>();
+    let pages = ocr::document_text(&storage, &cache, path, false)?;
+    Ok(pack_chunks(split_sections(&pages), CHUNK_TOKENS))
+}
+
+/// 生成论文摘要；已有同一模型、同一语言的结果时直接返回，regenerate 为 true 时重新生成
+#[tauri::command]
+pub async fn summarize_document(
+    document_id: String,
+    config: ModelConfig,
+    language: Option<String>,
+    regenerate: Option<bool>,
+    app_handle: tauri::AppHandle,
+) -> Result<DocumentSummary, String> {
+    use tauri::Manager;
+
+    let language = language
+        .map(|l| l.trim().to_string())
+        .filter(|l| !l.is_empty())
+        .unwrap_or_else(|| DEFAULT_LANGUAGE.to_string());
+    let target = config.target(&config.selected_model)?;
+    if !regenerate.unwrap_or(false) {
+        let storage = app_handle.state::<Storage>();
+        if let Some(summary) = load_summary(&storage, &document_id, &target.provider, &target.model, &language)? {
+            return Ok(summary);
+        }
+    }
+
+    let entry = library::find_entry(&app_handle.state::<Storage>(), &document_id)?
+        .ok_or_else(|| format!("Document not in library: {}", document_id))?;
+    let path = app_handle.state::<FileScope>().resolve_pdf(&entry.path)?;
+    let chunks = {
+        let app_handle = app_handle.clone();
+        tauri::async_runtime::spawn_blocking(move || load_chunks(&app_handle, &path))
+            .await
+            .map_err(|e| format!("Text extraction failed: {}", e))??
+    };
+    if chunks.is_empty() {
+        return Err("No text found in document".to_string());
+    }
+    let title = entry.title.clone().unwrap_or_default();
+    println!("📝 开始生成摘要：{}，共 {} 块，语言：{}", title, chunks.len(), language);
+
+    // map：逐块提炼要点
+    let total = chunks.len();
+    let mut notes = Vec::new();
+    for (i, chunk) in chunks.iter().enumerate() {
+        let prompt = format!(
+            "以下是论文《{}》的一部分（{}）。请用{}列出其中与研究问题、方法、实验结果、局限性、主要贡献相关的要点，\
+             没有涉及的方面直接略过，不超过 300 字。\n\n{}",
+            title,
+            if chunk.title.is_empty() { "开头部分" } else { &chunk.title },
+            language,
+            chunk.text
+        );
+        let completion = complete_quietly(&config, prompt, &document_id, &app_handle).await?;
+        notes.push(format!("【{}】\n{}", chunk.title, completion.content.trim()));
+        crate::emit_to_window(
+            &app_handle,
+            "summary_progress",
+            serde_json::json!({ "documentId": document_id, "done": i + 1, "total": total }),
+        );
+    }
+
+    // reduce：要点过长时分组压缩，直到能放进一次调用
+    while notes.len() > 1 && usage::estimate_tokens(&notes.join("\n\n")) > REDUCE_TOKENS {
+        let mut groups: Vec<Vec<String>> = vec![Vec::new()];
+        let mut used = 0;
+        for note in notes {
+            let tokens = usage::estimate_tokens(&note);
+            if used + tokens > REDUCE_TOKENS && groups.last().is_some_and(|g| !g.is_empty()) {
+                groups.push(Vec::new());
+                used = 0;
+            }
+            used += tokens;
+            if let Some(group) = groups.last_mut() {
+                group.push(note);
+            }
+        }
+        // 每组至少两条，否则无法收敛
+        if groups.iter().all(|g| g.len() < 2) {
+            notes = groups.into_iter().flatten().collect();
+            break;
+        }
+        let mut merged = Vec::new();
+        for group in groups {
+            if group.len() == 1 {
+                merged.extend(group);
+                continue;
+            }
+            let prompt = format!(
+                "以下是论文《{}》各部分的要点，请用{}合并去重，保留研究问题、方法、结果、局限性、贡献相关的信息，不超过 500 字。\n\n{}",
+                title,
+                language,
+                group.join("\n\n")
+            );
+            merged.push(complete_quietly(&config, prompt, &document_id, &app_handle).await?.content);
+        }
+        notes = merged;
+    }
+
+    // 最后一步输出结构化结果，流式推送给前端
+    let request = CompletionRequest {
+        system: SUMMARY_SYSTEM_PROMPT.to_string(),
+        prompt: format!(
+            "以下是论文《{}》各部分的要点。请用{}写出整篇论文的结构化摘要。\n\n{}\n\n{}",
+            title,
+            language,
+            notes.join("\n\n"),
+            SUMMARY_FORMAT
+        ),
+        images: Vec::new(),
+        show_reasoning: false,
+        priority: Priority::Interactive,
+    };
+    let completion = run_completion(&config, &request, &SUMMARY_EVENTS, Some(&document_id), &app_handle).await?;
+    // 模型没按格式输出时整段放进 problem，至少不丢内容
+    let (problem, method, results, limitations, contributions) = parse_summary(&completion.content)
+        .unwrap_or_else(|| (completion.content.trim().to_string(), String::new(), String::new(), String::new(), Vec::new()));
+
+    let summary = DocumentSummary {
+        document_id,
+        problem,
+        method,
+        results,
+        limitations,
+        contributions,
+        language,
+        provider: completion.provider,
+        model: completion.model,
+        created_at: now_secs(),
+    };
+    save_summary(&app_handle.state::<Storage>(), &target.provider, &target.model, &summary)?;
+    Ok(summary)
+}
+
+/// 文档已保存的全部摘要（不同模型、语言各一份）
+#[tauri::command]
+pub fn list_summaries(document_id: String, storage: tauri::State<'_, Storage>) -> Result<Vec<DocumentSummary>, String> {
+    document_summaries(&storage, &document_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fallback_summary_is_cached_under_the_requested_model() {
+        let storage = Storage::open_in_memory().unwrap();
+        let summary = DocumentSummary {
+            document_id: "doc".to_string(),
+            problem: "问题".to_string(),
+            method: String::new(),
+            results: String::new(),
+            limitations: String::new(),
+            contributions: vec!["贡献".to_string()],
+            language: "中文".to_string(),
+            provider: "ollama".to_string(),
+            model: "qwen2.5".to_string(),
+            created_at: 1,
+        };
+        save_summary(&storage, "openai", "gpt-4o", &summary).unwrap();
+
+        let cached = load_summary(&storage, "doc", "openai", "gpt-4o", "中文").unwrap().unwrap();
+        assert_eq!((cached.provider.as_str(), cached.model.as_str()), ("ollama", "qwen2.5"));
+        assert_eq!(cached.contributions, vec!["贡献"]);
+        assert!(load_summary(&storage, "doc", "ollama", "qwen2.5", "中文").unwrap().is_none());
+        assert!(load_summary(&storage, "doc", "openai", "gpt-4o", "English").unwrap().is_none());
+        assert_eq!(document_summaries(&storage, "doc").unwrap().len(), 1);
+    }
+}
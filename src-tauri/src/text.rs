@@ -0,0 +1,154 @@
+// 文本工具：中日韩字符判断、PDF 文本行拼接与分句，供目录、参考文献、检索、翻译记忆等模块共用
+
+// 句号后面常见的缩写，不作为句子边界
+const ABBREVIATIONS: &[&str] = &[
+    "e.g.", "i.e.", "et al.", "etc.", "vs.", "fig.", "figs.", "eq.", "eqs.", "sec.", "tab.", "ref.", "refs.", "no.",
+    "approx.", "cf.", "resp.",
+];
+
+/// 中日韩表意文字（不含标点与全角符号）
+pub fn is_cjk(c: char) -> bool {
+    matches!(c as u32, 0x3400..=0x4DBF | 0x4E00..=0x9FFF | 0xF900..=0xFAFF | 0x20000..=0x2A6DF)
+}
+
+/// 表意文字，或中文标点、全角字符；这些字符与相邻汉字之间都不加空格
+pub fn is_cjk_or_fullwidth(c: char) -> bool {
+    is_cjk(c) || matches!(c as u32, 0x3000..=0x303F | 0xFF00..=0xFFEF)
+}
+
+/// 拼接相邻行：英文行尾连字符去掉，中文之间、断开的网址和 DOI 中间不加空格
+pub fn join_line(text: &mut String, line: &str) {
+    let line = line.trim();
+    if line.is_empty() {
+        return;
+    }
+    if text.ends_with('-') && line.starts_with(|c: char| c.is_lowercase()) {
+        text.pop();
+    } else {
+        let cjk_join = text.chars().last().is_some_and(is_cjk_or_fullwidth) && line.starts_with(is_cjk_or_fullwidth);
+        if !text.is_empty() && !cjk_join && !text.ends_with('/') {
+            text.push(' ');
+        }
+    }
+    text.push_str(line);
+}
+
+fn ends_with_abbreviation(text: &str) -> bool {
+    let lower = text.to_lowercase();
+    ABBREVIATIONS.iter().any(|abbr| {
+        lower.ends_with(abbr)
+            && lower[..lower.len() - abbr.len()]
+                .chars()
+                .next_back()
+                .is_none_or(|c| !c.is_alphanumeric())
+    })
+}
+
+/// 按句子切分：中文句末标点直接断句，英文句点后需跟空白和大写字母，并跳过常见缩写
+pub fn split_sentences(text: &str) -> Vec<String> {
+    split_sentences_by(text, |sentence, next| {
+        (next.is_uppercase() || !next.is_ascii()) && !ends_with_abbreviation(sentence)
+    })
+}
+
+/// 分句的通用实现：中文句末标点直接断句；英文 ! ? 后需跟空白和大写字母；
+/// 句点后需跟空白，是否断句由 ends_sentence(到句点为止的文本, 后文第一个字符) 决定
+pub fn split_sentences_by(text: &str, ends_sentence: impl Fn(&str, char) -> bool) -> Vec<String> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut sentences = Vec::new();
+    let mut current = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        current.push(c);
+        i += 1;
+        let boundary = match c {
+            '。' | '！' | '？' => true,
+            '.' | '!' | '?' => {
+                // 句末标点后可能紧跟引号、括号，越过它们再看后文
+                let closers = chars[i..].iter().take_while(|c| "\"'”’)）".contains(**c)).count();
+                let rest = &chars[i + closers..];
+                let next = rest.iter().position(|c| !c.is_whitespace()).map(|p| (p, rest[p]));
+                match next {
+                    Some((gap, next)) if gap > 0 => {
+                        if c == '.' {
+                            ends_sentence(&current, next)
+                        } else {
+                            next.is_uppercase() || !next.is_ascii()
+                        }
+                    }
+                    _ => false,
+                }
+            }
+            _ => false,
+        };
+        if boundary {
+            // 句末的引号、括号跟着上一句
+            while i < chars.len() && "\"'”’)）".contains(chars[i]) {
+                current.push(chars[i]);
+                i += 1;
+            }
+            let sentence = current.trim().to_string();
+            if !sentence.is_empty() {
+                sentences.push(sentence);
+            }
+            current.clear();
+        }
+    }
+    let rest = current.trim();
+    if !rest.is_empty() {
+        sentences.push(rest.to_string());
+    }
+    sentences
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn join_line_handles_hyphens_cjk_and_urls() {
+        let cases = [
+            ("", "  First line ", "First line"),
+            ("trans-", "former models", "transformer models"),
+            ("state-of-the-", "Art", "state-of-the- Art"),
+            ("注意力机制", "是核心", "注意力机制是核心"),
+            ("如图 1 所示，", "模型", "如图 1 所示，模型"),
+            ("参见 Transformer", "模型", "参见 Transformer 模型"),
+            ("https://doi.org/", "10.1000/xyz", "https://doi.org/10.1000/xyz"),
+            ("kept", "   ", "kept"),
+        ];
+        for (text, line, expected) in cases {
+            let mut joined = text.to_string();
+            join_line(&mut joined, line);
+            assert_eq!(joined, expected, "{:?} + {:?}", text, line);
+        }
+    }
+
+    #[test]
+    fn split_sentences_handles_abbreviations_and_cjk() {
+        let cases: &[(&str, &[&str])] = &[
+            (
+                "We use Adam, e.g. with warmup. Results improve (see Fig. 3). The end",
+                &["We use Adam, e.g. with warmup.", "Results improve (see Fig. 3).", "The end"],
+            ),
+            ("Smith et al. Proposed it. It works.", &["Smith et al. Proposed it.", "It works."]),
+            ("Version 2.5 is faster. Really?  Yes!", &["Version 2.5 is faster.", "Really?", "Yes!"]),
+            ("注意力机制很重要。我们提出了新方法！效果如何？", &["注意力机制很重要。", "我们提出了新方法！", "效果如何？"]),
+            ("He said \"stop.\" Then left.", &["He said \"stop.\"", "Then left."]),
+            ("no boundary. lowercase follows", &["no boundary. lowercase follows"]),
+            ("   ", &[]),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(split_sentences(text), *expected, "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn cjk_predicates_separate_letters_from_punctuation() {
+        assert!(is_cjk('中') && is_cjk('𠀀') && is_cjk('並'));
+        assert!(!is_cjk('，') && !is_cjk('。') && !is_cjk('Ａ') && !is_cjk('a'));
+        assert!(is_cjk_or_fullwidth('，') && is_cjk_or_fullwidth('。') && is_cjk_or_fullwidth('Ａ'));
+        assert!(!is_cjk_or_fullwidth('a') && !is_cjk_or_fullwidth(','));
+    }
+}
@@ -0,0 +1,545 @@
+// 翻译记忆：用户确认过的原文与译文，按句子保存
+//
+// 新的划词先查精确匹配，再用 FTS5 三元组索引粗筛、编辑距离精排找相似句。
+// 命中的句对一方面立即显示给用户，一方面作为示例注入翻译提示词，保持术语和表达一致。
+// 支持 TMX 1.4 导入导出，方便在不同电脑间迁移。
+
+use regex::Regex;
+use rusqlite::{params, Connection};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::sync::LazyLock;
+
+use crate::export::format_date;
+use crate::file_scope;
+use crate::storage::{now_secs, Storage};
+use crate::text::split_sentences;
+use crate::usage::estimate_tokens_for;
+
+pub const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS translation_memory (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    source TEXT NOT NULL,
+    target TEXT NOT NULL,
+    source_key TEXT NOT NULL UNIQUE,
+    document_id TEXT,
+    created_at INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL
+);
+CREATE VIRTUAL TABLE IF NOT EXISTS translation_memory_fts USING fts5(source_key, tokenize = 'trigram');
+CREATE TRIGGER IF NOT EXISTS translation_memory_fts_insert AFTER INSERT ON translation_memory BEGIN
+    INSERT INTO translation_memory_fts (rowid, source_key) VALUES (new.id, new.source_key);
+END;
+CREATE TRIGGER IF NOT EXISTS translation_memory_fts_update AFTER UPDATE OF source_key ON translation_memory BEGIN
+    DELETE FROM translation_memory_fts WHERE rowid = old.id;
+    INSERT INTO translation_memory_fts (rowid, source_key) VALUES (new.id, new.source_key);
+END;
+CREATE TRIGGER IF NOT EXISTS translation_memory_fts_delete AFTER DELETE ON translation_memory BEGIN
+    DELETE FROM translation_memory_fts WHERE rowid = old.id;
+END;
+-- 索引建立之前已有的记忆补进索引
+INSERT INTO translation_memory_fts (rowid, source_key)
+    SELECT id, source_key FROM translation_memory WHERE id NOT IN (SELECT rowid FROM translation_memory_fts);
+";
+
+// 低于这个相似度的句子不算匹配
+const MIN_SCORE: f64 = 0.6;
+// 注入提示词的示例数量
+const FEW_SHOT_EXAMPLES: usize = 3;
+/// 示例占用的 token 上限，长文本分段时为它预留空间
+pub const FEW_SHOT_TOKENS: u64 = 800;
+// 索引粗筛取出的候选数
+const INDEX_CANDIDATES: usize = 200;
+// 三元组粗筛后进入编辑距离精排的候选数
+const RERANK_CANDIDATES: usize = 30;
+// 长句只取这么多个三元组去查索引，均匀分布在整句中
+const MAX_QUERY_TRIGRAMS: usize = 64;
+// 太长的段落不整体入库，只保存对齐的句对
+const MAX_SEGMENT_CHARS: usize = 1000;
+
+// TMX 里的语言代码
+const SOURCE_LANG: &str = "en";
+const TARGET_LANG: &str = "zh-CN";
+
+static TU_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?s)<tu\b[^>]*>(.*?)</tu>").unwrap());
+static TUV_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?s)<tuv\b([^>]*)>.*?<seg>(.*?)</seg>.*?</tuv>").unwrap());
+static LANG_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r#"(?:xml:)?lang\s*=\s*"([^"]*)""#).unwrap());
+// 行内标记：bpt/ept/ph/it 里是原格式的代码，连同内容一起去掉；其他标记只去掉标签
+static INLINE_TAG_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?s)<(?:bpt|ept|ph|it)\b[^>]*>.*?</(?:bpt|ept|ph|it)>|<[^>]+>").unwrap());
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MemoryMatch {
+    pub id: i64,
+    pub source: String,
+    pub target: String,
+    // 0 到 1，1 表示规范化后完全相同
+    pub score: f64,
+    pub exact: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TmxImport {
+    pub imported: usize,
+    pub skipped: usize,
+}
+
+/* ---------------------------------
+ * 文本处理
+ * --------------------------------- */
+
+/// 匹配用的规范形式：小写、合并空白、去掉句末标点
+pub fn normalize(text: &str) -> String {
+    let collapsed = text.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase();
+    collapsed
+        .trim_end_matches(|c: char| ".。!！?？;；:：,，".contains(c))
+        .trim()
+        .to_string()
+}
+
+// FTS5 查询：句中三元组任一命中即可，按 bm25 排序
+fn index_query(key: &str) -> Option<String> {
+    let chars: Vec<char> = key.chars().collect();
+    let mut seen = HashSet::new();
+    let grams: Vec<String> = chars
+        .windows(3)
+        .map(|w| w.iter().collect::<String>())
+        .filter(|g| !g.trim().is_empty() && seen.insert(g.clone()))
+        .collect();
+    if grams.is_empty() {
+        return None;
+    }
+    let step = grams.len().div_ceil(MAX_QUERY_TRIGRAMS);
+    let terms: Vec<String> = grams
+        .iter()
+        .step_by(step)
+        .map(|g| format!("\"{}\"", g.replace('"', "\"\"")))
+        .collect();
+    Some(terms.join(" OR "))
+}
+
+fn trigrams(text: &str) -> HashSet<[char; 3]> {
+    let chars: Vec<char> = format!("  {} ", text).chars().collect();
+    chars.windows(3).map(|w| [w[0], w[1], w[2]]).collect()
+}
+
+// 三元组 Dice 系数，用于粗筛
+fn dice(a: &HashSet<[char; 3]>, b: &HashSet<[char; 3]>) -> f64 {
+    if a.is_empty() || b.is_empty() {
+        return 0.0;
+    }
+    2.0 * a.intersection(b).count() as f64 / (a.len() + b.len()) as f64
+}
+
+// 基于字符编辑距离的相似度
+fn edit_similarity(a: &str, b: &str) -> f64 {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let longest = a.len().max(b.len());
+    if longest == 0 {
+        return 1.0;
+    }
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+    for (i, ca) in a.iter().enumerate() {
+        current[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    1.0 - previous[b.len()] as f64 / longest as f64
+}
+
+/* ---------------------------------
+ * 存取
+ * --------------------------------- */
+
+fn upsert_pair(conn: &Connection, source: &str, target: &str, document_id: Option<&str>) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT INTO translation_memory (source, target, source_key, document_id, created_at, updated_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?5)
+         ON CONFLICT(source_key) DO UPDATE SET
+            source = excluded.source, target = excluded.target, updated_at = excluded.updated_at",
+        params![source, target, normalize(source), document_id, now_secs()],
+    )?;
+    Ok(())
+}
+
+/// 保存一对确认过的译文，原文和译文句数相同时逐句对齐后分别保存，返回保存的句对数
+pub fn remember(storage: &Storage, source: &str, target: &str, document_id: Option<&str>) -> Result<usize, String> {
+    let source = source.trim();
+    let target = target.trim();
+    if normalize(source).is_empty() || target.is_empty() {
+        return Err("Source and translation are required".to_string());
+    }
+
+    let mut pairs: Vec<(&str, &str)> = Vec::new();
+    if source.chars().count() <= MAX_SEGMENT_CHARS {
+        pairs.push((source, target));
+    }
+    let source_sentences = split_sentences(source);
+    let target_sentences = split_sentences(target);
+    if source_sentences.len() > 1 && source_sentences.len() == target_sentences.len() {
+        pairs.extend(source_sentences.iter().map(String::as_str).zip(target_sentences.iter().map(String::as_str)));
+    }
+    storage.with_conn(|conn| {
+        let tx = conn.unchecked_transaction()?;
+        for (s, t) in &pairs {
+            upsert_pair(&tx, s, t, document_id)?;
+        }
+        tx.commit()
+    })?;
+    Ok(pairs.len())
+}
+
+/// 查找与 text 相似的记忆，按相似度从高到低
+pub fn lookup(storage: &Storage, text: &str, limit: usize) -> Result<Vec<MemoryMatch>, String> {
+    let key = normalize(text);
+    if key.is_empty() {
+        return Ok(Vec::new());
+    }
+    let length = key.chars().count() as f64;
+    let rows: Vec<(i64, String, String, String)> = storage.with_conn(|conn| {
+        let mut rows: Vec<(i64, String, String, String)> = conn
+            .prepare_cached("SELECT id, source, target, source_key FROM translation_memory WHERE source_key = ?1")?
+            .query_map(params![key], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)))?
+            .collect::<rusqlite::Result<_>>()?;
+        let Some(query) = index_query(&key) else {
+            return Ok(rows);
+        };
+        // 长度相差太多的句子编辑距离相似度不可能达标，直接在 SQL 里排除
+        let mut stmt = conn.prepare_cached(
+            "SELECT t.id, t.source, t.target, t.source_key
+             FROM translation_memory_fts f JOIN translation_memory t ON t.id = f.rowid
+             WHERE translation_memory_fts MATCH ?1 AND length(t.source_key) BETWEEN ?2 AND ?3 AND t.source_key != ?4
+             ORDER BY f.rank LIMIT ?5",
+        )?;
+        let similar = stmt.query_map(
+            params![
+                query,
+                (length * MIN_SCORE).floor() as i64,
+                (length / MIN_SCORE).ceil() as i64,
+                key,
+                INDEX_CANDIDATES as i64
+            ],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+        )?;
+        for row in similar {
+            rows.push(row?);
+        }
+        Ok(rows)
+    })?;
+
+    let query = trigrams(&key);
+    let mut candidates: Vec<(f64, (i64, String, String, String))> = rows
+        .into_iter()
+        .map(|row| (dice(&query, &trigrams(&row.3)), row))
+        .filter(|(coarse, _)| *coarse > 0.0)
+        .collect();
+    candidates.sort_by(|a, b| b.0.total_cmp(&a.0));
+    candidates.truncate(RERANK_CANDIDATES);
+
+    let mut matches: Vec<MemoryMatch> = candidates
+        .into_iter()
+        .filter_map(|(_, (id, source, target, source_key))| {
+            let exact = source_key == key;
+            let score = if exact { 1.0 } else { edit_similarity(&key, &source_key) };
+            (score >= MIN_SCORE).then_some(MemoryMatch {
+                id,
+                source,
+                target,
+                score,
+                exact,
+            })
+        })
+        .collect();
+    matches.sort_by(|a, b| b.score.total_cmp(&a.score));
+    matches.truncate(limit);
+    Ok(matches)
+}
+
+/// 附加到翻译系统提示词后面的示例，没有相似记忆时为空；model 为估算 token 用的分词器
+pub fn few_shot_prompt(storage: &Storage, text: &str, model: &str) -> String {
+    let matches = match lookup(storage, text, FEW_SHOT_EXAMPLES) {
+        Ok(matches) => matches,
+        Err(e) => {
+            println!("⚠️ 查询翻译记忆失败：{}", e);
+            return String::new();
+        }
+    };
+    if matches.is_empty() {
+        return String::new();
+    }
+    // 按相似度从高到低加入示例，不超过预留的 token 数
+    let mut examples: Vec<String> = Vec::new();
+    let mut tokens = 0;
+    for m in &matches {
+        let example = format!("原文：{}\n译文：{}", m.source, m.target);
+        tokens += estimate_tokens_for(model, &example);
+        if tokens > FEW_SHOT_TOKENS {
+            break;
+        }
+        examples.push(example);
+    }
+    if examples.is_empty() {
+        return String::new();
+    }
+    format!(
+        "\n\n以下是用户确认过的相似句子的译文，请沿用其中的术语和表达方式：\n\n{}",
+        examples.join("\n\n")
+    )
+}
+
+/* ---------------------------------
+ * TMX
+ * --------------------------------- */
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn unescape_xml(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+// TMX 要求的 YYYYMMDDThhmmssZ
+fn tmx_date(secs: i64) -> String {
+    let time = secs.rem_euclid(86_400);
+    format!(
+        "{}T{:02}{:02}{:02}Z",
+        format_date(secs).replace('-', ""),
+        time / 3600,
+        time % 3600 / 60,
+        time % 60
+    )
+}
+
+fn render_tmx(pairs: &[(String, String, i64)]) -> String {
+    let mut out = String::new();
+    out.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<tmx version=\"1.4\">\n");
+    out.push_str(&format!(
+        "  <header creationtool=\"PaperTalk\" creationtoolversion=\"{}\" segtype=\"sentence\" o-tmf=\"PaperTalk\" \
+         adminlang=\"en\" srclang=\"{}\" datatype=\"plaintext\"/>\n  <body>\n",
+        env!("CARGO_PKG_VERSION"),
+        SOURCE_LANG
+    ));
+    for (source, target, updated_at) in pairs {
+        out.push_str(&format!(
+            "    <tu creationdate=\"{}\">\n      <tuv xml:lang=\"{}\"><seg>{}</seg></tuv>\n      <tuv xml:lang=\"{}\"><seg>{}</seg></tuv>\n    </tu>\n",
+            tmx_date(*updated_at),
+            SOURCE_LANG,
+            escape_xml(source),
+            TARGET_LANG,
+            escape_xml(target)
+        ));
+    }
+    out.push_str("  </body>\n</tmx>\n");
+    out
+}
+
+/// 解析 TMX，按语言代码取英文原文和中文译文，没有语言标记时取前两个 tuv
+fn parse_tmx(content: &str) -> (Vec<(String, String)>, usize) {
+    let mut pairs = Vec::new();
+    let mut skipped = 0;
+    for tu in TU_RE.captures_iter(content) {
+        let variants: Vec<(String, String)> = TUV_RE
+            .captures_iter(&tu[1])
+            .map(|tuv| {
+                let lang = LANG_RE.captures(&tuv[1]).map(|c| c[1].to_lowercase()).unwrap_or_default();
+                let seg = unescape_xml(&INLINE_TAG_RE.replace_all(&tuv[2], ""));
+                (lang, seg.trim().to_string())
+            })
+            .collect();
+        let source = variants.iter().find(|(lang, _)| lang.starts_with("en"));
+        let target = variants.iter().find(|(lang, _)| lang.starts_with("zh"));
+        let pair = match (source, target) {
+            (Some(source), Some(target)) => Some((source.1.clone(), target.1.clone())),
+            _ if variants.len() >= 2 => Some((variants[0].1.clone(), variants[1].1.clone())),
+            _ => None,
+        };
+        match pair {
+            Some((source, target)) if !normalize(&source).is_empty() && !target.is_empty() => pairs.push((source, target)),
+            _ => skipped += 1,
+        }
+    }
+    (pairs, skipped)
+}
+
+/* ---------------------------------
+ * 命令
+ * --------------------------------- */
+
+/// 查找与选中文字相似的翻译记忆，在模型返回之前先给用户参考
+#[tauri::command]
+pub async fn lookup_translation_memory(
+    text: String,
+    limit: Option<usize>,
+    app_handle: tauri::AppHandle,
+) -> Result<Vec<MemoryMatch>, String> {
+    use tauri::Manager;
+
+    tauri::async_runtime::spawn_blocking(move || lookup(&app_handle.state::<Storage>(), &text, limit.unwrap_or(5)))
+        .await
+        .map_err(|e| format!("Translation memory lookup failed: {}", e))?
+}
+
+/// 把用户确认的译文存入翻译记忆
+#[tauri::command]
+pub fn add_translation_memory(
+    source: String,
+    target: String,
+    document_id: Option<String>,
+    storage: tauri::State<'_, Storage>,
+) -> Result<usize, String> {
+    let saved = remember(&storage, &source, &target, document_id.as_deref())?;
+    println!("🧠 翻译记忆新增 {} 条", saved);
+    Ok(saved)
+}
+
+#[tauri::command]
+pub fn delete_translation_memory(id: i64, storage: tauri::State<'_, Storage>) -> Result<(), String> {
+    storage.with_conn(|conn| conn.execute("DELETE FROM translation_memory WHERE id = ?1", params![id]))?;
+    Ok(())
+}
+
+/// 导出全部翻译记忆为 TMX 文件
+#[tauri::command]
+pub async fn export_tmx(app_handle: tauri::AppHandle) -> Result<Option<String>, String> {
+    use tauri::Manager;
+
+    let pairs: Vec<(String, String, i64)> = app_handle.state::<Storage>().with_conn(|conn| {
+        let mut stmt = conn.prepare("SELECT source, target, updated_at FROM translation_memory ORDER BY id")?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?;
+        rows.collect()
+    })?;
+    let Some(dest) =
+        file_scope::save_dialog(&app_handle, "TMX", &["tmx"], "papertalk-memory.tmx".to_string()).await?
+    else {
+        return Ok(None);
+    };
+    std::fs::write(&dest, render_tmx(&pairs)).map_err(|e| format!("Failed to write {}: {}", dest.display(), e))?;
+    println!("📤 已导出 {} 条翻译记忆: {}", pairs.len(), dest.display());
+    Ok(Some(dest.to_string_lossy().to_string()))
+}
+
+/// 从 TMX 文件导入翻译记忆，已有的原文会被覆盖
+#[tauri::command]
+pub async fn import_tmx(app_handle: tauri::AppHandle) -> Result<Option<TmxImport>, String> {
+    use tauri::Manager;
+
+    let Some(path) = file_scope::pick_file_dialog(&app_handle, "TMX", &["tmx", "xml"]).await? else {
+        return Ok(None);
+    };
+    let content = std::fs::read_to_string(&path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let (pairs, skipped) = parse_tmx(&content);
+
+    // 整个文件在一个事务中写入，要么全部导入要么都不导入
+    app_handle.state::<Storage>().with_conn(|conn| {
+        let tx = conn.unchecked_transaction()?;
+        for (source, target) in &pairs {
+            upsert_pair(&tx, source, target, None)?;
+        }
+        tx.commit()
+    })?;
+    println!("📥 从 {} 导入 {} 条翻译记忆，跳过 {} 条", path.display(), pairs.len(), skipped);
+    Ok(Some(TmxImport {
+        imported: pairs.len(),
+        skipped,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn count(storage: &Storage) -> i64 {
+        storage
+            .with_conn(|conn| conn.query_row("SELECT COUNT(*) FROM translation_memory", [], |row| row.get(0)))
+            .unwrap()
+    }
+
+    #[test]
+    fn edit_similarity_is_normalised_by_the_longer_string() {
+        assert_eq!(edit_similarity("", ""), 1.0);
+        assert_eq!(edit_similarity("attention", "attention"), 1.0);
+        assert_eq!(edit_similarity("abc", ""), 0.0);
+        assert!((edit_similarity("kitten", "sitting") - (1.0 - 3.0 / 7.0)).abs() < 1e-9);
+        assert!((edit_similarity("注意力机制", "注意机制") - 0.8).abs() < 1e-9);
+    }
+
+    #[test]
+    fn parse_tmx_picks_languages_and_unescapes() {
+        let tmx = r#"<?xml version="1.0"?>
+<tmx version="1.4"><header srclang="en"/><body>
+  <tu><tuv xml:lang="zh-CN"><seg>注意力 &amp; 记忆</seg></tuv><tuv xml:lang="EN-US"><seg>Attention &amp; <bpt i="1">&lt;b&gt;</bpt>memory</seg></tuv></tu>
+  <tu><tuv><seg>first</seg></tuv><tuv><seg>第一</seg></tuv></tu>
+  <tu><tuv lang="en"><seg>orphan</seg></tuv></tu>
+  <tu><tuv xml:lang="en"><seg>  </seg></tuv><tuv xml:lang="zh"><seg>空</seg></tuv></tu>
+</body></tmx>"#;
+        let (pairs, skipped) = parse_tmx(tmx);
+        assert_eq!(
+            pairs,
+            vec![
+                ("Attention & memory".to_string(), "注意力 & 记忆".to_string()),
+                ("first".to_string(), "第一".to_string()),
+            ]
+        );
+        assert_eq!(skipped, 2);
+
+        // 导出再导入得到相同的句对
+        let exported = render_tmx(&[("a < b \"q\"".to_string(), "甲 < 乙".to_string(), 0)]);
+        assert_eq!(parse_tmx(&exported).0, vec![("a < b \"q\"".to_string(), "甲 < 乙".to_string())]);
+    }
+
+    #[test]
+    fn remember_aligns_sentences_when_counts_match() {
+        let storage = Storage::open_in_memory().unwrap();
+        let saved = remember(
+            &storage,
+            "The model is small. It trains fast.",
+            "模型很小。训练很快。",
+            Some("doc"),
+        )
+        .unwrap();
+        assert_eq!(saved, 3);
+        let matches = lookup(&storage, "It trains fast", 5).unwrap();
+        assert_eq!(matches[0].target, "训练很快。");
+        assert!(matches[0].exact);
+
+        // 句数对不上时只保存整段
+        let saved = remember(&storage, "One. Two. Three.", "一二。三。", None).unwrap();
+        assert_eq!(saved, 1);
+        assert_eq!(count(&storage), 4);
+
+        // 同一原文再次保存时覆盖译文
+        remember(&storage, "It trains fast.", "训练速度很快。", None).unwrap();
+        assert_eq!(count(&storage), 4);
+        assert_eq!(lookup(&storage, "it trains fast", 1).unwrap()[0].target, "训练速度很快。");
+    }
+
+    #[test]
+    fn lookup_finds_similar_sentences_through_the_index() {
+        let storage = Storage::open_in_memory().unwrap();
+        remember(&storage, "The transformer uses multi-head attention.", "Transformer 使用多头注意力。", None).unwrap();
+        remember(&storage, "Convolutional networks share weights.", "卷积网络共享权重。", None).unwrap();
+
+        let matches = lookup(&storage, "The transformer uses multi-head self-attention.", 5).unwrap();
+        assert_eq!(matches.len(), 1);
+        assert!(!matches[0].exact);
+        assert!(matches[0].score >= MIN_SCORE);
+        assert!(lookup(&storage, "Completely unrelated text here", 5).unwrap().is_empty());
+
+        // 删除后索引同步
+        storage
+            .with_conn(|conn| conn.execute("DELETE FROM translation_memory", []))
+            .unwrap();
+        assert!(lookup(&storage, "The transformer uses multi-head attention.", 5).unwrap().is_empty());
+    }
+}
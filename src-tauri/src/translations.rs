@@ -0,0 +1,144 @@
+// 翻译记录：保存每次划词翻译的原文与译文
+//
+// 关联到文档的翻译同时写入全文索引，导出笔记时一并列出。
+
+use rusqlite::params;
+use serde::Serialize;
+
+use crate::providers::Completion;
+use crate::search;
+use crate::storage::{now_secs, Storage};
+
+pub const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS translations (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    document_id TEXT,
+    source TEXT NOT NULL,
+    translation TEXT NOT NULL,
+    provider TEXT NOT NULL,
+    model TEXT NOT NULL,
+    created_at INTEGER NOT NULL,
+    reasoning TEXT
+);
+CREATE INDEX IF NOT EXISTS idx_translations_document ON translations(document_id, created_at);
+";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TranslationRecord {
+    pub id: i64,
+    pub document_id: Option<String>,
+    pub source: String,
+    pub translation: String,
+    pub provider: String,
+    pub model: String,
+    pub created_at: i64,
+    // 推理模型的思考过程，未开启展示时为空
+    pub reasoning: Option<String>,
+}
+
+pub fn record_translation(
+    storage: &Storage,
+    document_id: Option<&str>,
+    source: &str,
+    completion: &Completion,
+) -> Result<i64, String> {
+    let source = source.trim();
+    let translation = completion.content.trim();
+    if source.is_empty() || translation.is_empty() {
+        return Err("Empty translation".to_string());
+    }
+
+    let reasoning = Some(completion.reasoning.trim()).filter(|r| !r.is_empty());
+    let id = storage.with_conn(|conn| {
+        conn.execute(
+            "INSERT INTO translations (document_id, source, translation, provider, model, created_at, reasoning)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![document_id, source, translation, completion.provider, completion.model, now_secs(), reasoning],
+        )?;
+        Ok(conn.last_insert_rowid())
+    })?;
+
+    index_translation(storage, id, document_id, source, translation)?;
+    Ok(id)
+}
+
+/// 用新的译文改写已有记录，记录不存在时返回 None
+pub fn update_translation(
+    storage: &Storage,
+    id: i64,
+    document_id: Option<&str>,
+    source: &str,
+    completion: &Completion,
+) -> Result<Option<i64>, String> {
+    let source = source.trim();
+    let translation = completion.content.trim();
+    if source.is_empty() || translation.is_empty() {
+        return Err("Empty translation".to_string());
+    }
+
+    let reasoning = Some(completion.reasoning.trim()).filter(|r| !r.is_empty());
+    let changed = storage.with_conn(|conn| {
+        conn.execute(
+            "UPDATE translations SET translation = ?1, provider = ?2, model = ?3, created_at = ?4, reasoning = ?5
+             WHERE id = ?6",
+            params![translation, completion.provider, completion.model, now_secs(), reasoning, id],
+        )
+    })?;
+    if changed == 0 {
+        return Ok(None);
+    }
+    index_translation(storage, id, document_id, source, translation)?;
+    Ok(Some(id))
+}
+
+// 原文和译文一起进入全文索引，同一条记录的旧索引会被替换
+fn index_translation(
+    storage: &Storage,
+    id: i64,
+    document_id: Option<&str>,
+    source: &str,
+    translation: &str,
+) -> Result<(), String> {
+    let Some(document_id) = document_id else {
+        return Ok(());
+    };
+    search::index_text(
+        storage,
+        document_id,
+        "translation",
+        Some(&id.to_string()),
+        None,
+        &format!("{}\n{}", source, translation),
+    )
+}
+
+/// 文档的翻译记录，同一原文只保留最近一次
+pub fn document_translations(storage: &Storage, document_id: &str) -> Result<Vec<TranslationRecord>, String> {
+    storage.with_conn(|conn| {
+        let mut stmt = conn.prepare(
+            "SELECT id, document_id, source, translation, provider, model, created_at, reasoning
+             FROM translations t
+             WHERE document_id = ?1
+               AND id = (SELECT MAX(id) FROM translations WHERE document_id = t.document_id AND source = t.source)
+             ORDER BY created_at, id",
+        )?;
+        let rows = stmt.query_map(params![document_id], |row| {
+            Ok(TranslationRecord {
+                id: row.get(0)?,
+                document_id: row.get(1)?,
+                source: row.get(2)?,
+                translation: row.get(3)?,
+                provider: row.get(4)?,
+                model: row.get(5)?,
+                created_at: row.get(6)?,
+                reasoning: row.get(7)?,
+            })
+        })?;
+        rows.collect()
+    })
+}
+
+#[tauri::command]
+pub fn list_translations(document_id: String, storage: tauri::State<'_, Storage>) -> Result<Vec<TranslationRecord>, String> {
+    document_translations(&storage, &document_id)
+}
@@ -0,0 +1,510 @@
+// Token 用量与费用统计
+//
+// 每次调用结束后记录一条用量，费用按记录时的价格表计算，
+// 之后修改价格不会改写历史记录。
+
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+
+use crate::providers::Completion;
+use crate::storage::{now_secs, Storage};
+use crate::text::is_cjk_or_fullwidth;
+
+pub const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS model_prices (
+    provider TEXT NOT NULL,
+    model TEXT NOT NULL,
+    input_per_million REAL NOT NULL,
+    output_per_million REAL NOT NULL,
+    currency TEXT NOT NULL,
+    PRIMARY KEY (provider, model)
+);
+CREATE TABLE IF NOT EXISTS usage_records (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    created_at INTEGER NOT NULL,
+    document_id TEXT,
+    provider TEXT NOT NULL,
+    model TEXT NOT NULL,
+    kind TEXT NOT NULL,
+    prompt_tokens INTEGER NOT NULL,
+    completion_tokens INTEGER NOT NULL,
+    estimated INTEGER NOT NULL DEFAULT 0,
+    cost REAL NOT NULL,
+    currency TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS idx_usage_records_created_at ON usage_records(created_at);
+CREATE INDEX IF NOT EXISTS idx_usage_records_document ON usage_records(document_id);
+";
+
+// 首次启动时写入的默认价格（元 / 百万 token），用户可以随时修改
+const DEFAULT_PRICES: &[(&str, &str, f64, f64)] = &[
+    ("deepseek", "deepseek-chat", 2.0, 8.0),
+    ("deepseek", "deepseek-reasoner", 4.0, 16.0),
+    ("kimi", "kimi-k2-0905-preview", 4.0, 16.0),
+];
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Usage {
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    // 服务没有返回用量时按文本长度估算
+    #[serde(default)]
+    pub estimated: bool,
+}
+
+impl Usage {
+    /// Ollama 最后一行 NDJSON 中的 prompt_eval_count / eval_count
+    pub fn from_ollama(prompt_eval_count: Option<u64>, eval_count: Option<u64>) -> Option<Self> {
+        if prompt_eval_count.is_none() && eval_count.is_none() {
+            return None;
+        }
+        Some(Usage {
+            prompt_tokens: prompt_eval_count.unwrap_or(0),
+            completion_tokens: eval_count.unwrap_or(0),
+            estimated: false,
+        })
+    }
+
+    /// OpenAI 兼容接口的 usage 对象；DeepSeek 放在顶层，Kimi 放在 choices[0] 中
+    pub fn from_openai_chunk(json: &serde_json::Value) -> Option<Self> {
+        let usage = json
+            .get("usage")
+            .filter(|u| !u.is_null())
+            .or_else(|| {
+                json.get("choices")
+                    .and_then(|c| c.get(0))
+                    .and_then(|c| c.get("usage"))
+                    .filter(|u| !u.is_null())
+            })?;
+        Some(Usage {
+            prompt_tokens: usage.get("prompt_tokens").and_then(|v| v.as_u64()).unwrap_or(0),
+            completion_tokens: usage.get("completion_tokens").and_then(|v| v.as_u64()).unwrap_or(0),
+            estimated: false,
+        })
+    }
+
+    pub fn estimate(model: &str, prompt: &str, completion: &str) -> Self {
+        Usage {
+            prompt_tokens: estimate_tokens_for(model, prompt),
+            completion_tokens: estimate_tokens_for(model, completion),
+            estimated: true,
+        }
+    }
+}
+
+/// 粗略的 token 估算：汉字按 1 个 token，其余字符按 4 个字符 1 个 token
+pub fn estimate_tokens(text: &str) -> u64 {
+    let mut cjk = 0u64;
+    let mut other = 0u64;
+    for c in text.chars() {
+        if is_cjk_or_fullwidth(c) {
+            cjk += 1;
+        } else {
+            other += 1;
+        }
+    }
+    cjk + other.div_ceil(4)
+}
+
+// 各模型分词器每个汉字、每个其他字符对应的 token 数，按模型名关键字匹配
+const TOKEN_RATIOS: &[(&str, f64, f64)] = &[
+    // DeepSeek 官方说明：1 个中文字符约 0.6 token，1 个英文字符约 0.3 token
+    ("deepseek", 0.6, 0.3),
+    ("moonshot", 0.6, 0.27),
+    ("kimi", 0.6, 0.27),
+    ("qwen", 0.7, 0.25),
+    ("glm", 0.7, 0.25),
+    // Llama、Mistral、Gemma 的词表中文较少，一个汉字常被拆成多个 token
+    ("llama", 1.3, 0.25),
+    ("mistral", 1.5, 0.27),
+    ("gemma", 1.0, 0.25),
+];
+
+/// 按模型的分词器估算 token 数，未知模型退回 estimate_tokens
+pub fn estimate_tokens_for(model: &str, text: &str) -> u64 {
+    let model = model.to_lowercase();
+    let Some((_, cjk_ratio, other_ratio)) = TOKEN_RATIOS.iter().find(|(key, _, _)| model.contains(key)) else {
+        return estimate_tokens(text);
+    };
+    let cjk = text.chars().filter(|c| is_cjk_or_fullwidth(*c)).count() as f64;
+    let other = text.chars().count() as f64 - cjk;
+    (cjk * cjk_ratio + other * other_ratio).ceil() as u64
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelPrice {
+    pub provider: String,
+    pub model: String,
+    pub input_per_million: f64,
+    pub output_per_million: f64,
+    pub currency: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct UsageReportRow {
+    // 分组键：文档 ID、日期（YYYY-MM-DD）或服务名
+    pub key: String,
+    pub requests: u64,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub cost: f64,
+    pub currency: String,
+}
+
+pub fn seed_default_prices(storage: &Storage) -> Result<(), String> {
+    storage.with_conn(|conn| {
+        for (provider, model, input, output) in DEFAULT_PRICES {
+            conn.execute(
+                "INSERT OR IGNORE INTO model_prices (provider, model, input_per_million, output_per_million, currency)
+                 VALUES (?1, ?2, ?3, ?4, 'CNY')",
+                params![provider, model, input, output],
+            )?;
+        }
+        Ok(())
+    })
+}
+
+pub fn list_prices(storage: &Storage) -> Result<Vec<ModelPrice>, String> {
+    storage.with_conn(|conn| {
+        let mut stmt = conn.prepare(
+            "SELECT provider, model, input_per_million, output_per_million, currency
+             FROM model_prices ORDER BY provider, model",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok(ModelPrice {
+                provider: row.get(0)?,
+                model: row.get(1)?,
+                input_per_million: row.get(2)?,
+                output_per_million: row.get(3)?,
+                currency: row.get(4)?,
+            })
+        })?;
+        rows.collect()
+    })
+}
+
+pub fn upsert_price(storage: &Storage, price: &ModelPrice) -> Result<(), String> {
+    storage.with_conn(|conn| {
+        conn.execute(
+            "INSERT INTO model_prices (provider, model, input_per_million, output_per_million, currency)
+             VALUES (?1, ?2, ?3, ?4, ?5)
+             ON CONFLICT(provider, model) DO UPDATE SET
+                input_per_million = excluded.input_per_million,
+                output_per_million = excluded.output_per_million,
+                currency = excluded.currency",
+            params![
+                price.provider,
+                price.model,
+                price.input_per_million,
+                price.output_per_million,
+                price.currency
+            ],
+        )?;
+        Ok(())
+    })
+}
+
+pub fn delete_price(storage: &Storage, provider: &str, model: &str) -> Result<(), String> {
+    storage.with_conn(|conn| {
+        conn.execute(
+            "DELETE FROM model_prices WHERE provider = ?1 AND model = ?2",
+            params![provider, model],
+        )?;
+        Ok(())
+    })
+}
+
+fn find_price(conn: &Connection, provider: &str, model: &str) -> rusqlite::Result<Option<ModelPrice>> {
+    conn.query_row(
+        "SELECT provider, model, input_per_million, output_per_million, currency
+         FROM model_prices WHERE provider = ?1 AND model = ?2",
+        params![provider, model],
+        |row| {
+            Ok(ModelPrice {
+                provider: row.get(0)?,
+                model: row.get(1)?,
+                input_per_million: row.get(2)?,
+                output_per_million: row.get(3)?,
+                currency: row.get(4)?,
+            })
+        },
+    )
+    .optional()
+}
+
+/// 记录一次调用的用量；没有价格的模型（例如本地 Ollama）费用记为 0
+pub fn record_usage(
+    storage: &Storage,
+    document_id: Option<&str>,
+    provider: &str,
+    model: &str,
+    kind: &str,
+    usage: &Usage,
+) -> Result<(), String> {
+    storage.with_conn(|conn| {
+        let (cost, currency) = match find_price(conn, provider, model)? {
+            Some(price) => (
+                (usage.prompt_tokens as f64 * price.input_per_million
+                    + usage.completion_tokens as f64 * price.output_per_million)
+                    / 1_000_000.0,
+                price.currency,
+            ),
+            None => (0.0, "CNY".to_string()),
+        };
+        conn.execute(
+            "INSERT INTO usage_records
+                (created_at, document_id, provider, model, kind, prompt_tokens, completion_tokens, estimated, cost, currency)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+            params![
+                now_secs(),
+                document_id,
+                provider,
+                model,
+                kind,
+                usage.prompt_tokens as i64,
+                usage.completion_tokens as i64,
+                usage.estimated,
+                cost,
+                currency
+            ],
+        )?;
+        Ok(())
+    })
+}
+
+/// 记录一次调用结束后的用量；用量已在 stream_completion 中解析或估算，
+/// 记录失败只打印日志，不影响调用结果
+pub fn record_completion(storage: &Storage, document_id: Option<&str>, kind: &str, completion: &Completion) {
+    let Some(usage) = &completion.usage else {
+        return;
+    };
+    if let Err(e) = record_usage(storage, document_id, &completion.provider, &completion.model, kind, usage) {
+        println!("⚠️ 记录用量失败：{}", e);
+    }
+}
+
+/// 按文档、日期或服务汇总用量；since/until 为 Unix 秒，可选
+pub fn usage_report(
+    storage: &Storage,
+    group_by: &str,
+    since: Option<i64>,
+    until: Option<i64>,
+) -> Result<Vec<UsageReportRow>, String> {
+    let key_expr = match group_by {
+        "document" => "COALESCE(document_id, '')",
+        "day" => "date(created_at, 'unixepoch', 'localtime')",
+        "provider" => "provider",
+        "model" => "provider || '/' || model",
+        _ => return Err(format!("Unknown usage grouping: {}", group_by)),
+    };
+    let sql = format!(
+        "SELECT {key} AS k, COUNT(*), SUM(prompt_tokens), SUM(completion_tokens), SUM(cost), currency
+         FROM usage_records
+         WHERE created_at >= ?1 AND created_at <= ?2
+         GROUP BY k, currency
+         ORDER BY k",
+        key = key_expr
+    );
+
+    storage.with_conn(|conn| {
+        let mut stmt = conn.prepare(&sql)?;
+        let rows = stmt.query_map(params![since.unwrap_or(0), until.unwrap_or(i64::MAX)], |row| {
+            Ok(UsageReportRow {
+                key: row.get(0)?,
+                requests: row.get::<_, i64>(1)? as u64,
+                prompt_tokens: row.get::<_, i64>(2)? as u64,
+                completion_tokens: row.get::<_, i64>(3)? as u64,
+                cost: row.get(4)?,
+                currency: row.get(5)?,
+            })
+        })?;
+        rows.collect()
+    })
+}
+
+#[tauri::command]
+pub fn get_usage_report(
+    group_by: String,
+    since: Option<i64>,
+    until: Option<i64>,
+    storage: tauri::State<'_, Storage>,
+) -> Result<Vec<UsageReportRow>, String> {
+    usage_report(&storage, &group_by, since, until)
+}
+
+#[tauri::command]
+pub fn get_price_table(storage: tauri::State<'_, Storage>) -> Result<Vec<ModelPrice>, String> {
+    list_prices(&storage)
+}
+
+#[tauri::command]
+pub fn set_model_price(price: ModelPrice, storage: tauri::State<'_, Storage>) -> Result<(), String> {
+    upsert_price(&storage, &price)
+}
+
+#[tauri::command]
+pub fn delete_model_price(provider: String, model: String, storage: tauri::State<'_, Storage>) -> Result<(), String> {
+    delete_price(&storage, &provider, &model)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn usage(prompt_tokens: u64, completion_tokens: u64) -> Usage {
+        Usage {
+            prompt_tokens,
+            completion_tokens,
+            estimated: false,
+        }
+    }
+
+    fn storage() -> Storage {
+        let storage = Storage::open_in_memory().unwrap();
+        seed_default_prices(&storage).unwrap();
+        storage
+    }
+
+    fn recorded_costs(storage: &Storage) -> Vec<(String, f64, String)> {
+        storage
+            .with_conn(|conn| {
+                let mut stmt = conn.prepare("SELECT model, cost, currency FROM usage_records ORDER BY id")?;
+                let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?;
+                rows.collect()
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn record_usage_prices_known_models_and_zeroes_unknown_ones() {
+        let storage = storage();
+        upsert_price(
+            &storage,
+            &ModelPrice {
+                provider: "openai".to_string(),
+                model: "gpt-4o".to_string(),
+                input_per_million: 2.5,
+                output_per_million: 10.0,
+                currency: "USD".to_string(),
+            },
+        )
+        .unwrap();
+
+        record_usage(&storage, None, "deepseek", "deepseek-chat", "chat", &usage(1_000, 500)).unwrap();
+        record_usage(&storage, None, "openai", "gpt-4o", "chat", &usage(2_000_000, 100_000)).unwrap();
+        record_usage(&storage, None, "ollama", "llama3", "chat", &usage(5_000, 5_000)).unwrap();
+
+        let costs = recorded_costs(&storage);
+        assert_eq!(costs.len(), 3);
+        // 1000 × 2 + 500 × 8 = 6000 元 / 百万 token
+        assert!((costs[0].1 - 0.006).abs() < 1e-9, "{:?}", costs[0]);
+        assert_eq!(costs[0].2, "CNY");
+        assert!((costs[1].1 - 6.0).abs() < 1e-9, "{:?}", costs[1]);
+        assert_eq!(costs[1].2, "USD");
+        assert_eq!((costs[2].1, costs[2].2.as_str()), (0.0, "CNY"));
+
+        // 改价不会改写已有记录
+        delete_price(&storage, "deepseek", "deepseek-chat").unwrap();
+        record_usage(&storage, None, "deepseek", "deepseek-chat", "chat", &usage(1_000, 500)).unwrap();
+        let costs = recorded_costs(&storage);
+        assert!((costs[0].1 - 0.006).abs() < 1e-9);
+        assert_eq!(costs[3].1, 0.0);
+    }
+
+    #[test]
+    fn usage_report_groups_records() {
+        let storage = storage();
+        record_usage(&storage, Some("doc-a"), "deepseek", "deepseek-chat", "translation", &usage(100, 10)).unwrap();
+        record_usage(&storage, Some("doc-a"), "kimi", "kimi-k2-0905-preview", "chat", &usage(200, 20)).unwrap();
+        record_usage(&storage, None, "deepseek", "deepseek-reasoner", "chat", &usage(300, 30)).unwrap();
+
+        let keys = |group_by: &str| -> Vec<(String, u64, u64, u64)> {
+            usage_report(&storage, group_by, None, None)
+                .unwrap()
+                .into_iter()
+                .map(|row| (row.key, row.requests, row.prompt_tokens, row.completion_tokens))
+                .collect()
+        };
+        assert_eq!(
+            keys("document"),
+            vec![("".to_string(), 1, 300, 30), ("doc-a".to_string(), 2, 300, 30)]
+        );
+        assert_eq!(
+            keys("provider"),
+            vec![("deepseek".to_string(), 2, 400, 40), ("kimi".to_string(), 1, 200, 20)]
+        );
+        assert_eq!(
+            keys("model"),
+            vec![
+                ("deepseek/deepseek-chat".to_string(), 1, 100, 10),
+                ("deepseek/deepseek-reasoner".to_string(), 1, 300, 30),
+                ("kimi/kimi-k2-0905-preview".to_string(), 1, 200, 20),
+            ]
+        );
+        let days = keys("day");
+        assert_eq!(days.len(), 1);
+        assert_eq!(days[0].1, 3);
+
+        // 时间范围过滤
+        let future = now_secs() + 3600;
+        assert!(usage_report(&storage, "provider", Some(future), None).unwrap().is_empty());
+        assert!(usage_report(&storage, "provider", None, Some(0)).unwrap().is_empty());
+
+        let err = usage_report(&storage, "kind; DROP TABLE usage_records", None, None).unwrap_err();
+        assert!(err.starts_with("Unknown usage grouping"), "{}", err);
+    }
+
+    #[test]
+    fn openai_usage_is_read_from_top_level_or_first_choice() {
+        let cases = [
+            // DeepSeek：顶层 usage
+            (
+                json!({"choices": [], "usage": {"prompt_tokens": 12, "completion_tokens": 34}}),
+                Some((12, 34)),
+            ),
+            // Kimi：choices[0].usage
+            (
+                json!({"choices": [{"delta": {}, "usage": {"prompt_tokens": 5, "completion_tokens": 6}}]}),
+                Some((5, 6)),
+            ),
+            // 顶层为 null 时看 choices[0]
+            (
+                json!({"usage": null, "choices": [{"usage": {"prompt_tokens": 7, "completion_tokens": 8}}]}),
+                Some((7, 8)),
+            ),
+            (json!({"usage": null, "choices": [{"usage": null}]}), None),
+            (json!({"choices": [{"delta": {"content": "hi"}}]}), None),
+            (json!({"usage": {"prompt_tokens": 9}}), Some((9, 0))),
+        ];
+        for (chunk, expected) in cases {
+            let parsed = Usage::from_openai_chunk(&chunk);
+            assert_eq!(parsed.as_ref().map(|u| (u.prompt_tokens, u.completion_tokens)), expected, "{}", chunk);
+            assert!(parsed.is_none_or(|u| !u.estimated));
+        }
+    }
+
+    #[test]
+    fn token_estimates_follow_the_model_tokenizer() {
+        let cases = [
+            // 未知模型：汉字 1 个 token，其余 4 个字符 1 个 token
+            ("gpt-4o", "", 0),
+            ("gpt-4o", "你好world", 2 + 2),
+            ("gpt-4o", "abcd", 1),
+            // DeepSeek：2 × 0.6 + 5 × 0.3 = 2.7
+            ("deepseek-chat", "你好world", 3),
+            ("DeepSeek-Reasoner", "你好", 2),
+            // Llama：汉字 1.3
+            ("llama3.1:8b", "中文", 3),
+            ("qwen2.5:7b", "中文", 2),
+            ("kimi-k2-0905-preview", "，。", 2),
+        ];
+        for (model, text, expected) in cases {
+            assert_eq!(estimate_tokens_for(model, text), expected, "{} {:?}", model, text);
+        }
+
+        let estimated = Usage::estimate("deepseek-chat", "你好world", "你好");
+        assert_eq!((estimated.prompt_tokens, estimated.completion_tokens), (3, 2));
+        assert!(estimated.estimated);
+    }
+}
@@ -0,0 +1,112 @@
+// 图片区域的翻译与讲解：阅读器截取的图、表、公式交给多模态模型
+//
+// 输出复用划词翻译的 translation_chunk / translation_complete 事件，
+// 前端的翻译面板不需要区分来源。
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde::Deserialize;
+
+use crate::providers::{Completion, CompletionRequest, ModelConfig};
+use crate::scheduler::Priority;
+use crate::{run_completion, StreamEvents};
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+// 超过这个大小的截图多半是误操作，模型服务也会拒绝
+const MAX_IMAGE_BYTES: usize = 10 * 1024 * 1024;
+
+const VISION_SYSTEM_PROMPT: &str = "你是一个专业的学术助手，擅长阅读论文中的图表和公式。";
+const TRANSLATE_REGION_PROMPT: &str = "图片截取自一篇论文（可能是图、表格或公式）。请把其中的文字翻译成中文：\
+表格用 Markdown 表格输出并保持行列结构，公式用 LaTeX 表示，专业术语保持不变。只输出翻译结果。";
+const EXPLAIN_REGION_PROMPT: &str = "图片截取自一篇论文（可能是图、表格或公式）。请用中文说明它展示了什么：\
+坐标轴、图例或表头的含义，主要的数据趋势或关键结果，以及它支撑了论文的什么结论。";
+
+const VISION_EVENTS: StreamEvents = StreamEvents {
+    kind: "vision",
+    chunk: "translation_chunk",
+    reasoning: "reasoning_chunk",
+    complete: "translation_complete",
+};
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RegionMode {
+    #[default]
+    Translate,
+    Explain,
+}
+
+/// 校验阅读器截取的 PNG（base64，可带 data URL 前缀），返回模型接口需要的 base64
+pub fn png_base64(image: &str) -> Result<String, String> {
+    let encoded = image.trim();
+    let encoded = encoded
+        .strip_prefix("data:image/png;base64,")
+        .unwrap_or(encoded);
+    // 按编码长度判断大小，不必先解码
+    if encoded.len() > MAX_IMAGE_BYTES.div_ceil(3) * 4 {
+        return Err(format!("Region image is too large ({} bytes)", encoded.len() / 4 * 3));
+    }
+    let bytes = STANDARD
+        .decode(encoded)
+        .map_err(|e| format!("Region image is not valid base64: {}", e))?;
+    if !bytes.starts_with(PNG_SIGNATURE) {
+        return Err("Region image must be a PNG".to_string());
+    }
+    Ok(encoded.to_string())
+}
+
+/// 翻译或讲解阅读器中框选的页面区域，image 为 PNG 文件内容的 base64
+#[tauri::command]
+pub async fn translate_region(
+    image: String,
+    mode: Option<RegionMode>,
+    config: ModelConfig,
+    show_reasoning: Option<bool>,
+    document_id: Option<String>,
+    priority: Option<Priority>,
+    app_handle: tauri::AppHandle,
+) -> Result<Completion, String> {
+    let encoded = png_base64(&image)?;
+    let mode = mode.unwrap_or_default();
+    println!(
+        "🖼️ 收到图片区域请求：{:?}，约 {} 字节，模型：{}",
+        mode,
+        encoded.len() / 4 * 3,
+        config.selected_model
+    );
+
+    let prompt = match mode {
+        RegionMode::Translate => TRANSLATE_REGION_PROMPT,
+        RegionMode::Explain => EXPLAIN_REGION_PROMPT,
+    };
+    let request = CompletionRequest {
+        system: VISION_SYSTEM_PROMPT.to_string(),
+        prompt: prompt.to_string(),
+        images: vec![encoded],
+        show_reasoning: show_reasoning.unwrap_or(false),
+        priority: priority.unwrap_or_default(),
+    };
+    run_completion(&config, &request, &VISION_EVENTS, document_id.as_deref(), &app_handle).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn png_base64_validates_the_image() {
+        let png = [PNG_SIGNATURE, b"IHDR"].concat();
+        let encoded = STANDARD.encode(&png);
+        assert_eq!(png_base64(&encoded).unwrap(), encoded);
+        // 前端可能直接传 data URL
+        assert_eq!(png_base64(&format!("data:image/png;base64,{}", encoded)).unwrap(), encoded);
+
+        let jpeg = STANDARD.encode(b"\xff\xd8\xff\xe0JFIF");
+        assert_eq!(png_base64(&jpeg).unwrap_err(), "Region image must be a PNG");
+        assert!(png_base64("not base64!").unwrap_err().starts_with("Region image is not valid base64"));
+        assert!(png_base64("").is_err());
+
+        let too_large = "A".repeat(MAX_IMAGE_BYTES.div_ceil(3) * 4 + 4);
+        assert!(png_base64(&too_large).unwrap_err().starts_with("Region image is too large"));
+    }
+}
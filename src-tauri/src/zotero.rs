@@ -0,0 +1,1132 @@
+// Zotero 集成：导入 Zotero 文献库 / BibTeX / CSL-JSON，导出笔记为 RIS
+//
+// zotero.sqlite 以只读、immutable 方式打开，Zotero 运行中（数据库被锁）也能读取，
+// 且绝不会写入。条目附带的 PDF 加入文献库并套用 Zotero 的元数据和分类；
+// 没有 PDF 的条目按 DOI 或标题匹配已有文档。
+// 笔记导出为 RIS，Zotero 导入时会把 N1 字段作为子笔记。
+
+use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
+use regex::Regex;
+use rusqlite::{params, Connection, OpenFlags};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+
+use crate::annotations;
+use crate::file_scope::{self, FileScope};
+use crate::library::{self, ExternalMetadata};
+use crate::pdf_text::PdfTextCache;
+use crate::references::{find_arxiv_id, find_doi};
+use crate::storage::{now_secs, Storage};
+
+pub const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS external_items (
+    source TEXT NOT NULL,
+    item_key TEXT NOT NULL,
+    document_id TEXT NOT NULL,
+    imported_at INTEGER NOT NULL,
+    PRIMARY KEY (source, item_key)
+);
+CREATE INDEX IF NOT EXISTS idx_external_items_document ON external_items(document_id);
+";
+
+// SQLite URI 中需要转义的字符
+const URI_PATH: &AsciiSet = &CONTROLS.add(b' ').add(b'#').add(b'?').add(b'%');
+
+static YEAR_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\b(1[89]\d{2}|20\d{2})\b").unwrap());
+static AUTHOR_SPLIT_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\s+and\s+").unwrap());
+static LATEX_ACCENT_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"\{?\\([\x22'`^~])\{?([A-Za-z])\}?\}?"#).unwrap());
+static LATEX_COMMAND_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\\[a-zA-Z]+\s*").unwrap());
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct LibraryImport {
+    // zotero、bibtex 或 csl-json
+    pub source: String,
+    pub items: usize,
+    // 通过 PDF 加入文献库的文档数
+    pub documents: usize,
+    // 没有 PDF、但匹配到已有文档并更新了元数据的条目数
+    pub matched: usize,
+    pub collections: usize,
+    pub missing_files: usize,
+    pub unmatched: usize,
+}
+
+struct ImportedItem {
+    key: String,
+    meta: ExternalMetadata,
+    pdfs: Vec<PathBuf>,
+    collections: Vec<String>,
+}
+
+struct ImportedCollection {
+    id: String,
+    name: String,
+    parent_id: Option<String>,
+}
+
+struct ImportSource {
+    source: &'static str,
+    items: Vec<ImportedItem>,
+    collections: Vec<ImportedCollection>,
+}
+
+fn year_of(text: &str) -> Option<String> {
+    YEAR_RE.captures(text).map(|c| c[1].to_string())
+}
+
+fn non_empty(value: Option<String>) -> Option<String> {
+    value.map(|v| v.trim().to_string()).filter(|v| !v.is_empty())
+}
+
+/* ---------------------------------
+ * Zotero 数据库
+ * --------------------------------- */
+
+fn open_read_only(path: &Path) -> Result<Connection, String> {
+    let mut raw = path.to_string_lossy().replace('\\', "/");
+    if !raw.starts_with('/') {
+        raw.insert(0, '/');
+    }
+    let uri = format!("file://{}?immutable=1", utf8_percent_encode(&raw, URI_PATH));
+    Connection::open_with_flags(
+        uri,
+        OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_URI | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+    )
+    .map_err(|e| format!("Failed to open Zotero database: {}", e))
+}
+
+/// 附件路径："storage:文件名" 在数据目录的 storage/<附件 key>/ 下，链接文件为绝对路径
+fn attachment_path(data_dir: &Path, attachment_key: &str, path: &str, link_mode: i64) -> Option<PathBuf> {
+    // 3 = 链接网址，没有本地文件
+    if link_mode == 3 || path.is_empty() {
+        return None;
+    }
+    if let Some(name) = path.strip_prefix("storage:") {
+        return Some(data_dir.join("storage").join(attachment_key).join(name));
+    }
+    // "attachments:" 相对于 Zotero 首选项中的链接附件根目录，这里无从得知
+    if path.starts_with("attachments:") {
+        return None;
+    }
+    Some(PathBuf::from(path))
+}
+
+fn read_zotero(db_path: &Path) -> Result<ImportSource, String> {
+    let conn = open_read_only(db_path)?;
+    let data_dir = db_path.parent().unwrap_or(Path::new("."));
+    let query_error = |e: rusqlite::Error| format!("Not a readable Zotero database: {}", e);
+
+    let mut collection_ids: HashMap<i64, String> = HashMap::new();
+    let mut collections = Vec::new();
+    {
+        let mut stmt = conn
+            .prepare(
+                "SELECT c.collectionID, c.key, c.collectionName, p.key
+                 FROM collections c LEFT JOIN collections p ON p.collectionID = c.parentCollectionID",
+            )
+            .map_err(query_error)?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, Option<String>>(3)?,
+                ))
+            })
+            .map_err(query_error)?;
+        for (id, key, name, parent_key) in rows.filter_map(Result::ok) {
+            let collection_id = format!("zotero:{}", key);
+            collection_ids.insert(id, collection_id.clone());
+            collections.push(ImportedCollection {
+                id: collection_id,
+                name,
+                parent_id: parent_key.map(|k| format!("zotero:{}", k)),
+            });
+        }
+    }
+
+    let parents: Vec<(i64, String)> = {
+        let mut stmt = conn
+            .prepare(
+                "SELECT i.itemID, i.key FROM items i JOIN itemTypes t ON t.itemTypeID = i.itemTypeID
+                 WHERE t.typeName NOT IN ('attachment', 'note', 'annotation')
+                   AND i.itemID NOT IN (SELECT itemID FROM deletedItems)",
+            )
+            .map_err(query_error)?;
+        let rows = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .map_err(query_error)?;
+        rows.filter_map(Result::ok).collect()
+    };
+
+    let mut fields_stmt = conn
+        .prepare(
+            "SELECT f.fieldName, v.value FROM itemData d
+             JOIN fields f ON f.fieldID = d.fieldID
+             JOIN itemDataValues v ON v.valueID = d.valueID
+             WHERE d.itemID = ?1",
+        )
+        .map_err(query_error)?;
+    let mut creators_stmt = conn
+        .prepare(
+            "SELECT c.firstName, c.lastName FROM itemCreators ic JOIN creators c ON c.creatorID = ic.creatorID
+             WHERE ic.itemID = ?1 ORDER BY ic.orderIndex",
+        )
+        .map_err(query_error)?;
+    let mut attachments_stmt = conn
+        .prepare(
+            "SELECT i.key, a.path, a.linkMode FROM itemAttachments a JOIN items i ON i.itemID = a.itemID
+             WHERE a.parentItemID = ?1 AND a.contentType = 'application/pdf'
+               AND a.itemID NOT IN (SELECT itemID FROM deletedItems)",
+        )
+        .map_err(query_error)?;
+    let mut collections_stmt = conn
+        .prepare("SELECT collectionID FROM collectionItems WHERE itemID = ?1")
+        .map_err(query_error)?;
+
+    let mut items = Vec::new();
+    for (item_id, key) in parents {
+        let fields: HashMap<String, String> = fields_stmt
+            .query_map(params![item_id], |row| Ok((row.get(0)?, row.get(1)?)))
+            .map_err(query_error)?
+            .filter_map(Result::ok)
+            .collect();
+        let authors: Vec<String> = creators_stmt
+            .query_map(params![item_id], |row| {
+                Ok((row.get::<_, Option<String>>(0)?, row.get::<_, Option<String>>(1)?))
+            })
+            .map_err(query_error)?
+            .filter_map(Result::ok)
+            .map(|(first, last)| {
+                [first.unwrap_or_default(), last.unwrap_or_default()]
+                    .iter()
+                    .map(|s| s.trim())
+                    .filter(|s| !s.is_empty())
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            })
+            .filter(|name| !name.is_empty())
+            .collect();
+        let pdfs: Vec<PathBuf> = attachments_stmt
+            .query_map(params![item_id], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, Option<String>>(1)?, row.get::<_, i64>(2)?))
+            })
+            .map_err(query_error)?
+            .filter_map(Result::ok)
+            .filter_map(|(attachment_key, path, mode)| {
+                attachment_path(data_dir, &attachment_key, &path.unwrap_or_default(), mode)
+            })
+            .collect();
+        let item_collections: Vec<String> = collections_stmt
+            .query_map(params![item_id], |row| row.get::<_, i64>(0))
+            .map_err(query_error)?
+            .filter_map(Result::ok)
+            .filter_map(|id| collection_ids.get(&id).cloned())
+            .collect();
+
+        // arXiv 编号常写在 extra 或 url 里
+        let hints = format!(
+            "{} {} {}",
+            fields.get("extra").map(String::as_str).unwrap_or(""),
+            fields.get("url").map(String::as_str).unwrap_or(""),
+            fields.get("archiveID").map(String::as_str).unwrap_or("")
+        );
+        items.push(ImportedItem {
+            key,
+            meta: ExternalMetadata {
+                title: non_empty(fields.get("title").cloned()),
+                authors,
+                doi: fields.get("DOI").and_then(|d| find_doi(d)),
+                arxiv_id: find_arxiv_id(&hints),
+                year: fields.get("date").and_then(|d| year_of(d)),
+            },
+            pdfs,
+            collections: item_collections,
+        });
+    }
+
+    // 不属于任何条目的独立 PDF
+    let mut stmt = conn
+        .prepare(
+            "SELECT i.itemID, i.key, a.path, a.linkMode FROM itemAttachments a JOIN items i ON i.itemID = a.itemID
+             WHERE a.parentItemID IS NULL AND a.contentType = 'application/pdf'
+               AND a.itemID NOT IN (SELECT itemID FROM deletedItems)",
+        )
+        .map_err(query_error)?;
+    let standalone: Vec<(i64, String, Option<String>, i64)> = stmt
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)))
+        .map_err(query_error)?
+        .filter_map(Result::ok)
+        .collect();
+    for (item_id, key, path, mode) in standalone {
+        let Some(pdf) = attachment_path(data_dir, &key, &path.unwrap_or_default(), mode) else {
+            continue;
+        };
+        let item_collections = collections_stmt
+            .query_map(params![item_id], |row| row.get::<_, i64>(0))
+            .map_err(query_error)?
+            .filter_map(Result::ok)
+            .filter_map(|id| collection_ids.get(&id).cloned())
+            .collect();
+        items.push(ImportedItem {
+            key,
+            meta: ExternalMetadata::default(),
+            pdfs: vec![pdf],
+            collections: item_collections,
+        });
+    }
+
+    Ok(ImportSource {
+        source: "zotero",
+        items,
+        collections,
+    })
+}
+
+/* ---------------------------------
+ * BibTeX
+ * --------------------------------- */
+
+pub struct BibEntry {
+    pub key: String,
+    pub fields: HashMap<String, String>,
+}
+
+struct BibParser<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl BibParser<'_> {
+    fn peek(&self) -> Option<u8> {
+        self.text.as_bytes().get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(|b| b.is_ascii_whitespace()) {
+            self.pos += 1;
+        }
+    }
+
+    fn read_while(&mut self, f: impl Fn(u8) -> bool) -> &str {
+        let start = self.pos;
+        while self.peek().is_some_and(&f) {
+            self.pos += 1;
+        }
+        &self.text[start..self.pos]
+    }
+
+    /// 读取配对的 {...}，返回不含最外层括号的内容
+    fn read_braced(&mut self) -> Option<String> {
+        let start = self.pos + 1;
+        let mut depth = 0;
+        while let Some(b) = self.peek() {
+            match b {
+                b'{' => depth += 1,
+                b'}' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        self.pos += 1;
+                        return Some(self.text[start..self.pos - 1].to_string());
+                    }
+                }
+                b'\\' => self.pos += 1,
+                _ => {}
+            }
+            self.pos += 1;
+        }
+        // 未闭合（文件被截断）：末尾的 \ 会让 pos 越过结尾，这里收回到结尾
+        self.pos = self.text.len();
+        None
+    }
+
+    fn read_quoted(&mut self) -> Option<String> {
+        let start = self.pos + 1;
+        let mut depth = 0;
+        self.pos += 1;
+        while let Some(b) = self.peek() {
+            match b {
+                b'{' => depth += 1,
+                b'}' => depth -= 1,
+                b'\\' => self.pos += 1,
+                b'"' if depth == 0 => {
+                    self.pos += 1;
+                    return Some(self.text[start..self.pos - 1].to_string());
+                }
+                _ => {}
+            }
+            self.pos += 1;
+        }
+        self.pos = self.text.len();
+        None
+    }
+
+    /// 字段值：{...}、"..." 或裸词，可用 # 连接
+    fn read_value(&mut self, strings: &HashMap<String, String>) -> Option<String> {
+        let mut value = String::new();
+        loop {
+            self.skip_whitespace();
+            match self.peek()? {
+                b'{' => value.push_str(&self.read_braced()?),
+                b'"' => value.push_str(&self.read_quoted()?),
+                _ => {
+                    let word = self.read_while(|b| b.is_ascii_alphanumeric() || matches!(b, b'_' | b'-' | b'.' | b':'));
+                    if word.is_empty() {
+                        return None;
+                    }
+                    let word = word.to_string();
+                    value.push_str(strings.get(&word.to_lowercase()).map(String::as_str).unwrap_or(&word));
+                }
+            }
+            self.skip_whitespace();
+            if self.peek() == Some(b'#') {
+                self.pos += 1;
+            } else {
+                return Some(value);
+            }
+        }
+    }
+
+    fn read_fields(&mut self, strings: &HashMap<String, String>, close: u8) -> HashMap<String, String> {
+        let mut fields = HashMap::new();
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                None => return fields,
+                Some(b',') => {
+                    self.pos += 1;
+                    continue;
+                }
+                Some(b) if b == close => {
+                    self.pos += 1;
+                    return fields;
+                }
+                _ => {}
+            }
+            let name = self
+                .read_while(|b| b.is_ascii_alphanumeric() || matches!(b, b'_' | b'-' | b'.' | b':'))
+                .to_lowercase();
+            self.skip_whitespace();
+            if name.is_empty() || self.peek() != Some(b'=') {
+                // 无法解析的内容，跳到下一个字段
+                self.read_while(|b| b != b',' && b != close);
+                continue;
+            }
+            self.pos += 1;
+            match self.read_value(strings) {
+                Some(value) => {
+                    fields.insert(name, value);
+                }
+                None => return fields,
+            }
+        }
+    }
+}
+
+pub fn parse_bibtex(text: &str) -> Vec<BibEntry> {
+    let mut parser = BibParser { text, pos: 0 };
+    let mut strings: HashMap<String, String> = HashMap::new();
+    let mut entries = Vec::new();
+
+    while let Some(offset) = parser.text.get(parser.pos..).and_then(|rest| rest.find('@')) {
+        parser.pos += offset + 1;
+        let kind = parser.read_while(|b| b.is_ascii_alphanumeric()).to_lowercase();
+        parser.skip_whitespace();
+        let close = match parser.peek() {
+            Some(b'{') => b'}',
+            Some(b'(') => b')',
+            _ => continue,
+        };
+        match kind.as_str() {
+            "comment" | "preamble" => {
+                if close == b'}' {
+                    parser.read_braced();
+                }
+                continue;
+            }
+            "string" => {
+                parser.pos += 1;
+                for (name, value) in parser.read_fields(&strings.clone(), close) {
+                    strings.insert(name, value);
+                }
+                continue;
+            }
+            _ => {}
+        }
+        parser.pos += 1;
+        parser.skip_whitespace();
+        let key = parser.read_while(|b| b != b',' && b != close && !b.is_ascii_whitespace()).to_string();
+        let fields = parser.read_fields(&strings, close);
+        entries.push(BibEntry { key, fields });
+    }
+    entries
+}
+
+fn accented(accent: &str, letter: &str) -> Option<&'static str> {
+    Some(match (accent, letter) {
+        ("\"", "a") => "ä",
+        ("\"", "o") => "ö",
+        ("\"", "u") => "ü",
+        ("\"", "A") => "Ä",
+        ("\"", "O") => "Ö",
+        ("\"", "U") => "Ü",
+        ("\"", "e") => "ë",
+        ("\"", "i") => "ï",
+        ("'", "a") => "á",
+        ("'", "e") => "é",
+        ("'", "i") => "í",
+        ("'", "o") => "ó",
+        ("'", "u") => "ú",
+        ("'", "E") => "É",
+        ("'", "c") => "ć",
+        ("'", "n") => "ń",
+        ("'", "s") => "ś",
+        ("`", "a") => "à",
+        ("`", "e") => "è",
+        ("`", "o") => "ò",
+        ("^", "a") => "â",
+        ("^", "e") => "ê",
+        ("^", "i") => "î",
+        ("^", "o") => "ô",
+        ("~", "n") => "ñ",
+        ("~", "a") => "ã",
+        ("~", "o") => "õ",
+        _ => return None,
+    })
+}
+
+/// 去掉 LaTeX 标记，保留可读文字
+fn clean_latex(text: &str) -> String {
+    let text = LATEX_ACCENT_RE.replace_all(text, |caps: &regex::Captures| {
+        accented(&caps[1], &caps[2]).map(str::to_string).unwrap_or_else(|| caps[2].to_string())
+    });
+    let text = text
+        .replace("\\&", "&")
+        .replace("\\%", "%")
+        .replace("\\_", "_")
+        .replace("\\$", "$")
+        .replace("\\ss", "ß")
+        .replace("---", "—")
+        .replace("--", "–")
+        .replace('~', " ");
+    let text = LATEX_COMMAND_RE.replace_all(&text, "");
+    let text: String = text.chars().filter(|c| !matches!(c, '{' | '}')).collect();
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// "Last, First" 转成 "First Last"
+fn bibtex_authors(field: &str) -> Vec<String> {
+    AUTHOR_SPLIT_RE
+        .split(field)
+        .map(clean_latex)
+        .filter(|name| !name.is_empty() && !name.eq_ignore_ascii_case("others"))
+        .map(|name| match name.split_once(',') {
+            Some((last, first)) => format!("{} {}", first.trim(), last.trim()).trim().to_string(),
+            None => name,
+        })
+        .collect()
+}
+
+/// file 字段（JabRef / Zotero 格式）："描述:路径:类型"，多个文件用分号分隔，冒号可用 \: 转义
+fn bibtex_files(field: &str, base_dir: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    for entry in field.split(';') {
+        let mut parts: Vec<String> = vec![String::new()];
+        let mut chars = entry.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => {
+                    if let Some(next) = chars.next() {
+                        parts.last_mut().unwrap().push(next);
+                    }
+                }
+                ':' => parts.push(String::new()),
+                _ => parts.last_mut().unwrap().push(c),
+            }
+        }
+        // 三段以上时首尾是描述和类型；中间多出的冒号是 Windows 盘符
+        let path = if parts.len() >= 3 {
+            parts[1..parts.len() - 1].join(":")
+        } else {
+            parts.join(":")
+        };
+        let path = path.trim();
+        if !path.to_lowercase().ends_with(".pdf") {
+            continue;
+        }
+        let path = PathBuf::from(path);
+        files.push(if path.is_absolute() { path } else { base_dir.join(path) });
+    }
+    files
+}
+
+fn read_bibtex(path: &Path) -> Result<ImportSource, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let base_dir = path.parent().unwrap_or(Path::new("."));
+
+    let mut group_names = HashSet::new();
+    let mut items = Vec::new();
+    for entry in parse_bibtex(&text) {
+        let field = |name: &str| entry.fields.get(name).map(|v| clean_latex(v)).filter(|v| !v.is_empty());
+        let is_arxiv = field("archiveprefix").is_some_and(|p| p.eq_ignore_ascii_case("arxiv"));
+        let arxiv_id = field("eprint")
+            .filter(|_| is_arxiv)
+            .and_then(|id| find_arxiv_id(&format!("arXiv:{}", id)))
+            .or_else(|| {
+                let hints = ["journal", "url", "note", "howpublished"]
+                    .iter()
+                    .filter_map(|f| entry.fields.get(*f))
+                    .cloned()
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                find_arxiv_id(&hints)
+            });
+        // JabRef 的 groups 字段对应分类
+        let groups: Vec<String> = field("groups")
+            .map(|g| g.split(',').map(|s| s.trim().to_string()).filter(|s| !s.is_empty()).collect())
+            .unwrap_or_default();
+        group_names.extend(groups.iter().cloned());
+
+        items.push(ImportedItem {
+            key: entry.key.clone(),
+            meta: ExternalMetadata {
+                title: field("title"),
+                authors: entry.fields.get("author").map(|a| bibtex_authors(a)).unwrap_or_default(),
+                doi: entry.fields.get("doi").and_then(|d| find_doi(d)),
+                arxiv_id,
+                year: field("year").or_else(|| field("date")).and_then(|d| year_of(&d)),
+            },
+            pdfs: entry.fields.get("file").map(|f| bibtex_files(f, base_dir)).unwrap_or_default(),
+            collections: groups.iter().map(|g| format!("bibtex:{}", g)).collect(),
+        });
+    }
+
+    Ok(ImportSource {
+        source: "bibtex",
+        items,
+        collections: group_names
+            .into_iter()
+            .map(|name| ImportedCollection {
+                id: format!("bibtex:{}", name),
+                name,
+                parent_id: None,
+            })
+            .collect(),
+    })
+}
+
+/* ---------------------------------
+ * CSL-JSON
+ * --------------------------------- */
+
+fn csl_authors(item: &Value) -> Vec<String> {
+    item.get("author")
+        .and_then(Value::as_array)
+        .map(|authors| {
+            authors
+                .iter()
+                .filter_map(|a| {
+                    if let Some(literal) = a.get("literal").and_then(Value::as_str) {
+                        return Some(literal.trim().to_string());
+                    }
+                    let given = a.get("given").and_then(Value::as_str).unwrap_or("");
+                    let family = a.get("family").and_then(Value::as_str).unwrap_or("");
+                    let name = format!("{} {}", given.trim(), family.trim()).trim().to_string();
+                    (!name.is_empty()).then_some(name)
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn csl_year(item: &Value) -> Option<String> {
+    let issued = item.get("issued")?;
+    if let Some(year) = issued
+        .get("date-parts")
+        .and_then(|p| p.get(0))
+        .and_then(|p| p.get(0))
+    {
+        return match year {
+            Value::Number(n) => Some(n.to_string()),
+            Value::String(s) => year_of(s),
+            _ => None,
+        };
+    }
+    issued.get("raw").and_then(Value::as_str).and_then(year_of)
+}
+
+/// CSL-JSON 不含附件，条目只能与文献库中已有的文档匹配
+fn read_csl_json(path: &Path) -> Result<ImportSource, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let value: Value = serde_json::from_str(&text).map_err(|e| format!("Invalid CSL-JSON: {}", e))?;
+    let list = match &value {
+        Value::Array(items) => items.clone(),
+        Value::Object(obj) => obj.get("items").and_then(Value::as_array).cloned().unwrap_or_default(),
+        _ => Vec::new(),
+    };
+
+    let items = list
+        .iter()
+        .map(|item| {
+            let text = |key: &str| non_empty(item.get(key).and_then(Value::as_str).map(str::to_string));
+            let hints = ["URL", "note", "number", "archive_location"]
+                .iter()
+                .filter_map(|k| text(k))
+                .collect::<Vec<_>>()
+                .join(" ");
+            ImportedItem {
+                key: item
+                    .get("id")
+                    .map(|id| id.as_str().map(str::to_string).unwrap_or_else(|| id.to_string()))
+                    .unwrap_or_default(),
+                meta: ExternalMetadata {
+                    title: text("title"),
+                    authors: csl_authors(item),
+                    doi: text("DOI").and_then(|d| find_doi(&d)),
+                    arxiv_id: find_arxiv_id(&hints),
+                    year: csl_year(item),
+                },
+                pdfs: Vec::new(),
+                collections: Vec::new(),
+            }
+        })
+        .collect();
+
+    Ok(ImportSource {
+        source: "csl-json",
+        items,
+        collections: Vec::new(),
+    })
+}
+
+/* ---------------------------------
+ * 写入文献库
+ * --------------------------------- */
+
+fn find_existing(storage: &Storage, meta: &ExternalMetadata) -> Result<Option<library::LibraryEntry>, String> {
+    if let Some(doi) = &meta.doi {
+        if let Some(entry) = library::find_by_doi(storage, doi)? {
+            return Ok(Some(entry));
+        }
+    }
+    match &meta.title {
+        Some(title) => library::find_by_title(storage, title),
+        None => Ok(None),
+    }
+}
+
+fn apply_import(
+    storage: &Storage,
+    cache: &PdfTextCache,
+    scope: &FileScope,
+    import: ImportSource,
+    mut progress: impl FnMut(usize, usize),
+) -> Result<LibraryImport, String> {
+    let mut summary = LibraryImport {
+        source: import.source.to_string(),
+        items: import.items.len(),
+        ..Default::default()
+    };
+
+    for collection in &import.collections {
+        library::upsert_collection(
+            storage,
+            &collection.id,
+            &collection.name,
+            collection.parent_id.as_deref(),
+            import.source,
+        )?;
+        summary.collections += 1;
+    }
+
+    let total = import.items.len();
+    for (i, item) in import.items.iter().enumerate() {
+        let mut linked = Vec::new();
+        for pdf in &item.pdfs {
+            if !pdf.exists() {
+                summary.missing_files += 1;
+                continue;
+            }
+            // 导入的文件视同用户选择过，加入授权范围
+            let result = scope
+                .allow_file(storage, pdf)
+                .and_then(|canonical| library::register_file(storage, cache, &canonical, false));
+            match result {
+                Ok(entry) => {
+                    linked.push(entry.id);
+                    summary.documents += 1;
+                }
+                Err(e) => println!("⚠️ 导入失败 {}: {}", pdf.display(), e),
+            }
+        }
+        if linked.is_empty() {
+            match find_existing(storage, &item.meta)? {
+                Some(entry) => {
+                    linked.push(entry.id);
+                    summary.matched += 1;
+                }
+                None if item.pdfs.is_empty() => summary.unmatched += 1,
+                None => {}
+            }
+        }
+
+        for document_id in &linked {
+            library::apply_external_metadata(storage, document_id, &item.meta)?;
+            for collection in &item.collections {
+                library::add_to_collection(storage, document_id, collection)?;
+            }
+            if !item.key.is_empty() {
+                storage.with_conn(|conn| {
+                    conn.execute(
+                        "INSERT OR REPLACE INTO external_items (source, item_key, document_id, imported_at)
+                         VALUES (?1, ?2, ?3, ?4)",
+                        params![import.source, item.key, document_id, now_secs()],
+                    )?;
+                    Ok(())
+                })?;
+            }
+        }
+        progress(i + 1, total);
+    }
+    Ok(summary)
+}
+
+/* ---------------------------------
+ * RIS 笔记导出
+ * --------------------------------- */
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// Markdown 正文转成简单 HTML：空行分段，段内换行保留
+fn note_html(body: &str) -> String {
+    body.split("\n\n")
+        .map(str::trim)
+        .filter(|p| !p.is_empty())
+        .map(|p| format!("<p>{}</p>", escape_html(p).replace('\n', "<br/>")))
+        .collect()
+}
+
+fn ris_line(out: &mut String, tag: &str, value: &str) {
+    let value = value.replace(['\r', '\n'], " ");
+    let value = value.trim();
+    if !value.is_empty() {
+        out.push_str(&format!("{}  - {}\r\n", tag, value));
+    }
+}
+
+pub fn render_ris(storage: &Storage, document_ids: &[String]) -> Result<String, String> {
+    let mut out = String::new();
+    for document_id in document_ids {
+        let Some(entry) = library::find_entry(storage, document_id)? else {
+            continue;
+        };
+        let annotations = annotations::document_annotations(storage, document_id)?;
+        if annotations.highlights.is_empty() && annotations.notes.is_empty() {
+            continue;
+        }
+        let zotero_key: Option<String> = storage.with_conn(|conn| {
+            conn.query_row(
+                "SELECT item_key FROM external_items WHERE document_id = ?1 AND source = 'zotero' LIMIT 1",
+                params![document_id],
+                |row| row.get(0),
+            )
+            .or_else(|e| match e {
+                rusqlite::Error::QueryReturnedNoRows => Ok(None),
+                e => Err(e),
+            })
+        })?;
+
+        ris_line(&mut out, "TY", if entry.doi.is_some() { "JOUR" } else { "GEN" });
+        if let Some(key) = &zotero_key {
+            ris_line(&mut out, "ID", key);
+        }
+        ris_line(&mut out, "TI", entry.title.as_deref().unwrap_or("Untitled"));
+        for author in &entry.authors {
+            ris_line(&mut out, "AU", author);
+        }
+        if let Some(year) = &entry.year {
+            ris_line(&mut out, "PY", year);
+        }
+        if let Some(doi) = &entry.doi {
+            ris_line(&mut out, "DO", doi);
+        }
+        if let Some(arxiv_id) = &entry.arxiv_id {
+            ris_line(&mut out, "UR", &format!("https://arxiv.org/abs/{}", arxiv_id));
+        }
+        let mut tags = HashSet::new();
+        for tag in annotations.notes.iter().flat_map(|n| n.tags.iter()) {
+            if tags.insert(tag.to_lowercase()) {
+                ris_line(&mut out, "KW", tag);
+            }
+        }
+
+        // 每条高亮连同其笔记成为一条 Zotero 子笔记，独立笔记各自一条
+        for highlight in &annotations.highlights {
+            let mut html = format!(
+                "<p><strong>p. {}</strong></p><blockquote>{}</blockquote>",
+                highlight.page,
+                escape_html(&highlight.text)
+            );
+            for note in annotations
+                .notes
+                .iter()
+                .filter(|n| n.highlight_id.as_deref() == Some(highlight.id.as_str()))
+            {
+                html.push_str(&note_html(&note.body));
+            }
+            ris_line(&mut out, "N1", &html);
+        }
+        for note in annotations.notes.iter().filter(|n| n.highlight_id.is_none()) {
+            let mut html = note.page.map(|p| format!("<p><strong>p. {}</strong></p>", p)).unwrap_or_default();
+            html.push_str(&note_html(&note.body));
+            ris_line(&mut out, "N1", &html);
+        }
+        out.push_str("ER  - \r\n\r\n");
+    }
+    Ok(out)
+}
+
+fn annotated_documents(storage: &Storage) -> Result<Vec<String>, String> {
+    storage.with_conn(|conn| {
+        let mut stmt = conn.prepare(
+            "SELECT id FROM documents
+             WHERE id IN (SELECT document_id FROM highlights UNION SELECT document_id FROM notes)
+             ORDER BY title COLLATE NOCASE",
+        )?;
+        let rows = stmt.query_map([], |row| row.get(0))?;
+        rows.collect()
+    })
+}
+
+/* ---------------------------------
+ * 命令
+ * --------------------------------- */
+
+/// 选择 zotero.sqlite、.bib 或 CSL .json 文件并导入，进度通过 library_import_progress 事件发送
+#[tauri::command]
+pub async fn import_reference_library(app_handle: tauri::AppHandle) -> Result<Option<LibraryImport>, String> {
+    use tauri::Manager;
+
+    let Some(path) = file_scope::pick_file_dialog(&app_handle, "Zotero / BibTeX / CSL-JSON", &["sqlite", "bib", "json"])
+        .await?
+    else {
+        return Ok(None);
+    };
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let extension = path
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        let import = match extension.as_str() {
+            "sqlite" => read_zotero(&path)?,
+            "bib" => read_bibtex(&path)?,
+            "json" => read_csl_json(&path)?,
+            _ => return Err(format!("Unsupported file: {}", path.display())),
+        };
+        println!("📚 从 {} 导入 {} 个条目: {}", import.source, import.items.len(), path.display());
+
+        let summary = apply_import(
+            &app_handle.state::<Storage>(),
+            &app_handle.state::<PdfTextCache>(),
+            &app_handle.state::<FileScope>(),
+            import,
+            |done, total| {
+                crate::emit_to_window(
+                    &app_handle,
+                    "library_import_progress",
+                    serde_json::json!({ "done": done, "total": total }),
+                )
+            },
+        )?;
+        println!(
+            "📚 导入完成: {} 个文档, {} 个匹配, {} 个缺少文件, {} 个未匹配",
+            summary.documents, summary.matched, summary.missing_files, summary.unmatched
+        );
+        Ok(Some(summary))
+    })
+    .await
+    .map_err(|e| format!("Import failed: {}", e))?
+}
+
+/// 把高亮和笔记导出为 RIS 文件，可在 Zotero 中通过“导入”加入；不传 document_ids 时导出所有有批注的文档
+#[tauri::command]
+pub async fn export_zotero_notes(
+    document_ids: Option<Vec<String>>,
+    app_handle: tauri::AppHandle,
+) -> Result<Option<String>, String> {
+    use tauri::Manager;
+
+    let ris = {
+        let storage = app_handle.state::<Storage>();
+        let ids = match document_ids {
+            Some(ids) => ids,
+            None => annotated_documents(&storage)?,
+        };
+        render_ris(&storage, &ids)?
+    };
+    if ris.is_empty() {
+        return Err("No annotations to export".to_string());
+    }
+
+    let Some(dest) = file_scope::save_dialog(&app_handle, "RIS", &["ris"], "papertalk-notes.ris".to_string()).await?
+    else {
+        return Ok(None);
+    };
+    std::fs::write(&dest, ris).map_err(|e| format!("Failed to write {}: {}", dest.display(), e))?;
+    println!("📤 已导出 Zotero 笔记: {}", dest.display());
+    Ok(Some(dest.to_string_lossy().to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn field<'a>(entry: &'a BibEntry, name: &str) -> Option<&'a str> {
+        entry.fields.get(name).map(String::as_str)
+    }
+
+    #[test]
+    fn parse_bibtex_handles_values_strings_and_comments() {
+        let text = r#"
+@comment{ ignored @article{not, title={an entry}} }
+@string{ conf = "Proc. of Something" }
+@inproceedings{ smith2020,
+  title = {Nested {Braces} and {M{o}re}},
+  author = "Smith, John and {Doe}, Jane",
+  booktitle = conf # " 2020",
+  note = {Escaped \{ brace and \} close},
+  year = 2020,
+}
+@article(paren2021, title = "Quoted {with \"inner\"} value")
+"#;
+        let entries = parse_bibtex(text);
+        assert_eq!(entries.len(), 2);
+
+        let first = &entries[0];
+        assert_eq!(first.key, "smith2020");
+        assert_eq!(field(first, "title"), Some("Nested {Braces} and {M{o}re}"));
+        assert_eq!(field(first, "author"), Some("Smith, John and {Doe}, Jane"));
+        assert_eq!(field(first, "booktitle"), Some("Proc. of Something 2020"));
+        assert_eq!(field(first, "note"), Some(r"Escaped \{ brace and \} close"));
+        assert_eq!(field(first, "year"), Some("2020"));
+
+        let second = &entries[1];
+        assert_eq!(second.key, "paren2021");
+        assert_eq!(field(second, "title"), Some(r#"Quoted {with \"inner\"} value"#));
+    }
+
+    #[test]
+    fn parse_bibtex_survives_truncated_input() {
+        let cases: &[(&str, usize)] = &[
+            (r"@misc{k, title={Trunc \", 1),
+            (r#"@misc{k, title="Trunc \"#, 1),
+            ("@misc{k, title={Unclosed", 1),
+            ("@misc{k, title=", 1),
+            ("@misc{k", 1),
+            ("@comment{never closed \\", 0),
+            ("@", 0),
+            ("@misc{k, title={Trunc ü \\", 1),
+        ];
+        for (text, count) in cases {
+            let entries = parse_bibtex(text);
+            assert_eq!(entries.len(), *count, "{:?}", text);
+            if let Some(entry) = entries.first() {
+                assert_eq!(entry.key, "k", "{:?}", text);
+                assert!(!entry.fields.contains_key("title"), "{:?}", text);
+            }
+        }
+
+        // 截断之前的完整条目和字段仍然保留
+        let entries = parse_bibtex("@misc{a, title={Done}}\n@misc{b, year=2001, title={Trunc \\");
+        assert_eq!(entries.len(), 2);
+        assert_eq!(field(&entries[0], "title"), Some("Done"));
+        assert_eq!(field(&entries[1], "year"), Some("2001"));
+    }
+
+    #[test]
+    fn clean_latex_strips_markup() {
+        let cases = [
+            (r#"M{\"u}ller"#, "Müller"),
+            (r"Caf\'e", "Café"),
+            (r"{\'{e}}t{\'e}", "été"),
+            (r"Q\&A -- 50\% off --- now", "Q&A – 50% off — now"),
+            ("Deep~Learning", "Deep Learning"),
+            (r"\emph{Attention} is   {All} You Need", "Attention is All You Need"),
+            (r"Stra{\ss}e", "Straße"),
+            ("", ""),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(clean_latex(input), expected, "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn bibtex_authors_normalizes_names() {
+        let cases: [(&str, &[&str]); 4] = [
+            ("Smith, John and Jane Doe", &["John Smith", "Jane Doe"]),
+            (r#"M{\"u}ller, Hans and others"#, &["Hans Müller"]),
+            ("{OpenAI}", &["OpenAI"]),
+            ("", &[]),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(bibtex_authors(input), expected, "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn bibtex_files_keeps_pdfs_and_resolves_relative_paths() {
+        let base = Path::new("/library");
+        let cases: [(&str, Vec<PathBuf>); 5] = [
+            ("Full Text:papers/a.pdf:application/pdf", vec![base.join("papers/a.pdf")]),
+            ("/abs/b.PDF", vec![PathBuf::from("/abs/b.PDF")]),
+            (
+                "Snapshot:snap.html:text/html;PDF:c.pdf:application/pdf",
+                vec![base.join("c.pdf")],
+            ),
+            (r"PDF:dir\:with\:colons/d.pdf:application/pdf", vec![base.join("dir:with:colons/d.pdf")]),
+            ("", vec![]),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(bibtex_files(input, base), expected, "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn csl_year_reads_date_parts_and_raw() {
+        let cases = [
+            (json!({"issued": {"date-parts": [[2019, 5, 1]]}}), Some("2019")),
+            (json!({"issued": {"date-parts": [["2018"]]}}), Some("2018")),
+            (json!({"issued": {"raw": "Spring 2017"}}), Some("2017")),
+            (json!({"issued": {"date-parts": [[null]]}}), None),
+            (json!({"issued": {"literal": "n.d."}}), None),
+            (json!({}), None),
+        ];
+        for (item, expected) in cases {
+            assert_eq!(csl_year(&item).as_deref(), expected, "{}", item);
+        }
+    }
+
+    #[test]
+    fn attachment_path_resolves_storage_and_linked_files() {
+        let data_dir = Path::new("/zotero");
+        let cases = [
+            ("storage:paper.pdf", 0, Some(data_dir.join("storage").join("ABCD1234").join("paper.pdf"))),
+            ("/home/me/linked.pdf", 2, Some(PathBuf::from("/home/me/linked.pdf"))),
+            ("attachments:rel/linked.pdf", 2, None),
+            ("https://example.com/paper.pdf", 3, None),
+            ("", 0, None),
+        ];
+        for (path, link_mode, expected) in cases {
+            assert_eq!(attachment_path(data_dir, "ABCD1234", path, link_mode), expected, "{:?}", path);
+        }
+    }
+}
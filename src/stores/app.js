@@ -1,46 +1,62 @@
 import { defineStore } from 'pinia'
 import { ref, computed } from 'vue'
+import { invoke } from '@tauri-apps/api/core'
 
 export const useAppStore = defineStore('app', () => {
   // State
   const currentPdf = ref(null)
+  const currentDocument = ref(null) // 文献库中的记录（含文档 ID 与阅读位置）
   const currentPage = ref(1)
   const totalPages = ref(0)
   const zoomLevel = ref(100)
   const isDarkMode = ref(false)
   const selectedText = ref('')
+  const selectedRegion = ref(null) // 框选的页面区域 { page, image: PNG 的 base64, preview: dataURL }
   const translatedText = ref('')
+  const equationResult = ref(null) // 公式讲解 { latex, symbols, explanation }
+  const translationWarnings = ref([]) // 译文质量检查 [{ kind, message }]
+  const backTranslate = ref(false) // 翻译后是否回译检查
   const translating = ref(false)
-  const notes = ref([])
+  const notes = ref([]) // 当前文档的笔记（保存在后端）
+  const highlights = ref([]) // 当前文档的高亮
   const ollamaStatus = ref('checking') // 'checking', 'running', 'not-found'
   const isStreaming = ref(false)
   const streamingText = ref('')
+  const showReasoning = ref(false) // 是否展示推理模型的思考过程
+  const reasoningText = ref('')
   
   // 大模型配置
   const modelConfig = ref({
     selectedModel: 'ollama', // ollama, deepseek, kimi
     ollama: {
       baseUrl: 'http://127.0.0.1:11434',
-      model: 'qwen3:8b'
+      model: 'qwen3:8b',
+      visionModel: 'qwen2.5vl:7b' // 翻译图表区域时使用的多模态模型
     },
     deepseek: {
       apiKey: '',
       baseUrl: 'https://api.deepseek.com',
-      model: 'deepseek-chat'
+      model: 'deepseek-chat',
+      visionModel: ''
     },
     kimi: {
       apiKey: '',
       baseUrl: 'https://api.moonshot.cn',
-      model: 'kimi-k2-0905-preview'
-    }
+      model: 'kimi-k2-0905-preview',
+      visionModel: 'moonshot-v1-8k-vision-preview'
+    },
+    // 当前模型失败后依次尝试的模型；云端模型需要开启“在线翻译兜底”
+    fallbackChain: ['deepseek', 'kimi'],
+    allowCloudFallback: false
   })
 
   // Chat 相关状态
-  const activePanel = ref('translation') // translation, chat
+  const activePanel = ref('translation') // translation, chat, summary
   const chatMessages = ref([])
   const currentChatMessage = ref('')
   const isChatting = ref(false)
   const chatStreamingText = ref('')
+  const chatReasoningText = ref('')
   const currentPageContent = ref('') // 当前页面内容
 
   // Getters
@@ -52,7 +68,18 @@ export const useAppStore = defineStore('app', () => {
   // Actions
   const setCurrentPdf = (pdf) => {
     currentPdf.value = pdf
+    currentDocument.value = null
     currentPage.value = 1
+    notes.value = []
+    highlights.value = []
+  }
+
+  // 恢复上次的阅读位置
+  const setCurrentDocument = (entry) => {
+    currentDocument.value = entry
+    if (entry && entry.last_page > 1) {
+      currentPage.value = entry.last_page
+    }
   }
 
   const setTotalPages = (pages) => {
@@ -87,25 +114,75 @@ export const useAppStore = defineStore('app', () => {
 
   const setSelectedText = (text) => {
     selectedText.value = text.trim()
+    selectedRegion.value = null
+  }
+
+  const setSelectedRegion = (region) => {
+    selectedRegion.value = region
+    selectedText.value = ''
   }
 
   const setTranslatedText = (text) => {
     translatedText.value = text
+    equationResult.value = null
+    translationWarnings.value = []
+  }
+
+  const setTranslationWarnings = (warnings) => {
+    translationWarnings.value = warnings || []
+  }
+
+  const setBackTranslate = (status) => {
+    backTranslate.value = status
+  }
+
+  const setEquationResult = (result) => {
+    equationResult.value = result
   }
 
   const setTranslating = (status) => {
     translating.value = status
   }
 
-  const addNote = (note) => {
-    if (note.trim()) {
-      notes.value.push({
-        id: Date.now(),
-        text: note.trim(),
-        page: currentPage.value,
-        createdAt: new Date().toISOString()
-      })
-    }
+  const setAnnotations = (annotations) => {
+    highlights.value = annotations?.highlights || []
+    notes.value = annotations?.notes || []
+  }
+
+  const addNote = async (body, highlightId = null) => {
+    if (!body.trim() || !currentDocument.value) return null
+    const note = await invoke('create_note', {
+      documentId: currentDocument.value.id,
+      body,
+      highlightId,
+      page: currentPage.value
+    })
+    notes.value.push(note)
+    return note
+  }
+
+  const addHighlight = async ({ page, quads, color, text }) => {
+    if (!currentDocument.value) return null
+    const highlight = await invoke('create_highlight', {
+      documentId: currentDocument.value.id,
+      page: page || currentPage.value,
+      quads,
+      color,
+      text
+    })
+    highlights.value.push(highlight)
+    return highlight
+  }
+
+  const removeNote = async (id) => {
+    await invoke('delete_note', { id })
+    notes.value = notes.value.filter((n) => n.id !== id)
+  }
+
+  const removeHighlight = async (id) => {
+    await invoke('delete_highlight', { id })
+    highlights.value = highlights.value.filter((h) => h.id !== id)
+    notes.value = notes.value.map((n) => (n.highlight_id === id ? { ...n, highlight_id: null } : n))
   }
 
   const setOllamaStatus = (status) => {
@@ -128,6 +205,18 @@ export const useAppStore = defineStore('app', () => {
     streamingText.value = ''
   }
 
+  const setShowReasoning = (status) => {
+    showReasoning.value = status
+  }
+
+  const appendReasoningText = (chunk) => {
+    reasoningText.value += chunk
+  }
+
+  const clearReasoningText = () => {
+    reasoningText.value = ''
+  }
+
   // 大模型配置相关操作
   const setSelectedModel = (model) => {
     modelConfig.value.selectedModel = model
@@ -141,6 +230,33 @@ export const useAppStore = defineStore('app', () => {
     return modelConfig.value[modelConfig.value.selectedModel]
   }
 
+  // 转换成后端 ModelConfig 的字段格式
+  const getBackendModelConfig = () => {
+    const config = modelConfig.value
+    return {
+      selected_model: config.selectedModel,
+      ollama: {
+        base_url: config.ollama.baseUrl,
+        model: config.ollama.model,
+        vision_model: config.ollama.visionModel || ''
+      },
+      deepseek: {
+        api_key: config.deepseek.apiKey,
+        base_url: config.deepseek.baseUrl,
+        model: config.deepseek.model,
+        vision_model: config.deepseek.visionModel || ''
+      },
+      kimi: {
+        api_key: config.kimi.apiKey,
+        base_url: config.kimi.baseUrl,
+        model: config.kimi.model,
+        vision_model: config.kimi.visionModel || ''
+      },
+      fallback_chain: config.fallbackChain,
+      allow_cloud_fallback: config.allowCloudFallback
+    }
+  }
+
   // Chat 相关操作
   const setActivePanel = (panel) => {
     activePanel.value = panel
@@ -151,6 +267,7 @@ export const useAppStore = defineStore('app', () => {
       id: Date.now(),
       role: message.role,
       content: message.content,
+      reasoning: message.reasoning || '',
       timestamp: new Date().toISOString()
     })
   }
@@ -175,6 +292,14 @@ export const useAppStore = defineStore('app', () => {
     chatStreamingText.value = ''
   }
 
+  const appendChatReasoningText = (chunk) => {
+    chatReasoningText.value += chunk
+  }
+
+  const clearChatReasoningText = () => {
+    chatReasoningText.value = ''
+  }
+
   const clearChatMessages = () => {
     chatMessages.value = []
   }
@@ -192,23 +317,32 @@ export const useAppStore = defineStore('app', () => {
   return {
     // State
     currentPdf,
+    currentDocument,
     currentPage,
     totalPages,
     zoomLevel,
     isDarkMode,
     selectedText,
+    selectedRegion,
     translatedText,
+    equationResult,
+    translationWarnings,
+    backTranslate,
     translating,
     notes,
+    highlights,
     ollamaStatus,
     isStreaming,
     streamingText,
+    showReasoning,
+    reasoningText,
     modelConfig,
     activePanel,
     chatMessages,
     currentChatMessage,
     isChatting,
     chatStreamingText,
+    chatReasoningText,
 
     // Getters
     hasPdf,
@@ -218,6 +352,7 @@ export const useAppStore = defineStore('app', () => {
 
     // Actions
     setCurrentPdf,
+    setCurrentDocument,
     setTotalPages,
     goToPage,
     nextPage,
@@ -225,19 +360,31 @@ export const useAppStore = defineStore('app', () => {
     setZoomLevel,
     toggleDarkMode,
     setSelectedText,
+    setSelectedRegion,
     setTranslatedText,
+    setEquationResult,
+    setTranslationWarnings,
+    setBackTranslate,
     setTranslating,
+    setAnnotations,
     addNote,
+    addHighlight,
+    removeNote,
+    removeHighlight,
     setOllamaStatus,
     setStreaming,
     setStreamingText,
     appendStreamingText,
     clearStreamingText,
+    setShowReasoning,
+    appendReasoningText,
+    clearReasoningText,
     
     // 大模型配置操作
     setSelectedModel,
     updateModelConfig,
     getCurrentModelConfig,
+    getBackendModelConfig,
 
     // Chat 操作
     setActivePanel,
@@ -247,6 +394,8 @@ export const useAppStore = defineStore('app', () => {
     setChatStreamingText,
     appendChatStreamingText,
     clearChatStreamingText,
+    appendChatReasoningText,
+    clearChatReasoningText,
     clearChatMessages,
     
     // 页面内容操作